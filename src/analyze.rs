@@ -0,0 +1,604 @@
+/*!
+ * Steganalysis, scoring how likely a file is to hide data. Four detectors:
+ *
+ * - a chi-square test of pairs of values, which LSB replacement evens out
+ *   (Westfeld and Pfitzmann), over growing shares of the samples from the first
+ *   so that a payload filling only the start of the image is found too
+ * - sample pair analysis, estimating the share of samples whose low bit was
+ *   replaced (Dumitrescu, Wu and Wang)
+ * - ancillary chunks that stand out: unregistered types, text that looks like
+ *   encoded or encrypted data, and data close to 8 bits of entropy per byte where
+ *   the chunk type isn't compressed by design
+ * - data after IEND, see `trailing`, when analyzing a whole file with `analyze_file`,
+ *   and ZIP archives there that make the file a polyglot, see `polyglot`
+ *
+ * They are heuristics: very noisy images can look embedded to the chi-square test,
+ * a payload scattered over a few samples goes unnoticed, and so does plain text in a
+ * registered chunk. The pixel tests only run on 8 bit greyscale and truecolour images.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::{ChunkCategory, ChunkType};
+use crate::ihdr::ColorType;
+use crate::png::Png;
+use crate::polyglot::{self, Archive};
+use crate::trailing;
+use crate::Error;
+use std::fmt;
+
+//Fewer samples than this say nothing reliable about their low bits
+const MIN_SAMPLES: usize = 256;
+//Text above this many bits per byte looks like base64 or hex rather than words
+const TEXT_ENTROPY: f64 = 5.5;
+//Data above this share of the entropy of random data as long is most likely compressed
+//or encrypted, 7 bits per byte for long data
+const RANDOM_ENTROPY: f64 = 7.0 / 8.0;
+//Close enough to the entropy of random data as long to be compressed or encrypted
+const NEAR_RANDOM_ENTROPY: f64 = 7.5 / 8.0;
+//Longer data has its expected entropy approximated rather than summed
+const EXACT_ENTROPY_LENGTH: usize = 4096;
+//Shorter chunks have too few bytes for their entropy to mean much
+const MIN_ENTROPY_LENGTH: usize = 64;
+//A few stray bytes after IEND, such as a newline, are left by careless tools
+const MIN_TRAILING_LENGTH: usize = 16;
+
+//Result of the chi-square test
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChiSquare {
+    //That the low bits of the samples were replaced, from 0 to 1
+    pub probability: f64,
+    //Share of the samples, from the first, the probability was found over
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    //Neither defined by the spec nor a registered extension
+    Unregistered,
+    //Uncompressed text with the entropy of encoded data
+    EncodedText,
+    //Data of a type that isn't compressed, with the entropy of compressed data
+    HighEntropy,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let anomaly = match self {
+            Self::Unregistered => "unregistered chunk",
+            Self::EncodedText => "text looks encoded",
+            Self::HighEntropy => "data looks compressed or encrypted",
+        };
+        write!(f, "{}", anomaly)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkAnomaly {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    //Shannon entropy of the chunk data, in bits per byte
+    pub entropy: f64,
+    pub anomaly: Anomaly,
+}
+
+impl ChunkAnomaly {
+    //How suspicious the chunk is, from 0 to 1
+    pub fn score(&self) -> f64 {
+        let length = self.length as usize;
+        let random = length >= MIN_ENTROPY_LENGTH
+            && self.entropy > RANDOM_ENTROPY * expected_entropy(length);
+        match self.anomaly {
+            Anomaly::Unregistered if random => 0.9,
+            Anomaly::Unregistered => 0.5,
+            Anomaly::EncodedText | Anomaly::HighEntropy => 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkEntropy {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    //Bits per byte, from 0 to 8
+    pub entropy: f64,
+}
+
+//Bytes after IEND
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrailingData {
+    //Where they start in the file
+    pub offset: usize,
+    pub length: usize,
+    pub entropy: f64,
+    //A ZIP archive the file opens as too
+    pub archive: Option<Archive>,
+}
+
+impl TrailingData {
+    pub fn score(&self) -> f64 {
+        if self.length >= MIN_TRAILING_LENGTH {
+            0.9
+        } else {
+            0.5
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    Unlikely,
+    Possible,
+    Likely,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = match self {
+            Self::Unlikely => "unlikely",
+            Self::Possible => "possible",
+            Self::Likely => "likely",
+        };
+        write!(f, "{}", verdict)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    //None when the image isn't 8 bit greyscale or truecolour, or is too small
+    pub chi_square: Option<ChiSquare>,
+    //Estimated share of the samples carrying hidden bits, from sample pair analysis,
+    //None too when the image doesn't fit its model, as flat synthetic ones
+    pub embedding_rate: Option<f64>,
+    pub chunks: Vec<ChunkAnomaly>,
+    //Every chunk, in file order
+    pub entropies: Vec<ChunkEntropy>,
+    //Only found by `analyze_file`
+    pub trailing: Option<TrailingData>,
+}
+
+impl Analysis {
+    /**
+     * How likely the file is to hide data, from 0 to 1: the strongest of the
+     * detectors, none of which is sure enough to reach 1 on its own.
+     */
+    pub fn score(&self) -> f64 {
+        let chi_square = self.chi_square.map_or(0.0, |chi| chi.probability * 0.9);
+        //Natural images estimate within a few percent of 0
+        let pairs = self
+            .embedding_rate
+            .map_or(0.0, |rate| (rate / 0.1).clamp(0.0, 1.0) * 0.9);
+        let trailing = self.trailing.map_or(0.0, |trailing| trailing.score());
+        self.chunks
+            .iter()
+            .map(ChunkAnomaly::score)
+            .fold(chi_square.max(pairs).max(trailing), f64::max)
+    }
+
+    pub fn verdict(&self) -> Verdict {
+        match self.score() {
+            score if score >= 0.7 => Verdict::Likely,
+            score if score >= 0.3 => Verdict::Possible,
+            _ => Verdict::Unlikely,
+        }
+    }
+}
+
+impl Png {
+    //Runs every detector the image allows
+    pub fn analyze(&self) -> Result<Analysis, Error> {
+        let header = self.header()?;
+        let supported = header.color_type != ColorType::Indexed && header.bit_depth == 8;
+        let (chi_square, embedding_rate) = if supported {
+            let scanlines = self.scanlines()?;
+            let channels = header.color_type.channels() as usize;
+            let colours = channels - header.color_type.has_alpha() as usize;
+            let samples: Vec<u8> = scanlines
+                .data()
+                .chunks_exact(channels)
+                .flat_map(|pixel| pixel[..colours].iter().copied())
+                .collect();
+            let rows: Vec<&[u8]> = scanlines.rows().collect();
+            (
+                chi_square_prefixes(&samples),
+                sample_pairs(&rows, channels, colours),
+            )
+        } else {
+            (None, None)
+        };
+        let entropies: Vec<ChunkEntropy> = self
+            .chunks()
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| ChunkEntropy {
+                index,
+                chunk_type: chunk.chunk_type().clone(),
+                length: chunk.length(),
+                entropy: entropy(chunk.data()),
+            })
+            .collect();
+        let chunks = self
+            .chunks()
+            .iter()
+            .zip(&entropies)
+            .filter_map(|(chunk, found)| chunk_anomaly(found.index, chunk, found.entropy))
+            .collect();
+        Ok(Analysis {
+            chi_square,
+            embedding_rate,
+            chunks,
+            entropies,
+            trailing: None,
+        })
+    }
+}
+
+//Same as `Png::analyze` for the bytes of a whole file, which can go on after IEND
+pub fn analyze_file(bytes: &[u8]) -> Result<Analysis, Error> {
+    let (image, rest) = trailing::split(bytes)?;
+    let mut analysis = Png::try_from(image)?.analyze()?;
+    if !rest.is_empty() {
+        analysis.trailing = Some(TrailingData {
+            offset: image.len(),
+            length: rest.len(),
+            entropy: entropy(rest),
+            archive: polyglot::find(bytes),
+        });
+    }
+    Ok(analysis)
+}
+
+//Shannon entropy of `data` in bits per byte, 0 for no data
+pub fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let length = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let frequency = count as f64 / length;
+            -frequency * frequency.log2()
+        })
+        .sum()
+}
+
+/**
+ * Entropy that `entropy` gives on average for `length` random bytes. It is below 8
+ * bits per byte as a short sample doesn't hold every byte value, 5.8 for 64 bytes and
+ * 7.2 for 256. Summed over the binomial count of a byte value up to
+ * `EXACT_ENTROPY_LENGTH`, approximated by the Miller-Madow bias above.
+ */
+pub fn expected_entropy(length: usize) -> f64 {
+    if length == 0 {
+        return 0.0;
+    }
+    let n = length as f64;
+    if length > EXACT_ENTROPY_LENGTH {
+        return 8.0 - 255.0 / (2.0 * n * std::f64::consts::LN_2);
+    }
+    //Probability that a byte value occurs `count` times, from (255/256)^n for none
+    let mut probability = (255.0f64 / 256.0).powf(n);
+    let mut sum = 0.0;
+    for count in 1..=length {
+        probability *= (n - count as f64 + 1.0) / count as f64 / 255.0;
+        let frequency = count as f64 / n;
+        sum -= probability * frequency * frequency.log2();
+    }
+    256.0 * sum
+}
+
+/**
+ * Whether an ancillary chunk, long enough to tell, is close to random data although
+ * its type isn't compressed by design, as zTXt, iCCP and compressed iTXt are.
+ */
+pub fn is_near_random(chunk: &Chunk, entropy: f64) -> bool {
+    let chunk_type = chunk.chunk_type();
+    let compressed = *chunk_type == ChunkType::zTXt
+        || *chunk_type == ChunkType::iCCP
+        || (*chunk_type == ChunkType::iTXt && compressed_international(chunk.data()));
+    !chunk_type.is_critical()
+        && !compressed
+        && chunk.data().len() >= MIN_ENTROPY_LENGTH
+        && entropy >= NEAR_RANDOM_ENTROPY * expected_entropy(chunk.data().len())
+}
+
+fn chunk_anomaly(index: usize, chunk: &Chunk, entropy: f64) -> Option<ChunkAnomaly> {
+    let chunk_type = chunk.chunk_type();
+    let anomaly = if chunk_type.is_critical() {
+        return None;
+    } else if !chunk_type.is_registered() {
+        Anomaly::Unregistered
+    } else if chunk_type.category() == ChunkCategory::Text
+        && *chunk_type != ChunkType::zTXt
+        && chunk.data().len() >= MIN_ENTROPY_LENGTH
+        && entropy > TEXT_ENTROPY
+    {
+        //zTXt, and iTXt when compressed, are random by nature
+        if *chunk_type == ChunkType::iTXt && compressed_international(chunk.data()) {
+            return None;
+        }
+        Anomaly::EncodedText
+    } else if is_near_random(chunk, entropy) {
+        Anomaly::HighEntropy
+    } else {
+        return None;
+    };
+    Some(ChunkAnomaly {
+        index,
+        chunk_type: chunk_type.clone(),
+        length: chunk.length(),
+        entropy,
+        anomaly,
+    })
+}
+
+//The compression flag follows the null byte ending the keyword
+fn compressed_international(data: &[u8]) -> bool {
+    let flag = data.iter().position(|&byte| byte == 0).map(|end| end + 1);
+    flag.and_then(|flag| data.get(flag)) == Some(&1)
+}
+
+//Highest chi-square probability over the first tenth, two tenths... of the samples
+fn chi_square_prefixes(samples: &[u8]) -> Option<ChiSquare> {
+    let mut best: Option<ChiSquare> = None;
+    for tenths in 1..=10 {
+        let prefix = &samples[..samples.len() * tenths / 10];
+        if prefix.len() < MIN_SAMPLES {
+            continue;
+        }
+        let Some(probability) = chi_square(prefix) else {
+            continue;
+        };
+        if best.is_none_or(|best| probability >= best.probability) {
+            best = Some(ChiSquare {
+                probability,
+                share: tenths as f64 / 10.0,
+            });
+        }
+    }
+    best
+}
+
+/**
+ * Probability that the low bits of `samples` were replaced. Replacing them makes
+ * the counts of each pair of values 2k and 2k+1 equal, so the test measures how far
+ * the counts are from their pair's mean and turns that into the probability of
+ * being this close by chance.
+ */
+fn chi_square(samples: &[u8]) -> Option<f64> {
+    let mut counts = [0usize; 256];
+    for &sample in samples {
+        counts[sample as usize] += 1;
+    }
+    let mut statistic = 0.0;
+    let mut pairs = 0;
+    for pair in counts.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        //Rare values say nothing
+        if pair[0] + pair[1] < 5 {
+            continue;
+        }
+        statistic += (pair[0] as f64 - expected).powi(2) / expected;
+        pairs += 1;
+    }
+    if pairs < 2 {
+        return None;
+    }
+    Some(upper_gamma((pairs - 1) as f64 / 2.0, statistic / 2.0))
+}
+
+/**
+ * Sample pair analysis over horizontally adjacent samples of the same channel,
+ * solving the quadratic relating the pair counts to the embedding rate and keeping
+ * the root closest to 0. It loses track when nearly every sample was replaced, which
+ * the chi-square test catches instead.
+ */
+fn sample_pairs(rows: &[&[u8]], channels: usize, colours: usize) -> Option<f64> {
+    let (mut w, mut x, mut y, mut z, mut pairs) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for row in rows {
+        for (left, right) in row
+            .chunks_exact(channels)
+            .zip(row.chunks_exact(channels).skip(1))
+        {
+            for channel in 0..colours {
+                let (u, v) = (left[channel], right[channel]);
+                if u >> 1 == v >> 1 && u != v {
+                    w += 1.0;
+                }
+                if u == v {
+                    z += 1.0;
+                }
+                if (v % 2 == 0 && u < v) || (v % 2 == 1 && u > v) {
+                    x += 1.0;
+                }
+                if (v % 2 == 0 && u > v) || (v % 2 == 1 && u < v) {
+                    y += 1.0;
+                }
+                pairs += 1.0;
+            }
+        }
+    }
+    if pairs < MIN_SAMPLES as f64 {
+        return None;
+    }
+    let a = (w + z) / 2.0;
+    let b = 2.0 * x - pairs;
+    let c = y - x;
+    let discriminant = b * b - 4.0 * a * c;
+    let rate = if a == 0.0 || discriminant < 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        c / b
+    } else {
+        let root = discriminant.sqrt();
+        let (first, second) = ((-b + root) / (2.0 * a), (-b - root) / (2.0 * a));
+        if first.abs() <= second.abs() {
+            first
+        } else {
+            second
+        }
+    };
+    //Small negative estimates are noise around 0, the others mean the model doesn't fit
+    match rate {
+        rate if (0.0..=1.0).contains(&rate) => Some(rate),
+        rate if rate > -0.1 && rate < 0.0 => Some(0.0),
+        _ => None,
+    }
+}
+
+//Regularized upper incomplete gamma function Q(a, x), series or continued fraction
+fn upper_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..1000 {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * 1e-12 {
+                break;
+            }
+        }
+        return (1.0 - sum * prefix).clamp(0.0, 1.0);
+    }
+    //Lentz's method
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut fraction = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        fraction *= delta;
+        if (delta - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    (prefix * fraction).clamp(0.0, 1.0)
+}
+
+//Lanczos approximation of ln Γ(x) for x > 0
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |sum, (index, coefficient)| {
+            sum + coefficient / (x + 1.0 + index as f64)
+        });
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+    use crate::stego::Channels;
+    use std::str::FromStr;
+
+    //A smooth gradient, whose values are all even
+    fn gradient() -> Png {
+        let mut png = PngBuilder::new(64, 64).build().unwrap();
+        let mut scanlines = png.scanlines().unwrap();
+        for (index, sample) in scanlines.data_mut().iter_mut().enumerate() {
+            *sample = ((index / 3 % 64) * 2 + index / 192 % 64) as u8 & !1;
+        }
+        png.set_scanlines(&scanlines).unwrap();
+        png
+    }
+
+    #[test]
+    pub fn test_analyze_pixels() {
+        let mut png = gradient();
+        let clean = png.analyze().unwrap();
+        assert!(clean.chi_square.unwrap().probability < 0.1);
+        assert_eq!(clean.verdict(), Verdict::Unlikely);
+
+        //Pseudo-random bytes, as an encrypted payload would be
+        let payload: Vec<u8> = (0..1000u32)
+            .map(|index| (index.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        png.embed_lsb(&payload, Channels::Colour, None).unwrap();
+        let embedded = png.analyze().unwrap();
+        let chi_square = embedded.chi_square.unwrap();
+        assert!(chi_square.probability > 0.9);
+        assert!(chi_square.share < 1.0);
+        assert_eq!(embedded.verdict(), Verdict::Likely);
+    }
+
+    #[test]
+    pub fn test_analyze_chunks() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        let random: Vec<u8> = (0..=255).collect();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::from_str("ruSt").unwrap(), random));
+        png.insert_chunk_ordered(Chunk::new(ChunkType::tEXt, b"Comment\0hello".to_vec()));
+        let analysis = png.analyze().unwrap();
+        assert_eq!(analysis.chi_square, None);
+        assert_eq!(analysis.chunks.len(), 1);
+        assert_eq!(analysis.chunks[0].anomaly, Anomaly::Unregistered);
+        assert!((analysis.chunks[0].entropy - 8.0).abs() < 1e-9);
+        assert_eq!(analysis.verdict(), Verdict::Likely);
+        assert_eq!(analysis.entropies.len(), png.chunks().len());
+
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::eXIf, (0..=255).collect()));
+        let analysis = png.analyze().unwrap();
+        assert_eq!(analysis.chunks[0].anomaly, Anomaly::HighEntropy);
+        assert_eq!(analysis.verdict(), Verdict::Possible);
+
+        let mut bytes = PngBuilder::new(4, 4).build().unwrap().as_bytes();
+        let offset = bytes.len();
+        assert_eq!(analyze_file(&bytes).unwrap().trailing, None);
+        bytes.extend_from_slice(&[0xa5; 100]);
+        let analysis = analyze_file(&bytes).unwrap();
+        let trailing = analysis.trailing.unwrap();
+        assert_eq!((trailing.offset, trailing.length), (offset, 100));
+        assert_eq!(trailing.archive, None);
+        assert_eq!(analysis.verdict(), Verdict::Likely);
+
+        assert_eq!(entropy(b""), 0.0);
+        assert_eq!(entropy(b"abab"), 1.0);
+        assert!((expected_entropy(64) - 5.77).abs() < 0.01);
+        assert!((expected_entropy(4096) - expected_entropy(4097)).abs() < 0.001);
+
+        //A short encrypted chunk is far from 8 bits per byte, yet as random as it gets
+        let short: Vec<u8> = (0..80u32)
+            .map(|index| (index.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        assert!(entropy(&short) < 7.0);
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::from_str("ruSt").unwrap(), short));
+        let analysis = png.analyze().unwrap();
+        assert_eq!(analysis.chunks[0].score(), 0.9);
+        assert!((upper_gamma(1.0, 2.0) - (-2.0f64).exp()).abs() < 1e-9);
+    }
+}
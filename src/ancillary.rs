@@ -0,0 +1,811 @@
+/*!
+ * The remaining ancillary chunks of the spec and its registered extensions: bKGD,
+ * sBIT, hIST, sPLT, eXIf, oFFs, pCAL, sCAL, sTER, cICP, mDCV and cLLI.
+ * bKGD and sBIT depend on the colour type, they are parsed against the header like tRNS.
+ * oFFs, sCAL and sTER, which scanners and scientific tools write, can also be written.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::Error;
+use std::fmt;
+use std::str::FromStr;
+
+//Content of the bKGD chunk, the colour to show the image against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Grayscale(u16),
+    Rgb(u16, u16, u16),
+    PaletteIndex(u8),
+}
+
+impl Background {
+    pub fn parse(chunk: &Chunk, header: &Ihdr) -> Result<Background, Error> {
+        let data = expect_type(chunk, &ChunkType::bKGD)?;
+        let expected = match header.color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb | ColorType::Rgba => 6,
+            ColorType::Indexed => 1,
+        };
+        expect_length(chunk, expected)?;
+        Ok(match header.color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => Self::Grayscale(read_u16(data, 0)),
+            ColorType::Rgb | ColorType::Rgba => {
+                Self::Rgb(read_u16(data, 0), read_u16(data, 2), read_u16(data, 4))
+            }
+            ColorType::Indexed => Self::PaletteIndex(data[0]),
+        })
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Grayscale(gray) => write!(f, "grey {}", gray),
+            Self::Rgb(red, green, blue) => write!(f, "rgb {},{},{}", red, green, blue),
+            Self::PaletteIndex(index) => write!(f, "palette entry {}", index),
+        }
+    }
+}
+
+//Content of the sBIT chunk, the significant bits of every channel of the original image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignificantBits(pub Vec<u8>);
+
+impl SignificantBits {
+    pub fn parse(chunk: &Chunk, header: &Ihdr) -> Result<SignificantBits, Error> {
+        let data = expect_type(chunk, &ChunkType::sBIT)?;
+        //Palette entries are 3 channels of 8 bits whatever the bit depth
+        let (channels, depth) = match header.color_type {
+            ColorType::Indexed => (3, 8),
+            color_type => (color_type.channels() as usize, header.bit_depth),
+        };
+        expect_length(chunk, channels)?;
+        if let Some(&bits) = data.iter().find(|&&bits| bits == 0 || bits > depth) {
+            return Err(Box::new(AncillaryError::InvalidValue(
+                "sBIT significant bits",
+                bits as u32,
+            )));
+        }
+        Ok(SignificantBits(data.to_vec()))
+    }
+}
+
+//Content of the hIST chunk, how often every palette entry is used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram(pub Vec<u16>);
+
+impl TryFrom<&Chunk> for Histogram {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::hIST)?;
+        if data.len() % 2 != 0 || data.len() > 512 {
+            return Err(invalid_length(chunk));
+        }
+        Ok(Histogram(
+            (0..data.len() / 2)
+                .map(|index| read_u16(data, index * 2))
+                .collect(),
+        ))
+    }
+}
+
+//Content of the sPLT chunk, a palette suggested for displays with few colours
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestedPalette {
+    pub name: String,
+    //8 or 16
+    pub sample_depth: u8,
+    //Red, green, blue and alpha, then the frequency of each entry
+    pub entries: Vec<([u16; 4], u16)>,
+}
+
+impl TryFrom<&Chunk> for SuggestedPalette {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::sPLT)?;
+        let (name, rest) = split_null(data).ok_or_else(|| invalid_length(chunk))?;
+        let (&sample_depth, rest) = rest.split_first().ok_or_else(|| invalid_length(chunk))?;
+        let entry_length = match sample_depth {
+            8 => 6,
+            16 => 10,
+            _ => {
+                return Err(Box::new(AncillaryError::InvalidValue(
+                    "sPLT sample depth",
+                    sample_depth as u32,
+                )))
+            }
+        };
+        if rest.len() % entry_length != 0 {
+            return Err(invalid_length(chunk));
+        }
+        let entries = rest
+            .chunks(entry_length)
+            .map(|entry| {
+                let sample = |index: usize| match sample_depth {
+                    8 => entry[index] as u16,
+                    _ => read_u16(entry, index * 2),
+                };
+                (
+                    [sample(0), sample(1), sample(2), sample(3)],
+                    read_u16(entry, entry_length - 2),
+                )
+            })
+            .collect();
+        Ok(SuggestedPalette {
+            name: latin1(name),
+            sample_depth,
+            entries,
+        })
+    }
+}
+
+//Content of the eXIf chunk, an Exif profile in TIFF layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exif {
+    pub big_endian: bool,
+    pub length: usize,
+}
+
+impl TryFrom<&Chunk> for Exif {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::eXIf)?;
+        let big_endian = match data.get(..4) {
+            Some(b"MM\0*") => true,
+            Some(b"II*\0") => false,
+            _ => return Err(Box::new(AncillaryError::InvalidExif)),
+        };
+        Ok(Exif {
+            big_endian,
+            length: data.len(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetUnit {
+    Pixel,
+    Micrometer,
+}
+
+impl FromStr for OffsetUnit {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "px" | "pixels" => Ok(Self::Pixel),
+            "um" | "micrometers" => Ok(Self::Micrometer),
+            _ => Err(Box::new(AncillaryError::UnknownValue(
+                "oFFs unit",
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for OffsetUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pixel => write!(f, "pixels"),
+            Self::Micrometer => write!(f, "micrometers"),
+        }
+    }
+}
+
+//Content of the oFFs chunk, the position of the image on a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageOffset {
+    pub x: i32,
+    pub y: i32,
+    pub unit: OffsetUnit,
+}
+
+impl TryFrom<&Chunk> for ImageOffset {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::oFFs)?;
+        expect_length(chunk, 9)?;
+        let unit = match data[8] {
+            0 => OffsetUnit::Pixel,
+            1 => OffsetUnit::Micrometer,
+            unit => {
+                return Err(Box::new(AncillaryError::InvalidValue(
+                    "oFFs unit",
+                    unit as u32,
+                )))
+            }
+        };
+        Ok(ImageOffset {
+            x: read_u32(data, 0) as i32,
+            y: read_u32(data, 4) as i32,
+            unit,
+        })
+    }
+}
+
+impl ImageOffset {
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.x.to_be_bytes().to_vec();
+        data.extend_from_slice(&self.y.to_be_bytes());
+        data.push(self.unit as u8);
+        Chunk::new(ChunkType::oFFs, data)
+    }
+}
+
+//x,y followed by an optional unit, px by default or um
+impl FromStr for ImageOffset {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Box::new(AncillaryError::InvalidOffset(s.to_string())) as Error;
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (x, y, unit) = match parts[..] {
+            [x, y] => (x, y, OffsetUnit::Pixel),
+            [x, y, unit] => (x, y, OffsetUnit::from_str(unit)?),
+            _ => return Err(invalid()),
+        };
+        Ok(ImageOffset {
+            x: x.parse().map_err(|_| invalid())?,
+            y: y.parse().map_err(|_| invalid())?,
+            unit,
+        })
+    }
+}
+
+impl fmt::Display for ImageOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{} {}", self.x, self.y, self.unit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Equation {
+    Linear,
+    BaseEExponential,
+    ArbitraryBaseExponential,
+    Hyperbolic,
+}
+
+impl fmt::Display for Equation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Linear => "linear",
+            Self::BaseEExponential => "base-e exponential",
+            Self::ArbitraryBaseExponential => "arbitrary-base exponential",
+            Self::Hyperbolic => "hyperbolic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//Content of the pCAL chunk, how sample values map to a physical quantity
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub name: String,
+    pub original_zero: i32,
+    pub original_max: i32,
+    pub equation: Equation,
+    pub unit: String,
+    //Kept as written, floating point numbers in ASCII
+    pub parameters: Vec<String>,
+}
+
+impl TryFrom<&Chunk> for Calibration {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::pCAL)?;
+        let (name, rest) = split_null(data).ok_or_else(|| invalid_length(chunk))?;
+        if rest.len() < 10 {
+            return Err(invalid_length(chunk));
+        }
+        let (equation, count) = match rest[8] {
+            0 => (Equation::Linear, 2),
+            1 => (Equation::BaseEExponential, 3),
+            2 => (Equation::ArbitraryBaseExponential, 4),
+            3 => (Equation::Hyperbolic, 4),
+            equation => {
+                return Err(Box::new(AncillaryError::InvalidValue(
+                    "pCAL equation type",
+                    equation as u32,
+                )))
+            }
+        };
+        if rest[9] != count {
+            return Err(Box::new(AncillaryError::InvalidValue(
+                "pCAL parameter count",
+                rest[9] as u32,
+            )));
+        }
+        let (unit, parameters) = split_null(&rest[10..]).ok_or_else(|| invalid_length(chunk))?;
+        let parameters: Vec<String> = parameters.split(|&byte| byte == 0).map(latin1).collect();
+        if parameters.len() != count as usize {
+            return Err(invalid_length(chunk));
+        }
+        Ok(Calibration {
+            name: latin1(name),
+            original_zero: read_u32(rest, 0) as i32,
+            original_max: read_u32(rest, 4) as i32,
+            equation,
+            unit: latin1(unit),
+            parameters,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleUnit {
+    Meter = 1,
+    Radian = 2,
+}
+
+impl FromStr for ScaleUnit {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "m" | "meters" => Ok(Self::Meter),
+            "rad" | "radians" => Ok(Self::Radian),
+            _ => Err(Box::new(AncillaryError::UnknownValue(
+                "sCAL unit",
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ScaleUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Meter => write!(f, "meters"),
+            Self::Radian => write!(f, "radians"),
+        }
+    }
+}
+
+//Content of the sCAL chunk, the physical size of a pixel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalScale {
+    pub width: f64,
+    pub height: f64,
+    pub unit: ScaleUnit,
+}
+
+impl TryFrom<&Chunk> for PhysicalScale {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::sCAL)?;
+        let (&unit, rest) = data.split_first().ok_or_else(|| invalid_length(chunk))?;
+        let unit = match unit {
+            1 => ScaleUnit::Meter,
+            2 => ScaleUnit::Radian,
+            unit => {
+                return Err(Box::new(AncillaryError::InvalidValue(
+                    "sCAL unit",
+                    unit as u32,
+                )))
+            }
+        };
+        let (width, height) = split_null(rest).ok_or_else(|| invalid_length(chunk))?;
+        let number = |bytes: &[u8]| {
+            std::str::from_utf8(bytes)
+                .ok()
+                .and_then(|text| text.parse::<f64>().ok())
+                .filter(|value| *value > 0.0)
+                .ok_or_else(|| Box::new(AncillaryError::InvalidScale(latin1(bytes))) as Error)
+        };
+        Ok(PhysicalScale {
+            width: number(width)?,
+            height: number(height)?,
+            unit,
+        })
+    }
+}
+
+impl PhysicalScale {
+    //Both sizes must be positive and finite
+    pub fn new(width: f64, height: f64, unit: ScaleUnit) -> Result<PhysicalScale, Error> {
+        for size in [width, height] {
+            if !(size.is_finite() && size > 0.0) {
+                return Err(Box::new(AncillaryError::InvalidScale(size.to_string())));
+            }
+        }
+        Ok(PhysicalScale {
+            width,
+            height,
+            unit,
+        })
+    }
+
+    //The sizes are written in ASCII, the shortest form that reads back the same
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = vec![self.unit as u8];
+        data.extend_from_slice(self.width.to_string().as_bytes());
+        data.push(0);
+        data.extend_from_slice(self.height.to_string().as_bytes());
+        Chunk::new(ChunkType::sCAL, data)
+    }
+}
+
+//width,height of a pixel followed by an optional unit, m by default or rad
+impl FromStr for PhysicalScale {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Box::new(AncillaryError::InvalidScale(s.to_string())) as Error;
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (width, height, unit) = match parts[..] {
+            [width, height] => (width, height, ScaleUnit::Meter),
+            [width, height, unit] => (width, height, ScaleUnit::from_str(unit)?),
+            _ => return Err(invalid()),
+        };
+        PhysicalScale::new(
+            width.parse().map_err(|_| invalid())?,
+            height.parse().map_err(|_| invalid())?,
+            unit,
+        )
+    }
+}
+
+impl fmt::Display for PhysicalScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} {}", self.width, self.height, self.unit)
+    }
+}
+
+//Content of the sTER chunk, the image is a side by side stereo pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    CrossFuse,
+    DivergingFuse,
+}
+
+impl StereoMode {
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::sTER, vec![*self as u8])
+    }
+}
+
+impl FromStr for StereoMode {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cross-fuse" => Ok(Self::CrossFuse),
+            "diverging-fuse" => Ok(Self::DivergingFuse),
+            _ => Err(Box::new(AncillaryError::UnknownValue(
+                "sTER mode",
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for StereoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CrossFuse => write!(f, "cross-fuse"),
+            Self::DivergingFuse => write!(f, "diverging-fuse"),
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for StereoMode {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::sTER)?;
+        expect_length(chunk, 1)?;
+        match data[0] {
+            0 => Ok(Self::CrossFuse),
+            1 => Ok(Self::DivergingFuse),
+            mode => Err(Box::new(AncillaryError::InvalidValue(
+                "sTER mode",
+                mode as u32,
+            ))),
+        }
+    }
+}
+
+//The placement, pixel size and stereo layout of a PNG, from its oFFs, sCAL and sTER chunks
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Extensions {
+    pub offset: Option<ImageOffset>,
+    pub scale: Option<PhysicalScale>,
+    pub stereo: Option<StereoMode>,
+}
+
+impl Extensions {
+    //Chunks for the values that are set, in the order oFFs, sCAL, sTER
+    pub fn to_chunks(&self) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        chunks.extend(self.offset.map(|offset| offset.to_chunk()));
+        chunks.extend(self.scale.map(|scale| scale.to_chunk()));
+        chunks.extend(self.stereo.map(|stereo| stereo.to_chunk()));
+        chunks
+    }
+
+    //Takes in the value of an oFFs, sCAL or sTER chunk, other chunks are left alone
+    pub fn read(&mut self, chunk: &Chunk) -> Result<(), Error> {
+        let chunk_type = chunk.chunk_type();
+        if *chunk_type == ChunkType::oFFs {
+            self.offset = Some(ImageOffset::try_from(chunk)?);
+        } else if *chunk_type == ChunkType::sCAL {
+            self.scale = Some(PhysicalScale::try_from(chunk)?);
+        } else if *chunk_type == ChunkType::sTER {
+            self.stereo = Some(StereoMode::try_from(chunk)?);
+        }
+        Ok(())
+    }
+}
+
+//Content of the cICP chunk, the colour space as code points of ITU-T H.273
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodingParameters {
+    pub primaries: u8,
+    pub transfer: u8,
+    //Always 0, RGB, in a PNG
+    pub matrix: u8,
+    pub full_range: bool,
+}
+
+impl CodingParameters {
+    //Common names of the primaries, None for the less usual ones
+    pub fn primaries_name(&self) -> Option<&'static str> {
+        match self.primaries {
+            1 => Some("BT.709"),
+            9 => Some("BT.2020"),
+            11 => Some("DCI-P3"),
+            12 => Some("Display P3"),
+            _ => None,
+        }
+    }
+
+    pub fn transfer_name(&self) -> Option<&'static str> {
+        match self.transfer {
+            1 | 6 | 14 | 15 => Some("BT.709"),
+            8 => Some("linear"),
+            13 => Some("sRGB"),
+            16 => Some("PQ"),
+            18 => Some("HLG"),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for CodingParameters {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::cICP)?;
+        expect_length(chunk, 4)?;
+        if data[2] != 0 {
+            return Err(Box::new(AncillaryError::InvalidValue(
+                "cICP matrix coefficients",
+                data[2] as u32,
+            )));
+        }
+        let full_range = match data[3] {
+            0 => false,
+            1 => true,
+            flag => {
+                return Err(Box::new(AncillaryError::InvalidValue(
+                    "cICP full range flag",
+                    flag as u32,
+                )))
+            }
+        };
+        Ok(CodingParameters {
+            primaries: data[0],
+            transfer: data[1],
+            matrix: data[2],
+            full_range,
+        })
+    }
+}
+
+//Content of the mDCV chunk, the display the image was mastered on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MasteringDisplay {
+    //x and y of red, green and blue in units of 0.00002
+    pub primaries: [(u16, u16); 3],
+    pub white: (u16, u16),
+    //In units of 0.0001 cd/m²
+    pub max_luminance: u32,
+    pub min_luminance: u32,
+}
+
+impl TryFrom<&Chunk> for MasteringDisplay {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::mDCV)?;
+        expect_length(chunk, 24)?;
+        let point = |index: usize| (read_u16(data, index * 4), read_u16(data, index * 4 + 2));
+        Ok(MasteringDisplay {
+            primaries: [point(0), point(1), point(2)],
+            white: point(3),
+            max_luminance: read_u32(data, 16),
+            min_luminance: read_u32(data, 20),
+        })
+    }
+}
+
+//Content of the cLLI chunk, the light levels of the content in units of 0.0001 cd/m²
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    pub max_content: u32,
+    pub max_frame_average: u32,
+}
+
+impl TryFrom<&Chunk> for ContentLightLevel {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_type(chunk, &ChunkType::cLLI)?;
+        expect_length(chunk, 8)?;
+        Ok(ContentLightLevel {
+            max_content: read_u32(data, 0),
+            max_frame_average: read_u32(data, 4),
+        })
+    }
+}
+
+fn expect_type<'a>(chunk: &'a Chunk, chunk_type: &ChunkType) -> Result<&'a [u8], Error> {
+    if chunk.chunk_type() != chunk_type {
+        return Err(Box::new(AncillaryError::WrongChunk(
+            chunk.chunk_type().to_string(),
+        )));
+    }
+    Ok(chunk.data())
+}
+
+fn expect_length(chunk: &Chunk, length: usize) -> Result<(), Error> {
+    if chunk.data().len() != length {
+        return Err(invalid_length(chunk));
+    }
+    Ok(())
+}
+
+fn invalid_length(chunk: &Chunk) -> Error {
+    Box::new(AncillaryError::InvalidLength(
+        chunk.chunk_type().to_string(),
+        chunk.data().len(),
+    ))
+}
+
+//The bytes before the first null and those after it
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let index = data.iter().position(|&byte| byte == 0)?;
+    Some((&data[..index], &data[index + 1..]))
+}
+
+//Latin-1 code points match the first 256 of Unicode
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+#[derive(Debug)]
+pub enum AncillaryError {
+    WrongChunk(String),
+    InvalidLength(String, usize),
+    InvalidValue(&'static str, u32),
+    InvalidExif,
+    InvalidScale(String),
+    InvalidOffset(String),
+    UnknownValue(&'static str, String),
+}
+
+impl std::error::Error for AncillaryError {}
+
+impl fmt::Display for AncillaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongChunk(chunk_type) => write!(f, "unexpected chunk: {}", chunk_type),
+            Self::InvalidLength(chunk_type, length) => {
+                write!(f, "invalid {} length: {}", chunk_type, length)
+            }
+            Self::InvalidValue(field, value) => write!(f, "invalid {}: {}", field, value),
+            Self::InvalidExif => write!(f, "eXIf doesn't start with a TIFF header"),
+            Self::InvalidScale(scale) => write!(f, "invalid sCAL size: {:?}", scale),
+            Self::InvalidOffset(offset) => {
+                write!(f, "invalid offset {:?}, expected x,y[,px|um]", offset)
+            }
+            Self::UnknownValue(field, value) => write!(f, "unknown {}: {}", field, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_header_dependent_chunks() {
+        let rgba = Ihdr::new(1, 1, 8, ColorType::Rgba);
+        let background = Chunk::new(ChunkType::bKGD, vec![0, 255, 0, 128, 0, 0]);
+        assert_eq!(
+            Background::parse(&background, &rgba).unwrap(),
+            Background::Rgb(255, 128, 0)
+        );
+        let indexed = Ihdr::new(1, 1, 2, ColorType::Indexed);
+        assert!(Background::parse(&background, &indexed).is_err());
+        let bits = Chunk::new(ChunkType::sBIT, vec![5, 6, 5]);
+        assert_eq!(
+            SignificantBits::parse(&bits, &indexed).unwrap().0,
+            [5, 6, 5]
+        );
+        assert!(SignificantBits::parse(&bits, &rgba).is_err());
+        let too_deep = Chunk::new(ChunkType::sBIT, vec![9, 8, 8, 8]);
+        assert!(SignificantBits::parse(&too_deep, &rgba).is_err());
+    }
+
+    #[test]
+    pub fn test_ancillary_chunks() {
+        let histogram = Chunk::new(ChunkType::hIST, vec![0, 1, 1, 0]);
+        assert_eq!(Histogram::try_from(&histogram).unwrap().0, [1, 256]);
+
+        let mut data = b"web\0\x08".to_vec();
+        data.extend_from_slice(&[255, 0, 0, 255, 0, 10]);
+        let palette = SuggestedPalette::try_from(&Chunk::new(ChunkType::sPLT, data)).unwrap();
+        assert_eq!(palette.name, "web");
+        assert_eq!(palette.entries, [([255, 0, 0, 255], 10)]);
+
+        let offset = Chunk::new(ChunkType::oFFs, vec![255, 255, 255, 254, 0, 0, 0, 3, 1]);
+        let offset = ImageOffset::try_from(&offset).unwrap();
+        assert_eq!(
+            (offset.x, offset.y, offset.unit),
+            (-2, 3, OffsetUnit::Micrometer)
+        );
+
+        let scale = Chunk::new(ChunkType::sCAL, b"\x011.5e-4\x000.0002".to_vec());
+        let scale = PhysicalScale::try_from(&scale).unwrap();
+        assert_eq!((scale.width, scale.height), (0.00015, 0.0002));
+
+        let mut data = b"temp\0".to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 255, 0, 2]);
+        data.extend_from_slice(b"K\x000\x001.5");
+        let calibration = Calibration::try_from(&Chunk::new(ChunkType::pCAL, data)).unwrap();
+        assert_eq!(calibration.unit, "K");
+        assert_eq!(calibration.parameters, ["0", "1.5"]);
+
+        let cicp = Chunk::new(ChunkType::cICP, vec![9, 16, 0, 1]);
+        let cicp = CodingParameters::try_from(&cicp).unwrap();
+        assert_eq!(cicp.transfer_name(), Some("PQ"));
+        assert!(
+            CodingParameters::try_from(&Chunk::new(ChunkType::cICP, vec![9, 16, 1, 1])).is_err()
+        );
+
+        let exif = Chunk::new(ChunkType::eXIf, b"II*\0\x08\0\0\0".to_vec());
+        assert!(!Exif::try_from(&exif).unwrap().big_endian);
+        assert!(Exif::try_from(&Chunk::new(ChunkType::eXIf, b"Exif".to_vec())).is_err());
+        let stereo = Chunk::new(ChunkType::sTER, vec![2]);
+        assert!(StereoMode::try_from(&stereo).is_err());
+    }
+
+    #[test]
+    pub fn test_extensions() {
+        let extensions = Extensions {
+            offset: Some(ImageOffset::from_str("-2,3,um").unwrap()),
+            scale: Some(PhysicalScale::from_str("1.5e-4,0.0002").unwrap()),
+            stereo: Some(StereoMode::from_str("diverging-fuse").unwrap()),
+        };
+        let chunks = extensions.to_chunks();
+        assert_eq!(chunks[0].data(), [255, 255, 255, 254, 0, 0, 0, 3, 1]);
+        assert_eq!(chunks[1].data(), b"\x010.00015\x000.0002");
+        assert_eq!(chunks[2].data(), [1]);
+        let mut read = Extensions::default();
+        for chunk in &chunks {
+            read.read(chunk).unwrap();
+        }
+        assert_eq!(read, extensions);
+        assert_eq!(extensions.offset.unwrap().to_string(), "-2,3 micrometers");
+        assert!(ImageOffset::from_str("1,2,cm").is_err());
+        assert!(PhysicalScale::from_str("0,1").is_err());
+        assert!(PhysicalScale::from_str("1,inf").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::fragment;
+use crate::png::Png;
+use crate::Error;
+use std::fmt;
+
+//Content of the acTL chunk, marks the file as an animated PNG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    //0 loops forever
+    pub num_plays: u32,
+}
+
+impl TryFrom<&Chunk> for AnimationControl {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, &ChunkType::acTL, AnimationControl::LENGTH)?;
+        Ok(AnimationControl {
+            num_frames: read_u32(data, 0),
+            num_plays: read_u32(data, 4),
+        })
+    }
+}
+
+impl AnimationControl {
+    pub const LENGTH: usize = 8;
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = [self.num_frames, self.num_plays]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        Chunk::new(ChunkType::acTL, data)
+    }
+}
+
+//How the frame area is treated before rendering the next frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    None,
+    Background,
+    Previous,
+}
+
+//How the frame is drawn over the output buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    Source,
+    Over,
+}
+
+//Content of an fcTL chunk, describes the frame whose data follows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl TryFrom<&Chunk> for FrameControl {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, &ChunkType::fcTL, FrameControl::LENGTH)?;
+        let dispose_op = match data[24] {
+            0 => DisposeOp::None,
+            1 => DisposeOp::Background,
+            2 => DisposeOp::Previous,
+            op => return Err(Box::new(ApngError::InvalidDisposeOp(op))),
+        };
+        let blend_op = match data[25] {
+            0 => BlendOp::Source,
+            1 => BlendOp::Over,
+            op => return Err(Box::new(ApngError::InvalidBlendOp(op))),
+        };
+        Ok(FrameControl {
+            sequence_number: read_u32(data, 0),
+            width: read_u32(data, 4),
+            height: read_u32(data, 8),
+            x_offset: read_u32(data, 12),
+            y_offset: read_u32(data, 16),
+            delay_num: u16::from_be_bytes([data[20], data[21]]),
+            delay_den: u16::from_be_bytes([data[22], data[23]]),
+            dispose_op,
+            blend_op,
+        })
+    }
+}
+
+impl FrameControl {
+    pub const LENGTH: usize = 26;
+
+    //Frame duration in seconds, a zero denominator means 1/100 s
+    pub fn delay(&self) -> f64 {
+        let den = if self.delay_den == 0 {
+            100
+        } else {
+            self.delay_den
+        };
+        self.delay_num as f64 / den as f64
+    }
+}
+
+/**
+ * A frame of an animated PNG: its fcTL and its compressed pixel data.
+ * The data comes from IDAT when the default image is the first frame, otherwise
+ * from the fdAT chunks following the fcTL, without their sequence numbers.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub control: FrameControl,
+    pub is_default_image: bool,
+    pub data: Vec<u8>,
+}
+
+impl Png {
+    //Whether the file has an acTL chunk, which makes it an APNG
+    pub fn is_animated(&self) -> bool {
+        self.chunk_by_type("acTL").is_some()
+    }
+
+    //Parses the acTL chunk, if any
+    pub fn animation_control(&self) -> Result<Option<AnimationControl>, Error> {
+        self.chunk_by_type("acTL")
+            .map(AnimationControl::try_from)
+            .transpose()
+    }
+
+    /**
+     * Lists the frames of an animated PNG in file order, empty for a static image.
+     * Sequence numbers of fcTL and fdAT chunks must be consecutive from 0.
+     */
+    pub fn frames(&self) -> Result<Vec<Frame>, Error> {
+        if !self.is_animated() {
+            return Ok(Vec::new());
+        }
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut sequence_number = 0;
+        for chunk in self.chunks() {
+            let chunk_type = chunk.chunk_type();
+            if *chunk_type == ChunkType::fcTL {
+                let control = FrameControl::try_from(chunk)?;
+                check_sequence(&mut sequence_number, control.sequence_number)?;
+                frames.push(Frame {
+                    control,
+                    is_default_image: false,
+                    data: Vec::new(),
+                });
+            } else if *chunk_type == ChunkType::IDAT {
+                //The default image is only the first frame if its fcTL comes first
+                if let [frame] = frames.as_mut_slice() {
+                    frame.is_default_image = true;
+                    frame.data.extend_from_slice(chunk.data());
+                }
+            } else if *chunk_type == ChunkType::fdAT {
+                let data = chunk.data();
+                if data.len() < 4 {
+                    return Err(Box::new(ApngError::InvalidLength(
+                        chunk_type.to_string(),
+                        data.len(),
+                    )));
+                }
+                check_sequence(&mut sequence_number, read_u32(data, 0))?;
+                match frames.last_mut() {
+                    Some(frame) if !frame.is_default_image => {
+                        frame.data.extend_from_slice(&data[4..])
+                    }
+                    _ => return Err(Box::new(ApngError::OrphanFrameData)),
+                }
+            }
+        }
+        Ok(frames)
+    }
+}
+
+impl Png {
+    /**
+     * Spreads `payload` over the frames of an animated PNG, a chunk of the given type
+     * placed right after the data of each frame. The chunks are parts as
+     * `fragment::split` makes them, so `fragment::reassemble` puts the payload back
+     * together in whatever order the frames end up. Short payloads leave the last
+     * frames out. Returns the number of chunks added.
+     */
+    pub fn embed_in_frames(
+        &mut self,
+        chunk_type: &ChunkType,
+        payload: &[u8],
+    ) -> Result<usize, Error> {
+        if chunk_type.is_critical() {
+            return Err(Box::new(ApngError::CriticalChunk(chunk_type.to_string())));
+        }
+        if !self.is_animated() {
+            return Err(Box::new(ApngError::NotAnimated));
+        }
+        //Validates the sequence first
+        let count = self.frames()?.len();
+        //Where the data of every frame ends, the next chunk after it
+        let mut ends = Vec::with_capacity(count);
+        let mut in_frame = false;
+        for (index, chunk) in self.chunks().iter().enumerate() {
+            let chunk_type = chunk.chunk_type();
+            if *chunk_type == ChunkType::fcTL {
+                in_frame = true;
+                ends.push(index + 1);
+            } else if in_frame && (*chunk_type == ChunkType::IDAT || *chunk_type == ChunkType::fdAT)
+            {
+                *ends.last_mut().expect("in a frame") = index + 1;
+            }
+        }
+        let part_length = payload.len().div_ceil(count.max(1)).max(1);
+        let parts = fragment::split(chunk_type, payload, part_length)?;
+        let added = parts.len();
+        //From the last so the earlier positions hold
+        for (end, part) in ends.into_iter().zip(parts).rev() {
+            self.chunks_mut().insert(end, part);
+        }
+        Ok(added)
+    }
+}
+
+fn check_sequence(expected: &mut u32, actual: u32) -> Result<(), Error> {
+    if actual != *expected {
+        return Err(Box::new(ApngError::InvalidSequenceNumber(
+            *expected, actual,
+        )));
+    }
+    *expected += 1;
+    Ok(())
+}
+
+fn expect_chunk<'a>(
+    chunk: &'a Chunk,
+    chunk_type: &ChunkType,
+    length: usize,
+) -> Result<&'a [u8], Error> {
+    if chunk.chunk_type() != chunk_type {
+        return Err(Box::new(ApngError::WrongChunk(
+            chunk.chunk_type().to_string(),
+        )));
+    }
+    let data = chunk.data();
+    if data.len() != length {
+        return Err(Box::new(ApngError::InvalidLength(
+            chunk_type.to_string(),
+            data.len(),
+        )));
+    }
+    Ok(data)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+#[derive(Debug)]
+pub enum ApngError {
+    WrongChunk(String),
+    InvalidLength(String, usize),
+    InvalidDisposeOp(u8),
+    InvalidBlendOp(u8),
+    InvalidSequenceNumber(u32, u32),
+    OrphanFrameData,
+    NotAnimated,
+    CriticalChunk(String),
+}
+
+impl std::error::Error for ApngError {}
+
+impl fmt::Display for ApngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongChunk(chunk_type) => write!(f, "unexpected chunk: {}", chunk_type),
+            Self::InvalidLength(chunk_type, length) => {
+                write!(f, "invalid {} length: {}", chunk_type, length)
+            }
+            Self::InvalidDisposeOp(op) => write!(f, "invalid fcTL dispose op: {}", op),
+            Self::InvalidBlendOp(op) => write!(f, "invalid fcTL blend op: {}", op),
+            Self::InvalidSequenceNumber(expected, actual) => write!(
+                f,
+                "APNG sequence number should be {}, current: {}",
+                expected, actual
+            ),
+            Self::OrphanFrameData => write!(f, "fdAT chunk without a preceding fcTL"),
+            Self::NotAnimated => write!(f, "not an animated PNG, there are no frames"),
+            Self::CriticalChunk(chunk_type) => {
+                write!(f, "{} is critical, decoders would fail on it", chunk_type)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+    use std::str::FromStr;
+
+    fn frame_control(sequence_number: u32) -> Chunk {
+        let mut data = vec![0; FrameControl::LENGTH];
+        data[..4].copy_from_slice(&sequence_number.to_be_bytes());
+        data[4..8].copy_from_slice(&2u32.to_be_bytes());
+        data[8..12].copy_from_slice(&2u32.to_be_bytes());
+        data[20..22].copy_from_slice(&1u16.to_be_bytes());
+        data[25] = 1;
+        Chunk::new(ChunkType::fcTL, data)
+    }
+
+    fn frame_data(sequence_number: u32, data: &[u8]) -> Chunk {
+        Chunk::new(
+            ChunkType::fdAT,
+            [&sequence_number.to_be_bytes(), data].concat(),
+        )
+    }
+
+    fn animated_png() -> Png {
+        let mut png = PngBuilder::new(2, 2).build().unwrap();
+        let control = AnimationControl {
+            num_frames: 2,
+            num_plays: 0,
+        };
+        png.insert_chunk_ordered(control.to_chunk());
+        let mut chunks = png.chunks().to_vec();
+        let idat = chunks.len() - 2;
+        chunks.insert(idat, frame_control(0));
+        chunks.insert(chunks.len() - 1, frame_control(1));
+        chunks.insert(chunks.len() - 1, frame_data(2, &[1, 2, 3]));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    pub fn test_static_png() {
+        let png = PngBuilder::new(2, 2).build().unwrap();
+        assert!(!png.is_animated());
+        assert!(png.animation_control().unwrap().is_none());
+        assert!(png.frames().unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_frames() {
+        let png = animated_png();
+        assert!(png.is_animated());
+        assert_eq!(png.animation_control().unwrap().unwrap().num_frames, 2);
+        let frames = png.frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].is_default_image);
+        assert_eq!(frames[0].data, png.idat_data());
+        assert!(!frames[1].is_default_image);
+        assert_eq!(frames[1].data, vec![1, 2, 3]);
+        assert_eq!(frames[1].control.blend_op, BlendOp::Over);
+        assert_eq!(frames[1].control.delay(), 0.01);
+    }
+
+    #[test]
+    pub fn test_invalid_sequence() {
+        let mut png = animated_png();
+        png.append_chunk(frame_data(5, &[]));
+        assert!(png.frames().is_err());
+    }
+
+    #[test]
+    pub fn test_embed_in_frames() {
+        let mut png = animated_png();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(
+            png.embed_in_frames(&chunk_type, b"hello, frames").unwrap(),
+            2
+        );
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "acTL", "fcTL", "IDAT", "ruSt", "fcTL", "fdAT", "ruSt", "IEND"]
+        );
+        assert_eq!(png.frames().unwrap().len(), 2);
+        assert!(png.validate().is_empty());
+        //As if the frames had been swapped
+        let mut parts: Vec<&Chunk> = png.chunks_by_type("ruSt").collect();
+        parts.reverse();
+        assert_eq!(fragment::reassemble(parts).unwrap(), b"hello, frames");
+
+        let mut still = PngBuilder::new(2, 2).build().unwrap();
+        assert!(still.embed_in_frames(&chunk_type, b"hello").is_err());
+        assert!(png.embed_in_frames(&ChunkType::IDAT, b"hello").is_err());
+    }
+
+    #[test]
+    pub fn test_animated_png_is_valid() {
+        assert!(animated_png().validate().is_empty());
+    }
+}
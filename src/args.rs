@@ -0,0 +1,1266 @@
+use crate::config::Config;
+#[cfg(feature = "keyring")]
+use crate::keychain::KeySource;
+use clap::builder::Resettable;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use pngme::ancillary::{ImageOffset, PhysicalScale, StereoMode};
+use pngme::capacity::EmbeddingMode;
+use pngme::chunk_type::ChunkType;
+use pngme::cover::{Size, Style};
+use pngme::crypto::KdfParams;
+use pngme::fragment::DEFAULT_PART_LENGTH;
+use pngme::metadata::{Chromaticities, Gamma, RenderingIntent, Timestamp};
+use pngme::payload::{Compression, Encoding};
+use regex::bytes::Regex;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "pngme",
+    version,
+    about = "Hide secret messages in PNG files",
+    after_help = "Any file path can be - to read from stdin or write to stdout.\n\n\
+                  Defaults for chunk_type, format, backup, schema and the [encryption] \
+                  key_file and Argon2id costs are read from ~/.config/pngme/config.toml.\n\n\
+                  Exit codes: 1 failure, 2 usage error, 3 not a PNG, 4 chunk not found, \
+                  5 invalid chunk type, 6 encryption failure, 7 I/O error"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: PngMeArgs,
+    ///Output format of list, decode, inspect, validate, diff, grep, stats, analyze and meta
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    ///Log more details to stderr: -v for timings, -vv for chunk decisions, -vvv for every chunk read
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    ///Only log errors, overrides -v
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    ///Keep a .bak copy of files modified in place
+    #[arg(long, global = true, overrides_with = "no_backup")]
+    pub backup: bool,
+    ///Don't keep a .bak copy, even if the config asks for one
+    #[arg(long, global = true, overrides_with = "backup")]
+    pub no_backup: bool,
+}
+
+impl Cli {
+    //Parses the command line, taking what it leaves out from the user's config
+    pub fn parse_with(config: &Config) -> Cli {
+        Cli::try_parse_from_with(env::args_os(), config).unwrap_or_else(|err| err.exit())
+    }
+
+    fn try_parse_from_with<I, T>(args: I, config: &Config) -> Result<Cli, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut command = Cli::command();
+        if let Some(format) = &config.format {
+            command = command.mut_arg("format", |arg| arg.default_value(format.clone()));
+        }
+        if let Some(chunk_type) = &config.chunk_type {
+            //Only where the chunk type is the last positional argument
+            for name in ["decode", "verify", "verify-payload", "remove", "extract"] {
+                command = command.mut_subcommand(name, |subcommand| {
+                    //Clears required_unless_present_any, the default always satisfies it
+                    subcommand.mut_arg("chunk_type", |arg| {
+                        arg.required(false)
+                            .required_unless_present(Resettable::Reset)
+                            .default_value(chunk_type.clone())
+                    })
+                });
+            }
+        }
+        if let Some(schema) = &config.schema {
+            for name in ["inspect", "decode"] {
+                command = command.mut_subcommand(name, |subcommand| {
+                    subcommand.mut_arg("schema", |arg| arg.default_value(schema.clone()))
+                });
+            }
+        }
+        let encryption = &config.encryption;
+        if let Some(key_file) = &encryption.key_file {
+            for name in ["encode", "decode", "verify-payload"] {
+                command = command.mut_subcommand(name, |subcommand| {
+                    subcommand.mut_arg("key_file", |arg| arg.default_value(key_file.clone()))
+                });
+            }
+        }
+        for (id, cost) in [
+            ("kdf_memory", encryption.kdf_memory),
+            ("kdf_iterations", encryption.kdf_iterations),
+            ("kdf_parallelism", encryption.kdf_parallelism),
+        ] {
+            if let Some(cost) = cost {
+                command = command.mut_subcommand("encode", |subcommand| {
+                    subcommand.mut_arg(id, |arg| arg.default_value(cost.to_string()))
+                });
+            }
+        }
+        let mut matches = command.try_get_matches_from(args)?;
+        //Gone from the matches once they are turned into the arguments
+        let default_chunk_type = matches.subcommand().is_some_and(|(_, subcommand)| {
+            subcommand
+                .try_get_raw("chunk_type")
+                .is_ok_and(|raw| raw.is_some())
+                && subcommand.value_source("chunk_type") == Some(ValueSource::DefaultValue)
+        });
+        let given: Vec<String> = match matches.subcommand() {
+            Some(("encode", encode)) => encode
+                .ids()
+                .filter(|id| encode.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+                .map(|id| id.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut cli = Cli::from_arg_matches_mut(&mut matches)?;
+        //The config default only stands in for a chunk type the command reads
+        match &mut cli.command {
+            PngMeArgs::Decode(args)
+                if default_chunk_type && (args.stealth || args.mode != EmbeddingMode::Chunk) =>
+            {
+                args.chunk_type = None;
+            }
+            PngMeArgs::Extract(args) if default_chunk_type && args.after_iend => {
+                args.chunk_type = None;
+            }
+            _ => {}
+        }
+        if let PngMeArgs::Encode(args) = &mut cli.command {
+            if let Some(profile) = args.profile {
+                profile.apply(args, |id| given.iter().any(|given| given == id));
+                //Clap only knows the profile can leave them out
+                let in_chunk = !args.stealth && args.mode == EmbeddingMode::Chunk;
+                let payload = args.message.is_some() || args.file.is_some() || args.stdin;
+                if in_chunk && (args.chunk_type.is_none() || !payload) {
+                    let message = format!(
+                        "--profile {} stores the payload in a chunk, give its type and the message",
+                        profile.to_possible_value().unwrap().get_name()
+                    );
+                    return Err(Cli::command().error(ErrorKind::MissingRequiredArgument, message));
+                }
+            }
+        }
+        Ok(cli)
+    }
+
+    pub fn backup(&self, config: &Config) -> bool {
+        !self.no_backup && (self.backup || config.backup)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    //Binary data is base64 encoded
+    Json,
+}
+
+//Consistent defaults for the flags of encode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Profile {
+    Stealth,
+    Capacity,
+    Robust,
+}
+
+impl Profile {
+    //Sets what the profile picks wherever `given` says the command line left a flag out
+    fn apply(self, args: &mut EncodeArgs, given: impl Fn(&str) -> bool) {
+        if !given("compress") {
+            args.compress = Some(Compression::Zlib);
+        }
+        //Copies in the chunks, then the pixels or after IEND. There is no error correction
+        //to pick, a copy is whole or damaged and the next one stands in for it
+        let placed = ["mode", "redundancy", "name", "append", "decoys", "spread"];
+        if self == Profile::Robust && !placed.iter().any(|id| given(id)) {
+            args.redundancy = Some(3);
+        }
+        //The timestamp gives away when, and takes room
+        if self != Profile::Robust && !given("no_timestamp") {
+            args.no_timestamp = true;
+        }
+        if self != Profile::Stealth {
+            return;
+        }
+        //Scattering the bits too in the pixel modes
+        args.encrypt = true;
+        //The rest only makes sense in chunks, of a type derived from the password unless
+        //a chunk type was given before the payload
+        let payload = given("message") || given("file") || given("stdin");
+        if args.mode != EmbeddingMode::Chunk || given("name") || (given("chunk_type") && payload) {
+            return;
+        }
+        args.stealth = true;
+        //Copies don't go with decoys or --spread
+        if given("redundancy") {
+            return;
+        }
+        if !given("decoys") && !given("deterministic") {
+            args.decoys = 3;
+        }
+        let decoy_payload = given("decoy_message") || given("decoy_file");
+        if !given("spread") && !decoy_payload {
+            args.spread = Some(4);
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PngMeArgs {
+    ///Hides a message in a new chunk of the given type
+    Encode(Box<EncodeArgs>),
+    ///Prints the message stored in the first chunk of the given type
+    Decode(DecodeArgs),
+    ///Splits a payload into shards hidden in several files, any --threshold of them
+    ///rebuilding it
+    Shard(ShardArgs),
+    ///Rebuilds a payload from the shards hidden in several files
+    Unshard(UnshardArgs),
+    ///Checks the signature of the payload stored in chunks of the given type
+    Verify(VerifyArgs),
+    ///Checks the payload against the checksum recorded when it was encoded
+    VerifyPayload(VerifyPayloadArgs),
+    ///Lists the named payloads recorded in the table of contents
+    LsPayloads(LsPayloadsArgs),
+    ///Writes the raw data of a chunk to a file
+    Extract(ExtractArgs),
+    ///Removes the first chunk of the given type
+    Remove(RemoveArgs),
+    ///Prints every chunk of the file
+    Print(PrintArgs),
+    ///Prints a table of the chunks with their length, CRC status and properties
+    List(ListArgs),
+    ///Decodes the content of known chunks and hexdumps the others
+    Inspect(InspectArgs),
+    ///Lists the chunks added, removed or modified between two files
+    Diff(DiffArgs),
+    ///Searches the ancillary chunks and text values of files for a regex
+    Grep(GrepArgs),
+    ///Summarizes chunk usage of a file, or of every file in a directory
+    Stats(StatsArgs),
+    ///Copies chunks of the given types from one file to another
+    CopyChunks(CopyChunksArgs),
+    ///Removes every ancillary chunk, hidden messages and metadata alike
+    Scrub(ScrubArgs),
+    ///Rewrites the file the way mainstream tools do: chunk order, no Software text or
+    ///tIME, pixel data deflated with the defaults of libpng
+    Normalize(NormalizeArgs),
+    ///Recomputes invalid chunk CRCs and rewrites the file
+    Repair(RepairArgs),
+    ///Rewrites payloads stored by older releases in the current format
+    Migrate(MigrateArgs),
+    ///Checks the file against the PNG spec and reports every issue found
+    Validate(ValidateArgs),
+    ///Re-compresses the pixel data to make the file smaller
+    Optimize(OptimizeArgs),
+    ///Reports how many bytes can be hidden in the file
+    Capacity(CapacityArgs),
+    ///Scores how likely the file is to hide data, in its pixels or its chunks
+    Analyze(AnalyzeArgs),
+    ///Appends a ZIP archive to an image, making a file that both PNG and ZIP readers open
+    Polyglot(PolyglotArgs),
+    ///Writes an image of every bit plane of every channel, to spot LSB embedding by eye
+    Bitplanes(BitplanesArgs),
+    ///Generates a cover image with enough grain in its pixels to hide a payload in them
+    Cover(CoverArgs),
+    ///Reads and writes the textual metadata of tEXt and zTXt chunks
+    Meta(MetaArgs),
+    ///Reads, writes and strips the XMP packet of iTXt chunks
+    Xmp(XmpArgs),
+    ///Reads and writes the last modification time of the tIME chunk
+    Time(TimeArgs),
+    ///Extracts and embeds the ICC color profile of the iCCP chunk
+    Icc(IccArgs),
+    ///Reads and corrects the gamma and colour space hints of gAMA, cHRM and sRGB
+    Color(ColorArgs),
+    ///Reads and writes the print resolution of the pHYs chunk
+    Dpi(DpiArgs),
+    ///Reads and writes the offset, pixel size and stereo layout of oFFs, sCAL and sTER
+    Ext(ExtArgs),
+    ///Browses the chunks interactively, deleting some of them before saving
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+    ///Runs a command on every PNG created or changed in a directory
+    #[cfg(feature = "watch")]
+    Watch(WatchArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BatchArgs {
+    ///Treat the path as a directory and process every file below it
+    #[arg(short, long)]
+    pub recursive: bool,
+    ///Only process files matching one of these patterns, defaults to *.png
+    #[arg(long, requires = "recursive")]
+    pub include: Vec<String>,
+    ///Skip files matching one of these patterns
+    #[arg(long, requires = "recursive")]
+    pub exclude: Vec<String>,
+}
+
+//Where a modified file goes, overwriting the input is never implicit. --dry-run writes
+//nothing, so it goes with either and stays out of their exclusive pair
+#[derive(Debug, Args)]
+#[group(skip)]
+pub struct OutputArgs {
+    ///Write the result to a new file
+    #[arg(short, long, conflicts_with = "in_place", required_unless_present_any = ["in_place", "dry_run"])]
+    pub output: Option<PathBuf>,
+    ///Overwrite the input file
+    #[arg(long, required_unless_present_any = ["output", "dry_run"])]
+    pub in_place: bool,
+    ///Report the changes and the file size difference without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl OutputArgs {
+    pub fn path<'a>(&'a self, input: &'a Path) -> &'a Path {
+        self.output.as_deref().unwrap_or(input)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct EncodeArgs {
+    ///A glob pattern such as '*.png' encodes every matching file
+    pub file_path: PathBuf,
+    ///Left out with --stealth or a pixel --mode, the message then follows the file path
+    #[arg(required_unless_present_any = ["stealth", "mode", "profile"])]
+    pub chunk_type: Option<String>,
+    ///The message, '-' reads it from stdin
+    #[arg(required_unless_present_any = ["file", "stdin", "stealth", "mode", "profile"])]
+    pub message: Option<String>,
+    ///Defaults for the other flags, which still win. stealth: --encrypt --stealth,
+    ///--spread 4 among 3 --decoys, or only --encrypt for a chunk type and message given;
+    ///capacity: the chunk type given; robust: --redundancy 3, copies in chunks and in the
+    ///pixels or after IEND, which survive tools that strip chunks or redraw the pixels.
+    ///There is no forward error correction, on purpose: a damaged copy is told by its
+    ///checksum and another one is used. All compress, stealth and capacity leave out the
+    ///timestamp
+    #[arg(long, value_enum)]
+    pub profile: Option<Profile>,
+    ///chunk, or lsb to hide the payload in the low bits of the pixels, which survives
+    ///tools that strip unknown chunks, alpha for the low bits of the opaque and
+    ///transparent pixels only, palette for the palette entries of indexed images,
+    ///after-iend to append it to the file, where decoders stop reading, or frames to
+    ///spread it over chunks of the type after every frame of an animated PNG.
+    ///With --encrypt the bits are scattered in an order derived from the password
+    #[arg(long, default_value_t = EmbeddingMode::Chunk)]
+    pub mode: EmbeddingMode,
+    ///Embed this file, with its name, instead of a message
+    #[arg(long, conflicts_with = "message")]
+    pub file: Option<PathBuf>,
+    ///MIME type recorded with the payload, guessed from the extension with --file
+    #[arg(long)]
+    pub mime: Option<String>,
+    ///Don't record when the payload was encoded
+    #[arg(long)]
+    pub no_timestamp: bool,
+    ///Also set the tIME chunk to the current time
+    #[arg(long, conflicts_with = "deterministic")]
+    pub touch: bool,
+    ///Also set the visible Author text, replacing the one already there
+    #[arg(long)]
+    pub author: Option<String>,
+    ///Also set the visible Copyright text
+    #[arg(long)]
+    pub copyright: Option<String>,
+    ///Also set the visible Comment text
+    #[arg(long)]
+    pub comment: Option<String>,
+    ///Read the message from stdin, same as '-' for the message
+    #[arg(long, conflicts_with_all = ["message", "file"])]
+    pub stdin: bool,
+    ///How the message or stdin is written: raw, base64 or hex
+    #[arg(long, default_value_t = Encoding::Raw)]
+    pub encoding: Encoding,
+    ///Compress the payload with zlib or zstd, decode detects it
+    #[arg(long)]
+    pub compress: Option<Compression>,
+    ///Encrypt the payload with a password, taken from PNGME_PASSWORD or prompted for
+    #[arg(long)]
+    pub encrypt: bool,
+    ///Take the password from this file, raw, instead. It must not be world-readable
+    #[arg(long, requires = "encrypt")]
+    pub key_file: Option<PathBuf>,
+    ///Same input, same output: no timestamp, and encryption with a salt derived from the
+    ///chunk type and --salt-label and a nonce derived from the payload. Equal payloads
+    ///in the same chunk type and label show as equal, and one guess of the password
+    ///costs the same Argon2 work against all of them
+    #[arg(long, conflicts_with = "decoys")]
+    #[cfg_attr(feature = "age", arg(conflicts_with = "recipient"))]
+    pub deterministic: bool,
+    ///Label mixed into the --deterministic salt, to keep payloads apart that share a chunk type
+    #[arg(long, requires = "deterministic")]
+    pub salt_label: Option<String>,
+    ///Store the payload in a chunk type derived from the password at the default Argon2id
+    ///costs, decode --stealth finds it
+    #[arg(long, requires = "encrypt", conflicts_with = "name")]
+    pub stealth: bool,
+    ///Also store this innocuous message the --stealth way under a decoy password, taken
+    ///from PNGME_DECOY_PASSWORD or prompted for. Decode gives away only the payload of
+    ///the password it gets, the real one is bare ciphertext among random --decoys, at
+    ///least 3, encrypted at the default Argon2id costs and padded to a decoy length
+    #[arg(long, requires = "stealth", conflicts_with_all = ["decoy_file", "sign", "hmac", "deterministic"])]
+    pub decoy_message: Option<String>,
+    ///Same as --decoy-message with this file, and its name, as the innocuous payload
+    #[arg(long, requires = "stealth", conflicts_with_all = ["sign", "hmac", "deterministic"])]
+    pub decoy_file: Option<PathBuf>,
+    ///Spread the payload over this many zTXt, iTXt and private chunks, listed by a
+    ///manifest stored encrypted at the default Argon2id costs in the --stealth chunk type,
+    ///so no large chunk stands out
+    #[arg(
+        long,
+        requires = "stealth",
+        conflicts_with_all = ["decoy_message", "decoy_file"],
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub spread: Option<u32>,
+    ///Take the password from keyring:<name> in the platform keychain, storing it there if missing
+    #[cfg(feature = "keyring")]
+    #[arg(long, requires = "encrypt", conflicts_with = "key_file")]
+    pub key_from: Option<KeySource>,
+    ///Argon2id memory cost of the key derivation, in KiB. Like --kdf-iterations and
+    ///--kdf-parallelism it only applies to payloads whose header records the costs, the
+    ///decoy included.
+    ///The --stealth chunk type, the --spread manifest and the hidden payload of
+    ///--decoy-message have no header, they always use the defaults
+    #[arg(long, requires = "encrypt", default_value_t = KdfParams::default().memory_kib)]
+    pub kdf_memory: u32,
+    ///Argon2id passes over the memory
+    #[arg(long, requires = "encrypt", default_value_t = KdfParams::default().iterations)]
+    pub kdf_iterations: u32,
+    ///Argon2id lanes computed in parallel
+    #[arg(long, requires = "encrypt", default_value_t = KdfParams::default().parallelism)]
+    pub kdf_parallelism: u32,
+    ///Encrypt the payload to this age1... recipient, can be repeated
+    #[cfg(feature = "age")]
+    #[arg(long, conflicts_with = "encrypt")]
+    pub recipient: Vec<String>,
+    ///Sign the payload with this PKCS#8 PEM Ed25519 private key, not world-readable
+    #[arg(long, value_name = "KEY_FILE")]
+    pub sign: Option<PathBuf>,
+    ///Append an HMAC-SHA256 of the payload under this key, checked by decode
+    #[arg(long, value_name = "KEY")]
+    pub hmac: Option<String>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    ///Messages longer than this many bytes are split across several chunks
+    #[arg(long, default_value_t = DEFAULT_PART_LENGTH)]
+    pub part_size: usize,
+    ///Also store this many chunks of random data, so the payload isn't the odd one out
+    #[arg(long, default_value_t = 0)]
+    pub decoys: usize,
+    ///Replace the chunks of the type already in the file
+    #[arg(long, conflicts_with = "append")]
+    pub replace: bool,
+    ///Keep the chunks of the type already in the file next to the new one
+    #[arg(long)]
+    pub append: bool,
+    ///Store the payload under this name, several named payloads can share the chunk type
+    #[arg(long, conflicts_with = "append")]
+    pub name: Option<String>,
+    ///Store this many copies, in chunks before the pixel data and before IEND, in the
+    ///pixels and after IEND, so that an edit losing some of them leaves the others
+    #[arg(
+        long,
+        conflicts_with_all = ["name", "append", "decoys", "decoy_message", "decoy_file", "spread"],
+        value_parser = clap::value_parser!(u8).range(1..=4)
+    )]
+    pub redundancy: Option<u8>,
+}
+
+#[derive(Debug, Args)]
+pub struct DecodeArgs {
+    ///A glob pattern such as '*.png' decodes every matching file
+    pub file_path: PathBuf,
+    ///Left out with --stealth, --keyword or a pixel --mode
+    #[arg(
+        required_unless_present_any = ["stealth", "mode", "keyword"],
+        conflicts_with_all = ["stealth", "keyword"]
+    )]
+    pub chunk_type: Option<String>,
+    ///chunk, or lsb, alpha, palette, after-iend or frames to read the payload hidden by
+    ///encode, in the pixels in the order derived from the password with --decrypt
+    #[arg(long, default_value_t = EmbeddingMode::Chunk)]
+    pub mode: EmbeddingMode,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+    ///Salvage what can be read from a damaged file instead of failing
+    #[arg(long)]
+    pub recover: bool,
+    ///Write the payload byte for byte to this file, '-' for stdout
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+    ///Write the payload to stdout as is, without UTF-8 check or trailing newline
+    #[arg(long, conflicts_with = "out")]
+    pub raw: bool,
+    ///Write the payload as raw bytes, base64 or hex, the latter two being safe to display
+    #[arg(long, default_value_t = Encoding::Raw)]
+    pub encoding: Encoding,
+    ///Show the payload as the fields described for its chunk type in this TOML or JSON file
+    #[arg(long)]
+    pub schema: Option<PathBuf>,
+    ///Decrypt the payload with a password, taken from PNGME_PASSWORD or prompted for
+    #[arg(long)]
+    pub decrypt: bool,
+    ///Take the password from this file, raw, instead. It must not be world-readable
+    #[arg(long, requires = "decrypt")]
+    pub key_file: Option<PathBuf>,
+    ///Look for the payload in the chunk type derived from the password
+    #[arg(long, requires = "decrypt")]
+    pub stealth: bool,
+    ///Take the password from keyring:<name> in the platform keychain
+    #[cfg(feature = "keyring")]
+    #[arg(long, requires = "decrypt", conflicts_with = "key_file")]
+    pub key_from: Option<KeySource>,
+    ///Decrypt an age encrypted payload with the identities of this file, not world-readable
+    #[cfg(feature = "age")]
+    #[arg(short, long, conflicts_with = "decrypt")]
+    pub identity: Option<PathBuf>,
+    ///Check the HMAC tag of the payload with this key
+    #[arg(long, value_name = "KEY")]
+    pub hmac: Option<String>,
+    ///Decode the payload stored under this name
+    #[arg(long)]
+    pub name: Option<String>,
+    ///Use the first intact copy stored by encode --redundancy, damaged ones are skipped
+    #[arg(long, conflicts_with_all = ["name", "recover"])]
+    pub redundant: bool,
+    ///Decode the text of this tEXt, zTXt or iTXt keyword instead of a chunk payload
+    #[arg(long, conflicts_with_all = ["stealth", "name", "redundant", "recover"])]
+    pub keyword: Option<String>,
+    ///Prefer the text of --keyword in this language, falling back to the closest one then
+    ///untagged text, as meta get does
+    #[arg(long, requires = "keyword", conflicts_with = "chunk_type")]
+    pub lang: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ShardArgs {
+    pub chunk_type: String,
+    ///The message, left out with --file
+    #[arg(required_unless_present = "file")]
+    pub message: Option<String>,
+    ///Shard this file instead of a message
+    #[arg(long, conflicts_with = "message")]
+    pub file: Option<PathBuf>,
+    ///Carrier images, one shard each
+    #[arg(long, num_args = 1.., required = true)]
+    pub files: Vec<PathBuf>,
+    ///Any this many of the files rebuild the payload, with Shamir's secret sharing, while
+    ///fewer tell nothing about it, at least 2 as every share of 1 is the payload. Without
+    ///it the payload is cut into parts, all needed
+    #[arg(long, value_parser = clap::value_parser!(u8).range(2..))]
+    pub threshold: Option<u8>,
+    ///Write the carriers to this directory instead of overwriting them
+    #[arg(long, required_unless_present = "in_place")]
+    pub out_dir: Option<PathBuf>,
+    ///Overwrite the carriers
+    #[arg(long, conflicts_with = "out_dir")]
+    pub in_place: bool,
+    ///Replace the chunks of the type already in the files
+    #[arg(long)]
+    pub replace: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct UnshardArgs {
+    pub chunk_type: String,
+    ///Files holding the shards, missing ones are fine as long as enough remain
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    ///Write the payload to this file, '-' for stdout
+    #[arg(short, long)]
+    pub out: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    ///PEM Ed25519 public key of the signer
+    #[arg(long)]
+    pub pubkey: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyPayloadArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    ///Check the payload stored under this name
+    #[arg(long)]
+    pub name: Option<String>,
+    ///Decrypt the payload with a password, taken from PNGME_PASSWORD or prompted for
+    #[arg(long)]
+    pub decrypt: bool,
+    ///Take the password from this file, raw, instead. It must not be world-readable
+    #[arg(long, requires = "decrypt")]
+    pub key_file: Option<PathBuf>,
+    ///Take the password from keyring:<name> in the platform keychain
+    #[cfg(feature = "keyring")]
+    #[arg(long, requires = "decrypt", conflicts_with = "key_file")]
+    pub key_from: Option<KeySource>,
+    ///Decrypt an age encrypted payload with the identities of this file, not world-readable
+    #[cfg(feature = "age")]
+    #[arg(short, long, conflicts_with = "decrypt")]
+    pub identity: Option<PathBuf>,
+    ///Check the HMAC tag of the payload with this key
+    #[arg(long, value_name = "KEY")]
+    pub hmac: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct LsPayloadsArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ExtractArgs {
+    pub file_path: PathBuf,
+    #[arg(required_unless_present = "after_iend")]
+    pub chunk_type: Option<String>,
+    #[arg(short, long)]
+    pub out: PathBuf,
+    ///Extract the Nth chunk of the type, counted from 0, as is. Without it the
+    ///payload is reassembled if it was split across several chunks
+    #[arg(long)]
+    pub index: Option<usize>,
+    ///Extract the bytes after IEND instead of a chunk type
+    #[arg(long, conflicts_with_all = ["chunk_type", "index"])]
+    pub after_iend: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RemoveArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    ///Remove the Nth chunk of the type, counted from 0, instead of the first
+    #[arg(long, conflicts_with = "all")]
+    pub index: Option<usize>,
+    ///Remove every chunk of the type
+    #[arg(long)]
+    pub all: bool,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    ///Overwrite the removed data with zeros in the old file and sync it to disk.
+    ///On copy-on-write or journaling filesystems, SSDs, snapshots and backups older
+    ///copies of the data can survive anyway
+    //Conflicting with the other outputs leaves --in-place
+    #[arg(long, conflicts_with_all = ["output", "dry_run"])]
+    pub shred: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PrintArgs {
+    pub file_path: PathBuf,
+    ///Dump the data of every chunk in hex and ASCII
+    #[arg(long)]
+    pub hex: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    pub file_path: PathBuf,
+    ///Also show the decoded fields of known chunks, as inspect does
+    #[arg(long)]
+    pub decoded: bool,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    pub file_path: PathBuf,
+    ///Only inspect chunks of this type
+    pub chunk_type: Option<String>,
+    ///Dump the whole data of every chunk, decoded ones included
+    #[arg(long)]
+    pub hex: bool,
+    ///TOML or JSON file describing the fields of private chunk types
+    #[arg(long)]
+    pub schema: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    pub old_file: PathBuf,
+    pub new_file: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct GrepArgs {
+    pub pattern: Regex,
+    ///Files, glob patterns, or directories with --recursive
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    ///A directory is walked as with --recursive
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CopyChunksArgs {
+    #[arg(long)]
+    pub from: PathBuf,
+    #[arg(long)]
+    pub to: PathBuf,
+    ///Chunk types to copy, e.g. tEXt,ruSt
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub types: Vec<ChunkType>,
+    ///Also copy unsafe-to-copy chunks, which may not match the other image
+    #[arg(long)]
+    pub allow_unsafe: bool,
+    ///--in-place overwrites the --to file
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ScrubArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+    ///Ancillary chunk types to keep, e.g. pHYs,gAMA
+    #[arg(long, value_delimiter = ',')]
+    pub keep: Vec<ChunkType>,
+    ///Also keep every safe-to-copy chunk, such as tEXt, pHYs or eXIf
+    #[arg(long)]
+    pub keep_safe_to_copy: bool,
+    ///Only remove these chunk types, e.g. tEXt,zTXt,iTXt,eXIf,tIME, keeping every other one
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["keep", "keep_safe_to_copy"])]
+    pub strip: Vec<ChunkType>,
+    ///Also remove kept chunks that duplicate an earlier one byte for byte
+    #[arg(long)]
+    pub dedup: bool,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct NormalizeArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct RepairArgs {
+    pub file_path: PathBuf,
+    ///--dry-run reports the chunks that would be repaired
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct MigrateArgs {
+    pub file_path: PathBuf,
+    ///Only migrate chunks of this type, by default every private ancillary chunk starting
+    ///with a magic of pngme. Plain text messages are only migrated in a chunk of this type
+    pub chunk_type: Option<String>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    ///Migrated payloads longer than this many bytes are split across several chunks
+    #[arg(long, default_value_t = DEFAULT_PART_LENGTH)]
+    pub part_size: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct OptimizeArgs {
+    pub file_path: PathBuf,
+    ///zlib compression level, from 0 to 9
+    #[arg(long, default_value_t = 9, value_parser = clap::value_parser!(u32).range(0..=9))]
+    pub level: u32,
+    ///Use zopfli instead of zlib, much slower but usually smaller
+    #[cfg(feature = "zopfli")]
+    #[arg(long)]
+    pub zopfli: bool,
+    ///Also remove ancillary chunks that duplicate an earlier one byte for byte
+    #[arg(long)]
+    pub dedup: bool,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CapacityArgs {
+    pub file_path: PathBuf,
+    ///chunk, text, lsb, alpha or palette, defaults to every mode. lsb also compares 1, 2 and 4 bits per
+    ///channel and the distortion they cause
+    #[arg(long)]
+    pub mode: Option<EmbeddingMode>,
+    ///Payload size used for the file growth estimate
+    #[arg(long, default_value_t = 1024)]
+    pub payload_size: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct BitplanesArgs {
+    pub file_path: PathBuf,
+    ///Directory the images go to, named after their channel and bit, e.g. red_0.png
+    #[arg(short, long)]
+    pub out: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct CoverArgs {
+    ///WIDTHxHEIGHT in pixels
+    #[arg(long, default_value = "1920x1080")]
+    pub size: Size,
+    ///noise, fine clouds in every channel, gradient, two colours blended across the
+    ///image, or photo-like, soft shapes lit from the top. All of them have grain in every
+    ///sample and look like a photo to analyze
+    #[arg(long, default_value_t = Style::PhotoLike)]
+    pub style: Style,
+    ///Generate the same image every time for this seed
+    #[arg(long)]
+    pub seed: Option<u64>,
+    ///'-' writes it to stdout
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct PolyglotArgs {
+    pub image: PathBuf,
+    pub archive: PathBuf,
+    ///'-' writes it to stdout
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaArgs {
+    #[command(subcommand)]
+    pub command: MetaCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MetaCommand {
+    ///Sets the text of a keyword, replacing the one already there
+    Set(MetaSetArgs),
+    ///Prints the text of a keyword
+    Get(MetaGetArgs),
+    ///Prints every keyword and its text
+    List(MetaListArgs),
+    ///Copies the safe-to-copy metadata chunks of another file, e.g. after resizing
+    Copy(MetaCopyArgs),
+    ///Stamps the text, time and resolution of a TOML template onto files
+    Apply(MetaApplyArgs),
+    ///Compares the text, resolution, time and colour information of two files
+    Diff(MetaDiffArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct MetaSetArgs {
+    pub file_path: PathBuf,
+    ///1 to 79 printable Latin-1 characters, such as Author, Title or Description
+    pub keyword: String,
+    pub text: String,
+    ///Store the text in an iTXt chunk tagged with this language, such as en-US, replacing
+    ///only the text of that language
+    #[arg(long)]
+    pub lang: Option<String>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaGetArgs {
+    pub file_path: PathBuf,
+    pub keyword: String,
+    ///Prefer the text in this language, falling back to the closest one then untagged text
+    #[arg(long)]
+    pub lang: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaListArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaCopyArgs {
+    ///The file holding the metadata, such as the original of an edited image
+    #[arg(long)]
+    pub from: PathBuf,
+    ///The file receiving the metadata
+    #[arg(long)]
+    pub to: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaDiffArgs {
+    pub old_file: PathBuf,
+    pub new_file: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaApplyArgs {
+    ///Declares time, dpi and [text] and [itxt] tables of keywords
+    pub template: PathBuf,
+    ///Glob patterns such as '*.png' are expanded
+    #[arg(required = true)]
+    pub file_paths: Vec<PathBuf>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct XmpArgs {
+    #[command(subcommand)]
+    pub command: XmpCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum XmpCommand {
+    ///Prints the XMP packet
+    Get(XmpGetArgs),
+    ///Stores an XMP packet, replacing the one already there
+    Set(XmpSetArgs),
+    ///Removes every XMP packet
+    Strip(XmpStripArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct XmpGetArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct XmpSetArgs {
+    pub file_path: PathBuf,
+    ///File holding the XMP packet, - for stdin
+    pub packet: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct XmpStripArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct TimeArgs {
+    #[command(subcommand)]
+    pub command: TimeCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TimeCommand {
+    ///Prints the last modification time, in UTC
+    Get(TimeGetArgs),
+    ///Sets the last modification time
+    Set(TimeSetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct TimeGetArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct TimeSetArgs {
+    pub file_path: PathBuf,
+    ///RFC 3339 time such as 2024-01-31T12:00:00+01:00, stored in UTC
+    #[arg(required_unless_present = "now", conflicts_with = "now")]
+    pub time: Option<Timestamp>,
+    ///Use the current time
+    #[arg(long)]
+    pub now: bool,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct IccArgs {
+    #[command(subcommand)]
+    pub command: IccCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IccCommand {
+    ///Writes the embedded profile to a file
+    Extract(IccExtractArgs),
+    ///Embeds a profile, replacing the iCCP and sRGB chunks already there
+    Embed(IccEmbedArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct IccExtractArgs {
+    pub file_path: PathBuf,
+    ///Defaults to the file path with an .icc extension, - for stdout
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct IccEmbedArgs {
+    pub file_path: PathBuf,
+    ///The .icc or .icm profile
+    pub profile: PathBuf,
+    ///Profile name, a keyword of 1 to 79 Latin-1 characters, defaults to the file stem
+    #[arg(long)]
+    pub name: Option<String>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ColorArgs {
+    #[command(subcommand)]
+    pub command: ColorCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ColorCommand {
+    ///Prints the gamma, chromaticities and rendering intent
+    Get(ColorGetArgs),
+    ///Sets some of them, replacing the chunks already there
+    Set(ColorSetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ColorGetArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ColorSetArgs {
+    pub file_path: PathBuf,
+    ///File gamma such as 0.45455, values above 1 are the display gamma, 2.2 for 1/2.2
+    #[arg(long, required_unless_present_any = ["chromaticities", "srgb"])]
+    pub gamma: Option<Gamma>,
+    ///srgb, or the x,y of the white point, red, green and blue such as
+    ///0.3127,0.329,0.64,0.33,0.3,0.6,0.15,0.06
+    #[arg(long)]
+    pub chromaticities: Option<Chromaticities>,
+    ///Mark the image as sRGB with a rendering intent: perceptual, relative-colorimetric,
+    ///saturation or absolute-colorimetric. Removes the ICC profile, and also sets the
+    ///matching gamma and chromaticities unless they are given
+    #[arg(long)]
+    pub srgb: Option<RenderingIntent>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DpiArgs {
+    #[command(subcommand)]
+    pub command: DpiCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DpiCommand {
+    ///Prints the resolution in dots per inch
+    Get(DpiGetArgs),
+    ///Sets the resolution, replacing the pHYs chunk already there
+    Set(DpiSetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct DpiGetArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct DpiSetArgs {
+    pub file_path: PathBuf,
+    ///Horizontal resolution in dots per inch, also the vertical one unless --vertical is given
+    pub dpi: f64,
+    ///Vertical resolution in dots per inch
+    #[arg(long)]
+    pub vertical: Option<f64>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExtArgs {
+    #[command(subcommand)]
+    pub command: ExtCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExtCommand {
+    ///Prints the image offset, pixel size and stereo layout
+    Get(ExtGetArgs),
+    ///Sets some of them, replacing the chunks already there
+    Set(ExtSetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ExtGetArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ExtSetArgs {
+    pub file_path: PathBuf,
+    ///Position of the image on a page as x,y followed by px, the default, or um
+    #[arg(long, required_unless_present_any = ["scale", "stereo"], allow_hyphen_values = true)]
+    pub offset: Option<ImageOffset>,
+    ///Physical width,height of a pixel followed by m, the default, or rad
+    #[arg(long)]
+    pub scale: Option<PhysicalScale>,
+    ///Layout of a side by side stereo pair: cross-fuse or diverging-fuse
+    #[arg(long)]
+    pub stereo: Option<StereoMode>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Debug, Args)]
+pub struct TuiArgs {
+    pub file_path: PathBuf,
+}
+
+#[cfg(feature = "watch")]
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    pub dir: PathBuf,
+    ///Command to run, the file path is inserted after its name: --on-new decode ruSt
+    #[arg(long, required = true, num_args = 1.., allow_hyphen_values = true)]
+    pub on_new: Vec<String>,
+    ///Also watch subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+    ///Only run on files matching one of these patterns, defaults to *.png
+    #[arg(long)]
+    pub include: Vec<String>,
+    ///Skip files matching one of these patterns
+    #[arg(long)]
+    pub exclude: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EncryptionConfig;
+
+    #[test]
+    pub fn test_parse_with_config() {
+        let config = Config {
+            chunk_type: Some("ruSt".to_string()),
+            ..Config::default()
+        };
+        let decode = |args: &[&str]| {
+            let args = ["pngme", "decode", "f.png"].iter().chain(args);
+            match Cli::try_parse_from_with(args, &config).unwrap().command {
+                PngMeArgs::Decode(args) => args.chunk_type,
+                command => panic!("{:?}", command),
+            }
+        };
+        assert_eq!(decode(&[]), Some("ruSt".to_string()));
+        assert_eq!(decode(&["myTp"]), Some("myTp".to_string()));
+        assert_eq!(decode(&["--stealth", "--decrypt"]), None);
+        assert_eq!(decode(&["--mode", "lsb"]), None);
+        assert!(
+            Cli::try_parse_from_with(["pngme", "decode", "f.png"], &Config::default()).is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_encryption_config() {
+        let config = Config {
+            encryption: EncryptionConfig {
+                key_file: Some("key".to_string()),
+                kdf_memory: Some(1024),
+                ..EncryptionConfig::default()
+            },
+            ..Config::default()
+        };
+        let parse = |args: &[&str]| Cli::try_parse_from_with(args, &config).unwrap().command;
+        let encode = |args: &[&str]| {
+            let args: Vec<&str> = ["pngme", "encode", "f.png", "--in-place", "ruSt", "hi"]
+                .iter()
+                .chain(args)
+                .copied()
+                .collect();
+            match parse(&args) {
+                PngMeArgs::Encode(args) => args,
+                command => panic!("{:?}", command),
+            }
+        };
+        let defaults = encode(&["--encrypt"]);
+        assert_eq!(defaults.key_file, Some(PathBuf::from("key")));
+        assert_eq!(
+            (defaults.kdf_memory, defaults.kdf_iterations),
+            (1024, KdfParams::default().iterations)
+        );
+        let given = encode(&["--encrypt", "--key-file", "other", "--kdf-memory", "2048"]);
+        assert_eq!(given.key_file, Some(PathBuf::from("other")));
+        assert_eq!(given.kdf_memory, 2048);
+        //The defaults don't ask for encryption
+        assert!(!encode(&[]).encrypt);
+        match parse(&["pngme", "decode", "f.png", "ruSt", "--decrypt"]) {
+            PngMeArgs::Decode(args) => assert_eq!(args.key_file, Some(PathBuf::from("key"))),
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    pub fn test_profile() {
+        let encode = |args: &[&str]| {
+            let args = ["pngme", "encode", "f.png", "--in-place"]
+                .iter()
+                .chain(args);
+            match Cli::try_parse_from_with(args, &Config::default())
+                .unwrap()
+                .command
+            {
+                PngMeArgs::Encode(args) => args,
+                command => panic!("{:?}", command),
+            }
+        };
+        let robust = encode(&["--profile", "robust", "ruSt", "hello"]);
+        assert_eq!(
+            (robust.redundancy, robust.mode),
+            (Some(3), EmbeddingMode::Chunk)
+        );
+        assert_eq!(
+            encode(&["--profile", "robust", "--redundancy", "2", "ruSt", "hello"]).redundancy,
+            Some(2)
+        );
+        assert_eq!(
+            encode(&["--profile", "robust", "--mode", "lsb", "hello"]).redundancy,
+            None
+        );
+        let stealth = encode(&["--profile", "stealth", "hello"]);
+        assert!(stealth.stealth && stealth.encrypt && stealth.spread.is_some());
+        let named = encode(&["--profile", "stealth", "ruSt", "hello"]);
+        assert!(!named.stealth && named.encrypt && named.spread.is_none() && named.decoys == 0);
+    }
+}
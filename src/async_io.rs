@@ -0,0 +1,148 @@
+/*!
+ * Async counterparts of `stream` and `ops`, for services that embed pngme in an
+ * async runtime (e.g. an upload pipeline) and can't block the executor on I/O.
+ */
+use crate::chunk::{Chunk, ChunkError};
+use crate::chunk_type::ChunkType;
+use crate::ops::EncodePosition;
+use crate::png::{Png, PngError};
+use crate::Error;
+use bytes::Bytes;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+//Reads a PNG one chunk at a time, see `PngReader`
+pub struct AsyncPngReader<R: AsyncRead + Unpin> {
+    reader: R,
+}
+
+impl<R: AsyncRead + Unpin> AsyncPngReader<R> {
+    //Reads and checks the PNG signature, leaving the reader at the first chunk
+    pub async fn new(mut reader: R) -> Result<Self, Error> {
+        let mut signature = [0; 8];
+        reader.read_exact(&mut signature).await?;
+        if signature != Png::STANDARD_HEADER {
+            return Err(Box::new(PngError::InvalidSignature(signature)));
+        }
+        Ok(AsyncPngReader { reader })
+    }
+
+    //Returns the next chunk, or None once the end of the input has been reached
+    pub async fn read_chunk(&mut self) -> Result<Option<Chunk>, Error> {
+        let mut length = [0; 4];
+        let read = self.reader.read(&mut length).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.reader.read_exact(&mut length[read..]).await?;
+        let length = u32::from_be_bytes(length);
+        if length > Chunk::MAX_LENGTH {
+            return Err(Box::new(ChunkError::InvalidLength(length)));
+        }
+
+        let mut chunk_type = [0; 4];
+        self.reader.read_exact(&mut chunk_type).await?;
+        let chunk_type = ChunkType::try_from(chunk_type)?;
+
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)
+            .await?;
+        if data.len() != length as usize {
+            return Err(Box::new(io::Error::from(io::ErrorKind::UnexpectedEof)));
+        }
+
+        let crc = self.reader.read_u32().await?;
+        Ok(Some(Chunk::from_parts(chunk_type, Bytes::from(data), crc)))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl Png {
+    //Reads the whole PNG from an async reader
+    pub async fn from_async_reader<R: AsyncRead + Unpin>(reader: R) -> Result<Png, Error> {
+        let mut reader = AsyncPngReader::new(reader).await?;
+        let mut chunks = Vec::new();
+        while let Some(chunk) = reader.read_chunk().await? {
+            chunks.push(chunk);
+        }
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+async fn write_chunk<W: AsyncWrite + Unpin>(output: &mut W, chunk: &Chunk) -> Result<(), Error> {
+    output.write_all(&chunk.as_bytes()).await?;
+    Ok(())
+}
+
+//Copies the PNG from `input` to `output`, adding `chunk` where `ops::encode` would
+pub async fn encode<R, W>(input: R, mut output: W, chunk: Chunk) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut reader = AsyncPngReader::new(input).await?;
+    output.write_all(&Png::STANDARD_HEADER).await?;
+    let mut chunk = Some(chunk);
+    let mut position = EncodePosition::default();
+    while let Some(existing) = reader.read_chunk().await? {
+        existing.verify_crc()?;
+        if position.is_reached(existing.chunk_type()) {
+            if let Some(chunk) = chunk.take() {
+                write_chunk(&mut output, &chunk).await?;
+            }
+        }
+        write_chunk(&mut output, &existing).await?;
+    }
+    if let Some(chunk) = chunk {
+        write_chunk(&mut output, &chunk).await?;
+    }
+    output.flush().await?;
+    Ok(())
+}
+
+//Returns the first chunk of the given type
+pub async fn decode<R: AsyncRead + Unpin>(input: R, chunk_type: &str) -> Result<Chunk, Error> {
+    let mut reader = AsyncPngReader::new(input).await?;
+    while let Some(chunk) = reader.read_chunk().await? {
+        if chunk.chunk_type().to_string() == chunk_type {
+            chunk.verify_crc()?;
+            return Ok(chunk);
+        }
+    }
+    Err(Box::new(PngError::ChunkNotFound(chunk_type.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    pub async fn test_from_async_reader() {
+        let bytes = PngBuilder::new(4, 4).build().unwrap().as_bytes();
+        let png = Png::from_async_reader(bytes.as_slice()).await.unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+        assert!(Png::from_async_reader(&bytes[..bytes.len() - 1])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_async_encode_decode() {
+        let bytes = PngBuilder::new(4, 4).build().unwrap().as_bytes();
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Hello".to_vec());
+        let mut encoded = Vec::new();
+        encode(bytes.as_slice(), &mut encoded, chunk).await.unwrap();
+
+        let expected = crate::ops::decode(encoded.as_slice(), "ruSt").unwrap();
+        let chunk = decode(encoded.as_slice(), "ruSt").await.unwrap();
+        assert_eq!(chunk.data(), expected.data());
+        assert!(decode(bytes.as_slice(), "ruSt").await.is_err());
+    }
+}
@@ -0,0 +1,151 @@
+/*!
+ * Running a command over every file matched by a glob pattern or found in a directory.
+ */
+use crate::args::BatchArgs;
+use glob::Pattern;
+use pngme::Result;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+//Characters that make a path a glob pattern
+const GLOB_CHARS: [char; 3] = ['*', '?', '['];
+
+/**
+ * Files matched by `pattern`, sorted. A path without glob characters is returned
+ * as is, whether it exists or not, so the command reports the usual error.
+ */
+pub fn expand(pattern: &Path) -> Result<Vec<PathBuf>> {
+    let pattern_str = pattern.to_string_lossy();
+    if !pattern_str.contains(GLOB_CHARS) {
+        return Ok(vec![pattern.to_path_buf()]);
+    }
+    let files = glob::glob(&pattern_str)?.collect::<std::result::Result<Vec<_>, _>>()?;
+    if files.is_empty() {
+        return Err(Box::new(BatchError::NoMatch(pattern_str.into_owned())));
+    }
+    Ok(files)
+}
+
+//Files to process for `path`, walking it as a directory with --recursive
+pub fn files(path: &Path, args: &BatchArgs) -> Result<Vec<PathBuf>> {
+    if !args.recursive {
+        return expand(path);
+    }
+    let filter = FileFilter::new(&args.include, &args.exclude)?;
+    let mut files = Vec::new();
+    walk(path, path, &filter, &mut files)?;
+    log::info!("found {} file(s) in {}", files.len(), path.display());
+    if files.is_empty() {
+        return Err(Box::new(BatchError::NoMatch(path.display().to_string())));
+    }
+    Ok(files)
+}
+
+/**
+ * Include and exclude patterns of a directory walk. A pattern matches a file when it
+ * matches its name or its path relative to the directory. Without include patterns
+ * only .png files are kept.
+ */
+pub struct FileFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl FileFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<FileFilter> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<std::result::Result<Vec<_>, _>>()
+        };
+        Ok(FileFilter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, relative: &Path) -> bool {
+        let matches = |pattern: &Pattern| {
+            pattern.matches_path(relative)
+                || relative
+                    .file_name()
+                    .is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+        };
+        let included = if self.include.is_empty() {
+            relative
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+        } else {
+            self.include.iter().any(matches)
+        };
+        included && !self.exclude.iter().any(matches)
+    }
+}
+
+//Collects the matching files under `dir` in sorted order, symlinked directories are skipped
+fn walk(root: &Path, dir: &Path, filter: &FileFilter, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::result::Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            walk(root, &path, filter, files)?;
+        } else if filter.matches(path.strip_prefix(root)?) {
+            files.push(path);
+        } else {
+            log::debug!("skipping {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Runs `f` on every file. A single file fails like a plain command would, with
+ * several files each failure is reported next to its file and the others still run.
+ */
+pub fn for_each<F>(files: &[PathBuf], mut f: F) -> Result<()>
+where
+    F: FnMut(&Path) -> Result<()>,
+{
+    if let [file] = files {
+        return f(file);
+    }
+    let mut failed = 0;
+    for file in files {
+        let start = Instant::now();
+        let result = f(file);
+        log::info!("{}: processed in {:.2?}", file.display(), start.elapsed());
+        if let Err(err) = result {
+            eprintln!("{}: {}", file.display(), err);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(Box::new(BatchError::Failed(failed, files.len())));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum BatchError {
+    NoMatch(String),
+    Failed(usize, usize),
+    OutputWithSeveralFiles,
+}
+
+impl std::error::Error for BatchError {}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoMatch(pattern) => write!(f, "no file matches {}", pattern),
+            Self::Failed(failed, total) => write!(f, "{} of {} files failed", failed, total),
+            Self::OutputWithSeveralFiles => {
+                write!(f, "an output file can't be used with several input files")
+            }
+        }
+    }
+}
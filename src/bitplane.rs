@@ -0,0 +1,110 @@
+/*!
+ * Bit planes, the images made of one bit of one channel of every pixel. The high
+ * planes of a natural image show its shapes and the low ones still follow them some,
+ * while bits replaced by a payload look like noise, which makes viewing the low
+ * planes the usual manual check for LSB steganography.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::Error;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+#[derive(Debug, Clone)]
+pub struct BitPlane {
+    //Name of the channel, see `channel_names`
+    pub channel: &'static str,
+    //From 0 for the low bit
+    pub bit: u8,
+    //1 bit greyscale, white where the bit is set
+    pub png: Png,
+}
+
+//The channels of the samples of a pixel, in the order they are stored
+pub fn channel_names(color_type: ColorType) -> &'static [&'static str] {
+    match color_type {
+        ColorType::Grayscale => &["grey"],
+        ColorType::GrayscaleAlpha => &["grey", "alpha"],
+        ColorType::Rgb => &["red", "green", "blue"],
+        ColorType::Rgba => &["red", "green", "blue", "alpha"],
+        ColorType::Indexed => &["index"],
+    }
+}
+
+impl Png {
+    /**
+     * Every bit plane of every channel, channel after channel from the low bit up.
+     * Indexed images have a single channel, the palette index of the pixels.
+     */
+    pub fn bit_planes(&self) -> Result<Vec<BitPlane>, Error> {
+        let scanlines = self.scanlines()?;
+        let header = scanlines.header();
+        let channels = header.color_type.channels() as usize;
+        let depth = header.bit_depth as usize;
+        let plane_header = Ihdr::new(header.width, header.height, 1, ColorType::Grayscale);
+        let mut planes = Vec::new();
+        for (channel, &name) in channel_names(header.color_type).iter().enumerate() {
+            for bit in 0..depth {
+                //Filter type 0 (none) then the packed pixels
+                let mut line = vec![0; 1 + plane_header.row_length(header.width)];
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                for row in scanlines.rows() {
+                    line[1..].fill(0);
+                    for x in 0..header.width as usize {
+                        if sample(row, x * channels + channel, depth) >> bit & 1 == 1 {
+                            line[1 + x / 8] |= 0x80 >> (x % 8);
+                        }
+                    }
+                    encoder.write_all(&line)?;
+                }
+                planes.push(BitPlane {
+                    channel: name,
+                    bit: bit as u8,
+                    png: Png::from_chunks(vec![
+                        plane_header.to_chunk(),
+                        Chunk::new(ChunkType::IDAT, encoder.finish()?),
+                        Chunk::new(ChunkType::IEND, Vec::new()),
+                    ]),
+                });
+            }
+        }
+        Ok(planes)
+    }
+}
+
+//The Nth sample of a row, big endian for 16 bits and packed from the high bits below 8
+fn sample(row: &[u8], index: usize, depth: usize) -> u16 {
+    if depth == 16 {
+        return u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]);
+    }
+    let bit = index * depth;
+    let shift = 8 - depth - bit % 8;
+    (row[bit / 8] as u16 >> shift) & ((1 << depth) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+
+    #[test]
+    pub fn test_bit_planes() {
+        let png = PngBuilder::new(3, 2).color(&[0b101, 0x80]).build().unwrap();
+        let planes = png.bit_planes().unwrap();
+        assert_eq!(planes.len(), 16);
+        assert_eq!((planes[0].channel, planes[0].bit), ("grey", 0));
+        assert_eq!((planes[15].channel, planes[15].bit), ("alpha", 7));
+        let rows = |plane: &BitPlane| plane.png.scanlines().unwrap().into_data();
+        assert_eq!(rows(&planes[0]), vec![0b1110_0000; 2]);
+        assert_eq!(rows(&planes[1]), vec![0; 2]);
+        assert_eq!(rows(&planes[2]), vec![0b1110_0000; 2]);
+        assert_eq!(rows(&planes[14]), vec![0; 2]);
+        assert_eq!(rows(&planes[15]), vec![0b1110_0000; 2]);
+
+        assert_eq!(sample(&[0b1001_0011], 1, 2), 0b01);
+        assert_eq!(sample(&[0, 1, 2, 3], 1, 16), 0x0203);
+    }
+}
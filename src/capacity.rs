@@ -0,0 +1,285 @@
+use crate::chunk::Chunk;
+use crate::fragment::{DEFAULT_PART_LENGTH, HEADER_LENGTH};
+use crate::png::Png;
+use crate::stego;
+use crate::Error;
+use std::fmt;
+use std::str::FromStr;
+
+//Keyword of the tEXt chunks used by the text mode
+pub const TEXT_KEYWORD: &str = "Comment";
+
+//Where a payload gets hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingMode {
+    //Private ancillary chunks, split in parts when large
+    Chunk,
+    //Base64 values of tEXt chunks, which look like regular metadata
+    Text,
+    //Least significant bit of every colour sample
+    Lsb,
+    //Least significant bit of the alpha of fully opaque or transparent pixels
+    Alpha,
+    //Least significant bit of the palette entries of indexed images
+    Palette,
+    //Bytes appended after IEND, which decoders never read, see `trailing`
+    AfterIend,
+    //Private ancillary chunks after the data of every frame of an animated PNG
+    Frames,
+}
+
+impl EmbeddingMode {
+    pub const ALL: [EmbeddingMode; 7] = [
+        Self::Chunk,
+        Self::Text,
+        Self::Lsb,
+        Self::Alpha,
+        Self::Palette,
+        Self::AfterIend,
+        Self::Frames,
+    ];
+}
+
+impl FromStr for EmbeddingMode {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chunk" => Ok(Self::Chunk),
+            "text" => Ok(Self::Text),
+            "lsb" => Ok(Self::Lsb),
+            "alpha" => Ok(Self::Alpha),
+            "palette" => Ok(Self::Palette),
+            "after-iend" => Ok(Self::AfterIend),
+            "frames" => Ok(Self::Frames),
+            _ => Err(Box::new(CapacityError::InvalidMode(s.to_string()))),
+        }
+    }
+}
+
+impl fmt::Display for EmbeddingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Chunk => "chunk",
+            Self::Text => "text",
+            Self::Lsb => "lsb",
+            Self::Alpha => "alpha",
+            Self::Palette => "palette",
+            Self::AfterIend => "after-iend",
+            Self::Frames => "frames",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    pub mode: EmbeddingMode,
+    //Largest payload in bytes, None when only limited by the file size
+    pub max_payload: Option<u64>,
+    //Largest payload a single chunk holds, None for pixel based modes
+    pub chunk_payload: Option<u64>,
+}
+
+impl Capacity {
+    /**
+     * Estimated number of bytes the file grows by when hiding `payload` bytes.
+     * LSB embedding leaves the pixel count unchanged but the hidden bits look like
+     * noise to the compressor, so the estimate is the worst case of one byte per byte.
+     */
+    pub fn growth(&self, payload: u64) -> u64 {
+        let metadata = Chunk::METADATA_LENGTH as u64;
+        match self.mode {
+            EmbeddingMode::Chunk => {
+                let part = DEFAULT_PART_LENGTH as u64;
+                if payload <= part {
+                    metadata + payload
+                } else {
+                    payload.div_ceil(part) * (metadata + HEADER_LENGTH as u64) + payload
+                }
+            }
+            EmbeddingMode::Text => {
+                let text = payload.div_ceil(3) * 4;
+                let chunks = text.div_ceil(text_chunk_length()).max(1);
+                chunks * (metadata + TEXT_KEYWORD.len() as u64 + 1) + text
+            }
+            EmbeddingMode::Lsb | EmbeddingMode::Alpha | EmbeddingMode::AfterIend => payload,
+            //The palette keeps its size
+            EmbeddingMode::Palette => 0,
+            //A single frame, every other one adds a chunk and a part header
+            EmbeddingMode::Frames => metadata + HEADER_LENGTH as u64 + payload,
+        }
+    }
+}
+
+//Characters of a tEXt value that fit in one chunk next to the keyword
+fn text_chunk_length() -> u64 {
+    Chunk::MAX_LENGTH as u64 - TEXT_KEYWORD.len() as u64 - 1
+}
+
+impl Png {
+    //How many bytes can be hidden in this image with the given mode
+    pub fn embedding_capacity(&self, mode: EmbeddingMode) -> Result<Capacity, Error> {
+        let (max_payload, chunk_payload) = match mode {
+            EmbeddingMode::Chunk => (None, Some(Chunk::MAX_LENGTH as u64)),
+            EmbeddingMode::Text => (None, Some(text_chunk_length() / 4 * 3)),
+            //Nothing fits in the pixels of images the LSB mode doesn't support
+            EmbeddingMode::Lsb => (Some(stego::capacity(&self.header()?).unwrap_or(0)), None),
+            EmbeddingMode::Alpha => (Some(self.alpha_capacity().unwrap_or(0)), None),
+            EmbeddingMode::Palette => (Some(self.palette_capacity().unwrap_or(0)), None),
+            EmbeddingMode::AfterIend => (None, None),
+            EmbeddingMode::Frames if !self.is_animated() => (Some(0), None),
+            EmbeddingMode::Frames => (None, Some(Chunk::MAX_LENGTH as u64)),
+        };
+        Ok(Capacity {
+            mode,
+            max_payload,
+            chunk_payload,
+        })
+    }
+}
+
+/**
+ * What the pixels of an image offer to the LSB mode, to compare carriers: how many
+ * colour samples can hold hidden bits, and the payload and distortion when each
+ * holds 1, 2 or 4 of them.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LsbCapacity {
+    pub bit_depth: u8,
+    //Colour channels per pixel, alpha is left alone
+    pub channels: u8,
+    pub samples: u64,
+}
+
+impl LsbCapacity {
+    //Bits per channel the report covers, encode --mode lsb uses 1
+    pub const BITS_PER_CHANNEL: [u8; 3] = [1, 2, 4];
+
+    //Largest payload in bytes with this many bits in every sample, 0 when not even the
+    //length header fits, `psnr` then gives None for it
+    pub fn max_payload(&self, bits_per_channel: u8) -> u64 {
+        (self.samples * bits_per_channel as u64 / 8).saturating_sub(stego::LENGTH_HEADER as u64)
+    }
+
+    /**
+     * Expected peak signal-to-noise ratio of the colour samples, in dB, once
+     * `payload` bytes are hidden with this many bits per channel. The hidden bits
+     * look random, so each sample they replace has a mean squared error of
+     * (4^bits - 1) / 6. None when the payload doesn't fit, always so without samples.
+     */
+    pub fn psnr(&self, bits_per_channel: u8, payload: u64) -> Option<f64> {
+        let bits = (payload + stego::LENGTH_HEADER as u64) * 8;
+        let used = bits.div_ceil(bits_per_channel as u64);
+        if used > self.samples {
+            return None;
+        }
+        let error = ((1u64 << (2 * bits_per_channel)) - 1) as f64 / 6.0;
+        let mse = error * used as f64 / self.samples as f64;
+        let peak = ((1u64 << self.bit_depth) - 1) as f64;
+        Some(10.0 * (peak * peak / mse).log10())
+    }
+}
+
+impl Png {
+    //Fails for images the LSB mode doesn't support, indexed or under 8 bits
+    pub fn lsb_capacity(&self) -> Result<LsbCapacity, Error> {
+        let header = self.header()?;
+        let color_type = header.color_type;
+        Ok(LsbCapacity {
+            bit_depth: header.bit_depth,
+            channels: color_type.channels() - color_type.has_alpha() as u8,
+            samples: stego::sample_count(&header)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum CapacityError {
+    InvalidMode(String),
+}
+
+impl std::error::Error for CapacityError {}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMode(mode) => write!(
+                f,
+                "invalid embedding mode: {}, expected chunk, text, lsb, alpha, palette, after-iend or frames",
+                mode
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, Ihdr};
+    use crate::png::PngBuilder;
+
+    #[test]
+    pub fn test_chunk_capacity() {
+        let png = PngBuilder::new(4, 4).build().unwrap();
+        let capacity = png.embedding_capacity(EmbeddingMode::Chunk).unwrap();
+        assert_eq!(capacity.max_payload, None);
+        assert_eq!(capacity.growth(100), 112);
+        let parts = (DEFAULT_PART_LENGTH * 2) as u64;
+        assert_eq!(capacity.growth(parts), parts + 2 * 24);
+    }
+
+    #[test]
+    pub fn test_text_capacity() {
+        let png = PngBuilder::new(4, 4).build().unwrap();
+        let capacity = png.embedding_capacity(EmbeddingMode::Text).unwrap();
+        //12 bytes of chunk metadata, "Comment\0" and 8 characters of base64
+        assert_eq!(capacity.growth(6), 12 + 8 + 8);
+    }
+
+    #[test]
+    pub fn test_lsb_capacity() {
+        let png = PngBuilder::new(4, 4)
+            .color(&[1, 2, 3, 255])
+            .build()
+            .unwrap();
+        let capacity = png.embedding_capacity(EmbeddingMode::Lsb).unwrap();
+        //The payload length takes 4 bytes
+        assert_eq!(capacity.max_payload, Some(4 * 4 * 3 / 8 - 4));
+
+        let indexed = Png::from_chunks(vec![Ihdr::new(4, 4, 8, ColorType::Indexed).to_chunk()]);
+        let capacity = indexed.embedding_capacity(EmbeddingMode::Lsb).unwrap();
+        assert_eq!(capacity.max_payload, Some(0));
+    }
+
+    #[test]
+    pub fn test_lsb_report() {
+        let png = PngBuilder::new(32, 32).build().unwrap();
+        let capacity = png.lsb_capacity().unwrap();
+        assert_eq!((capacity.bit_depth, capacity.channels), (8, 3));
+        let full = png.embedding_capacity(EmbeddingMode::Lsb).unwrap();
+        assert_eq!(Some(capacity.max_payload(1)), full.max_payload);
+        assert_eq!(capacity.max_payload(4), 32 * 32 * 3 / 2 - 4);
+        //Every low bit replaced by a random one, an error of 0.5 squared steps
+        let psnr = capacity.psnr(1, capacity.max_payload(1)).unwrap();
+        assert!((psnr - 51.14).abs() < 0.01);
+        assert!(capacity.psnr(1, 10).unwrap() > psnr);
+        assert!(capacity.psnr(4, 10) < capacity.psnr(1, 10));
+        assert_eq!(capacity.psnr(1, capacity.max_payload(1) + 1), None);
+        let empty = LsbCapacity {
+            samples: 0,
+            ..capacity
+        };
+        assert_eq!((empty.max_payload(4), empty.psnr(4, 0)), (0, None));
+
+        let indexed = Png::from_chunks(vec![Ihdr::new(4, 4, 8, ColorType::Indexed).to_chunk()]);
+        assert!(indexed.lsb_capacity().is_err());
+    }
+
+    #[test]
+    pub fn test_mode_from_str() {
+        for mode in EmbeddingMode::ALL {
+            assert_eq!(EmbeddingMode::from_str(&mode.to_string()).unwrap(), mode);
+        }
+        assert!(EmbeddingMode::from_str("exif").is_err());
+    }
+}
@@ -0,0 +1,362 @@
+use crate::chunk_type::ChunkType;
+use crate::Error;
+use bytes::Bytes;
+use crc::{Crc, CRC_32_ISO_HDLC};
+use std::fmt;
+use std::ops::Range;
+
+//CRC-32 used by the PNG spec, computed over the chunk type and chunk data bytes
+pub const CRC_32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    length: u32,
+    chunk_type: ChunkType,
+    //Shared so chunks parsed from a buffer can point into it instead of copying
+    data: Bytes,
+    crc: u32,
+}
+
+impl TryFrom<&[u8]> for Chunk {
+    type Error = Error;
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let (chunk_type, data, crc) = Self::parse_fields(bytes)?;
+        if data.end + 4 != bytes.len() {
+            return Err(Box::new(ChunkError::Truncated(bytes.len())));
+        }
+        Ok(Self::from_parts(
+            chunk_type,
+            Bytes::copy_from_slice(&bytes[data]),
+            crc,
+        ))
+    }
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chunk {{")?;
+        writeln!(f, "  Length: {}", self.length())?;
+        writeln!(f, "  Type: {}", self.chunk_type())?;
+        writeln!(f, "  Data: {} bytes", self.data().len())?;
+        if self.is_crc_valid() {
+            writeln!(f, "  Crc: {}", self.crc())?;
+        } else {
+            writeln!(f, "  Crc: {} (invalid)", self.crc())?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Chunk {
+    //Length, chunk type and CRC fields, 4 bytes each
+    pub const METADATA_LENGTH: usize = 12;
+    //Chunk lengths are limited to 2^31 - 1 bytes by the spec
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        let crc = Self::checksum(&chunk_type, &data);
+        Self::from_parts(chunk_type, Bytes::from(data), crc)
+    }
+
+    /**
+     * Parses the chunk at the start of `bytes`, which may be followed by more data.
+     * The chunk data is a view into the same buffer, no bytes are copied.
+     * Returns the chunk along with the total number of bytes it takes.
+     */
+    pub fn from_shared(bytes: &Bytes) -> Result<(Chunk, usize), Error> {
+        let (chunk_type, data, crc) = Self::parse_fields(bytes)?;
+        let size = data.end + 4;
+        Ok((Self::from_parts(chunk_type, bytes.slice(data), crc), size))
+    }
+
+    //Reads the fields of the chunk at the start of `bytes`, returning where its data lies
+    fn parse_fields(bytes: &[u8]) -> Result<(ChunkType, Range<usize>, u32), Error> {
+        if bytes.len() < Self::METADATA_LENGTH {
+            return Err(Box::new(ChunkError::Truncated(bytes.len())));
+        }
+        let length = u32::from_be_bytes(bytes[0..4].try_into()?);
+        if length > Self::MAX_LENGTH {
+            return Err(Box::new(ChunkError::InvalidLength(length)));
+        }
+        let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&bytes[4..8])?)?;
+
+        let data = 8..8 + length as usize;
+        if bytes.len() < data.end + 4 {
+            return Err(Box::new(ChunkError::Truncated(bytes.len())));
+        }
+        let crc = u32::from_be_bytes(bytes[data.end..data.end + 4].try_into()?);
+        Ok((chunk_type, data, crc))
+    }
+
+    //Builds a chunk keeping the CRC exactly as it was read from the file
+    pub(crate) fn from_parts(chunk_type: ChunkType, data: Bytes, crc: u32) -> Chunk {
+        Chunk {
+            length: data.len() as u32,
+            chunk_type,
+            data,
+            crc,
+        }
+    }
+
+    //Computes the CRC of the given chunk type and data
+    pub fn checksum(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+        let mut digest = CRC_32.digest();
+        digest.update(&chunk_type.bytes());
+        digest.update(data);
+        digest.finalize()
+    }
+
+    //Length of the data field, not of the whole chunk
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    //The data buffer itself, cloning it doesn't copy the data
+    pub(crate) fn shared_data(&self) -> &Bytes {
+        &self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    //Replaces the data, keeping length and CRC in sync with it
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.length = data.len() as u32;
+        self.crc = Self::checksum(&self.chunk_type, &data);
+        self.data = Bytes::from(data);
+    }
+
+    //Checks the stored CRC against the one computed from the chunk type and data
+    pub fn is_crc_valid(&self) -> bool {
+        self.crc == Self::checksum(&self.chunk_type, &self.data)
+    }
+
+    //Errors if the stored CRC doesn't match the chunk contents
+    pub fn verify_crc(&self) -> Result<(), Error> {
+        let expected = Self::checksum(&self.chunk_type, &self.data);
+        if self.crc != expected {
+            return Err(Box::new(ChunkError::InvalidCrc {
+                chunk_type: self.chunk_type.to_string(),
+                expected,
+                actual: self.crc,
+            }));
+        }
+        Ok(())
+    }
+
+    //Recomputes the CRC, returns true if the stored one was wrong
+    pub fn repair_crc(&mut self) -> bool {
+        let expected = Self::checksum(&self.chunk_type, &self.data);
+        let repaired = self.crc != expected;
+        self.crc = expected;
+        repaired
+    }
+
+    pub fn data_as_string(&self) -> Result<String, Error> {
+        Ok(String::from_utf8(self.data.to_vec())?)
+    }
+
+    //Returns the chunk as it is laid out in a PNG file: length, type, data, CRC
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.length
+            .to_be_bytes()
+            .iter()
+            .chain(self.chunk_type.bytes().iter())
+            .chain(self.data.iter())
+            .chain(self.crc.to_be_bytes().iter())
+            .copied()
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum ChunkError {
+    InvalidLength(u32),
+    Truncated(usize),
+    InvalidCrc {
+        chunk_type: String,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl std::error::Error for ChunkError {}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength(length) => {
+                write!(f, "chunk length exceeds 2^31 - 1, current: {}", length)
+            }
+            Self::Truncated(length) => write!(f, "chunk is truncated, bytes left: {}", length),
+            Self::InvalidCrc {
+                chunk_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "invalid crc for {} chunk, expected: {}, current: {} (run `pngme repair`)",
+                chunk_type, expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_chunk() -> Chunk {
+        let data_length: u32 = 42;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        let crc: u32 = 2882656334;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        Chunk::try_from(chunk_data.as_ref()).unwrap()
+    }
+
+    #[test]
+    pub fn test_new_chunk() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = "This is where your secret message will be!"
+            .as_bytes()
+            .to_vec();
+        let chunk = Chunk::new(chunk_type, data);
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    pub fn test_chunk_length() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.length(), 42);
+    }
+
+    #[test]
+    pub fn test_chunk_type() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.chunk_type().to_string(), String::from("RuSt"));
+    }
+
+    #[test]
+    pub fn test_chunk_string() {
+        let chunk = testing_chunk();
+        let chunk_string = chunk.data_as_string().unwrap();
+        let expected_chunk_string = String::from("This is where your secret message will be!");
+        assert_eq!(chunk_string, expected_chunk_string);
+    }
+
+    #[test]
+    pub fn test_chunk_crc() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    pub fn test_valid_crc() {
+        let chunk = testing_chunk();
+        assert!(chunk.is_crc_valid());
+        assert!(chunk.verify_crc().is_ok());
+    }
+
+    #[test]
+    pub fn test_invalid_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let chunk = Chunk::try_from(bytes.as_ref()).unwrap();
+        assert!(!chunk.is_crc_valid());
+        assert!(chunk.verify_crc().is_err());
+    }
+
+    #[test]
+    pub fn test_repair_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[10] = b'X';
+        let mut chunk = Chunk::try_from(bytes.as_ref()).unwrap();
+        assert!(chunk.repair_crc());
+        assert!(chunk.is_crc_valid());
+        assert!(!chunk.repair_crc());
+    }
+
+    #[test]
+    pub fn test_set_data() {
+        let mut chunk = testing_chunk();
+        chunk.set_data(b"New message".to_vec());
+        assert_eq!(chunk.length(), 11);
+        assert_eq!(chunk.data(), b"New message");
+        assert!(chunk.is_crc_valid());
+        let expected = Chunk::new(
+            ChunkType::from_str("RuSt").unwrap(),
+            b"New message".to_vec(),
+        );
+        assert_eq!(chunk.crc(), expected.crc());
+    }
+
+    #[test]
+    pub fn test_chunk_as_bytes_round_trip() {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+        let actual = Chunk::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(actual.as_bytes(), bytes);
+    }
+
+    #[test]
+    pub fn test_truncated_chunk() {
+        let bytes = testing_chunk().as_bytes();
+        let chunk = Chunk::try_from(&bytes[..bytes.len() - 1]);
+        assert!(chunk.is_err());
+
+        let chunk = Chunk::try_from(&bytes[..8]);
+        assert!(chunk.is_err());
+    }
+
+    #[test]
+    pub fn test_invalid_length() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[0] = 0x80;
+        let chunk = Chunk::try_from(bytes.as_ref());
+        assert!(chunk.is_err());
+    }
+
+    #[test]
+    pub fn test_from_shared_is_zero_copy() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes.extend_from_slice(b"trailing");
+        let bytes = Bytes::from(bytes);
+        let (chunk, size) = Chunk::from_shared(&bytes).unwrap();
+        assert_eq!(size, bytes.len() - 8);
+        assert_eq!(
+            &chunk.data_as_string().unwrap(),
+            "This is where your secret message will be!"
+        );
+        let start = bytes.as_ptr() as usize;
+        let data = chunk.data().as_ptr() as usize;
+        assert!(data >= start && data < start + bytes.len());
+    }
+
+    #[test]
+    pub fn test_chunk_trait_impls() {
+        let chunk = testing_chunk();
+        let _chunk_string = format!("{}", chunk);
+    }
+}
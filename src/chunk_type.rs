@@ -47,6 +47,64 @@ impl PartialEq for ChunkType {
 
 impl Eq for ChunkType {}
 
+/**
+ * Chunk types registered by the PNG specification and its extensions.
+ * Names keep the exact spec casing, since the case of each letter carries the property bits.
+ */
+#[allow(non_upper_case_globals)]
+impl ChunkType {
+    //Critical chunks
+    pub const IHDR: ChunkType = ChunkType::from_static(b"IHDR");
+    pub const PLTE: ChunkType = ChunkType::from_static(b"PLTE");
+    pub const IDAT: ChunkType = ChunkType::from_static(b"IDAT");
+    pub const IEND: ChunkType = ChunkType::from_static(b"IEND");
+
+    //Colour space information
+    pub const cHRM: ChunkType = ChunkType::from_static(b"cHRM");
+    pub const gAMA: ChunkType = ChunkType::from_static(b"gAMA");
+    pub const iCCP: ChunkType = ChunkType::from_static(b"iCCP");
+    pub const sBIT: ChunkType = ChunkType::from_static(b"sBIT");
+    pub const sRGB: ChunkType = ChunkType::from_static(b"sRGB");
+    pub const cICP: ChunkType = ChunkType::from_static(b"cICP");
+    pub const mDCV: ChunkType = ChunkType::from_static(b"mDCV");
+    pub const cLLI: ChunkType = ChunkType::from_static(b"cLLI");
+
+    //Transparency
+    pub const tRNS: ChunkType = ChunkType::from_static(b"tRNS");
+
+    //Textual information
+    pub const tEXt: ChunkType = ChunkType::from_static(b"tEXt");
+    pub const zTXt: ChunkType = ChunkType::from_static(b"zTXt");
+    pub const iTXt: ChunkType = ChunkType::from_static(b"iTXt");
+
+    //Time stamp
+    pub const tIME: ChunkType = ChunkType::from_static(b"tIME");
+
+    //Miscellaneous information
+    pub const bKGD: ChunkType = ChunkType::from_static(b"bKGD");
+    pub const hIST: ChunkType = ChunkType::from_static(b"hIST");
+    pub const pHYs: ChunkType = ChunkType::from_static(b"pHYs");
+    pub const sPLT: ChunkType = ChunkType::from_static(b"sPLT");
+    pub const eXIf: ChunkType = ChunkType::from_static(b"eXIf");
+
+    //Animation (APNG)
+    pub const acTL: ChunkType = ChunkType::from_static(b"acTL");
+    pub const fcTL: ChunkType = ChunkType::from_static(b"fcTL");
+    pub const fdAT: ChunkType = ChunkType::from_static(b"fdAT");
+
+    //Registered extensions
+    pub const oFFs: ChunkType = ChunkType::from_static(b"oFFs");
+    pub const pCAL: ChunkType = ChunkType::from_static(b"pCAL");
+    pub const sCAL: ChunkType = ChunkType::from_static(b"sCAL");
+    pub const gIFg: ChunkType = ChunkType::from_static(b"gIFg");
+    pub const gIFx: ChunkType = ChunkType::from_static(b"gIFx");
+    pub const sTER: ChunkType = ChunkType::from_static(b"sTER");
+
+    const fn from_static(bytes: &[u8; 4]) -> ChunkType {
+        ChunkType { chunks: *bytes }
+    }
+}
+
 impl ChunkType {
     //Returns the raw bytes contained in this chunk
     pub fn bytes(&self) -> [u8; 4] {
@@ -74,20 +132,14 @@ impl ChunkType {
      * Chunks that are neccesary for successfull display of the file's content are called "critical chunks"
      */
     pub fn is_critical(&self) -> bool {
-        match self.chunks[0] >> 5 & 0x1 {
-            0 => true,
-            _ => false,
-        }
+        matches!(self.chunks[0] >> 5 & 0x1, 0)
     }
     /**
      * Private bit: bit 5 of third byte
      * 0 (uppercase) = public, 1 (lowecase) = private
      */
     pub fn is_public(&self) -> bool {
-        match self.chunks[1] >> 5 & 0x1 {
-            0 => true,
-            _ => false,
-        }
+        matches!(self.chunks[1] >> 5 & 0x1, 0)
     }
 
     /**
@@ -95,10 +147,7 @@ impl ChunkType {
      * Must be 0 (uppercase) in files conforming to this version of PNG.
      */
     pub fn is_reserved_bit_valid(&self) -> bool {
-        match self.chunks[2] >> 5 & 0x1 {
-            0 => true,
-            _ => false,
-        }
+        matches!(self.chunks[2] >> 5 & 0x1, 0)
     }
 
     /**
@@ -106,11 +155,139 @@ impl ChunkType {
      * 0 (uppercase) = unsafe to copy, 1 (lowercase) = safe to copy.
      */
     pub fn is_safe_to_copy(&self) -> bool {
-        match self.chunks[3] >> 5 & 0x1 {
-            1 => true,
-            _ => false,
+        matches!(self.chunks[3] >> 5 & 0x1, 1)
+    }
+
+    //Returns which group of the spec this chunk type belongs to
+    pub fn category(&self) -> ChunkCategory {
+        match &self.chunks {
+            b"IHDR" | b"PLTE" | b"IDAT" | b"IEND" => ChunkCategory::Critical,
+            b"cHRM" | b"gAMA" | b"iCCP" | b"sBIT" | b"sRGB" | b"cICP" | b"mDCV" | b"cLLI" => {
+                ChunkCategory::ColorSpace
+            }
+            b"tRNS" => ChunkCategory::Transparency,
+            b"tEXt" | b"zTXt" | b"iTXt" => ChunkCategory::Text,
+            b"tIME" => ChunkCategory::Time,
+            b"bKGD" | b"hIST" | b"pHYs" | b"sPLT" | b"eXIf" => ChunkCategory::Miscellaneous,
+            b"acTL" | b"fcTL" | b"fdAT" => ChunkCategory::Animation,
+            b"oFFs" | b"pCAL" | b"sCAL" | b"gIFg" | b"gIFx" | b"sTER" => ChunkCategory::Extension,
+            _ if self.is_public() => ChunkCategory::Unknown,
+            _ => ChunkCategory::Private,
         }
     }
+
+    //Where the spec allows this chunk type to appear relative to the other chunks
+    pub(crate) fn ordering(&self) -> ChunkOrdering {
+        match &self.chunks {
+            b"IHDR" => ChunkOrdering::First,
+            b"IEND" => ChunkOrdering::Last,
+            b"PLTE" => ChunkOrdering::Palette,
+            b"IDAT" => ChunkOrdering::Data,
+            b"cHRM" | b"gAMA" | b"iCCP" | b"sBIT" | b"sRGB" | b"cICP" | b"mDCV" | b"cLLI" => {
+                ChunkOrdering::BeforePalette
+            }
+            b"tRNS" | b"bKGD" | b"hIST" => ChunkOrdering::AfterPalette,
+            b"pHYs" | b"sPLT" | b"eXIf" | b"acTL" | b"oFFs" | b"pCAL" | b"sCAL" | b"sTER" => {
+                ChunkOrdering::BeforeData
+            }
+            b"fcTL" | b"fdAT" => ChunkOrdering::Frame,
+            _ => ChunkOrdering::Anywhere,
+        }
+    }
+
+    //Chunk types the spec allows at most once per file
+    pub fn is_unique(&self) -> bool {
+        matches!(
+            &self.chunks,
+            b"IHDR"
+                | b"PLTE"
+                | b"IEND"
+                | b"cHRM"
+                | b"gAMA"
+                | b"iCCP"
+                | b"sBIT"
+                | b"sRGB"
+                | b"cICP"
+                | b"mDCV"
+                | b"cLLI"
+                | b"tRNS"
+                | b"bKGD"
+                | b"hIST"
+                | b"pHYs"
+                | b"tIME"
+                | b"eXIf"
+                | b"acTL"
+                | b"oFFs"
+                | b"pCAL"
+                | b"sCAL"
+                | b"sTER"
+        )
+    }
+
+    //True for chunk types defined by the spec or its registered extensions
+    pub fn is_registered(&self) -> bool {
+        !matches!(
+            self.category(),
+            ChunkCategory::Private | ChunkCategory::Unknown
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkCategory {
+    Critical,
+    ColorSpace,
+    Transparency,
+    Text,
+    Time,
+    Miscellaneous,
+    Animation,
+    Extension,
+    //Not registered, with the private bit set
+    Private,
+    //Not registered, but claiming to be public
+    Unknown,
+}
+
+impl fmt::Display for ChunkCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Critical => "critical",
+            Self::ColorSpace => "colour space",
+            Self::Transparency => "transparency",
+            Self::Text => "text",
+            Self::Time => "time",
+            Self::Miscellaneous => "miscellaneous",
+            Self::Animation => "animation",
+            Self::Extension => "extension",
+            Self::Private => "private",
+            Self::Unknown => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//Position constraints of the PNG chunk ordering rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChunkOrdering {
+    //IHDR
+    First,
+    //IEND
+    Last,
+    //PLTE, before IDAT and the chunks that depend on the palette
+    Palette,
+    //IDAT, all of them consecutive
+    Data,
+    //Colour space chunks, before PLTE and IDAT
+    BeforePalette,
+    //tRNS, bKGD and hIST, after PLTE and before IDAT
+    AfterPalette,
+    //Before IDAT
+    BeforeData,
+    //APNG fcTL and fdAT, in sequence order, fdAT after IDAT
+    Frame,
+    //Anywhere between IHDR and IEND
+    Anywhere,
 }
 
 #[derive(Debug)]
@@ -220,6 +397,33 @@ mod tests {
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_chunk_type_constants() {
+        assert_eq!(ChunkType::IHDR, ChunkType::from_str("IHDR").unwrap());
+        assert_eq!(&ChunkType::tEXt.to_string(), "tEXt");
+        assert!(ChunkType::IEND.is_critical());
+        assert!(!ChunkType::pHYs.is_critical());
+    }
+
+    #[test]
+    pub fn test_chunk_type_category() {
+        assert_eq!(ChunkType::IDAT.category(), ChunkCategory::Critical);
+        assert_eq!(ChunkType::gAMA.category(), ChunkCategory::ColorSpace);
+        assert_eq!(ChunkType::zTXt.category(), ChunkCategory::Text);
+        assert_eq!(ChunkType::fcTL.category(), ChunkCategory::Animation);
+        let chunk = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(chunk.category(), ChunkCategory::Private);
+        let chunk = ChunkType::from_str("rUSt").unwrap();
+        assert_eq!(chunk.category(), ChunkCategory::Unknown);
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_registered() {
+        assert!(ChunkType::IHDR.is_registered());
+        assert!(ChunkType::sCAL.is_registered());
+        assert!(!ChunkType::from_str("ruSt").unwrap().is_registered());
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
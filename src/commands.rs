@@ -0,0 +1,2954 @@
+use crate::args::{
+    AnalyzeArgs, BatchArgs, BitplanesArgs, CapacityArgs, ColorArgs, ColorCommand, CopyChunksArgs,
+    CoverArgs, DecodeArgs, DiffArgs, DpiArgs, DpiCommand, EncodeArgs, ExtArgs, ExtCommand,
+    ExtractArgs, GrepArgs, IccArgs, IccCommand, InspectArgs, ListArgs, LsPayloadsArgs, MetaArgs,
+    MetaCommand, MigrateArgs, NormalizeArgs, OptimizeArgs, OutputFormat, PolyglotArgs, PrintArgs,
+    RemoveArgs, RepairArgs, ScrubArgs, ShardArgs, StatsArgs, TimeArgs, TimeCommand, UnshardArgs,
+    ValidateArgs, VerifyArgs, VerifyPayloadArgs, XmpArgs, XmpCommand,
+};
+use crate::batch::{self, BatchError};
+#[cfg(feature = "keyring")]
+use crate::keychain::{KeySource, KeychainError};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+#[cfg(feature = "mmap")]
+use bytes::Bytes;
+use pngme::analyze::{self, Analysis};
+use pngme::ancillary::Extensions;
+use pngme::capacity::{EmbeddingMode, LsbCapacity};
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::crypto::{self, CryptoError, KdfParams, HMAC_MAGIC, SIGNED_MAGIC};
+use pngme::decoy;
+use pngme::diff::{self, ChunkChange, ChunkSummary};
+use pngme::fragment;
+use pngme::ihdr::Ihdr;
+use pngme::inspect;
+use pngme::metadata::{
+    Chromaticities, ColorInfo, Gamma, IccProfile, PhysicalDimensions, Timestamp,
+};
+use pngme::named;
+use pngme::ops::{self, EncodeOptions, Existing, Occurrence, ScrubOptions};
+use pngme::payload::{self, Encoding, Encryption, Envelope, ENVELOPE_MAGIC};
+use pngme::png::{Png, PngError};
+use pngme::polyglot;
+use pngme::redundancy;
+use pngme::schema::SchemaSet;
+use pngme::search;
+use pngme::shard::{self, Shard};
+use pngme::spread::{self, Manifest};
+use pngme::stats::Stats;
+use pngme::stego::{self, Channels};
+#[cfg(not(feature = "mmap"))]
+use pngme::stream::PngReader;
+#[cfg(feature = "mmap")]
+use pngme::stream::SharedPngReader;
+use pngme::template::MetadataTemplate;
+use pngme::text::{self, InternationalText, TextEntry, TextError};
+use pngme::trailing::{self, TrailingError};
+use pngme::validate::ValidationError;
+use pngme::xmp::XmpError;
+use pngme::Result;
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type FileReader = Box<dyn Iterator<Item = Result<Chunk>>>;
+type FileWriter = dyn Write;
+
+//`-` as a path stands for stdin or stdout
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+//Status messages go to stderr when stdout carries the PNG itself
+macro_rules! status {
+    ($output:expr, $($arg:tt)*) => {
+        if is_stdio($output) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+fn input(path: &Path) -> Result<Box<dyn Read>> {
+    if is_stdio(path) {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+//Chunks are streamed from disk so large files never need to be fully loaded
+#[cfg(not(feature = "mmap"))]
+fn open(path: &Path) -> Result<FileReader> {
+    let reader = PngReader::new(input(path)?)?;
+    Ok(Box::new(reader))
+}
+
+//Chunks point straight into the mapped file, the OS pages in only what gets used
+#[cfg(feature = "mmap")]
+fn open(path: &Path) -> Result<FileReader> {
+    //A pipe can't be mapped, it's read into memory instead
+    if is_stdio(path) {
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        return Ok(Box::new(SharedPngReader::new(Bytes::from(bytes))?));
+    }
+    let file = File::open(path)?;
+    //SAFETY: the file might be modified by another process while mapped, which is
+    //the documented caveat of memory-mapping. pngme itself never writes to a file it
+    //has mapped, rewrites go to a temporary file that replaces the original.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    let reader = SharedPngReader::new(Bytes::from_owner(map))?;
+    Ok(Box::new(reader))
+}
+
+/**
+ * Streams `input` into `output` through `f`.
+ * The result is written to a temporary file next to `output` and moved into place
+ * once complete, so `input` and `output` can be the same path.
+ * An output of `-` writes to stdout directly.
+ */
+pub(crate) fn rewrite<T, F>(input: &Path, output: &Path, f: F) -> Result<T>
+where
+    F: FnOnce(FileReader, &mut FileWriter) -> Result<T>,
+{
+    let reader = open(input)?;
+    if is_stdio(output) {
+        let mut writer = BufWriter::new(io::stdout().lock());
+        let result = f(reader, &mut writer)?;
+        writer.flush()?;
+        return Ok(result);
+    }
+    let temp_path = temp_path(output);
+    let result = File::create(&temp_path)
+        .map_err(|err| err.into())
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            let result = f(reader, &mut writer)?;
+            writer.into_inner()?.sync_all()?;
+            Ok(result)
+        })
+        .and_then(|result| {
+            if BACKUP.load(Ordering::Relaxed) && input == output {
+                let backup = backup_path(output);
+                fs::copy(output, &backup)?;
+                log::info!("kept a backup in {}", backup.display());
+            }
+            fs::rename(&temp_path, output)?;
+            Ok(result)
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+//Input and output sizes of a rewrite that only measured its output
+struct DryRun {
+    input_length: u64,
+    output_length: u64,
+}
+
+impl fmt::Display for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "File size: {} -> {} bytes ({:+})",
+            self.input_length,
+            self.output_length,
+            self.output_length as i64 - self.input_length as i64
+        )
+    }
+}
+
+//Counts the bytes written and throws them away
+#[derive(Default)]
+struct Measure(u64);
+
+impl Write for Measure {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+//Runs `f` like `rewrite` without writing anything, `output` is never touched
+fn dry_run<T, F>(input: &Path, f: F) -> Result<(T, DryRun)>
+where
+    F: FnOnce(FileReader, &mut FileWriter) -> Result<T>,
+{
+    let input_length = Rc::new(Cell::new(Png::STANDARD_HEADER.len() as u64));
+    let counter = Rc::clone(&input_length);
+    let reader = open(input)?.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            counter.set(counter.get() + chunk.length() as u64 + Chunk::METADATA_LENGTH as u64);
+        }
+    });
+    let mut writer = Measure::default();
+    let result = f(Box::new(reader), &mut writer)?;
+    let dry_run = DryRun {
+        input_length: input_length.get(),
+        output_length: writer.0,
+    };
+    Ok((result, dry_run))
+}
+
+//`rewrite` or `dry_run`, depending on the flag
+fn apply<T, F>(input: &Path, output: &Path, dry: bool, f: F) -> Result<(T, Option<DryRun>)>
+where
+    F: FnOnce(FileReader, &mut FileWriter) -> Result<T>,
+{
+    if dry {
+        let (result, dry_run) = dry_run(input, f)?;
+        return Ok((result, Some(dry_run)));
+    }
+    Ok((rewrite(input, output, f)?, None))
+}
+
+//Whether files rewritten in place keep a copy of the original, see `set_backup`
+static BACKUP: AtomicBool = AtomicBool::new(false);
+
+pub fn set_backup(backup: bool) {
+    BACKUP.store(backup, Ordering::Relaxed);
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".bak");
+    path.with_file_name(file_name)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.pngme.tmp", file_name))
+}
+
+//Where a payload that isn't in chunks goes
+#[derive(Debug, Clone, Copy)]
+enum Carrier {
+    Pixels(Channels),
+    AfterIend,
+}
+
+//None for chunks, refusing the options that only make sense for chunks
+fn carrier(mode: EmbeddingMode, chunk_options: &[(bool, &'static str)]) -> Result<Option<Carrier>> {
+    let carrier = match mode {
+        //Frames puts chunks of its own where it needs them
+        EmbeddingMode::Chunk | EmbeddingMode::Frames => return Ok(None),
+        EmbeddingMode::Lsb => Carrier::Pixels(Channels::Colour),
+        EmbeddingMode::Alpha => Carrier::Pixels(Channels::Alpha),
+        EmbeddingMode::Palette => Carrier::Pixels(Channels::Palette),
+        EmbeddingMode::AfterIend => Carrier::AfterIend,
+        EmbeddingMode::Text => return Err(Box::new(CommandError::UnsupportedMode(mode))),
+    };
+    match chunk_options.iter().find(|(used, _)| *used) {
+        Some((_, option)) => Err(Box::new(CommandError::NotInChunks(mode, option))),
+        None => Ok(Some(carrier)),
+    }
+}
+
+//Where the payload goes, in chunks of a type or outside chunks altogether
+enum Target {
+    Chunks(ChunkType),
+    Carrier(Carrier),
+}
+
+//What the payload is encrypted with
+enum Key {
+    Password(Vec<u8>),
+    #[cfg(feature = "age")]
+    Recipients,
+}
+
+impl Key {
+    fn password(&self) -> Option<&[u8]> {
+        match self {
+            Key::Password(password) => Some(password),
+            #[cfg(feature = "age")]
+            Key::Recipients => None,
+        }
+    }
+}
+
+//How encode stores the sealed payload, with everything each way needs
+enum Placement {
+    //In chunks of the type, split into parts when long
+    Chunks(ChunkType),
+    //Under a name, next to the other named payloads of the type
+    Named(ChunkType, String),
+    //The hidden payload in the one chunk of its type among the decoys, next to the decoy
+    //payload in chunks of its own type
+    Deniable {
+        hidden: Chunk,
+        decoy_type: ChunkType,
+        decoy: Vec<u8>,
+    },
+    //The encrypted manifest in the --stealth chunk type, listing the pieces
+    Spread {
+        manifest: Chunk,
+        pieces: Vec<Chunk>,
+    },
+    //This many copies in chunks of the type, the pixels and after IEND
+    Redundant(ChunkType, u8),
+    //In chunks of the type after every frame
+    Frames(ChunkType),
+    Pixels(Channels),
+    AfterIend,
+}
+
+impl Placement {
+    //Writes the PNG of `reader` with the payload to `writer`, returning the chunks it replaced
+    fn write(
+        &self,
+        reader: FileReader,
+        writer: &mut FileWriter,
+        payload: &[u8],
+        options: &EncodeOptions,
+        password: Option<&[u8]>,
+    ) -> Result<Vec<Chunk>> {
+        match self {
+            Placement::Chunks(chunk_type) => {
+                ops::encode_payload_chunks(reader, writer, chunk_type, payload, options)
+            }
+            Placement::Named(chunk_type, name) => {
+                ops::encode_named_payload_chunks(reader, writer, chunk_type, name, payload, options)
+            }
+            Placement::Deniable {
+                hidden,
+                decoy_type,
+                decoy,
+            } => ops::encode_deniable_payload_chunks(
+                reader,
+                writer,
+                decoy_type,
+                decoy,
+                hidden.clone(),
+                options,
+            ),
+            Placement::Spread { manifest, pieces } => ops::encode_spread_payload_chunks(
+                reader,
+                writer,
+                manifest.clone(),
+                pieces.clone(),
+                options,
+            ),
+            Placement::Redundant(chunk_type, count) => store_redundant(
+                reader, writer, chunk_type, payload, *count, options, password,
+            ),
+            Placement::Frames(chunk_type) => {
+                embed_in_frames(reader, writer, chunk_type, payload, options)
+            }
+            Placement::Pixels(channels) => {
+                embed_in_pixels(reader, writer, payload, *channels, password)?;
+                Ok(Vec::new())
+            }
+            Placement::AfterIend => {
+                append_after_iend(reader, writer, payload)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    //The chunks `write` would add, or what it would do instead, for --dry-run
+    fn dry_run(&self, payload: &[u8], args: &EncodeArgs) -> Result<(Vec<Chunk>, Option<String>)> {
+        let added = match self {
+            Placement::Chunks(chunk_type) => fragment::split(chunk_type, payload, args.part_size)?,
+            Placement::Named(chunk_type, name) => {
+                named::split(chunk_type, name, payload, args.part_size)?
+            }
+            Placement::Deniable {
+                hidden,
+                decoy_type,
+                decoy,
+            } => {
+                let mut added = fragment::split(decoy_type, decoy, args.part_size)?;
+                added.push(hidden.clone());
+                added
+            }
+            Placement::Spread { manifest, pieces } => {
+                let mut added = vec![manifest.clone()];
+                added.extend(pieces.iter().cloned());
+                added
+            }
+            Placement::Redundant(_, count) => {
+                let description =
+                    format!("Would store {} copies of {} bytes", count, payload.len());
+                return Ok((Vec::new(), Some(description)));
+            }
+            Placement::Frames(_) => {
+                let description = format!(
+                    "Would spread {} bytes over the frames with --mode frames",
+                    payload.len()
+                );
+                return Ok((Vec::new(), Some(description)));
+            }
+            Placement::Pixels(_) | Placement::AfterIend => {
+                let description = format!(
+                    "Would hide {} bytes with --mode {}",
+                    payload.len(),
+                    args.mode
+                );
+                return Ok((Vec::new(), Some(description)));
+            }
+        };
+        Ok((added, None))
+    }
+}
+
+//None for chunks, refusing the options that only make sense for chunks placed by encode
+fn encode_carrier(args: &EncodeArgs) -> Result<Option<Carrier>> {
+    let placed = [
+        (args.stealth, "--stealth"),
+        (args.name.is_some(), "--name"),
+        (args.decoys > 0, "--decoys"),
+        (
+            args.decoy_message.is_some() || args.decoy_file.is_some(),
+            "--decoy-message",
+        ),
+        (args.append, "--append"),
+        (args.spread.is_some(), "--spread"),
+        (args.redundancy.is_some(), "--redundancy"),
+    ];
+    if args.mode == EmbeddingMode::Frames {
+        if let Some((_, option)) = placed.iter().find(|(used, _)| *used) {
+            return Err(Box::new(CommandError::NotWithFrames(option)));
+        }
+    }
+    //Frames replaces the chunks of its type, outside chunks there are none
+    let mut chunk_options = placed.to_vec();
+    chunk_options.push((args.replace, "--replace"));
+    carrier(args.mode, &chunk_options)
+}
+
+//The envelope and content of the message, the --file or stdin
+fn encode_content(
+    args: &EncodeArgs,
+    message: Option<&str>,
+    from_stdin: bool,
+) -> Result<(Envelope, Vec<u8>)> {
+    let mut envelope = Envelope {
+        mime: args.mime.clone(),
+        timestamp: (!args.no_timestamp && !args.deterministic).then(unix_time),
+        compression: args.compress,
+        ..Envelope::default()
+    };
+    let content = match (&args.file, message) {
+        _ if from_stdin => {
+            let mut message = Vec::new();
+            io::stdin().lock().read_to_end(&mut message)?;
+            args.encoding.decode(&message)?
+        }
+        (Some(path), _) => {
+            let name = file_name(path);
+            if envelope.mime.is_none() {
+                envelope.mime = payload::guess_mime(&name).map(String::from);
+            }
+            envelope.name = Some(name);
+            fs::read(path)?
+        }
+        (None, Some(message)) => args.encoding.decode(message.as_bytes())?,
+        (None, None) => Vec::new(),
+    };
+    Ok((envelope, content))
+}
+
+//The payload chunks of the type, or all of them, out of the PNG of `reader`
+fn take_replaced(
+    reader: FileReader,
+    chunk_type: &ChunkType,
+    options: &EncodeOptions,
+) -> Result<(Png, Vec<Chunk>)> {
+    let mut png = Png::from_chunks(reader.collect::<Result<_>>()?);
+    let replaced = png.remove_all_chunks(&chunk_type.to_string());
+    if !replaced.is_empty() && options.existing != Existing::Replace {
+        return Err(Box::new(PngError::ChunkExists(chunk_type.to_string())));
+    }
+    Ok((png, replaced))
+}
+
+fn embed_in_frames(
+    reader: FileReader,
+    writer: &mut FileWriter,
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    options: &EncodeOptions,
+) -> Result<Vec<Chunk>> {
+    let (mut png, replaced) = take_replaced(reader, chunk_type, options)?;
+    png.embed_in_frames(chunk_type, payload)?;
+    writer.write_all(&png.as_bytes())?;
+    Ok(replaced)
+}
+
+fn store_redundant(
+    reader: FileReader,
+    writer: &mut FileWriter,
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    count: u8,
+    options: &EncodeOptions,
+    password: Option<&[u8]>,
+) -> Result<Vec<Chunk>> {
+    let (png, replaced) = take_replaced(reader, chunk_type, options)?;
+    let (bytes, locations) = redundancy::store(png, chunk_type, payload, count, password)?;
+    let locations: Vec<String> = locations.iter().map(ToString::to_string).collect();
+    log::info!("stored copies in {}", locations.join(", "));
+    writer.write_all(&bytes)?;
+    Ok(replaced)
+}
+
+fn embed_in_pixels(
+    reader: FileReader,
+    writer: &mut FileWriter,
+    payload: &[u8],
+    channels: Channels,
+    password: Option<&[u8]>,
+) -> Result<()> {
+    let mut png = Png::from_chunks(reader.collect::<Result<_>>()?);
+    png.embed_lsb(payload, channels, password)?;
+    writer.write_all(&png.as_bytes())?;
+    Ok(())
+}
+
+fn append_after_iend(reader: FileReader, writer: &mut FileWriter, payload: &[u8]) -> Result<()> {
+    let png = Png::from_chunks(reader.collect::<Result<_>>()?);
+    //Chunks after IEND would be taken for part of the payload
+    let iend = png
+        .chunks()
+        .iter()
+        .position(|chunk| *chunk.chunk_type() == ChunkType::IEND);
+    if iend.is_some_and(|iend| iend + 1 < png.chunks().len()) {
+        return Err(Box::new(TrailingError::TrailingDataExists));
+    }
+    writer.write_all(&png.as_bytes())?;
+    writer.write_all(payload)?;
+    Ok(())
+}
+
+//Where the sealed payload goes, given the decoy it hides behind
+fn placement(
+    args: &EncodeArgs,
+    target: Target,
+    payload: &[u8],
+    password: Option<&[u8]>,
+    decoy: Option<(ChunkType, Vec<u8>)>,
+) -> Result<Placement> {
+    let chunk_type = match target {
+        Target::Carrier(Carrier::Pixels(channels)) => return Ok(Placement::Pixels(channels)),
+        Target::Carrier(Carrier::AfterIend) => return Ok(Placement::AfterIend),
+        Target::Chunks(chunk_type) => chunk_type,
+    };
+    if args.mode == EmbeddingMode::Frames {
+        return Ok(Placement::Frames(chunk_type));
+    }
+    if let Some(count) = args.redundancy {
+        return Ok(Placement::Redundant(chunk_type, count));
+    }
+    //The manifest of --spread takes the place of the payload in the --stealth chunk type
+    if let (Some(count), Some(password)) = (args.spread, password) {
+        let (pieces, manifest) = spread::spread(payload, count as usize)?;
+        let manifest = crypto::encrypt_bare(&manifest.to_bytes(), password, &KdfParams::default())?;
+        let manifest = Chunk::new(chunk_type, manifest);
+        return Ok(Placement::Spread { manifest, pieces });
+    }
+    Ok(match (&args.name, decoy) {
+        (Some(name), _) => Placement::Named(chunk_type, name.clone()),
+        (None, Some((decoy_type, decoy))) => Placement::Deniable {
+            hidden: Chunk::new(chunk_type, payload.to_vec()),
+            decoy_type,
+            decoy,
+        },
+        (None, None) => Placement::Chunks(chunk_type),
+    })
+}
+
+pub fn encode(args: EncodeArgs) -> Result<()> {
+    let carrier = encode_carrier(&args)?;
+    //With --stealth or outside chunks the first argument after the file path is the message.
+    //No target yet for --stealth, the chunk type is derived from the password
+    let given = args.message.is_some() || args.file.is_some() || args.stdin;
+    let (target, message) = match (carrier, args.stealth, &args.chunk_type) {
+        (Some(_), _, Some(_)) if given => {
+            let error = CommandError::NotInChunks(args.mode, "a chunk type");
+            return Err(Box::new(error));
+        }
+        (Some(carrier), _, message) => (Some(Target::Carrier(carrier)), message.clone()),
+        (None, true, Some(_)) if given => return Err(Box::new(CommandError::StealthWithChunkType)),
+        (None, true, message) => (None, message.clone()),
+        (None, false, chunk_type) => {
+            let chunk_type = ChunkType::from_str(chunk_type.as_deref().unwrap_or_default())?;
+            (Some(Target::Chunks(chunk_type)), args.message.clone())
+        }
+    };
+    let options = EncodeOptions {
+        part_length: args.part_size,
+        existing: match (args.replace, args.append) {
+            (true, _) => Existing::Replace,
+            (false, true) => Existing::Append,
+            (false, false) => Existing::Reject,
+        },
+        decoys: args.decoys,
+    };
+    let from_stdin = args.stdin || message.as_deref() == Some("-");
+    if from_stdin && is_stdio(&args.file_path) {
+        return Err(Box::new(CommandError::StdinUsedTwice));
+    }
+    let (mut envelope, content) = encode_content(&args, message.as_deref(), from_stdin)?;
+    let source = PasswordSource {
+        key_file: args.key_file.as_deref(),
+        #[cfg(feature = "keyring")]
+        key_from: args.key_from.as_ref(),
+    };
+    //--stealth always has a password, it requires --encrypt
+    let (key, target) = match target {
+        Some(target) if args.encrypt => (Some(Key::Password(password(true, &source)?)), target),
+        #[cfg(feature = "age")]
+        Some(target) if !args.recipient.is_empty() => (Some(Key::Recipients), target),
+        Some(target) => (None, target),
+        None => {
+            let password = password(true, &source)?;
+            let chunk_type = crypto::stealth_chunk_type(&password)?;
+            log::debug!("derived chunk type {}", chunk_type);
+            (Some(Key::Password(password)), Target::Chunks(chunk_type))
+        }
+    };
+    envelope.encryption = key.as_ref().map(|key| match key {
+        Key::Password(_) => Encryption::Password,
+        #[cfg(feature = "age")]
+        Key::Recipients => Encryption::Age,
+    });
+    let password = key.as_ref().and_then(Key::password);
+    let params = KdfParams {
+        memory_kib: args.kdf_memory,
+        iterations: args.kdf_iterations,
+        parallelism: args.kdf_parallelism,
+    };
+    let decoy = match password {
+        Some(password) => decoy_payload(&args, password, &params)?,
+        None => None,
+    };
+    //The real payload is then bare ciphertext of an envelope, with no trace of encryption
+    if decoy.is_some() {
+        envelope.encryption = None;
+    }
+    //The deterministic salt is only shared within a chunk type, or mode, and label
+    let context = format!(
+        "{}\0{}",
+        match &target {
+            Target::Chunks(chunk_type) => chunk_type.to_string(),
+            Target::Carrier(_) => args.mode.to_string(),
+        },
+        args.salt_label.as_deref().unwrap_or_default()
+    );
+    let mut payload = envelope.seal(&content, |body| match &key {
+        Some(Key::Password(password)) if args.deterministic => {
+            crypto::encrypt_deterministic(body, password, &params, context.as_bytes())
+        }
+        Some(Key::Password(password)) => crypto::encrypt(body, password, &params),
+        #[cfg(feature = "age")]
+        Some(Key::Recipients) => crypto::encrypt_to(body, &args.recipient),
+        //Never called, the envelope of a payload without a key is not encrypted
+        None => Ok(body.to_vec()),
+    })?;
+    //Padded to the length of a decoy
+    if let (Some((decoy_type, decoy)), Some(password)) = (&decoy, password) {
+        let decoy_chunks = fragment::split(decoy_type, decoy, args.part_size)?;
+        let needed = payload.len() + decoy::PADDING_LENGTH + crypto::BARE_OVERHEAD;
+        let length = decoy::hidden_length(&decoy_chunks, needed)? - crypto::BARE_OVERHEAD;
+        let padded = decoy::pad(&payload, length);
+        payload = crypto::encrypt_bare(&padded, password, &KdfParams::default())?;
+        if payload.len() > args.part_size {
+            let error = CommandError::HiddenTooLarge(payload.len(), args.part_size);
+            return Err(Box::new(error));
+        }
+    }
+    if let Some(key) = &args.hmac {
+        payload = crypto::append_hmac(&payload, key.as_bytes())?;
+    }
+    if let Some(key_file) = &args.sign {
+        let key = String::from_utf8(read_key_file(key_file)?)?;
+        payload = crypto::sign(&payload, &key)?;
+    }
+    let placement = placement(&args, target, &payload, password, decoy)?;
+    //Standard keywords of the spec
+    let mut text = Vec::new();
+    for (keyword, value) in [
+        ("Author", &args.author),
+        ("Copyright", &args.copyright),
+        ("Comment", &args.comment),
+    ] {
+        if let Some(value) = value {
+            text.push((keyword, text::text_chunk(keyword, value)?));
+        }
+    }
+    let files = batch::expand(&args.file_path)?;
+    let several = files.len() > 1;
+    if several && args.output.output.is_some() {
+        return Err(Box::new(BatchError::OutputWithSeveralFiles));
+    }
+    batch::for_each(&files, |file| {
+        let output = args.output.path(file);
+        //Reading the chunks would stumble on the bytes already there
+        let after_iend = matches!(placement, Placement::AfterIend | Placement::Redundant(..));
+        if after_iend && !is_stdio(file) {
+            let bytes = fs::read(file)?;
+            if !trailing::split(&bytes)?.1.is_empty() {
+                return Err(Box::new(TrailingError::TrailingDataExists));
+            }
+        }
+        let (replaced, dry_run) = apply(file, output, args.output.dry_run, |reader, writer| {
+            let reader: FileReader = if args.touch {
+                Box::new(ops::touch(reader, Timestamp::now()))
+            } else {
+                reader
+            };
+            let reader: FileReader = if text.is_empty() {
+                reader
+            } else {
+                let chunks = text.iter().map(|(_, chunk)| chunk.clone()).collect();
+                Box::new(ops::stamp_text(reader, chunks))
+            };
+            placement.write(reader, writer, &payload, &options, password)
+        })?;
+        if let Some(dry_run) = dry_run {
+            if several {
+                println!("{}:", file.display());
+            }
+            for chunk in &replaced {
+                println!(
+                    "Would remove {} chunk, {} bytes",
+                    chunk.chunk_type(),
+                    chunk.length()
+                );
+            }
+            let (added, description) = placement.dry_run(&payload, &args)?;
+            if let Some(description) = description {
+                println!("{}", description);
+            }
+            for chunk in &added {
+                println!(
+                    "Would add {} chunk, {} bytes",
+                    chunk.chunk_type(),
+                    chunk.length()
+                );
+            }
+            //A hidden payload needs several decoys to hide among
+            let decoys = match placement {
+                Placement::Deniable { .. } => args.decoys.max(decoy::MIN_HIDDEN_DECOYS),
+                _ => args.decoys,
+            };
+            if decoys > 0 {
+                println!("Would add {} decoy chunk(s) of random sizes", decoys);
+            }
+            if args.touch {
+                println!("Would set tIME to {}", Timestamp::now());
+            }
+            if !text.is_empty() {
+                let keywords: Vec<&str> = text.iter().map(|(keyword, _)| *keyword).collect();
+                println!("Would set {}", keywords.join(", "));
+            }
+            println!("{}", dry_run);
+        } else if several {
+            println!("{}: encoded", file.display());
+        }
+        Ok(())
+    })
+}
+
+//File name of `path`, empty when it has none
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/**
+ * The innocuous payload of --decoy-message or --decoy-file, sealed like any encrypted
+ * payload but under the decoy password, and the --stealth chunk type of that password.
+ */
+fn decoy_payload(
+    args: &EncodeArgs,
+    password: &[u8],
+    params: &KdfParams,
+) -> Result<Option<(ChunkType, Vec<u8>)>> {
+    let mut envelope = Envelope {
+        timestamp: (!args.no_timestamp).then(unix_time),
+        compression: args.compress,
+        encryption: Some(Encryption::Password),
+        ..Envelope::default()
+    };
+    let content = match (&args.decoy_message, &args.decoy_file) {
+        (Some(message), _) => args.encoding.decode(message.as_bytes())?,
+        (None, Some(path)) => {
+            let name = file_name(path);
+            envelope.mime = payload::guess_mime(&name).map(String::from);
+            envelope.name = Some(name);
+            fs::read(path)?
+        }
+        (None, None) => return Ok(None),
+    };
+    let decoy_password = ask_secret(true, "PNGME_DECOY_PASSWORD", "Decoy password")?;
+    if decoy_password == password {
+        return Err(Box::new(CommandError::DecoyPasswordReused));
+    }
+    let chunk_type = crypto::stealth_chunk_type(&decoy_password)?;
+    if chunk_type == crypto::stealth_chunk_type(password)? {
+        return Err(Box::new(CommandError::DecoyChunkType));
+    }
+    let payload = envelope.seal(&content, |body| {
+        crypto::encrypt(body, &decoy_password, params)
+    })?;
+    Ok(Some((chunk_type, payload)))
+}
+
+pub fn decode(args: DecodeArgs, format: OutputFormat) -> Result<()> {
+    let files = batch::files(&args.file_path, &args.batch)?;
+    let several = files.len() > 1;
+    let out = match &args.out {
+        Some(out) => Some(out.as_path()),
+        None if args.raw => Some(Path::new("-")),
+        None => None,
+    };
+    if several && out.is_some() {
+        return Err(Box::new(BatchError::OutputWithSeveralFiles));
+    }
+    //Asked for once, even when decoding several files
+    let secrets = Secrets::new(
+        args.decrypt,
+        &PasswordSource {
+            key_file: args.key_file.as_deref(),
+            #[cfg(feature = "keyring")]
+            key_from: args.key_from.as_ref(),
+        },
+        #[cfg(feature = "age")]
+        args.identity.as_deref(),
+        args.hmac.clone(),
+    )?;
+    let carrier = carrier(
+        args.mode,
+        &[
+            (args.chunk_type.is_some(), "a chunk type"),
+            (args.stealth, "--stealth"),
+            (args.name.is_some(), "--name"),
+            (args.redundant, "--redundant"),
+            (args.keyword.is_some(), "--keyword"),
+        ],
+    )?;
+    if let Some(language) = &args.lang {
+        text::validate_language(language)?;
+    }
+    //Empty outside chunks
+    let chunk_type = match (&args.chunk_type, &secrets.password) {
+        (Some(chunk_type), _) => chunk_type.clone(),
+        (None, Some(password)) if args.stealth => crypto::stealth_chunk_type(password)?.to_string(),
+        (None, _) => String::new(),
+    };
+    let schemas = load_schemas(args.schema.as_deref())?;
+    //Payloads of types without a schema are shown as usual
+    let schema = ChunkType::from_str(&chunk_type)
+        .ok()
+        .and_then(|chunk_type| schemas.get(&chunk_type));
+    batch::for_each(&files, |file| {
+        let payload = match carrier {
+            Some(Carrier::Pixels(channels)) => {
+                pixel_payload(file, args.recover, channels, secrets.password.as_deref())?
+            }
+            Some(Carrier::AfterIend) => trailing_data(file)?,
+            None => match &args.keyword {
+                Some(keyword) => select_text(file, keyword, args.lang.as_deref())?
+                    .text
+                    .into_bytes(),
+                None => decode_file(file, &chunk_type, &args, secrets.password.as_deref())?,
+            },
+        };
+        //Bare ciphertext in the --stealth chunk type is the manifest of encode --spread,
+        //or the envelope of the hidden payload of encode --decoy-message
+        let bare = args.stealth
+            && ![ENVELOPE_MAGIC, SIGNED_MAGIC, HMAC_MAGIC]
+                .iter()
+                .any(|magic| payload.starts_with(magic));
+        let (payload, hidden) = match &secrets.password {
+            Some(password) if bare => {
+                let revealed = crypto::decrypt_bare(&payload, password, &KdfParams::default())?;
+                match Manifest::parse(&revealed)? {
+                    Some(manifest) => {
+                        let png = Png::from_chunks(open(file)?.collect::<Result<_>>()?);
+                        (spread::gather(png.chunks(), &manifest)?, false)
+                    }
+                    None => (decoy::unpad(&revealed)?.to_vec(), true),
+                }
+            }
+            _ => (payload, false),
+        };
+        let payload = secrets.unwrap(file, payload)?;
+        //Payloads without envelope are shown as they are
+        let (envelope, content) = secrets
+            .open(&payload)?
+            .unwrap_or_else(|| (Envelope::default(), payload));
+        if secrets.password.is_some()
+            && !hidden
+            && envelope.encryption != Some(Encryption::Password)
+        {
+            return Err(Box::new(CryptoError::NotEncrypted));
+        }
+        if let Some(out) = out {
+            return write_payload(out, &args.encoding.encode(&content));
+        }
+        let fields = schema
+            .filter(|_| args.encoding == Encoding::Raw)
+            .map(|schema| schema.decode(&content))
+            .transpose()?;
+        if format == OutputFormat::Json {
+            let mut value = json!({
+                "type": chunk_type,
+                "length": content.len(),
+                "payload": BASE64.encode(&content),
+                "text": std::str::from_utf8(&content).ok(),
+                "name": envelope.name,
+                "mime": envelope.mime,
+                "timestamp": envelope.timestamp,
+            });
+            if several {
+                value["file"] = Value::String(file.display().to_string());
+            }
+            if let Some(fields) = fields {
+                value["fields"] = fields_json(fields);
+            }
+            println!("{}", value);
+            return Ok(());
+        }
+        if let Some(fields) = fields {
+            if several {
+                println!("{}:", file.display());
+            }
+            for (name, value) in fields {
+                println!("{}: {}", name, value);
+            }
+            return Ok(());
+        }
+        let text = match &envelope.name {
+            _ if args.encoding != Encoding::Raw => {
+                String::from_utf8(args.encoding.encode(&content))?
+            }
+            Some(name) => format!(
+                "file {} ({}), {} bytes, use --out to save it",
+                name,
+                envelope.mime.as_deref().unwrap_or("unknown type"),
+                content.len()
+            ),
+            None => std::str::from_utf8(&content)
+                .map_err(|_| CommandError::NotText)?
+                .to_string(),
+        };
+        if several {
+            println!("{}: {}", file.display(), text);
+        } else {
+            println!("{}", text);
+        }
+        Ok(())
+    })
+}
+
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    let payload = ops::decode_payload_chunks(open(&args.file_path)?, &args.chunk_type)?;
+    crypto::verify(&payload, &fs::read_to_string(&args.pubkey)?)?;
+    println!("Signature OK");
+    Ok(())
+}
+
+pub fn verify_payload(args: VerifyPayloadArgs) -> Result<()> {
+    let secrets = Secrets::new(
+        args.decrypt,
+        &PasswordSource {
+            key_file: args.key_file.as_deref(),
+            #[cfg(feature = "keyring")]
+            key_from: args.key_from.as_ref(),
+        },
+        #[cfg(feature = "age")]
+        args.identity.as_deref(),
+        args.hmac.clone(),
+    )?;
+    let chunks = open(&args.file_path)?;
+    let payload = match &args.name {
+        Some(name) => ops::decode_named_payload_chunks(chunks, &args.chunk_type, name)?,
+        None => ops::decode_payload_chunks(chunks, &args.chunk_type)?,
+    };
+    let payload = secrets.unwrap(&args.file_path, payload)?;
+    let checksum = match Envelope::parse(&payload)? {
+        Some(sealed) => sealed.checksum,
+        None => return Err(Box::new(CommandError::NoChecksum)),
+    };
+    //Fails on a checksum mismatch
+    let (_, content) = secrets
+        .open(&payload)?
+        .expect("the payload has an envelope");
+    println!("Payload OK, {} bytes, {}", content.len(), checksum);
+    Ok(())
+}
+
+//Keys for the layers around a payload, gathered once even when reading several files
+struct Secrets {
+    password: Option<Vec<u8>>,
+    #[cfg(feature = "age")]
+    identity_file: Option<Vec<u8>>,
+    hmac: Option<String>,
+}
+
+impl Secrets {
+    fn new(
+        decrypt: bool,
+        source: &PasswordSource,
+        #[cfg(feature = "age")] identity: Option<&Path>,
+        hmac: Option<String>,
+    ) -> Result<Secrets> {
+        let password = if decrypt {
+            Some(password(false, source)?)
+        } else {
+            None
+        };
+        Ok(Secrets {
+            password,
+            #[cfg(feature = "age")]
+            identity_file: match identity {
+                Some(path) => Some(read_key_file(path)?),
+                None => None,
+            },
+            hmac,
+        })
+    }
+
+    //Strips the signature and checks the HMAC tag, leaving the envelope
+    fn unwrap(&self, file: &Path, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+        //Checking the signature is left to verify
+        if let Some((_, signed)) = crypto::split_signature(&payload) {
+            log::info!(
+                "{}: payload is signed, verify checks the signature",
+                file.display()
+            );
+            payload = signed.to_vec();
+        }
+        match &self.hmac {
+            Some(key) => payload = crypto::check_hmac(&payload, key.as_bytes())?.to_vec(),
+            None if crypto::has_hmac(&payload) => {
+                return Err(Box::new(CryptoError::HmacKeyNeeded));
+            }
+            None => {}
+        }
+        Ok(payload)
+    }
+
+    //Also reads the payloads written before envelopes
+    fn open(&self, payload: &[u8]) -> Result<Option<(Envelope, Vec<u8>)>> {
+        match Envelope::open(payload, |encryption, body| self.decrypt(encryption, body))? {
+            Some(opened) => Ok(Some(opened)),
+            None => {
+                payload::open_legacy(payload, |encryption, body| self.decrypt(encryption, body))
+            }
+        }
+    }
+
+    fn decrypt(&self, encryption: Encryption, body: &[u8]) -> Result<Vec<u8>> {
+        match encryption {
+            Encryption::Password => match &self.password {
+                Some(password) => crypto::decrypt(body, password),
+                None => Err(Box::new(CryptoError::Encrypted)),
+            },
+            #[cfg(feature = "age")]
+            Encryption::Age => match &self.identity_file {
+                Some(identity_file) => crypto::decrypt_with(body, identity_file),
+                None => Err(Box::new(CryptoError::Encrypted)),
+            },
+            #[cfg(not(feature = "age"))]
+            Encryption::Age => Err(Box::new(CryptoError::Encrypted)),
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+//Where the password comes from instead of PNGME_PASSWORD or the prompt
+struct PasswordSource<'a> {
+    key_file: Option<&'a Path>,
+    #[cfg(feature = "keyring")]
+    key_from: Option<&'a KeySource>,
+}
+
+/**
+ * From `source` or PNGME_PASSWORD, otherwise prompted for without echo on the
+ * terminal, even when stdin is redirected. `confirm` asks twice and refuses an empty
+ * password, for encode, which also stores a password missing from the keychain.
+ */
+fn password(confirm: bool, source: &PasswordSource) -> Result<Vec<u8>> {
+    #[cfg(feature = "keyring")]
+    if let Some(key_from) = source.key_from {
+        if let Some(password) = key_from.get()? {
+            return Ok(password);
+        }
+        if !confirm {
+            return Err(Box::new(KeychainError::NotFound(key_from.clone())));
+        }
+        let password = ask_password(true)?;
+        key_from.store(&password)?;
+        log::info!("stored the password in {}", key_from);
+        return Ok(password);
+    }
+    if let Some(path) = source.key_file {
+        let mut key = read_key_file(path)?;
+        //Left by editors and echo, not part of the key
+        if key.ends_with(b"\n") {
+            key.pop();
+            if key.ends_with(b"\r") {
+                key.pop();
+            }
+        }
+        if key.is_empty() {
+            return Err(Box::new(CommandError::EmptyPassword));
+        }
+        return Ok(key);
+    }
+    ask_password(confirm)
+}
+
+fn ask_password(confirm: bool) -> Result<Vec<u8>> {
+    ask_secret(confirm, "PNGME_PASSWORD", "Password")
+}
+
+//From the `variable` environment variable, otherwise prompted for as `name`
+fn ask_secret(confirm: bool, variable: &str, name: &str) -> Result<Vec<u8>> {
+    if let Some(password) = env::var_os(variable) {
+        return Ok(password.to_string_lossy().into_owned().into_bytes());
+    }
+    let prompt = |text: String| rpassword::prompt_password(text).map_err(CommandError::NoPrompt);
+    let password = prompt(format!("{}: ", name))?;
+    if confirm && password.is_empty() {
+        return Err(Box::new(CommandError::EmptyPassword));
+    }
+    if confirm && prompt(format!("Confirm {}: ", name.to_lowercase()))? != password {
+        return Err(Box::new(CommandError::PasswordMismatch));
+    }
+    Ok(password.into_bytes())
+}
+
+//Reads a file holding a secret, refusing one every user of the system can read
+fn read_key_file(path: &Path) -> Result<Vec<u8>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(path)?.permissions().mode() & 0o004 != 0 {
+            return Err(Box::new(CommandError::KeyFileReadable(path.to_path_buf())));
+        }
+    }
+    Ok(fs::read(path)?)
+}
+
+fn decode_file(
+    file: &Path,
+    chunk_type: &str,
+    args: &DecodeArgs,
+    password: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    if args.redundant {
+        let mut bytes = Vec::new();
+        input(file)?.read_to_end(&mut bytes)?;
+        let recovered = redundancy::recover(&bytes, chunk_type, password)?;
+        for location in &recovered.damaged {
+            log::warn!("{}: the copy in {} is damaged", file.display(), location);
+        }
+        log::info!(
+            "{}: read the copy in {}",
+            file.display(),
+            recovered.location
+        );
+        return Ok(recovered.payload);
+    }
+    if !args.recover {
+        return match &args.name {
+            Some(name) => ops::decode_named_payload_chunks(open(file)?, chunk_type, name),
+            None => ops::decode_payload_chunks(open(file)?, chunk_type),
+        };
+    }
+    let recovery = Png::recover(input(file)?)?;
+    for range in &recovery.skipped {
+        log::warn!("skipped unreadable bytes {}..{}", range.start, range.end);
+    }
+    match &args.name {
+        Some(name) => ops::decode_named_payload_chunks(
+            recovery.png.chunks().iter().cloned().map(Ok),
+            chunk_type,
+            name,
+        ),
+        None => recovery.png.payload(chunk_type),
+    }
+}
+
+//The text of `keyword` in the language closest to `language`
+fn select_text(file: &Path, keyword: &str, language: Option<&str>) -> Result<InternationalText> {
+    let entries: Vec<InternationalText> = ops::read_international_text(open(file)?)?
+        .into_iter()
+        .filter(|entry| entry.keyword == keyword)
+        .collect();
+    match text::select_language(&entries, language) {
+        Some(entry) => Ok(entry.clone()),
+        None => Err(Box::new(TextError::KeywordNotFound(keyword.to_string()))),
+    }
+}
+
+//The payload hidden in the pixels, scattered with the password when there is one
+fn pixel_payload(
+    file: &Path,
+    recover: bool,
+    channels: Channels,
+    password: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    if !recover {
+        let png = Png::from_chunks(open(file)?.collect::<Result<_>>()?);
+        return png.extract_lsb(channels, password);
+    }
+    let recovery = Png::recover(input(file)?)?;
+    for range in &recovery.skipped {
+        log::warn!("skipped unreadable bytes {}..{}", range.start, range.end);
+    }
+    recovery.png.extract_lsb(channels, password)
+}
+
+//The bytes after IEND, failing when there are none
+fn trailing_data(file: &Path) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    input(file)?.read_to_end(&mut bytes)?;
+    match trailing::split(&bytes)? {
+        (_, []) => Err(Box::new(TrailingError::NoTrailingData)),
+        (_, rest) => Ok(rest.to_vec()),
+    }
+}
+
+pub fn shard(args: ShardArgs) -> Result<()> {
+    let count = u8::try_from(args.files.len())
+        .map_err(|_| CommandError::TooManyShards(args.files.len()))?;
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    let content = match (&args.file, &args.message) {
+        (Some(path), _) => fs::read(path)?,
+        (None, message) => message.clone().unwrap_or_default().into_bytes(),
+    };
+    let mut options = EncodeOptions::default();
+    if args.replace {
+        options.existing = Existing::Replace;
+    }
+    let outputs: Vec<PathBuf> = args
+        .files
+        .iter()
+        .map(|file| match &args.out_dir {
+            Some(dir) => dir.join(file.file_name().unwrap_or_default()),
+            None => file.clone(),
+        })
+        .collect();
+    //Carriers of the same name would overwrite each other's shard in --out-dir
+    let mut seen = HashSet::new();
+    if let Some(output) = outputs.iter().find(|output| !seen.insert(*output)) {
+        return Err(Box::new(CommandError::DuplicateOutput(output.clone())));
+    }
+    if let Some(dir) = &args.out_dir {
+        fs::create_dir_all(dir)?;
+    }
+    let shards = shard::split(&content, count, args.threshold)?;
+    for ((file, output), shard) in args.files.iter().zip(&outputs).zip(&shards) {
+        rewrite(file, output, |reader, writer| {
+            ops::encode_payload_chunks(reader, writer, &chunk_type, &shard.to_bytes(), &options)
+        })?;
+        status!(
+            output,
+            "Wrote shard {} of {}, {} bytes, to {}",
+            shard.index,
+            shard.count,
+            shard.data.len(),
+            output.display()
+        );
+    }
+    match shards.first().map(|shard| shard.threshold) {
+        Some(threshold) if threshold < count => {
+            println!(
+                "Any {} of the {} files rebuild the payload",
+                threshold, count
+            )
+        }
+        _ => println!("All {} files are needed to rebuild the payload", count),
+    }
+    Ok(())
+}
+
+pub fn unshard(args: UnshardArgs) -> Result<()> {
+    let mut shards = Vec::new();
+    for file in &args.files {
+        //The threshold may leave some out
+        let shard = open(file)
+            .and_then(|chunks| ops::decode_payload_chunks(chunks, &args.chunk_type))
+            .and_then(|data| Shard::parse(&data));
+        match shard {
+            Ok(shard) => shards.push(shard),
+            Err(err) => log::warn!("{}: no shard, {}", file.display(), err),
+        }
+    }
+    write_payload(&args.out, &shard::combine(&shards)?)
+}
+
+pub fn ls_payloads(args: LsPayloadsArgs, format: OutputFormat) -> Result<()> {
+    let toc = ops::read_toc(open(&args.file_path)?)?;
+    if format == OutputFormat::Json {
+        let entries: Vec<Value> = toc
+            .entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "name": entry.name,
+                    "type": entry.chunk_type.to_string(),
+                    "length": entry.length,
+                })
+            })
+            .collect();
+        println!("{}", Value::Array(entries));
+        return Ok(());
+    }
+    println!("{:<24}  {:<4}  {:>10}", "NAME", "TYPE", "LENGTH");
+    for entry in &toc.entries {
+        println!(
+            "{:<24}  {:<4}  {:>10}",
+            entry.name,
+            entry.chunk_type.to_string(),
+            entry.length
+        );
+    }
+    Ok(())
+}
+
+pub fn extract(args: ExtractArgs) -> Result<()> {
+    let chunk_type = match &args.chunk_type {
+        Some(chunk_type) => chunk_type,
+        None => return write_payload(&args.out, &trailing_data(&args.file_path)?),
+    };
+    let chunks = open(&args.file_path)?;
+    let data = match args.index {
+        Some(index) => ops::decode_nth_chunk(chunks, chunk_type, index)?
+            .data()
+            .to_vec(),
+        None => ops::decode_payload_chunks(chunks, chunk_type)?,
+    };
+    write_payload(&args.out, &data)
+}
+
+//Writes bytes as they are to a file or stdout, the status going to stderr for the latter
+fn write_payload(out: &Path, data: &[u8]) -> Result<()> {
+    if is_stdio(out) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()?;
+    } else {
+        fs::write(out, data)?;
+    }
+    status!(out, "Wrote {} bytes to {}", data.len(), out.display());
+    Ok(())
+}
+
+pub fn remove(args: RemoveArgs) -> Result<()> {
+    let occurrence = match (args.index, args.all) {
+        (_, true) => Occurrence::All,
+        (Some(index), false) => Occurrence::Nth(index),
+        (None, false) => Occurrence::First,
+    };
+    let output = args.output.path(&args.file_path);
+    let shredder = if args.shred {
+        Some(Shredder::new(
+            &args.file_path,
+            &args.chunk_type,
+            occurrence,
+        )?)
+    } else {
+        None
+    };
+    let (removed, dry_run) = apply(
+        &args.file_path,
+        output,
+        args.output.dry_run,
+        |reader, writer| ops::remove_occurrences(reader, writer, &args.chunk_type, occurrence),
+    )?;
+    if let Some(dry_run) = dry_run {
+        for chunk in &removed {
+            println!(
+                "Would remove {} chunk, {} bytes",
+                chunk.chunk_type(),
+                chunk.length()
+            );
+        }
+        println!("{}", dry_run);
+        return Ok(());
+    }
+    for chunk in &removed {
+        status!(output, "Removed chunk: {}", chunk);
+    }
+    if let Some(shredder) = shredder {
+        let shredded = shredder.shred()?;
+        status!(output, "Overwrote {} bytes of the old file", shredded);
+    }
+    Ok(())
+}
+
+/**
+ * Zeroes the data of removed chunks in the file they were removed from.
+ * The file is opened before it is rewritten, the rewrite renaming a new file over it,
+ * so the old data is still reachable through the handle afterwards.
+ */
+struct Shredder {
+    file: File,
+    //Offset and length of the data of every chunk to zero
+    ranges: Vec<(u64, u64)>,
+}
+
+impl Shredder {
+    fn new(path: &Path, chunk_type: &str, occurrence: Occurrence) -> Result<Shredder> {
+        //A backup would keep the very data being shredded
+        if BACKUP.load(Ordering::Relaxed) {
+            return Err(Box::new(CommandError::ShredWithBackup));
+        }
+        let file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+        let mut ranges = Vec::new();
+        let mut nth = 0;
+        for chunk in with_offsets(open(path)?) {
+            let (_, offset, chunk) = chunk?;
+            if chunk.chunk_type().to_string() == chunk_type {
+                if occurrence.matches(nth) {
+                    //After the length and type fields
+                    ranges.push((offset as u64 + 8, chunk.length() as u64));
+                }
+                nth += 1;
+            }
+        }
+        Ok(Shredder { file, ranges })
+    }
+
+    //Returns how many bytes were overwritten
+    fn shred(mut self) -> Result<u64> {
+        let mut shredded = 0;
+        for &(offset, length) in &self.ranges {
+            self.file.seek(SeekFrom::Start(offset))?;
+            io::copy(&mut io::repeat(0).take(length), &mut self.file)?;
+            shredded += length;
+        }
+        self.file.sync_all()?;
+        Ok(shredded)
+    }
+}
+
+pub fn print(args: PrintArgs) -> Result<()> {
+    for chunk in open(&args.file_path)? {
+        let chunk = chunk?;
+        println!("{}", chunk);
+        if args.hex {
+            print_hexdump(chunk.data(), usize::MAX);
+        }
+    }
+    Ok(())
+}
+
+pub fn list(args: ListArgs, format: OutputFormat) -> Result<()> {
+    let files = batch::files(&args.file_path, &args.batch)?;
+    let several = files.len() > 1;
+    batch::for_each(&files, |file| {
+        let chunks = with_offsets(open(file)?);
+        if format == OutputFormat::Json {
+            let mut header = None;
+            let chunks = chunks
+                .map(|chunk| {
+                    let (index, offset, chunk) = chunk?;
+                    let mut value = chunk_json(index, offset, &chunk);
+                    if args.decoded {
+                        match decoded_fields(&chunk, &mut header) {
+                            Ok(Some(fields)) => value["fields"] = fields_json(fields),
+                            Ok(None) => {}
+                            Err(err) => value["error"] = Value::String(err.to_string()),
+                        }
+                    }
+                    Ok(value)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let value = if several {
+                json!({"file": file.display().to_string(), "chunks": chunks})
+            } else {
+                Value::Array(chunks)
+            };
+            println!("{}", value);
+            return Ok(());
+        }
+        if several {
+            println!("{}:", file.display());
+        }
+        print_chunk_table(chunks, args.decoded)
+    })
+}
+
+//Fields of the chunk as `inspect::describe` decodes them, `header` is updated from IHDR
+fn decoded_fields(chunk: &Chunk, header: &mut Option<Ihdr>) -> Result<Option<inspect::Fields>> {
+    if *chunk.chunk_type() == ChunkType::IHDR {
+        *header = Ihdr::try_from(chunk).ok();
+    }
+    //Image data is never worth decoding here
+    if *chunk.chunk_type() == ChunkType::IDAT || *chunk.chunk_type() == ChunkType::IEND {
+        return Ok(None);
+    }
+    inspect::describe(chunk, header.as_ref())
+}
+
+fn fields_json<N: Into<String>>(fields: Vec<(N, String)>) -> Value {
+    Value::Object(
+        fields
+            .into_iter()
+            .map(|(name, value)| (name.into(), Value::String(value)))
+            .collect(),
+    )
+}
+
+//Longest field value shown by list --decoded, longer ones are cut
+const SUMMARY_VALUE_LIMIT: usize = 40;
+
+//One line view of the fields for the chunk table
+fn fields_summary(fields: &inspect::Fields) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| {
+            let value: String = value
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect();
+            if value.chars().count() > SUMMARY_VALUE_LIMIT {
+                let cut: String = value.chars().take(SUMMARY_VALUE_LIMIT).collect();
+                format!("{}: {}...", name, cut)
+            } else {
+                format!("{}: {}", name, value)
+            }
+        })
+        .collect();
+    fields.join(", ")
+}
+
+fn print_chunk_table(
+    chunks: impl Iterator<Item = Result<(usize, usize, Chunk)>>,
+    decoded: bool,
+) -> Result<()> {
+    println!(
+        "{:>5}  {:>10}  {:<4}  {:>10}  {:<7}  PROPERTIES",
+        "INDEX", "OFFSET", "TYPE", "LENGTH", "CRC"
+    );
+    let mut header = None;
+    for chunk in chunks {
+        let (index, offset, chunk) = chunk?;
+        let chunk_type = chunk.chunk_type();
+        let properties = [
+            if chunk_type.is_critical() {
+                "critical"
+            } else {
+                "ancillary"
+            },
+            if chunk_type.is_public() {
+                "public"
+            } else {
+                "private"
+            },
+            if chunk_type.is_safe_to_copy() {
+                "safe-to-copy"
+            } else {
+                "unsafe-to-copy"
+            },
+        ];
+        println!(
+            "{:>5}  {:>10}  {:<4}  {:>10}  {:<7}  {}",
+            index,
+            offset,
+            chunk_type,
+            chunk.length(),
+            if chunk.is_crc_valid() {
+                "ok"
+            } else {
+                "invalid"
+            },
+            properties.join(" ")
+        );
+        if decoded {
+            match decoded_fields(&chunk, &mut header) {
+                Ok(Some(fields)) if !fields.is_empty() => {
+                    println!("{:>7}{}", "", fields_summary(&fields))
+                }
+                Ok(_) => {}
+                Err(err) => println!("{:>7}error: {}", "", err),
+            }
+        }
+    }
+    Ok(())
+}
+
+//Index and file offset of every chunk
+fn with_offsets(reader: FileReader) -> impl Iterator<Item = Result<(usize, usize, Chunk)>> {
+    let mut offset = Png::STANDARD_HEADER.len();
+    reader.enumerate().map(move |(index, chunk)| {
+        let chunk = chunk?;
+        let chunk_offset = offset;
+        offset += chunk.length() as usize + Chunk::METADATA_LENGTH;
+        Ok((index, chunk_offset, chunk))
+    })
+}
+
+//Everything `list` shows about a chunk, the data itself is left out
+fn chunk_json(index: usize, offset: usize, chunk: &Chunk) -> Value {
+    let chunk_type = chunk.chunk_type();
+    json!({
+        "index": index,
+        "offset": offset,
+        "type": chunk_type.to_string(),
+        "length": chunk.length(),
+        "crc": chunk.crc(),
+        "crc_valid": chunk.is_crc_valid(),
+        "critical": chunk_type.is_critical(),
+        "public": chunk_type.is_public(),
+        "safe_to_copy": chunk_type.is_safe_to_copy(),
+    })
+}
+
+//Bytes of an unknown chunk shown by inspect without --hex
+const HEXDUMP_LIMIT: usize = 256;
+
+//The schemas of the file, none without one
+fn load_schemas(path: Option<&Path>) -> Result<SchemaSet> {
+    match path {
+        Some(path) => SchemaSet::from_str(&fs::read_to_string(path)?),
+        None => Ok(SchemaSet::default()),
+    }
+}
+
+pub fn inspect(args: InspectArgs, format: OutputFormat) -> Result<()> {
+    let schemas = load_schemas(args.schema.as_deref())?;
+    let mut header = None;
+    let mut inspected = Vec::new();
+    for chunk in with_offsets(open(&args.file_path)?) {
+        let (index, offset, chunk) = chunk?;
+        if *chunk.chunk_type() == ChunkType::IHDR {
+            header = Ihdr::try_from(&chunk).ok();
+        }
+        if let Some(chunk_type) = &args.chunk_type {
+            if chunk.chunk_type().to_string() != *chunk_type {
+                continue;
+            }
+        }
+        //Schemas only cover the chunk types pngme doesn't know
+        let description = match inspect::describe(&chunk, header.as_ref()) {
+            Ok(None) => schemas
+                .get(chunk.chunk_type())
+                .map(|schema| schema.decode(chunk.data()))
+                .transpose(),
+            description => description.map(|fields| {
+                fields.map(|fields| {
+                    fields
+                        .into_iter()
+                        .map(|(name, value)| (name.to_string(), value))
+                        .collect()
+                })
+            }),
+        };
+        if format == OutputFormat::Json {
+            let mut value = chunk_json(index, offset, &chunk);
+            match description {
+                Ok(Some(fields)) => value["fields"] = fields_json(fields),
+                Ok(None) => value["data"] = Value::String(BASE64.encode(chunk.data())),
+                Err(err) => {
+                    value["error"] = Value::String(err.to_string());
+                    value["data"] = Value::String(BASE64.encode(chunk.data()));
+                }
+            }
+            if args.hex {
+                value["data"] = Value::String(BASE64.encode(chunk.data()));
+            }
+            inspected.push(value);
+            continue;
+        }
+        println!(
+            "#{} {}, {} bytes",
+            index,
+            chunk.chunk_type(),
+            chunk.length()
+        );
+        //The dump is cut short unless asked for, and only there for undecoded chunks
+        let limit = if args.hex { usize::MAX } else { HEXDUMP_LIMIT };
+        match description {
+            Ok(Some(fields)) => {
+                for (name, value) in fields {
+                    println!("  {}: {}", name, value);
+                }
+                if args.hex {
+                    print_hexdump(chunk.data(), limit);
+                }
+            }
+            Ok(None) => print_hexdump(chunk.data(), limit),
+            Err(err) => {
+                println!("  invalid: {}", err);
+                print_hexdump(chunk.data(), limit);
+            }
+        }
+    }
+    if format == OutputFormat::Json {
+        println!("{}", Value::Array(inspected));
+    }
+    Ok(())
+}
+
+fn print_hexdump(data: &[u8], limit: usize) {
+    for line in inspect::hexdump(data, limit).lines() {
+        println!("  {}", line);
+    }
+}
+
+pub fn diff(args: DiffArgs, format: OutputFormat) -> Result<()> {
+    let old = Png::from_chunks(open(&args.old_file)?.collect::<Result<_>>()?);
+    let new = Png::from_chunks(open(&args.new_file)?.collect::<Result<_>>()?);
+    let changes = diff::diff(&old, &new);
+    if format == OutputFormat::Json {
+        let summary = |chunk: &ChunkSummary| {
+            json!({
+                "index": chunk.index,
+                "type": chunk.chunk_type.to_string(),
+                "length": chunk.length,
+                "crc": chunk.crc,
+            })
+        };
+        let changes: Vec<Value> = changes
+            .iter()
+            .map(|change| match change {
+                ChunkChange::Added(chunk) => json!({"change": "added", "new": summary(chunk)}),
+                ChunkChange::Removed(chunk) => {
+                    json!({"change": "removed", "old": summary(chunk)})
+                }
+                ChunkChange::Modified(old, new) => {
+                    json!({"change": "modified", "old": summary(old), "new": summary(new)})
+                }
+            })
+            .collect();
+        println!("{}", Value::Array(changes));
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{}", change);
+    }
+    if changes.is_empty() {
+        println!("No differences");
+    }
+    Ok(())
+}
+
+pub fn grep(args: GrepArgs, format: OutputFormat) -> Result<()> {
+    let mut files = Vec::new();
+    for path in &args.files {
+        files.extend(batch::files(path, &args.batch)?);
+    }
+    batch::for_each(&files, |file| {
+        for found in search::search(open(file)?, &args.pattern)? {
+            if format == OutputFormat::Json {
+                let value = json!({
+                    "file": file.display().to_string(),
+                    "type": found.chunk_type.to_string(),
+                    "index": found.chunk_index,
+                    "offset": found.offset,
+                    "snippet": found.snippet,
+                });
+                println!("{}", value);
+            } else {
+                println!(
+                    "{}: {} #{}: {}",
+                    file.display(),
+                    found.chunk_type,
+                    found.chunk_index,
+                    found.snippet
+                );
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn stats(args: StatsArgs, format: OutputFormat) -> Result<()> {
+    let batch = BatchArgs {
+        recursive: args.batch.recursive || args.file_path.is_dir(),
+        ..args.batch
+    };
+    let files = batch::files(&args.file_path, &batch)?;
+    let mut stats = Stats::default();
+    let mut indicators = Vec::new();
+    //Files that fail are left out of the totals, which are still reported
+    let result = batch::for_each(&files, |file| {
+        let found = stats.add(open(file)?)?;
+        if !found.is_empty() {
+            indicators.push((file.to_path_buf(), found));
+        }
+        Ok(())
+    });
+    if format == OutputFormat::Json {
+        let by_type: serde_json::Map<String, Value> = stats
+            .by_type
+            .iter()
+            .map(|(chunk_type, type_stats)| {
+                let value = json!({
+                    "count": type_stats.count,
+                    "bytes": type_stats.bytes,
+                    "entropy": type_stats.entropy(),
+                });
+                (chunk_type.clone(), value)
+            })
+            .collect();
+        let indicators: Vec<Value> = indicators
+            .iter()
+            .flat_map(|(file, found)| {
+                found.iter().map(move |indicator| {
+                    json!({
+                        "file": file.display().to_string(),
+                        "type": indicator.chunk_type.to_string(),
+                        "reason": indicator.reason.to_string(),
+                        "count": indicator.count,
+                    })
+                })
+            })
+            .collect();
+        let value = json!({
+            "files": stats.files,
+            "bytes": stats.total_bytes,
+            "critical_chunks": stats.critical_chunks,
+            "ancillary_chunks": stats.ancillary_chunks,
+            "overhead": stats.overhead(),
+            "types": by_type,
+            "indicators": indicators,
+        });
+        println!("{}", value);
+        return result;
+    }
+    println!("Files: {}, {} bytes", stats.files, stats.total_bytes);
+    println!(
+        "Chunks: {} critical, {} ancillary",
+        stats.critical_chunks, stats.ancillary_chunks
+    );
+    println!("Metadata overhead: {:.1}%", stats.overhead() * 100.0);
+    println!(
+        "{:<4}  {:>7}  {:>12}  {:>7}",
+        "TYPE", "COUNT", "BYTES", "ENTROPY"
+    );
+    for (chunk_type, type_stats) in &stats.by_type {
+        println!(
+            "{:<4}  {:>7}  {:>12}  {:>7.2}",
+            chunk_type,
+            type_stats.count,
+            type_stats.bytes,
+            type_stats.entropy()
+        );
+    }
+    if !indicators.is_empty() {
+        println!("Possible hidden data:");
+    }
+    for (file, found) in &indicators {
+        for indicator in found {
+            println!(
+                "  {}: {} x{}, {}",
+                file.display(),
+                indicator.chunk_type,
+                indicator.count,
+                indicator.reason
+            );
+        }
+    }
+    result
+}
+
+pub fn copy_chunks(args: CopyChunksArgs) -> Result<()> {
+    let source = Png::from_chunks(open(&args.from)?.collect::<Result<_>>()?);
+    let output = args.output.path(&args.to);
+    let (copied, dry_run) = apply(&args.to, output, args.output.dry_run, |reader, writer| {
+        let mut target = Png::from_chunks(reader.collect::<Result<_>>()?);
+        let copied = target.copy_chunks_from(&source, &args.types, args.allow_unsafe)?;
+        writer.write_all(&target.as_bytes())?;
+        Ok(copied)
+    })?;
+    match dry_run {
+        Some(dry_run) => {
+            status!(output, "Would copy {} chunk(s)", copied);
+            println!("{}", dry_run);
+        }
+        None => status!(output, "Copied {} chunk(s) to {}", copied, output.display()),
+    }
+    Ok(())
+}
+
+pub fn scrub(args: ScrubArgs) -> Result<()> {
+    if let Some(critical) = args
+        .strip
+        .iter()
+        .find(|chunk_type| chunk_type.is_critical())
+    {
+        return Err(Box::new(CommandError::StripCritical(critical.clone())));
+    }
+    let options = ScrubOptions {
+        keep: args.keep,
+        keep_safe_to_copy: args.keep_safe_to_copy,
+        strip: args.strip,
+        dedup: args.dedup,
+    };
+    let files = batch::files(&args.file_path, &args.batch)?;
+    let several = files.len() > 1;
+    if several && args.output.output.is_some() {
+        return Err(Box::new(BatchError::OutputWithSeveralFiles));
+    }
+    batch::for_each(&files, |file| {
+        let output = args.output.path(file);
+        let (removed, dry_run) = apply(file, output, args.output.dry_run, |reader, writer| {
+            ops::scrub_chunks(reader, writer, &options)
+        })?;
+        if several {
+            println!("{}:", file.display());
+        }
+        let verb = if dry_run.is_some() {
+            "Would remove"
+        } else {
+            "Removed"
+        };
+        for chunk in &removed {
+            status!(
+                output,
+                "{} {} chunk, {} bytes",
+                verb,
+                chunk.chunk_type(),
+                chunk.length()
+            );
+        }
+        match dry_run {
+            Some(dry_run) => println!("{}", dry_run),
+            None => print_removed_total(output, &removed),
+        }
+        Ok(())
+    })
+}
+
+pub fn normalize(args: NormalizeArgs) -> Result<()> {
+    let files = batch::files(&args.file_path, &args.batch)?;
+    let several = files.len() > 1;
+    if several && args.output.output.is_some() {
+        return Err(Box::new(BatchError::OutputWithSeveralFiles));
+    }
+    batch::for_each(&files, |file| {
+        let output = args.output.path(file);
+        let (normalized, dry_run) = apply(file, output, args.output.dry_run, |reader, writer| {
+            let mut png = Png::from_chunks(reader.collect::<Result<_>>()?);
+            let normalized = png.normalize()?;
+            writer.write_all(&png.as_bytes())?;
+            Ok(normalized)
+        })?;
+        if several {
+            println!("{}:", file.display());
+        }
+        let verb = if dry_run.is_some() {
+            "Would remove"
+        } else {
+            "Removed"
+        };
+        for chunk in &normalized.removed {
+            match text::keyword(chunk) {
+                Some(keyword) => {
+                    status!(output, "{} {} chunk {}", verb, chunk.chunk_type(), keyword)
+                }
+                None => status!(output, "{} {} chunk", verb, chunk.chunk_type()),
+            }
+        }
+        if normalized.reordered {
+            status!(output, "Chunks reordered");
+        }
+        status!(
+            output,
+            "Pixel data: {} -> {} bytes",
+            normalized.pixel_data.0,
+            normalized.pixel_data.1
+        );
+        if let Some(dry_run) = dry_run {
+            println!("{}", dry_run);
+        }
+        Ok(())
+    })
+}
+
+pub fn repair(args: RepairArgs) -> Result<()> {
+    let output = args.output.path(&args.file_path);
+    let (repaired, dry_run) = apply(
+        &args.file_path,
+        output,
+        args.output.dry_run,
+        |reader, writer| ops::repair_chunks(reader, writer),
+    )?;
+    let verb = if dry_run.is_some() {
+        "Would repair"
+    } else {
+        "Repaired"
+    };
+    for chunk in &repaired {
+        status!(
+            output,
+            "{} chunk {} ({}): crc {} -> {}",
+            verb,
+            chunk.index,
+            chunk.chunk_type,
+            chunk.old_crc,
+            chunk.new_crc
+        );
+    }
+    status!(output, "{} {} chunk(s)", verb, repaired.len());
+    if let Some(dry_run) = dry_run {
+        println!("{}", dry_run);
+    }
+    Ok(())
+}
+
+pub fn migrate(args: MigrateArgs) -> Result<()> {
+    let chunk_type = match &args.chunk_type {
+        Some(chunk_type) => Some(ChunkType::from_str(chunk_type)?),
+        None => None,
+    };
+    let output = args.output.path(&args.file_path);
+    let (migrated, dry_run) = apply(
+        &args.file_path,
+        output,
+        args.output.dry_run,
+        |reader, writer| ops::migrate_chunks(reader, writer, chunk_type.as_ref(), args.part_size),
+    )?;
+    let verb = if dry_run.is_some() {
+        "Would migrate"
+    } else {
+        "Migrated"
+    };
+    for chunk_type in &migrated {
+        status!(
+            output,
+            "{} {} payload to envelope version {}",
+            verb,
+            chunk_type,
+            payload::ENVELOPE_VERSION
+        );
+    }
+    status!(output, "{} {} payload(s)", verb, migrated.len());
+    if let Some(dry_run) = dry_run {
+        println!("{}", dry_run);
+    }
+    Ok(())
+}
+
+pub fn meta(args: MetaArgs, format: OutputFormat) -> Result<()> {
+    match args.command {
+        MetaCommand::Set(args) => {
+            let output = args.output.path(&args.file_path);
+            let (replaced, dry_run) = match &args.lang {
+                Some(language) => {
+                    let entry = InternationalText::new(&args.keyword, language, &args.text)?;
+                    apply(
+                        &args.file_path,
+                        output,
+                        args.output.dry_run,
+                        |reader, writer| ops::set_international_text_chunks(reader, writer, &entry),
+                    )
+                    .map(|(replaced, dry_run)| (replaced.is_some(), dry_run))?
+                }
+                None => {
+                    let entry = TextEntry::new(&args.keyword, &args.text)?;
+                    apply(
+                        &args.file_path,
+                        output,
+                        args.output.dry_run,
+                        |reader, writer| ops::set_text_chunks(reader, writer, &entry),
+                    )
+                    .map(|(replaced, dry_run)| (replaced.is_some(), dry_run))?
+                }
+            };
+            let keyword = match &args.lang {
+                Some(language) => format!("{} ({})", args.keyword, language),
+                None => args.keyword.clone(),
+            };
+            if let Some(dry_run) = dry_run {
+                println!("Would set {}", keyword);
+                println!("{}", dry_run);
+                return Ok(());
+            }
+            if replaced {
+                status!(output, "Replaced {}", keyword);
+            } else {
+                status!(output, "Set {}", keyword);
+            }
+            Ok(())
+        }
+        MetaCommand::Get(args) => {
+            if let Some(language) = &args.lang {
+                text::validate_language(language)?;
+            }
+            let entry = select_text(&args.file_path, &args.keyword, args.lang.as_deref())?;
+            if format == OutputFormat::Json {
+                println!(
+                    "{}",
+                    json!({"keyword": entry.keyword, "language": entry.language, "text": entry.text})
+                );
+            } else {
+                println!("{}", entry.text);
+            }
+            Ok(())
+        }
+        MetaCommand::List(args) => {
+            let entries = ops::read_text(open(&args.file_path)?)?;
+            if format == OutputFormat::Json {
+                let entries: Vec<Value> = entries
+                    .iter()
+                    .map(|entry| json!({"keyword": entry.keyword, "text": entry.text}))
+                    .collect();
+                println!("{}", Value::Array(entries));
+                return Ok(());
+            }
+            for entry in &entries {
+                println!("{}: {}", entry.keyword, entry.text);
+            }
+            Ok(())
+        }
+        MetaCommand::Copy(args) => {
+            let source = Png::from_chunks(open(&args.from)?.collect::<Result<_>>()?);
+            let output = args.output.path(&args.to);
+            let (copied, dry_run) =
+                apply(&args.to, output, args.output.dry_run, |reader, writer| {
+                    let mut target = Png::from_chunks(reader.collect::<Result<_>>()?);
+                    let copied = target.copy_metadata_from(&source);
+                    writer.write_all(&target.as_bytes())?;
+                    Ok(copied)
+                })?;
+            if let Some(dry_run) = dry_run {
+                println!(
+                    "Would copy {} chunk(s) from {}",
+                    copied,
+                    args.from.display()
+                );
+                println!("{}", dry_run);
+                return Ok(());
+            }
+            status!(output, "Copied {} chunk(s) to {}", copied, output.display());
+            Ok(())
+        }
+        MetaCommand::Diff(args) => {
+            let old = Png::from_chunks(open(&args.old_file)?.collect::<Result<_>>()?);
+            let new = Png::from_chunks(open(&args.new_file)?.collect::<Result<_>>()?);
+            let changes = diff::diff_metadata(&old, &new)?;
+            if format == OutputFormat::Json {
+                let changes: Vec<Value> = changes
+                    .iter()
+                    .map(|change| json!({"key": change.key, "old": change.old, "new": change.new}))
+                    .collect();
+                println!("{}", Value::Array(changes));
+                return Ok(());
+            }
+            for change in &changes {
+                println!("{}", change);
+            }
+            if changes.is_empty() {
+                println!("No differences");
+            }
+            Ok(())
+        }
+        MetaCommand::Apply(args) => {
+            let template = MetadataTemplate::from_str(&fs::read_to_string(&args.template)?)?;
+            let mut files = Vec::new();
+            for path in &args.file_paths {
+                files.extend(batch::expand(path)?);
+            }
+            let several = files.len() > 1;
+            if several && args.output.output.is_some() {
+                return Err(Box::new(BatchError::OutputWithSeveralFiles));
+            }
+            batch::for_each(&files, |file| {
+                let output = args.output.path(file);
+                let (replaced, dry_run) =
+                    apply(file, output, args.output.dry_run, |reader, writer| {
+                        let mut png = Png::from_chunks(reader.collect::<Result<_>>()?);
+                        let replaced = template.apply(&mut png);
+                        writer.write_all(&png.as_bytes())?;
+                        Ok(replaced)
+                    })?;
+                if several {
+                    println!("{}:", file.display());
+                }
+                if let Some(dry_run) = dry_run {
+                    println!(
+                        "Would set {} chunk(s), replacing {}",
+                        template.chunks().len(),
+                        replaced
+                    );
+                    println!("{}", dry_run);
+                    return Ok(());
+                }
+                status!(
+                    output,
+                    "Set {} chunk(s), replacing {}",
+                    template.chunks().len(),
+                    replaced
+                );
+                Ok(())
+            })
+        }
+    }
+}
+
+pub fn time(args: TimeArgs, format: OutputFormat) -> Result<()> {
+    match args.command {
+        TimeCommand::Get(args) => {
+            let time = ops::read_time(open(&args.file_path)?)?
+                .ok_or_else(|| PngError::ChunkNotFound(ChunkType::tIME.to_string()))?;
+            if format == OutputFormat::Json {
+                println!("{}", json!({"time": time.to_string()}));
+            } else {
+                println!("{}", time);
+            }
+            Ok(())
+        }
+        TimeCommand::Set(args) => {
+            let time = match args.time {
+                Some(time) => time,
+                None => Timestamp::now(),
+            };
+            let output = args.output.path(&args.file_path);
+            let (previous, dry_run) = apply(
+                &args.file_path,
+                output,
+                args.output.dry_run,
+                |reader, writer| ops::set_time_chunks(reader, writer, time),
+            )?;
+            if let Some(dry_run) = dry_run {
+                println!("Would set tIME to {}", time);
+                println!("{}", dry_run);
+                return Ok(());
+            }
+            match previous {
+                Some(previous) => status!(output, "Set tIME to {}, was {}", time, previous),
+                None => status!(output, "Set tIME to {}", time),
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn color(args: ColorArgs, format: OutputFormat) -> Result<()> {
+    match args.command {
+        ColorCommand::Get(args) => {
+            let info = ops::read_color(open(&args.file_path)?)?;
+            if format == OutputFormat::Json {
+                println!(
+                    "{}",
+                    json!({
+                        "gamma": info.gamma.map(|gamma| gamma.value()),
+                        "chromaticities": info.chromaticities.map(|chrm| chrm.to_string()),
+                        "rendering_intent": info.rendering_intent.map(|intent| intent.to_string()),
+                    })
+                );
+                return Ok(());
+            }
+            if info == ColorInfo::default() {
+                println!("No gAMA, cHRM or sRGB chunk");
+            }
+            if let Some(gamma) = info.gamma {
+                println!("Gamma: {}", gamma);
+            }
+            if let Some(chrm) = info.chromaticities {
+                println!("Chromaticities: {}", chrm);
+            }
+            if let Some(intent) = info.rendering_intent {
+                println!("sRGB: {}", intent);
+            }
+            Ok(())
+        }
+        ColorCommand::Set(args) => {
+            //The spec recommends the sRGB values for decoders that don't know sRGB
+            let srgb = args.srgb.is_some();
+            let info = ColorInfo {
+                gamma: args.gamma.or(srgb.then_some(Gamma::SRGB)),
+                chromaticities: args.chromaticities.or(srgb.then_some(Chromaticities::SRGB)),
+                rendering_intent: args.srgb,
+            };
+            let output = args.output.path(&args.file_path);
+            let (replaced, dry_run) = apply(
+                &args.file_path,
+                output,
+                args.output.dry_run,
+                |reader, writer| ops::set_color_chunks(reader, writer, &info),
+            )?;
+            let verb = if dry_run.is_some() {
+                "Would replace"
+            } else {
+                "Replaced"
+            };
+            for chunk in &replaced {
+                status!(output, "{} {} chunk", verb, chunk.chunk_type());
+            }
+            let set: Vec<String> = info
+                .to_chunks()
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string())
+                .collect();
+            if let Some(dry_run) = dry_run {
+                println!("Would set {}", set.join(", "));
+                println!("{}", dry_run);
+            } else {
+                status!(output, "Set {}", set.join(", "));
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn dpi(args: DpiArgs, format: OutputFormat) -> Result<()> {
+    match args.command {
+        DpiCommand::Get(args) => {
+            let phys = ops::read_phys(open(&args.file_path)?)?
+                .ok_or_else(|| PngError::ChunkNotFound(ChunkType::pHYs.to_string()))?;
+            let dpi = phys.dpi();
+            if format == OutputFormat::Json {
+                println!(
+                    "{}",
+                    json!({
+                        "dpi_x": dpi.map(|(x, _)| x),
+                        "dpi_y": dpi.map(|(_, y)| y),
+                        "pixels_per_unit_x": phys.pixels_per_unit_x,
+                        "pixels_per_unit_y": phys.pixels_per_unit_y,
+                        "unit": if dpi.is_some() { "meter" } else { "unknown" },
+                    })
+                );
+                return Ok(());
+            }
+            match dpi {
+                Some((x, y)) => println!(
+                    "{:.0}x{:.0} dpi, {}x{} pixels per meter",
+                    x, y, phys.pixels_per_unit_x, phys.pixels_per_unit_y
+                ),
+                None => println!(
+                    "No physical unit, pixel aspect ratio {}:{}",
+                    phys.pixels_per_unit_x, phys.pixels_per_unit_y
+                ),
+            }
+            Ok(())
+        }
+        DpiCommand::Set(args) => {
+            let phys = PhysicalDimensions::from_dpi(args.dpi, args.vertical.unwrap_or(args.dpi))?;
+            let output = args.output.path(&args.file_path);
+            let (previous, dry_run) = apply(
+                &args.file_path,
+                output,
+                args.output.dry_run,
+                |reader, writer| ops::set_phys_chunks(reader, writer, &phys),
+            )?;
+            let (x, y) = phys.dpi().expect("from_dpi sets the unit");
+            if let Some(dry_run) = dry_run {
+                println!("Would set the resolution to {:.0}x{:.0} dpi", x, y);
+                println!("{}", dry_run);
+                return Ok(());
+            }
+            match previous.and_then(|previous| previous.dpi()) {
+                Some((old_x, old_y)) => status!(
+                    output,
+                    "Set the resolution to {:.0}x{:.0} dpi, was {:.0}x{:.0}",
+                    x,
+                    y,
+                    old_x,
+                    old_y
+                ),
+                None => status!(output, "Set the resolution to {:.0}x{:.0} dpi", x, y),
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn ext(args: ExtArgs, format: OutputFormat) -> Result<()> {
+    match args.command {
+        ExtCommand::Get(args) => {
+            let extensions = ops::read_extensions(open(&args.file_path)?)?;
+            if format == OutputFormat::Json {
+                println!(
+                    "{}",
+                    json!({
+                        "offset": extensions.offset.map(|offset| json!({
+                            "x": offset.x,
+                            "y": offset.y,
+                            "unit": offset.unit.to_string(),
+                        })),
+                        "scale": extensions.scale.map(|scale| json!({
+                            "width": scale.width,
+                            "height": scale.height,
+                            "unit": scale.unit.to_string(),
+                        })),
+                        "stereo": extensions.stereo.map(|stereo| stereo.to_string()),
+                    })
+                );
+                return Ok(());
+            }
+            if extensions == Extensions::default() {
+                println!("No oFFs, sCAL or sTER chunk");
+            }
+            if let Some(offset) = extensions.offset {
+                println!("Offset: {}", offset);
+            }
+            if let Some(scale) = extensions.scale {
+                println!("Pixel size: {}", scale);
+            }
+            if let Some(stereo) = extensions.stereo {
+                println!("Stereo: {}", stereo);
+            }
+            Ok(())
+        }
+        ExtCommand::Set(args) => {
+            let extensions = Extensions {
+                offset: args.offset,
+                scale: args.scale,
+                stereo: args.stereo,
+            };
+            let output = args.output.path(&args.file_path);
+            let (replaced, dry_run) = apply(
+                &args.file_path,
+                output,
+                args.output.dry_run,
+                |reader, writer| ops::set_extensions_chunks(reader, writer, &extensions),
+            )?;
+            let verb = if dry_run.is_some() {
+                "Would replace"
+            } else {
+                "Replaced"
+            };
+            for chunk in &replaced {
+                status!(output, "{} {} chunk", verb, chunk.chunk_type());
+            }
+            let set: Vec<String> = extensions
+                .to_chunks()
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string())
+                .collect();
+            if let Some(dry_run) = dry_run {
+                println!("Would set {}", set.join(", "));
+                println!("{}", dry_run);
+            } else {
+                status!(output, "Set {}", set.join(", "));
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn icc(args: IccArgs) -> Result<()> {
+    match args.command {
+        IccCommand::Extract(args) => {
+            let profile = ops::read_icc(open(&args.file_path)?)?
+                .ok_or_else(|| PngError::ChunkNotFound(ChunkType::iCCP.to_string()))?;
+            let out = args
+                .out
+                .unwrap_or_else(|| args.file_path.with_extension("icc"));
+            write_payload(&out, &profile.profile)
+        }
+        IccCommand::Embed(args) => {
+            let name = match &args.name {
+                Some(name) => name.clone(),
+                None => args
+                    .profile
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .filter(|stem| text::validate_keyword(stem).is_ok())
+                    .unwrap_or_else(|| "ICC profile".to_string()),
+            };
+            let profile = IccProfile::new(&name, fs::read(&args.profile)?)?;
+            let output = args.output.path(&args.file_path);
+            let (replaced, dry_run) = apply(
+                &args.file_path,
+                output,
+                args.output.dry_run,
+                |reader, writer| ops::embed_icc_chunks(reader, writer, &profile),
+            )?;
+            let verb = if dry_run.is_some() {
+                "Would remove"
+            } else {
+                "Removed"
+            };
+            for chunk in &replaced {
+                status!(
+                    output,
+                    "{} {} chunk, {} bytes",
+                    verb,
+                    chunk.chunk_type(),
+                    chunk.length()
+                );
+            }
+            if let Some(dry_run) = dry_run {
+                println!(
+                    "Would embed {}, {} bytes",
+                    profile.name,
+                    profile.profile.len()
+                );
+                println!("{}", dry_run);
+            } else {
+                status!(
+                    output,
+                    "Embedded {}, {} bytes",
+                    profile.name,
+                    profile.profile.len()
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn xmp(args: XmpArgs) -> Result<()> {
+    match args.command {
+        XmpCommand::Get(args) => {
+            let packet = ops::read_xmp(open(&args.file_path)?)?.ok_or(XmpError::NotFound)?;
+            println!("{}", packet.trim_end());
+            Ok(())
+        }
+        XmpCommand::Set(args) => {
+            if is_stdio(&args.packet) && is_stdio(&args.file_path) {
+                return Err(Box::new(CommandError::StdinUsedTwice));
+            }
+            let mut packet = String::new();
+            input(&args.packet)?.read_to_string(&mut packet)?;
+            let output = args.output.path(&args.file_path);
+            let (replaced, dry_run) = apply(
+                &args.file_path,
+                output,
+                args.output.dry_run,
+                |reader, writer| ops::set_xmp_chunks(reader, writer, &packet),
+            )?;
+            if let Some(dry_run) = dry_run {
+                println!("Would set the XMP packet");
+                println!("{}", dry_run);
+            } else if replaced {
+                status!(output, "Replaced the XMP packet");
+            } else {
+                status!(output, "Set the XMP packet");
+            }
+            Ok(())
+        }
+        XmpCommand::Strip(args) => {
+            let output = args.output.path(&args.file_path);
+            let (stripped, dry_run) = apply(
+                &args.file_path,
+                output,
+                args.output.dry_run,
+                |reader, writer| ops::strip_xmp_chunks(reader, writer),
+            )?;
+            if let Some(dry_run) = dry_run {
+                println!("Would strip {} XMP chunk(s)", stripped);
+                println!("{}", dry_run);
+            } else {
+                status!(output, "Stripped {} XMP chunk(s)", stripped);
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn validate(args: ValidateArgs, format: OutputFormat) -> Result<()> {
+    let issues = ops::validate(input(&args.file_path)?)?;
+    if format == OutputFormat::Json {
+        let issues_json: Vec<Value> = issues
+            .iter()
+            .map(|issue| {
+                json!({
+                    "code": issue.code.as_str(),
+                    "chunk_index": issue.chunk_index,
+                    "message": issue.message,
+                })
+            })
+            .collect();
+        let value = json!({
+            "file": args.file_path.display().to_string(),
+            "valid": issues.is_empty(),
+            "issues": issues_json,
+        });
+        println!("{}", value);
+    } else {
+        for issue in &issues {
+            println!("{}", issue);
+        }
+    }
+    if !issues.is_empty() {
+        return Err(Box::new(ValidationError::IssuesFound(issues.len())));
+    }
+    if format == OutputFormat::Text {
+        println!("{}: valid", args.file_path.display());
+    }
+    Ok(())
+}
+
+pub fn optimize(args: OptimizeArgs) -> Result<()> {
+    let output = args.output.path(&args.file_path);
+    let ((old_length, new_length, duplicates), dry_run) = apply(
+        &args.file_path,
+        output,
+        args.output.dry_run,
+        |reader, writer| {
+            let mut png = Png::from_chunks(reader.collect::<Result<_>>()?);
+            let duplicates = if args.dedup {
+                png.dedup_chunks()
+            } else {
+                Vec::new()
+            };
+            let old_length = png.idat_data().len();
+            let mut optimized = png.clone();
+            #[cfg(feature = "zopfli")]
+            let new_length = if args.zopfli {
+                optimized.recompress_zopfli()?
+            } else {
+                optimized.recompress(args.level)?
+            };
+            #[cfg(not(feature = "zopfli"))]
+            let new_length = optimized.recompress(args.level)?;
+            //Never grow the file, the original compression might already be better
+            let smallest = if new_length < old_length {
+                &optimized
+            } else {
+                &png
+            };
+            writer.write_all(&smallest.as_bytes())?;
+            Ok((old_length, new_length.min(old_length), duplicates))
+        },
+    )?;
+    if args.dedup {
+        print_removed_total(output, &duplicates);
+    }
+    status!(output, "Pixel data: {} -> {} bytes", old_length, new_length);
+    if let Some(dry_run) = dry_run {
+        println!("{}", dry_run);
+    }
+    Ok(())
+}
+
+fn print_removed_total(output: &Path, removed: &[Chunk]) {
+    let reclaimed: usize = removed
+        .iter()
+        .map(|chunk| chunk.length() as usize + Chunk::METADATA_LENGTH)
+        .sum();
+    status!(
+        output,
+        "Removed {} chunk(s), {} bytes reclaimed",
+        removed.len(),
+        reclaimed
+    );
+}
+
+pub fn capacity(args: CapacityArgs) -> Result<()> {
+    let png = Png::from_chunks(open(&args.file_path)?.collect::<Result<_>>()?);
+    let modes = match args.mode {
+        Some(mode) => vec![mode],
+        None => EmbeddingMode::ALL.to_vec(),
+    };
+    for mode in modes {
+        let capacity = png.embedding_capacity(mode)?;
+        let max_payload = match (capacity.max_payload, capacity.chunk_payload) {
+            (Some(bytes), _) => format!("{} bytes", bytes),
+            (None, Some(bytes)) => format!("unlimited, up to {} bytes per chunk", bytes),
+            (None, None) => "unlimited".to_string(),
+        };
+        println!(
+            "{}: {}, a {} byte payload adds about {} bytes",
+            mode,
+            max_payload,
+            args.payload_size,
+            capacity.growth(args.payload_size)
+        );
+        if mode != EmbeddingMode::Lsb {
+            continue;
+        }
+        let lsb = match png.lsb_capacity() {
+            Ok(lsb) => lsb,
+            Err(err) => {
+                println!("  {}", err);
+                continue;
+            }
+        };
+        println!(
+            "  {} bit samples, {} colour channel(s), {} samples",
+            lsb.bit_depth, lsb.channels, lsb.samples
+        );
+        for bits in LsbCapacity::BITS_PER_CHANNEL {
+            let max_payload = lsb.max_payload(bits);
+            let payload = match lsb.psnr(bits, args.payload_size) {
+                Some(psnr) => format!("{:.1} dB with {} bytes", psnr, args.payload_size),
+                None => format!("{} bytes don't fit", args.payload_size),
+            };
+            let full = match lsb.psnr(bits, max_payload) {
+                Some(psnr) => format!("PSNR about {:.1} dB when full", psnr),
+                None => "no room for the length header".to_string(),
+            };
+            println!(
+                "  {} bit(s) per channel: {} bytes, {}, {}",
+                bits, max_payload, full, payload
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn analyze(args: AnalyzeArgs, format: OutputFormat) -> Result<()> {
+    let mut bytes = Vec::new();
+    input(&args.file_path)?.read_to_end(&mut bytes)?;
+    let analysis = analyze::analyze_file(&bytes)?;
+    if format == OutputFormat::Json {
+        println!("{}", analysis_json(&args.file_path, &analysis));
+        return Ok(());
+    }
+    match analysis.chi_square {
+        Some(chi_square) => println!(
+            "chi-square: {:.2} probability of LSB embedding, over the first {:.0}% of samples",
+            chi_square.probability,
+            chi_square.share * 100.0
+        ),
+        None => println!("chi-square: not run, the image isn't 8 bit greyscale or truecolour"),
+    }
+    if let Some(rate) = analysis.embedding_rate {
+        println!(
+            "sample pairs: about {:.1}% of samples carry hidden bits",
+            rate * 100.0
+        );
+    }
+    println!(
+        "{:>5}  {:<4}  {:>10}  {:>7}",
+        "INDEX", "TYPE", "LENGTH", "ENTROPY"
+    );
+    for chunk in &analysis.entropies {
+        println!(
+            "{:>5}  {:<4}  {:>10}  {:>7.2}",
+            chunk.index, chunk.chunk_type, chunk.length, chunk.entropy
+        );
+    }
+    for anomaly in &analysis.chunks {
+        println!(
+            "chunk #{} {}, {} bytes, {:.1} bits/byte entropy: {}",
+            anomaly.index, anomaly.chunk_type, anomaly.length, anomaly.entropy, anomaly.anomaly
+        );
+    }
+    if let Some(trailing) = analysis.trailing {
+        println!(
+            "after IEND: {} bytes at offset {}, {:.1} bits/byte entropy, extract --after-iend saves them",
+            trailing.length, trailing.offset, trailing.entropy
+        );
+        if let Some(archive) = trailing.archive {
+            println!(
+                "polyglot: the file is a ZIP archive too, {} entries from offset {}",
+                archive.entries, archive.offset
+            );
+        }
+    }
+    println!(
+        "{}: score {:.2}, hidden data {}",
+        args.file_path.display(),
+        analysis.score(),
+        analysis.verdict()
+    );
+    Ok(())
+}
+
+pub fn polyglot(args: PolyglotArgs) -> Result<()> {
+    let mut image = Vec::new();
+    input(&args.image)?.read_to_end(&mut image)?;
+    let mut archive = Vec::new();
+    input(&args.archive)?.read_to_end(&mut archive)?;
+    write_payload(&args.output, &polyglot::create(&image, &archive)?)
+}
+
+pub fn bitplanes(args: BitplanesArgs) -> Result<()> {
+    let png = Png::from_chunks(open(&args.file_path)?.collect::<Result<_>>()?);
+    let planes = png.bit_planes()?;
+    fs::create_dir_all(&args.out)?;
+    for plane in &planes {
+        let path = args
+            .out
+            .join(format!("{}_{}.png", plane.channel, plane.bit));
+        fs::write(path, plane.png.as_bytes())?;
+    }
+    println!(
+        "Wrote {} bit planes to {}",
+        planes.len(),
+        args.out.display()
+    );
+    Ok(())
+}
+
+pub fn cover(args: CoverArgs) -> Result<()> {
+    let png = Png::cover(args.size, args.style, args.seed)?;
+    let capacity = stego::capacity(&png.header()?)?;
+    log::info!(
+        "{} {} cover, {} bytes fit in --mode lsb",
+        args.size,
+        args.style,
+        capacity
+    );
+    write_payload(&args.output, &png.as_bytes())
+}
+
+fn analysis_json(file: &Path, analysis: &Analysis) -> Value {
+    let chunks: Vec<Value> = analysis
+        .chunks
+        .iter()
+        .map(|anomaly| {
+            json!({
+                "index": anomaly.index,
+                "type": anomaly.chunk_type.to_string(),
+                "length": anomaly.length,
+                "entropy": anomaly.entropy,
+                "anomaly": anomaly.anomaly.to_string(),
+            })
+        })
+        .collect();
+    let entropies: Vec<Value> = analysis
+        .entropies
+        .iter()
+        .map(|chunk| {
+            json!({
+                "index": chunk.index,
+                "type": chunk.chunk_type.to_string(),
+                "length": chunk.length,
+                "entropy": chunk.entropy,
+            })
+        })
+        .collect();
+    json!({
+        "file": file.display().to_string(),
+        "chi_square": analysis.chi_square.map(|chi_square| json!({
+            "probability": chi_square.probability,
+            "share": chi_square.share,
+        })),
+        "embedding_rate": analysis.embedding_rate,
+        "chunks": chunks,
+        "entropies": entropies,
+        "trailing": analysis.trailing.map(|trailing| json!({
+            "offset": trailing.offset,
+            "length": trailing.length,
+            "entropy": trailing.entropy,
+            "archive": trailing.archive.map(|archive| json!({
+                "offset": archive.offset,
+                "entries": archive.entries,
+            })),
+        })),
+        "score": analysis.score(),
+        "verdict": analysis.verdict().to_string(),
+    })
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    StdinUsedTwice,
+    PasswordMismatch,
+    EmptyPassword,
+    NoPrompt(io::Error),
+    KeyFileReadable(PathBuf),
+    NotText,
+    NoChecksum,
+    ShredWithBackup,
+    StealthWithChunkType,
+    StripCritical(ChunkType),
+    NotInChunks(EmbeddingMode, &'static str),
+    NotWithFrames(&'static str),
+    UnsupportedMode(EmbeddingMode),
+    DecoyPasswordReused,
+    DecoyChunkType,
+    HiddenTooLarge(usize, usize),
+    TooManyShards(usize),
+    DuplicateOutput(PathBuf),
+}
+
+impl std::error::Error for CommandError {}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StdinUsedTwice => write!(f, "stdin can't hold both the PNG and the message"),
+            Self::PasswordMismatch => write!(f, "passwords don't match"),
+            Self::EmptyPassword => write!(f, "the password can't be empty"),
+            Self::NoPrompt(err) => write!(
+                f,
+                "can't prompt for the password ({}), set PNGME_PASSWORD instead",
+                err
+            ),
+            Self::NotText => write!(
+                f,
+                "payload isn't UTF-8 text, show it with --encoding or write it with --raw or --out"
+            ),
+            Self::StealthWithChunkType => write!(
+                f,
+                "--stealth derives the chunk type, give the message right after the file path"
+            ),
+            Self::NotInChunks(mode, option) => write!(
+                f,
+                "--mode {} doesn't store the payload in chunks, it can't be used with {}",
+                mode, option
+            ),
+            Self::NotWithFrames(option) => write!(
+                f,
+                "--mode frames places its chunks after every frame, it can't be used with {}",
+                option
+            ),
+            Self::UnsupportedMode(mode) => write!(
+                f,
+                "{} mode is only reported by capacity, use chunk, lsb, alpha, palette, after-iend or frames",
+                mode
+            ),
+            Self::DecoyPasswordReused => {
+                write!(f, "the decoy password must differ from the password")
+            }
+            Self::DecoyChunkType => write!(
+                f,
+                "the password and the decoy password derive the same chunk type, change one"
+            ),
+            Self::HiddenTooLarge(length, part_size) => write!(
+                f,
+                "the hidden payload is {} bytes, it must fit in one chunk of --part-size {}",
+                length, part_size
+            ),
+            Self::TooManyShards(count) => {
+                write!(f, "{} files given, a payload has at most 255 shards", count)
+            }
+            Self::DuplicateOutput(path) => write!(
+                f,
+                "two shards would be written to {}, give carriers distinct names",
+                path.display()
+            ),
+            Self::ShredWithBackup => write!(f, "--shred can't be used while backups are kept"),
+            Self::StripCritical(chunk_type) => write!(
+                f,
+                "{} is a critical chunk, the image can't be read without it",
+                chunk_type
+            ),
+            Self::KeyFileReadable(path) => write!(
+                f,
+                "{} is readable by every user, restrict it with chmod 600",
+                path.display()
+            ),
+            Self::NoChecksum => write!(
+                f,
+                "payload was stored without envelope and has no checksum, migrate with its chunk type adds one to a message"
+            ),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+/*!
+ * User defaults read from `$XDG_CONFIG_HOME/pngme/config.toml`, falling back to
+ * `~/.config/pngme/config.toml`. Flags given on the command line always win.
+ *
+ * ```toml
+ * #Chunk type used by decode, verify, verify-payload, remove and extract when none is given
+ * chunk_type = "ruSt"
+ * #text or json
+ * format = "json"
+ * #Keep a .bak copy of files modified in place
+ * backup = true
+ * #Layouts of private chunk types for inspect and decode, see `pngme::schema`
+ * schema = "/home/me/.config/pngme/schema.toml"
+ *
+ * [encryption]
+ * #--key-file of encode, decode and verify-payload, --key-from still takes precedence
+ * key_file = "/home/me/.config/pngme/key"
+ * #--kdf-memory, --kdf-iterations and --kdf-parallelism of encode
+ * kdf_memory = 262144
+ * kdf_iterations = 3
+ * kdf_parallelism = 4
+ * ```
+ */
+use pngme::Result;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub chunk_type: Option<String>,
+    pub format: Option<String>,
+    #[serde(default)]
+    pub backup: bool,
+    pub schema: Option<String>,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionConfig {
+    pub key_file: Option<String>,
+    pub kdf_memory: Option<u32>,
+    pub kdf_iterations: Option<u32>,
+    pub kdf_parallelism: Option<u32>,
+}
+
+impl Config {
+    //The user's config, the defaults when there is no file
+    pub fn load() -> Result<Config> {
+        let path = match path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(Box::new(err)),
+        };
+        let config = toml::from_str(&text)
+            .map_err(|err| ConfigError::Invalid(path, err.message().to_string()))?;
+        Ok(config)
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("pngme").join("config.toml"))
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Invalid(PathBuf, String),
+}
+
+impl std::error::Error for ConfigError {}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(path, message) => write!(f, "invalid {}: {}", path.display(), message),
+        }
+    }
+}
@@ -0,0 +1,344 @@
+/*!
+ * Generated cover images to hide payloads in. A flat image shows every changed low bit,
+ * so each style has grain in every sample: noise is fine fractal noise in every
+ * channel, gradient blends two colours across the image and photo-like layers fractal
+ * noise into soft shapes with the light falling from the top, like an out of focus
+ * photo.
+ * They must not look like they hide something already, so the grain is correlated
+ * between neighbouring pixels, as sensor noise after demosaicing is, which keeps
+ * sample pair analysis near 0. Samples are then developed to levels 1.5 apart, the comb
+ * a levels adjustment leaves in the histogram, which keeps the counts of the values 2k
+ * and 2k+1 apart as the chi-square test expects of a photo.
+ * The images are RGB written with the defaults of libpng.
+ */
+use crate::png::{Png, PngBuilder};
+use crate::Error;
+use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
+use std::fmt;
+use std::str::FromStr;
+
+//Amplitude of the grain added to every sample
+const GRAIN: f32 = 6.0;
+//Step between the levels samples are developed to
+const LEVELS: f32 = 1.5;
+//Lattice cell of the coarsest octave of the noise style, in pixels
+const NOISE_CELL: f32 = 16.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+//WIDTHxHEIGHT, e.g. 1920x1080
+impl FromStr for Size {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CoverError::InvalidSize(s.to_string());
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let size = Size {
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+        };
+        if size.width == 0 || size.height == 0 {
+            return Err(Box::new(invalid()));
+        }
+        Ok(size)
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Noise,
+    Gradient,
+    PhotoLike,
+}
+
+impl FromStr for Style {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "noise" => Ok(Self::Noise),
+            "gradient" => Ok(Self::Gradient),
+            "photo-like" => Ok(Self::PhotoLike),
+            _ => Err(Box::new(CoverError::InvalidStyle(s.to_string()))),
+        }
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Noise => "noise",
+            Self::Gradient => "gradient",
+            Self::PhotoLike => "photo-like",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//SplitMix64, enough for pixels and reproducible from a seed
+struct Generator(u64);
+
+impl Generator {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    //In [0, 1)
+    fn unit(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn colour(&mut self) -> [f32; 3] {
+        [0; 3].map(|_| 40.0 + self.unit() * 180.0)
+    }
+
+    //Centred on 0, about `GRAIN` wide
+    fn grain(&mut self) -> f32 {
+        (self.unit() + self.unit() - 1.0) * GRAIN
+    }
+
+    /**
+     * Grain for every sample of a `width` by `height` RGB image, white grain blurred
+     * over 3 by 3 pixels and scaled back to about `GRAIN` wide.
+     */
+    fn grain_field(&mut self, width: usize, height: usize) -> Vec<f32> {
+        let stride = (width + 2) * 3;
+        let white: Vec<f32> = (0..stride * (height + 2)).map(|_| self.grain()).collect();
+        let weights = [1.0, 2.0, 1.0];
+        let mut field = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width * 3 {
+                let mut sum = 0.0;
+                for (dy, wy) in weights.iter().enumerate() {
+                    for (dx, wx) in weights.iter().enumerate() {
+                        sum += wy * wx * white[(y + dy) * stride + x + dx * 3];
+                    }
+                }
+                field.push(sum / 6.0);
+            }
+        }
+        field
+    }
+}
+
+impl Png {
+    /**
+     * A `size` RGB image of the given style, the same for the same `seed`, random
+     * without one.
+     */
+    pub fn cover(size: Size, style: Style, seed: Option<u64>) -> Result<Png, Error> {
+        let mut png = PngBuilder::new(size.width, size.height).build()?;
+        let mut scanlines = png.scanlines()?;
+        let mut generator = Generator(seed.unwrap_or_else(|| OsRng.next_u64()));
+        let (width, height) = (size.width as usize, size.height as usize);
+        let grain = generator.grain_field(width, height);
+        match style {
+            Style::Noise => {
+                let channels =
+                    [0; 3].map(|_| fractal_noise(&mut generator, width, height, NOISE_CELL));
+                for (y, row) in scanlines.rows_mut().enumerate() {
+                    for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                        let index = y * width + x;
+                        for channel in 0..3 {
+                            let value = 20.0 + 215.0 * channels[channel][index];
+                            pixel[channel] = develop(value + grain[index * 3 + channel]);
+                        }
+                    }
+                }
+            }
+            Style::Gradient => {
+                let (from, to) = (generator.colour(), generator.colour());
+                let angle = generator.unit() * std::f32::consts::TAU;
+                let (dx, dy) = (angle.cos(), angle.sin());
+                //Projections of the corners, to stretch the blend over the whole image
+                let (right, bottom) = (width as f32, height as f32);
+                let corners = [(0.0, 0.0), (right, 0.0), (0.0, bottom), (right, bottom)];
+                let projected = corners.map(|(x, y)| x * dx + y * dy);
+                let start = projected.iter().copied().fold(f32::INFINITY, f32::min);
+                let end = projected.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                let extent = (end - start).max(f32::EPSILON);
+                for (y, row) in scanlines.rows_mut().enumerate() {
+                    for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                        let t = ((x as f32 * dx + y as f32 * dy - start) / extent).clamp(0.0, 1.0);
+                        let index = (y * width + x) * 3;
+                        for channel in 0..3 {
+                            let value = from[channel] + (to[channel] - from[channel]) * t;
+                            pixel[channel] = develop(value + grain[index + channel]);
+                        }
+                    }
+                }
+            }
+            Style::PhotoLike => {
+                let coarsest = width.max(height) as f32 / 3.0;
+                let shapes = fractal_noise(&mut generator, width, height, coarsest);
+                let tint = fractal_noise(&mut generator, width, height, coarsest);
+                let (light, dark) = (generator.colour(), generator.colour());
+                for (y, row) in scanlines.rows_mut().enumerate() {
+                    //Brighter towards the top, as under a sky
+                    let falloff = 1.1 - 0.4 * y as f32 / height as f32;
+                    for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                        let index = y * width + x;
+                        let brightness = (0.35 + 0.9 * shapes[index]) * falloff;
+                        for channel in 0..3 {
+                            let base =
+                                dark[channel] + (light[channel] - dark[channel]) * tint[index];
+                            let value = base * brightness + grain[index * 3 + channel];
+                            pixel[channel] = develop(value);
+                        }
+                    }
+                }
+            }
+        }
+        png.set_scanlines_libpng(&scanlines)?;
+        Ok(png)
+    }
+}
+
+//The nearest of the levels `LEVELS` apart, as an 8 bit sample
+fn develop(value: f32) -> u8 {
+    ((value / LEVELS).round() * LEVELS)
+        .round()
+        .clamp(0.0, 255.0) as u8
+}
+
+/**
+ * Octaves of value noise, random values on lattices from `coarsest` pixels apart down
+ * to 4 interpolated smoothly between lattice points, scaled to [0, 1]. Finer ones
+ * would be as uncorrelated as white noise.
+ */
+fn fractal_noise(
+    generator: &mut Generator,
+    width: usize,
+    height: usize,
+    coarsest: f32,
+) -> Vec<f32> {
+    let mut field = vec![0.0; width * height];
+    let mut cell = coarsest.max(4.0);
+    let mut amplitude = 1.0;
+    while cell >= 4.0 {
+        let columns = (width as f32 / cell) as usize + 2;
+        let rows = (height as f32 / cell) as usize + 2;
+        let lattice: Vec<f32> = (0..columns * rows).map(|_| generator.unit()).collect();
+        for y in 0..height {
+            let (row, fy) = split(y as f32 / cell);
+            for x in 0..width {
+                let (column, fx) = split(x as f32 / cell);
+                let at = |dx: usize, dy: usize| lattice[(row + dy) * columns + column + dx];
+                let top = at(0, 0) + (at(1, 0) - at(0, 0)) * fx;
+                let bottom = at(0, 1) + (at(1, 1) - at(0, 1)) * fx;
+                field[y * width + x] += (top + (bottom - top) * fy) * amplitude;
+            }
+        }
+        cell /= 2.0;
+        amplitude /= 2.0;
+    }
+    let (min, max) = field
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| {
+            (min.min(value), max.max(value))
+        });
+    let range = (max - min).max(f32::EPSILON);
+    field.iter().map(|value| (value - min) / range).collect()
+}
+
+//Lattice index and the smoothstep of the position between it and the next
+fn split(position: f32) -> (usize, f32) {
+    let fraction = position.fract();
+    (
+        position as usize,
+        fraction * fraction * (3.0 - 2.0 * fraction),
+    )
+}
+
+#[derive(Debug)]
+pub enum CoverError {
+    InvalidSize(String),
+    InvalidStyle(String),
+}
+
+impl std::error::Error for CoverError {}
+
+impl fmt::Display for CoverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSize(size) => {
+                write!(f, "invalid size: {}, expected WIDTHxHEIGHT", size)
+            }
+            Self::InvalidStyle(style) => write!(
+                f,
+                "invalid cover style: {}, expected noise, gradient or photo-like",
+                style
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::Verdict;
+    use crate::stego;
+
+    #[test]
+    pub fn test_cover() {
+        let size = Size::from_str("40x30").unwrap();
+        assert_eq!(size.to_string(), "40x30");
+        for style in [Style::Noise, Style::Gradient, Style::PhotoLike] {
+            let png = Png::cover(size, style, Some(7)).unwrap();
+            let header = png.header().unwrap();
+            assert_eq!((header.width, header.height), (40, 30));
+            assert_eq!(stego::capacity(&header).unwrap(), 446);
+            let data = png.scanlines().unwrap().into_data();
+            assert_eq!(
+                Png::cover(size, style, Some(7))
+                    .unwrap()
+                    .scanlines()
+                    .unwrap()
+                    .into_data(),
+                data
+            );
+            //Low bits set in about half of the samples
+            let set = data.iter().filter(|&&sample| sample & 1 == 1).count();
+            assert!((1200..2400).contains(&set), "{} {}", style, set);
+        }
+        //Nothing hidden yet as far as the detectors can tell
+        for style in [Style::Noise, Style::Gradient, Style::PhotoLike] {
+            for seed in 0..4 {
+                let png =
+                    Png::cover(Size::from_str("160x120").unwrap(), style, Some(seed)).unwrap();
+                let analysis = png.analyze().unwrap();
+                assert_eq!(
+                    analysis.verdict(),
+                    Verdict::Unlikely,
+                    "{} {} {:?}",
+                    style,
+                    seed,
+                    analysis
+                );
+            }
+        }
+        assert_ne!(
+            Png::cover(size, Style::PhotoLike, Some(1))
+                .unwrap()
+                .as_bytes(),
+            Png::cover(size, Style::PhotoLike, Some(2))
+                .unwrap()
+                .as_bytes()
+        );
+        assert!(Size::from_str("1920").is_err());
+        assert!(Size::from_str("0x10").is_err());
+        assert!(Style::from_str("cubist").is_err());
+    }
+}
@@ -0,0 +1,580 @@
+/*!
+ * Password based encryption of payloads with ChaCha20-Poly1305.
+ * An encrypted payload is `ENCRYPTED_MAGIC`, the Argon2id memory cost in KiB, time cost
+ * and parallelism as big endian u32, the 16 byte salt, the 12 byte nonce and the
+ * ciphertext followed by its 16 byte tag.
+ * The key is derived from the password with Argon2id, so guessing it is slow, and the
+ * cost parameters travel with the payload so they can be raised without breaking decode.
+ * With the age feature payloads can also be encrypted to age recipients, they are then
+ * stored in the binary age format, which starts with `AGE_MAGIC`.
+ * A signed payload is `SIGNED_MAGIC` and the 64 byte Ed25519 signature of the bytes
+ * that follow, encrypted or not, so it can be verified without decrypting.
+ * A payload with an integrity tag is `HMAC_MAGIC`, the payload and its 32 byte
+ * HMAC-SHA256 under a shared key.
+ * `stealth_chunk_type` derives the chunk type holding a payload from its password with
+ * Argon2id, so without the password nothing tells which chunk to look at, and checking
+ * a guess against the chunk type is as slow as trying to decrypt.
+ * A bare encrypted payload is only the salt, the nonce and the ciphertext with its tag,
+ * with nothing to tell it from random bytes, so the cost parameters have to be known.
+ */
+use crate::chunk_type::ChunkType;
+use crate::named;
+use crate::Error;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt;
+#[cfg(feature = "age")]
+use std::io::{Read, Write};
+#[cfg(feature = "age")]
+use std::str::FromStr;
+
+pub const ENCRYPTED_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'E'];
+pub const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+pub const SIGNED_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'S'];
+pub const HMAC_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'H'];
+const HMAC_LENGTH: usize = 32;
+const PARAMS_LENGTH: usize = 12;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+const DETERMINISTIC_CONTEXT: &[u8] = b"pngme deterministic salt";
+const STEALTH_CONTEXT: &[u8] = b"pngme stealth chunk type";
+const STEALTH_SALT: [u8; SALT_LENGTH] = *b"pngme-stealth-ct";
+pub const HEADER_LENGTH: usize = ENCRYPTED_MAGIC.len() + PARAMS_LENGTH + SALT_LENGTH + NONCE_LENGTH;
+//Bytes `encrypt_bare` adds to a payload
+pub const BARE_OVERHEAD: usize = SALT_LENGTH + NONCE_LENGTH + TAG_LENGTH;
+//Refuses memory costs above 2 GiB, a crafted payload could otherwise exhaust memory
+pub const MAX_MEMORY_KIB: u32 = 2 << 20;
+//Refuses more passes and lanes too, a crafted payload could otherwise take hours
+pub const MAX_ITERATIONS: u32 = 64;
+pub const MAX_PARALLELISM: u32 = 16;
+
+//Argon2id cost parameters, the defaults are the ones recommended by OWASP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+//Encrypted with a password or to age recipients
+pub fn is_encrypted(payload: &[u8]) -> bool {
+    payload.starts_with(&ENCRYPTED_MAGIC) || payload.starts_with(AGE_MAGIC)
+}
+
+//Encrypts with a fresh salt and nonce, encrypting twice never gives the same bytes
+pub fn encrypt(payload: &[u8], password: &[u8], params: &KdfParams) -> Result<Vec<u8>, Error> {
+    let mut salt = [0; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(password, &salt, params)?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    encrypt_with(payload, &key, &salt, &nonce, params)
+}
+
+/**
+ * Same as `encrypt`, but the same payload, password and `context` always give the same
+ * output. The salt is a hash of the context, so Argon2 work can be shared between
+ * attacks on every payload encrypted this way in the same context, and equal payloads
+ * under the same password and context are visibly equal.
+ * The nonce is a keyed hash of the payload, so it only repeats for the same payload.
+ */
+pub fn encrypt_deterministic(
+    payload: &[u8],
+    password: &[u8],
+    params: &KdfParams,
+    context: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let digest = Sha256::new()
+        .chain_update(DETERMINISTIC_CONTEXT)
+        .chain_update(context)
+        .finalize();
+    let salt = &digest[..SALT_LENGTH];
+    let key = derive_key(password, salt, params)?;
+    //A key of its own for the nonce, derived from the encryption key
+    let nonce_key = Sha256::new()
+        .chain_update(b"pngme nonce key")
+        .chain_update(key)
+        .finalize();
+    let digest = hmac(&nonce_key)?
+        .chain_update(payload)
+        .finalize()
+        .into_bytes();
+    let nonce = Nonce::clone_from_slice(&digest[..NONCE_LENGTH]);
+    encrypt_with(payload, &key, salt, &nonce, params)
+}
+
+fn encrypt_with(
+    payload: &[u8],
+    key: &Key,
+    salt: &[u8],
+    nonce: &Nonce,
+    params: &KdfParams,
+) -> Result<Vec<u8>, Error> {
+    let cipher = ChaCha20Poly1305::new(key);
+    let ciphertext = cipher
+        .encrypt(nonce, payload)
+        .map_err(|_| CryptoError::Encryption)?;
+
+    let mut encrypted = Vec::with_capacity(HEADER_LENGTH + ciphertext.len());
+    encrypted.extend_from_slice(&ENCRYPTED_MAGIC);
+    for cost in [params.memory_kib, params.iterations, params.parallelism] {
+        encrypted.extend_from_slice(&cost.to_be_bytes());
+    }
+    encrypted.extend_from_slice(salt);
+    encrypted.extend_from_slice(nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    Ok(encrypted)
+}
+
+//Encrypts without magic or cost parameters, see `decrypt_bare`
+pub fn encrypt_bare(payload: &[u8], password: &[u8], params: &KdfParams) -> Result<Vec<u8>, Error> {
+    let encrypted = encrypt(payload, password, params)?;
+    Ok(encrypted[ENCRYPTED_MAGIC.len() + PARAMS_LENGTH..].to_vec())
+}
+
+//Decrypts what `encrypt_bare` gave for the same cost parameters
+pub fn decrypt_bare(payload: &[u8], password: &[u8], params: &KdfParams) -> Result<Vec<u8>, Error> {
+    let mut encrypted = Vec::with_capacity(ENCRYPTED_MAGIC.len() + PARAMS_LENGTH + payload.len());
+    encrypted.extend_from_slice(&ENCRYPTED_MAGIC);
+    for cost in [params.memory_kib, params.iterations, params.parallelism] {
+        encrypted.extend_from_slice(&cost.to_be_bytes());
+    }
+    encrypted.extend_from_slice(payload);
+    decrypt(&encrypted, password)
+}
+
+//Fails the same way for a wrong password and a tampered payload
+pub fn decrypt(payload: &[u8], password: &[u8]) -> Result<Vec<u8>, Error> {
+    let rest = payload
+        .strip_prefix(&ENCRYPTED_MAGIC)
+        .ok_or(CryptoError::NotEncrypted)?;
+    if rest.len() < PARAMS_LENGTH + SALT_LENGTH + NONCE_LENGTH {
+        return Err(Box::new(CryptoError::Truncated));
+    }
+    let (params, rest) = rest.split_at(PARAMS_LENGTH);
+    let cost = |at: usize| {
+        u32::from_be_bytes([params[at], params[at + 1], params[at + 2], params[at + 3]])
+    };
+    let params = KdfParams {
+        memory_kib: cost(0),
+        iterations: cost(4),
+        parallelism: cost(8),
+    };
+    let (salt, rest) = rest.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+    let cipher = ChaCha20Poly1305::new(&derive_key(password, salt, &params)?);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CryptoError::Decryption)?;
+    Ok(plaintext)
+}
+
+//Encrypts so any of the identities matching the `age1...` recipients can decrypt
+#[cfg(feature = "age")]
+pub fn encrypt_to(payload: &[u8], recipients: &[String]) -> Result<Vec<u8>, Error> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| {
+            age::x25519::Recipient::from_str(recipient)
+                .map_err(|err| CryptoError::InvalidRecipient(recipient.clone(), err.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let encryptor = age::Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient),
+    )
+    .map_err(|err| CryptoError::Age(err.to_string()))?;
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(payload)?;
+    writer.finish()?;
+    Ok(encrypted)
+}
+
+//Decrypts with the identities of an age identity file, as written by age-keygen
+#[cfg(feature = "age")]
+pub fn decrypt_with(payload: &[u8], identity_file: &[u8]) -> Result<Vec<u8>, Error> {
+    if !payload.starts_with(AGE_MAGIC) {
+        return Err(Box::new(CryptoError::NotEncrypted));
+    }
+    let identities = age::IdentityFile::from_buffer(identity_file)?
+        .into_identities()
+        .map_err(|err| CryptoError::Age(err.to_string()))?;
+    let decryptor =
+        age::Decryptor::new(payload).map_err(|err| CryptoError::Age(err.to_string()))?;
+    let mut reader = decryptor
+        .decrypt(
+            identities
+                .iter()
+                .map(|identity| identity.as_ref() as &dyn age::Identity),
+        )
+        .map_err(|_| CryptoError::Decryption)?;
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted)?;
+    Ok(decrypted)
+}
+
+//Prefixes the payload with its signature, `key_pem` is a PKCS#8 Ed25519 private key
+pub fn sign(payload: &[u8], key_pem: &str) -> Result<Vec<u8>, Error> {
+    let key = SigningKey::from_pkcs8_pem(key_pem)
+        .map_err(|err| CryptoError::InvalidKey(err.to_string()))?;
+    let signature = key.sign(payload);
+    let mut signed = Vec::with_capacity(SIGNED_MAGIC.len() + Signature::BYTE_SIZE + payload.len());
+    signed.extend_from_slice(&SIGNED_MAGIC);
+    signed.extend_from_slice(&signature.to_bytes());
+    signed.extend_from_slice(payload);
+    Ok(signed)
+}
+
+//Signature and signed bytes of a signed payload, None for any other payload
+pub fn split_signature(payload: &[u8]) -> Option<(&[u8], &[u8])> {
+    let rest = payload.strip_prefix(&SIGNED_MAGIC)?;
+    if rest.len() < Signature::BYTE_SIZE {
+        return None;
+    }
+    Some(rest.split_at(Signature::BYTE_SIZE))
+}
+
+//The signed bytes once checked against `pubkey_pem`, an SPKI Ed25519 public key
+pub fn verify<'a>(payload: &'a [u8], pubkey_pem: &str) -> Result<&'a [u8], Error> {
+    let (signature, signed) = split_signature(payload).ok_or(CryptoError::NotSigned)?;
+    let key = VerifyingKey::from_public_key_pem(pubkey_pem)
+        .map_err(|err| CryptoError::InvalidKey(err.to_string()))?;
+    let signature = Signature::from_slice(signature).map_err(|_| CryptoError::BadSignature)?;
+    key.verify(signed, &signature)
+        .map_err(|_| CryptoError::BadSignature)?;
+    Ok(signed)
+}
+
+pub fn has_hmac(payload: &[u8]) -> bool {
+    payload.starts_with(&HMAC_MAGIC)
+}
+
+//Appends the HMAC-SHA256 of the payload under `key`
+pub fn append_hmac(payload: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut tagged = Vec::with_capacity(HMAC_MAGIC.len() + payload.len() + HMAC_LENGTH);
+    tagged.extend_from_slice(&HMAC_MAGIC);
+    tagged.extend_from_slice(payload);
+    let tag = hmac(key)?.chain_update(payload).finalize().into_bytes();
+    tagged.extend_from_slice(&tag);
+    Ok(tagged)
+}
+
+//The payload without its tag, once the tag is checked in constant time
+pub fn check_hmac<'a>(payload: &'a [u8], key: &[u8]) -> Result<&'a [u8], Error> {
+    let rest = payload
+        .strip_prefix(&HMAC_MAGIC)
+        .ok_or(CryptoError::NoHmac)?;
+    if rest.len() < HMAC_LENGTH {
+        return Err(Box::new(CryptoError::Truncated));
+    }
+    let (payload, tag) = rest.split_at(rest.len() - HMAC_LENGTH);
+    hmac(key)?
+        .chain_update(payload)
+        .verify_slice(tag)
+        .map_err(|_| CryptoError::BadHmac)?;
+    Ok(payload)
+}
+
+fn hmac(key: &[u8]) -> Result<Hmac<Sha256>, Error> {
+    let mac = <Hmac<Sha256> as Mac>::new_from_slice(key)
+        .map_err(|err| CryptoError::InvalidKey(err.to_string()))?;
+    Ok(mac)
+}
+
+/**
+ * Chunk type derived from a password, ancillary, private and safe to copy like the
+ * usual payload types but never `named::TOC_CHUNK_TYPE`.
+ * The password goes through Argon2id with the default costs and a fixed salt first, a
+ * fast hash would let anyone holding the file rule out guesses without the KDF.
+ */
+pub fn stealth_chunk_type(password: &[u8]) -> Result<ChunkType, Error> {
+    let key = derive_key(password, &STEALTH_SALT, &KdfParams::default())?;
+    let chunk_type = (0..=u8::MAX)
+        .map(|counter| {
+            let digest = Sha256::new()
+                .chain_update(STEALTH_CONTEXT)
+                .chain_update([counter])
+                .chain_update(key)
+                .finalize();
+            let letter = |byte: u8| b'a' + byte % 26;
+            let bytes = [
+                letter(digest[0]),
+                letter(digest[1]),
+                //The reserved bit must be clear
+                letter(digest[2]).to_ascii_uppercase(),
+                letter(digest[3]),
+            ];
+            ChunkType::try_from(bytes).expect("letters are a valid chunk type")
+        })
+        .find(|chunk_type| chunk_type.to_string() != named::TOC_CHUNK_TYPE)
+        .expect("a digest other than the table of contents type");
+    Ok(chunk_type)
+}
+
+pub(crate) fn derive_key(password: &[u8], salt: &[u8], params: &KdfParams) -> Result<Key, Error> {
+    if params.memory_kib > MAX_MEMORY_KIB {
+        return Err(Box::new(CryptoError::MemoryCostTooHigh(params.memory_kib)));
+    }
+    if params.iterations > MAX_ITERATIONS {
+        return Err(Box::new(CryptoError::IterationsTooHigh(params.iterations)));
+    }
+    if params.parallelism > MAX_PARALLELISM {
+        return Err(Box::new(CryptoError::ParallelismTooHigh(
+            params.parallelism,
+        )));
+    }
+    let params = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        None,
+    )
+    .map_err(|err| CryptoError::KeyDerivation(err.to_string()))?;
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password, salt, &mut key)
+        .map_err(|err| CryptoError::KeyDerivation(err.to_string()))?;
+    Ok(key)
+}
+
+#[derive(Debug)]
+pub enum CryptoError {
+    NotEncrypted,
+    Encrypted,
+    Truncated,
+    MemoryCostTooHigh(u32),
+    IterationsTooHigh(u32),
+    ParallelismTooHigh(u32),
+    KeyDerivation(String),
+    Encryption,
+    Decryption,
+    InvalidRecipient(String, String),
+    Age(String),
+    InvalidKey(String),
+    NotSigned,
+    BadSignature,
+    NoHmac,
+    HmacKeyNeeded,
+    BadHmac,
+}
+
+impl std::error::Error for CryptoError {}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEncrypted => write!(f, "payload isn't encrypted"),
+            Self::Encrypted => write!(
+                f,
+                "payload is encrypted, a password or age identity is needed"
+            ),
+            Self::Truncated => write!(f, "encrypted payload is truncated"),
+            Self::MemoryCostTooHigh(memory) => write!(
+                f,
+                "key derivation memory of {} KiB is over the {} KiB limit",
+                memory, MAX_MEMORY_KIB
+            ),
+            Self::IterationsTooHigh(iterations) => write!(
+                f,
+                "key derivation of {} passes is over the {} passes limit",
+                iterations, MAX_ITERATIONS
+            ),
+            Self::ParallelismTooHigh(parallelism) => write!(
+                f,
+                "key derivation over {} lanes is over the {} lanes limit",
+                parallelism, MAX_PARALLELISM
+            ),
+            Self::KeyDerivation(message) => write!(f, "key derivation failed: {}", message),
+            Self::Encryption => write!(f, "encryption failed"),
+            Self::Decryption => write!(f, "wrong password or damaged payload"),
+            Self::InvalidRecipient(recipient, message) => {
+                write!(f, "invalid age recipient {}: {}", recipient, message)
+            }
+            Self::Age(message) => write!(f, "age: {}", message),
+            Self::InvalidKey(message) => write!(f, "invalid Ed25519 key: {}", message),
+            Self::NotSigned => write!(f, "payload isn't signed"),
+            Self::NoHmac => write!(f, "payload has no HMAC tag"),
+            Self::HmacKeyNeeded => {
+                write!(f, "payload has an HMAC tag, a key is needed to check it")
+            }
+            Self::BadHmac => write!(
+                f,
+                "HMAC doesn't match, the payload was tampered with or the key is wrong"
+            ),
+            Self::BadSignature => write!(
+                f,
+                "signature doesn't match, the payload was tampered with or signed by another key"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Cheap parameters so the tests stay fast
+    const PARAMS: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    pub fn test_encrypt_decrypt() {
+        let encrypted = encrypt(b"attack at dawn", b"hunter2", &PARAMS).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(encrypted.len(), HEADER_LENGTH + 14 + 16);
+        assert_eq!(decrypt(&encrypted, b"hunter2").unwrap(), b"attack at dawn");
+        assert_ne!(
+            encrypt(b"attack at dawn", b"hunter2", &PARAMS).unwrap(),
+            encrypted
+        );
+    }
+
+    #[test]
+    pub fn test_encrypt_deterministic() {
+        let encrypt = |payload: &[u8], context: &[u8]| {
+            encrypt_deterministic(payload, b"hunter2", &PARAMS, context).unwrap()
+        };
+        let encrypted = encrypt(b"attack at dawn", b"ruSt");
+        assert_eq!(decrypt(&encrypted, b"hunter2").unwrap(), b"attack at dawn");
+        assert_eq!(encrypt(b"attack at dawn", b"ruSt"), encrypted);
+        //Another context salts the key differently
+        let elsewhere = encrypt(b"attack at dawn", b"ruSt\0label");
+        assert_ne!(elsewhere[..HEADER_LENGTH], encrypted[..HEADER_LENGTH]);
+        assert_eq!(decrypt(&elsewhere, b"hunter2").unwrap(), b"attack at dawn");
+        let other = encrypt(b"attack at dusk", b"ruSt");
+        //Same salt, different nonce
+        assert_eq!(
+            other[..HEADER_LENGTH - NONCE_LENGTH],
+            encrypted[..HEADER_LENGTH - NONCE_LENGTH]
+        );
+        assert_ne!(other[..HEADER_LENGTH], encrypted[..HEADER_LENGTH]);
+    }
+
+    #[test]
+    pub fn test_encrypt_bare() {
+        let encrypted = encrypt_bare(b"attack at dawn", b"hunter2", &PARAMS).unwrap();
+        assert!(!is_encrypted(&encrypted));
+        assert_eq!(encrypted.len(), BARE_OVERHEAD + 14);
+        let decrypted = decrypt_bare(&encrypted, b"hunter2", &PARAMS).unwrap();
+        assert_eq!(decrypted, b"attack at dawn");
+        assert!(decrypt_bare(&encrypted, b"hunter3", &PARAMS).is_err());
+        assert!(decrypt_bare(&encrypted[..20], b"hunter2", &PARAMS).is_err());
+    }
+
+    #[test]
+    pub fn test_decrypt_failures() {
+        let mut encrypted = encrypt(b"attack at dawn", b"hunter2", &PARAMS).unwrap();
+        assert!(decrypt(&encrypted, b"hunter3").is_err());
+        *encrypted.last_mut().unwrap() ^= 1;
+        assert!(decrypt(&encrypted, b"hunter2").is_err());
+        assert!(decrypt(b"attack at dawn", b"hunter2").is_err());
+    }
+
+    #[test]
+    pub fn test_params_in_header() {
+        let params = KdfParams {
+            memory_kib: 128,
+            iterations: 2,
+            ..PARAMS
+        };
+        let mut encrypted = encrypt(b"attack at dawn", b"hunter2", &params).unwrap();
+        assert_eq!(encrypted[4..8], 128u32.to_be_bytes());
+        assert_eq!(decrypt(&encrypted, b"hunter2").unwrap(), b"attack at dawn");
+        //Decoding with other costs derives another key
+        encrypted[11] = 1;
+        assert!(decrypt(&encrypted, b"hunter2").is_err());
+        encrypted[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decrypt(&encrypted, b"hunter2").is_err());
+        //Crafted passes and lanes are refused before any work is done
+        encrypted[4..8].copy_from_slice(&128u32.to_be_bytes());
+        for (range, limit) in [(8..12, MAX_ITERATIONS), (12..16, MAX_PARALLELISM)] {
+            let mut crafted = encrypted.clone();
+            crafted[range].copy_from_slice(&(limit + 1).to_be_bytes());
+            let error = decrypt(&crafted, b"hunter2").unwrap_err();
+            assert!(error.to_string().contains("limit"), "{}", error);
+        }
+    }
+
+    #[test]
+    pub fn test_sign_verify() {
+        use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+        use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let key_pem = key.to_pkcs8_pem(LineEnding::LF).unwrap();
+        let pubkey_pem = key
+            .verifying_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap();
+        let mut signed = sign(b"attack at dawn", &key_pem).unwrap();
+        assert_eq!(verify(&signed, &pubkey_pem).unwrap(), b"attack at dawn");
+
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        let other_pem = other.to_public_key_pem(LineEnding::LF).unwrap();
+        assert!(verify(&signed, &other_pem).is_err());
+        *signed.last_mut().unwrap() ^= 1;
+        assert!(verify(&signed, &pubkey_pem).is_err());
+        assert!(verify(b"attack at dawn", &pubkey_pem).is_err());
+    }
+
+    #[test]
+    pub fn test_hmac() {
+        let mut tagged = append_hmac(b"attack at dawn", b"shared").unwrap();
+        assert!(has_hmac(&tagged));
+        assert_eq!(check_hmac(&tagged, b"shared").unwrap(), b"attack at dawn");
+        assert!(check_hmac(&tagged, b"guess").is_err());
+        tagged[5] ^= 1;
+        assert!(check_hmac(&tagged, b"shared").is_err());
+        assert!(check_hmac(b"attack at dawn", b"shared").is_err());
+    }
+
+    #[test]
+    pub fn test_stealth_chunk_type() {
+        let chunk_type = stealth_chunk_type(b"correct horse").unwrap();
+        assert_eq!(chunk_type, stealth_chunk_type(b"correct horse").unwrap());
+        assert_ne!(chunk_type, stealth_chunk_type(b"battery staple").unwrap());
+        assert!(chunk_type.is_valid());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+        assert!(chunk_type.is_safe_to_copy());
+    }
+
+    #[cfg(feature = "age")]
+    #[test]
+    pub fn test_age_recipients() {
+        use age::secrecy::ExposeSecret;
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let encrypted = encrypt_to(b"attack at dawn", &[recipient]).unwrap();
+        assert!(is_encrypted(&encrypted));
+        let identity_file = identity.to_string().expose_secret().to_string();
+        assert_eq!(
+            decrypt_with(&encrypted, identity_file.as_bytes()).unwrap(),
+            b"attack at dawn"
+        );
+        let other = age::x25519::Identity::generate();
+        let other_file = other.to_string().expose_secret().to_string();
+        assert!(decrypt_with(&encrypted, other_file.as_bytes()).is_err());
+        assert!(encrypt_to(b"attack at dawn", &["age1nope".to_string()]).is_err());
+    }
+}
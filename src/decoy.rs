@@ -0,0 +1,197 @@
+/*!
+ * Decoy chunks, random data of plausible sizes stored next to a payload so its chunks
+ * aren't the only odd ones in the file.
+ * Encrypted or compressed payloads look random too, so nothing but the key tells the
+ * decoys apart. Their types are private ancillary ones like the payload's.
+ * A hidden payload among them is padded to a length drawn the same way, ending with the
+ * big endian u32 length of the padding, so its size doesn't single it out either.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::named;
+use crate::Error;
+use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
+use std::fmt;
+use std::ops::Range;
+
+//Decoys a hidden payload is stored among at least
+pub const MIN_HIDDEN_DECOYS: usize = 3;
+pub const PADDING_LENGTH: usize = 4;
+
+//Random number below `bound`, which must not be 0
+fn below(bound: u32) -> u32 {
+    OsRng.next_u32() % bound
+}
+
+pub(crate) fn random_chunk_type() -> ChunkType {
+    let letter = || b'a' + below(26) as u8;
+    //Ancillary, private, reserved bit clear and safe to copy
+    let bytes = [letter(), letter(), letter().to_ascii_uppercase(), letter()];
+    ChunkType::try_from(bytes).expect("letters are a valid chunk type")
+}
+
+//Lengths of the decoys of `payload`, from half to one and a half times its longest chunk
+pub fn lengths(payload: &[Chunk]) -> Range<u32> {
+    let typical_length = payload
+        .iter()
+        .map(|chunk| chunk.length())
+        .max()
+        .unwrap_or(64)
+        .max(2);
+    typical_length / 2..typical_length / 2 + typical_length
+}
+
+/**
+ * `count` chunks of random data with `lengths(payload)`, of types other than those of
+ * `payload`, of `others` and of the table of contents.
+ */
+pub fn generate(count: usize, payload: &[Chunk], others: &[ChunkType]) -> Vec<Chunk> {
+    let lengths = lengths(payload);
+    let mut decoys = Vec::with_capacity(count);
+    while decoys.len() < count {
+        let chunk_type = random_chunk_type();
+        if payload
+            .iter()
+            .any(|chunk| *chunk.chunk_type() == chunk_type)
+            || others.contains(&chunk_type)
+            || chunk_type.to_string() == named::TOC_CHUNK_TYPE
+        {
+            continue;
+        }
+        let length = lengths.start + below(lengths.end - lengths.start);
+        let mut data = vec![0; length as usize];
+        OsRng.fill_bytes(&mut data);
+        decoys.push(Chunk::new(chunk_type, data));
+    }
+    decoys
+}
+
+/**
+ * A random length of `lengths(payload)` for a hidden payload that will be `needed`
+ * bytes long at least, failing when it doesn't fit the lengths.
+ */
+pub fn hidden_length(payload: &[Chunk], needed: usize) -> Result<usize, Error> {
+    let lengths = lengths(payload);
+    let start = lengths.start.max(needed as u32);
+    if needed >= lengths.end as usize {
+        return Err(Box::new(DecoyError::HiddenTooLarge(
+            needed,
+            lengths.end as usize - 1,
+        )));
+    }
+    Ok((start + below(lengths.end - start)) as usize)
+}
+
+//`payload` followed by random padding and its length, `length` bytes in all
+pub fn pad(payload: &[u8], length: usize) -> Vec<u8> {
+    let padding = length.saturating_sub(payload.len() + PADDING_LENGTH);
+    let mut padded = payload.to_vec();
+    padded.resize(payload.len() + padding, 0);
+    OsRng.fill_bytes(&mut padded[payload.len()..]);
+    padded.extend_from_slice(&(padding as u32).to_be_bytes());
+    padded
+}
+
+//The payload `pad` was given
+pub fn unpad(padded: &[u8]) -> Result<&[u8], Error> {
+    let rest = padded
+        .len()
+        .checked_sub(PADDING_LENGTH)
+        .ok_or(DecoyError::InvalidPadding)?;
+    let length = u32::from_be_bytes(padded[rest..].try_into().expect("4 bytes")) as usize;
+    let end = rest.checked_sub(length).ok_or(DecoyError::InvalidPadding)?;
+    Ok(&padded[..end])
+}
+
+//Inserts the decoys at random places among the payload chunks, whose order is kept
+pub fn mix(mut chunks: Vec<Chunk>, decoys: Vec<Chunk>) -> Vec<Chunk> {
+    for decoy in decoys {
+        let index = below(chunks.len() as u32 + 1) as usize;
+        chunks.insert(index, decoy);
+    }
+    chunks
+}
+
+#[derive(Debug)]
+pub enum DecoyError {
+    HiddenTooLarge(usize, usize),
+    InvalidPadding,
+}
+
+impl std::error::Error for DecoyError {}
+
+impl fmt::Display for DecoyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HiddenTooLarge(length, max) => write!(
+                f,
+                "the hidden payload is {} bytes, the decoys are {} at most, use a longer decoy",
+                length, max
+            ),
+            Self::InvalidPadding => write!(f, "invalid padding of a hidden payload"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    pub fn test_generate() {
+        let payload = vec![Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            vec![7; 100],
+        )];
+        let decoys = generate(20, &payload, &[]);
+        assert_eq!(decoys.len(), 20);
+        for decoy in &decoys {
+            assert_ne!(decoy.chunk_type().to_string(), "ruSt");
+            assert!(!decoy.chunk_type().is_critical() && !decoy.chunk_type().is_public());
+            assert!((50..150).contains(&decoy.length()));
+        }
+    }
+
+    #[test]
+    pub fn test_hidden_length() {
+        let payload = vec![Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            vec![7; 100],
+        )];
+        let decoys: Vec<usize> = generate(50, &payload, &[])
+            .iter()
+            .map(|decoy| decoy.length() as usize)
+            .collect();
+        let (shortest, longest) = (decoys.iter().min().unwrap(), decoys.iter().max().unwrap());
+        for needed in [10, 60, 120] {
+            let length = hidden_length(&payload, needed).unwrap();
+            assert!(length >= needed);
+            assert!(lengths(&payload).contains(&(length as u32)));
+            let padded = pad(&[1; 10], length);
+            assert_eq!(padded.len(), length);
+            assert_eq!(unpad(&padded).unwrap(), [1; 10]);
+        }
+        //Drawn like the decoys, which span about the same lengths
+        assert!(*shortest < 60 && *longest > 140, "{} {}", shortest, longest);
+        assert!(hidden_length(&payload, 150).is_err());
+        assert!(unpad(&[0, 0, 1]).is_err());
+        assert!(unpad(&[0, 0, 0, 9]).is_err());
+    }
+
+    #[test]
+    pub fn test_mix() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let payload: Vec<Chunk> = (0..3)
+            .map(|index| Chunk::new(chunk_type.clone(), vec![index]))
+            .collect();
+        let mixed = mix(payload.clone(), generate(5, &payload, &[]));
+        assert_eq!(mixed.len(), 8);
+        let kept: Vec<&[u8]> = mixed
+            .iter()
+            .filter(|chunk| *chunk.chunk_type() == chunk_type)
+            .map(|chunk| chunk.data())
+            .collect();
+        assert_eq!(kept, [[0], [1], [2]]);
+    }
+}
@@ -0,0 +1,293 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::text::{self, InternationalText, TextEntry};
+use crate::xmp;
+use crate::Error;
+use std::collections::HashMap;
+use std::fmt;
+
+//Where a chunk sits in its file and what it holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSummary {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub crc: u32,
+}
+
+impl ChunkSummary {
+    fn new(index: usize, chunk: &Chunk) -> ChunkSummary {
+        ChunkSummary {
+            index,
+            chunk_type: chunk.chunk_type().clone(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+        }
+    }
+}
+
+impl fmt::Display for ChunkSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} #{} ({} bytes, crc {:08x})",
+            self.chunk_type, self.index, self.length, self.crc
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkChange {
+    Added(ChunkSummary),
+    Removed(ChunkSummary),
+    Modified(ChunkSummary, ChunkSummary),
+}
+
+impl fmt::Display for ChunkChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(chunk) => write!(f, "+ {}", chunk),
+            Self::Removed(chunk) => write!(f, "- {}", chunk),
+            Self::Modified(old, new) => write!(f, "~ {} -> {}", old, new),
+        }
+    }
+}
+
+/**
+ * Compares two PNGs chunk by chunk. The Nth chunk of a type in `old` is compared
+ * with the Nth chunk of the same type in `new`: different content makes it modified,
+ * a missing counterpart makes it removed or added. Moving a chunk isn't a change.
+ * Changes are listed in the order of `old`, then the additions in the order of `new`.
+ */
+pub fn diff(old: &Png, new: &Png) -> Vec<ChunkChange> {
+    let mut new_by_type: HashMap<[u8; 4], Vec<(usize, &Chunk)>> = HashMap::new();
+    for (index, chunk) in new.chunks().iter().enumerate() {
+        new_by_type
+            .entry(chunk.chunk_type().bytes())
+            .or_default()
+            .push((index, chunk));
+    }
+
+    let mut changes = Vec::new();
+    let mut matched = vec![false; new.chunks().len()];
+    let mut occurrences: HashMap<[u8; 4], usize> = HashMap::new();
+    for (index, chunk) in old.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type().bytes();
+        let nth = occurrences.entry(chunk_type).or_insert(0);
+        let counterpart = new_by_type
+            .get(&chunk_type)
+            .and_then(|chunks| chunks.get(*nth));
+        *nth += 1;
+        match counterpart {
+            Some(&(new_index, new_chunk)) => {
+                matched[new_index] = true;
+                let same = new_chunk.crc() == chunk.crc() && new_chunk.data() == chunk.data();
+                if !same {
+                    changes.push(ChunkChange::Modified(
+                        ChunkSummary::new(index, chunk),
+                        ChunkSummary::new(new_index, new_chunk),
+                    ));
+                }
+            }
+            None => changes.push(ChunkChange::Removed(ChunkSummary::new(index, chunk))),
+        }
+    }
+    for (index, chunk) in new.chunks().iter().enumerate() {
+        if !matched[index] {
+            changes.push(ChunkChange::Added(ChunkSummary::new(index, chunk)));
+        }
+    }
+    changes
+}
+
+//A metadata value that differs between two files, None where a file doesn't have it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataChange {
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl fmt::Display for MetadataChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.old, &self.new) {
+            (None, Some(new)) => write!(f, "+ {}: {}", self.key, new),
+            (Some(old), None) => write!(f, "- {}: {}", self.key, old),
+            (old, new) => write!(
+                f,
+                "~ {}: {} -> {}",
+                self.key,
+                old.as_deref().unwrap_or_default(),
+                new.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/**
+ * The metadata of a PNG as it reads, in a fixed order: the resolution, modification
+ * time, colour space hints and ICC profile, then the text of every keyword, keyed
+ * `text:<keyword>` whether it is in a tEXt, zTXt or iTXt chunk. Only the first text
+ * of a keyword counts, as in `Png::text`, and the XMP packet is left out.
+ */
+pub fn metadata(png: &Png) -> Result<Vec<(String, String)>, Error> {
+    let mut values = Vec::new();
+    if let Some(phys) = png.physical_dimensions()? {
+        let value = match phys.dpi() {
+            Some((x, y)) => format!("{:.0}x{:.0} dpi", x, y),
+            None => format!(
+                "aspect ratio {}:{}",
+                phys.pixels_per_unit_x, phys.pixels_per_unit_y
+            ),
+        };
+        values.push(("dpi".to_string(), value));
+    }
+    if let Some(time) = png.last_modified()? {
+        values.push(("time".to_string(), time.to_string()));
+    }
+    let color = png.color_info()?;
+    if let Some(gamma) = color.gamma {
+        values.push(("gamma".to_string(), gamma.to_string()));
+    }
+    if let Some(chromaticities) = color.chromaticities {
+        values.push(("chromaticities".to_string(), chromaticities.to_string()));
+    }
+    if let Some(intent) = color.rendering_intent {
+        values.push(("srgb".to_string(), intent.to_string()));
+    }
+    if let Some(profile) = png.icc_profile()? {
+        let value = format!("{}, {} bytes", profile.name, profile.profile.len());
+        values.push(("icc".to_string(), value));
+    }
+    for chunk in png.chunks() {
+        let (keyword, text) = if text::is_text_chunk(chunk.chunk_type()) {
+            let entry = TextEntry::try_from(chunk)?;
+            (entry.keyword, entry.text)
+        } else if *chunk.chunk_type() == ChunkType::iTXt && !xmp::is_xmp_chunk(chunk) {
+            let entry = InternationalText::try_from(chunk)?;
+            (entry.keyword, entry.text)
+        } else {
+            continue;
+        };
+        let key = format!("text:{}", keyword);
+        if !values.iter().any(|(present, _)| *present == key) {
+            values.push((key, text));
+        }
+    }
+    Ok(values)
+}
+
+/**
+ * Compares the metadata of two PNGs, see `metadata`, rather than their chunks: a
+ * value moved to another chunk or re-compressed isn't a change. Changes are listed
+ * in the order of `old`, then the additions in the order of `new`.
+ */
+pub fn diff_metadata(old: &Png, new: &Png) -> Result<Vec<MetadataChange>, Error> {
+    let old = metadata(old)?;
+    let new = metadata(new)?;
+    let find = |values: &[(String, String)], key: &str| {
+        values
+            .iter()
+            .find(|(present, _)| present == key)
+            .map(|(_, value)| value.clone())
+    };
+    let mut changes = Vec::new();
+    for (key, value) in &old {
+        let counterpart = find(&new, key);
+        if counterpart.as_ref() != Some(value) {
+            changes.push(MetadataChange {
+                key: key.clone(),
+                old: Some(value.clone()),
+                new: counterpart,
+            });
+        }
+    }
+    for (key, value) in &new {
+        if find(&old, key).is_none() {
+            changes.push(MetadataChange {
+                key: key.clone(),
+                old: None,
+                new: Some(value.clone()),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::PhysicalDimensions;
+    use crate::png::PngBuilder;
+    use std::str::FromStr;
+
+    fn message_chunk(message: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            message.as_bytes().to_vec(),
+        )
+    }
+
+    #[test]
+    pub fn test_identical() {
+        let png = PngBuilder::new(2, 2).build().unwrap();
+        assert!(diff(&png, &png).is_empty());
+    }
+
+    #[test]
+    pub fn test_changes() {
+        let mut old = PngBuilder::new(2, 2).build().unwrap();
+        old.insert_chunk_ordered(message_chunk("First"));
+        old.insert_chunk_ordered(Chunk::new(ChunkType::gAMA, vec![0, 0, 0xb1, 0x8f]));
+        let mut new = PngBuilder::new(2, 2).build().unwrap();
+        new.insert_chunk_ordered(message_chunk("Changed"));
+        new.insert_chunk_ordered(message_chunk("Second"));
+
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 3);
+        assert!(
+            matches!(&changes[0], ChunkChange::Removed(chunk) if chunk.chunk_type == ChunkType::gAMA)
+        );
+        match &changes[1] {
+            ChunkChange::Modified(old, new) => {
+                assert_eq!((old.length, new.length), (5, 7));
+                assert_eq!(new.index, 2);
+            }
+            change => panic!("unexpected change: {}", change),
+        }
+        assert!(matches!(&changes[2], ChunkChange::Added(chunk) if chunk.index == 3));
+    }
+
+    #[test]
+    pub fn test_diff_metadata() {
+        let entry =
+            |keyword: &str, text: &str| TextEntry::new(keyword, text).unwrap().to_chunk().unwrap();
+        let mut old = PngBuilder::new(2, 2).build().unwrap();
+        old.insert_chunk_ordered(entry("Author", "Me"));
+        old.insert_chunk_ordered(entry("Title", "Cat"));
+        old.insert_chunk_ordered(PhysicalDimensions::from_dpi(72.0, 72.0).unwrap().to_chunk());
+        let mut new = PngBuilder::new(2, 2).build().unwrap();
+        new.insert_chunk_ordered(
+            PhysicalDimensions::from_dpi(300.0, 300.0)
+                .unwrap()
+                .to_chunk(),
+        );
+        new.insert_chunk_ordered(entry("Title", "Dog"));
+        new.insert_chunk_ordered(text::international_chunk("Comment", "Neü").unwrap());
+        new.insert_chunk_ordered(message_chunk("Ignored"));
+
+        let changes = diff_metadata(&old, &new).unwrap();
+        let lines: Vec<String> = changes.iter().map(|change| change.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "~ dpi: 72x72 dpi -> 300x300 dpi",
+                "- text:Author: Me",
+                "~ text:Title: Cat -> Dog",
+                "+ text:Comment: Neü",
+            ]
+        );
+        assert!(diff_metadata(&new, &new).unwrap().is_empty());
+    }
+}
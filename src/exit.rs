@@ -0,0 +1,44 @@
+/*!
+ * Exit codes, so scripts can tell failures apart without parsing stderr.
+ * 2 is left to clap, which uses it for usage errors.
+ */
+use pngme::chunk_type::ChunkTypeError;
+use pngme::crypto::CryptoError;
+use pngme::png::PngError;
+use pngme::text::TextError;
+use pngme::xmp::XmpError;
+use pngme::Error;
+use std::io;
+use std::process::ExitCode;
+
+//Any failure without a more specific code, including batches with failed files
+pub const FAILURE: u8 = 1;
+pub const NOT_A_PNG: u8 = 3;
+pub const CHUNK_NOT_FOUND: u8 = 4;
+pub const INVALID_CHUNK_TYPE: u8 = 5;
+//Wrong password, damaged or unexpectedly encrypted payload
+pub const CRYPTO: u8 = 6;
+pub const IO: u8 = 7;
+
+pub fn code(err: &Error) -> ExitCode {
+    let code = if let Some(err) = err.downcast_ref::<PngError>() {
+        match err {
+            PngError::InvalidSignature(_) => NOT_A_PNG,
+            PngError::ChunkNotFound(_) | PngError::PayloadNotFound(_) => CHUNK_NOT_FOUND,
+            _ => FAILURE,
+        }
+    } else if let Some(TextError::KeywordNotFound(_)) = err.downcast_ref::<TextError>() {
+        CHUNK_NOT_FOUND
+    } else if let Some(XmpError::NotFound) = err.downcast_ref::<XmpError>() {
+        CHUNK_NOT_FOUND
+    } else if err.is::<ChunkTypeError>() {
+        INVALID_CHUNK_TYPE
+    } else if err.is::<CryptoError>() {
+        CRYPTO
+    } else if err.is::<io::Error>() {
+        IO
+    } else {
+        FAILURE
+    };
+    ExitCode::from(code)
+}
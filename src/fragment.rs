@@ -0,0 +1,223 @@
+/*!
+ * Splitting of large payloads across several chunks of the same type.
+ * Each part starts with a 12 byte header: `PART_MAGIC`, the part index and the part
+ * count, both big endian u32. The magic starts with a byte that is never valid UTF-8,
+ * so a plain text message can't be mistaken for a part.
+ * Payloads that fit in one part are stored as a plain chunk, without a header.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::named;
+use crate::png::{Png, PngError};
+use crate::Error;
+use std::fmt;
+
+pub const PART_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'P'];
+pub const HEADER_LENGTH: usize = 12;
+//Payload bytes per part when the caller doesn't choose
+pub const DEFAULT_PART_LENGTH: usize = 8 << 20;
+
+//Splits `payload` into chunks of the given type holding at most `part_length` bytes each
+pub fn split(
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    part_length: usize,
+) -> Result<Vec<Chunk>, Error> {
+    if part_length == 0 || part_length + HEADER_LENGTH > Chunk::MAX_LENGTH as usize {
+        return Err(Box::new(FragmentError::InvalidPartLength(part_length)));
+    }
+    if payload.len() <= part_length {
+        return Ok(vec![Chunk::new(chunk_type.clone(), payload.to_vec())]);
+    }
+    let count = u32::try_from(payload.len().div_ceil(part_length))
+        .map_err(|_| FragmentError::InvalidPartLength(part_length))?;
+    let chunks = payload
+        .chunks(part_length)
+        .enumerate()
+        .map(|(index, part)| {
+            let mut data = Vec::with_capacity(HEADER_LENGTH + part.len());
+            data.extend_from_slice(&PART_MAGIC);
+            data.extend_from_slice(&(index as u32).to_be_bytes());
+            data.extend_from_slice(&count.to_be_bytes());
+            data.extend_from_slice(part);
+            Chunk::new(chunk_type.clone(), data)
+        })
+        .collect();
+    Ok(chunks)
+}
+
+//Index, count and payload of a part, None for a plain chunk
+pub fn parse_part(data: &[u8]) -> Option<(u32, u32, &[u8])> {
+    if data.len() < HEADER_LENGTH || data[..4] != PART_MAGIC {
+        return None;
+    }
+    let index = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    let count = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+    Some((index, count, &data[HEADER_LENGTH..]))
+}
+
+/**
+ * Rebuilds the payload from chunks of a single type, in any order.
+ * A plain first chunk is returned as is. Otherwise every part must be there exactly
+ * once and agree on the part count, plain chunks of the same type are ignored.
+ */
+pub fn reassemble<'a, I>(chunks: I) -> Result<Vec<u8>, Error>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    let mut chunks = chunks.into_iter().peekable();
+    let first = match chunks.peek() {
+        Some(first) => first.data(),
+        None => return Err(Box::new(FragmentError::MissingParts(0, 0))),
+    };
+    if parse_part(first).is_none() {
+        return Ok(first.to_vec());
+    }
+    let found: Vec<(u32, u32, &[u8])> = chunks
+        .filter_map(|chunk| parse_part(chunk.data()))
+        .collect();
+    //The count is read from the file, fewer parts than it can't be complete anyway
+    let count = found[0].1;
+    if count as usize > found.len() {
+        return Err(Box::new(FragmentError::MissingParts(
+            found.len() as u32,
+            count,
+        )));
+    }
+    let mut parts: Vec<Option<&[u8]>> = vec![None; count as usize];
+    for (index, count, part) in found {
+        if count as usize != parts.len() {
+            return Err(Box::new(FragmentError::InconsistentCount(
+                parts.len() as u32,
+                count,
+            )));
+        }
+        match parts.get_mut(index as usize) {
+            Some(slot @ None) => *slot = Some(part),
+            Some(Some(_)) => return Err(Box::new(FragmentError::DuplicatePart(index))),
+            None => return Err(Box::new(FragmentError::InvalidIndex(index, count))),
+        }
+    }
+    let found = parts.iter().filter(|part| part.is_some()).count();
+    if found != parts.len() {
+        return Err(Box::new(FragmentError::MissingParts(
+            found as u32,
+            parts.len() as u32,
+        )));
+    }
+    Ok(parts.into_iter().flatten().flatten().copied().collect())
+}
+
+impl Png {
+    //Inserts the payload as one chunk or as parts, see `split`
+    pub fn insert_payload(
+        &mut self,
+        chunk_type: &ChunkType,
+        payload: &[u8],
+        part_length: usize,
+    ) -> Result<(), Error> {
+        for chunk in split(chunk_type, payload, part_length)? {
+            self.insert_chunk_ordered(chunk);
+        }
+        Ok(())
+    }
+
+    //Payload stored in chunks of the given type, reassembled if it was split
+    pub fn payload(&self, chunk_type: &str) -> Result<Vec<u8>, Error> {
+        if self.chunk_by_type(chunk_type).is_none() {
+            return Err(Box::new(PngError::ChunkNotFound(chunk_type.to_string())));
+        }
+        //Named payloads of the type are left to `named::reassemble`
+        reassemble(
+            self.chunks_by_type(chunk_type)
+                .filter(|chunk| named::parse_name(chunk.data()).is_none()),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum FragmentError {
+    InvalidPartLength(usize),
+    InconsistentCount(u32, u32),
+    InvalidIndex(u32, u32),
+    DuplicatePart(u32),
+    MissingParts(u32, u32),
+}
+
+impl std::error::Error for FragmentError {}
+
+impl fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPartLength(length) => write!(f, "invalid part length: {}", length),
+            Self::InconsistentCount(expected, actual) => write!(
+                f,
+                "parts disagree on the part count: {} and {}",
+                expected, actual
+            ),
+            Self::InvalidIndex(index, count) => {
+                write!(f, "part index {} out of {} parts", index, count)
+            }
+            Self::DuplicatePart(index) => write!(f, "part {} is present twice", index),
+            Self::MissingParts(found, count) => {
+                write!(f, "only {} of {} parts found", found, count)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+    use std::str::FromStr;
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    #[test]
+    pub fn test_small_payload_is_plain() {
+        let chunks = split(&chunk_type(), b"Hello", 16).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].data(), b"Hello");
+        assert_eq!(reassemble(&chunks).unwrap(), b"Hello");
+    }
+
+    #[test]
+    pub fn test_split_and_reassemble() {
+        let payload: Vec<u8> = (0..100).collect();
+        let mut chunks = split(&chunk_type(), &payload, 30).unwrap();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(parse_part(chunks[3].data()), Some((3, 4, &payload[90..])));
+        chunks.reverse();
+        assert_eq!(reassemble(&chunks).unwrap(), payload);
+
+        chunks.remove(1);
+        assert!(reassemble(&chunks).is_err());
+        assert!(split(&chunk_type(), &payload, 0).is_err());
+
+        //A crafted count is refused before anything is allocated for it
+        let mut data = chunks[0].data().to_vec();
+        data[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let crafted = [Chunk::new(chunk_type(), data)];
+        assert!(matches!(
+            *reassemble(&crafted)
+                .unwrap_err()
+                .downcast::<FragmentError>()
+                .unwrap(),
+            FragmentError::MissingParts(1, u32::MAX)
+        ));
+    }
+
+    #[test]
+    pub fn test_png_payload() {
+        let payload = vec![7; 1000];
+        let mut png = PngBuilder::new(2, 2).build().unwrap();
+        png.insert_payload(&chunk_type(), &payload, 256).unwrap();
+        assert_eq!(png.chunks_by_type("ruSt").count(), 4);
+        assert!(png.validate().is_empty());
+        assert_eq!(png.payload("ruSt").unwrap(), payload);
+        assert!(png.payload("teSt").is_err());
+    }
+}
@@ -0,0 +1,345 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{Png, PngError};
+use crate::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = Error;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Grayscale),
+            2 => Ok(Self::Rgb),
+            3 => Ok(Self::Indexed),
+            4 => Ok(Self::GrayscaleAlpha),
+            6 => Ok(Self::Rgba),
+            _ => Err(Box::new(IhdrError::InvalidColorType(value))),
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Grayscale => "greyscale",
+            Self::Rgb => "truecolour",
+            Self::Indexed => "indexed-colour",
+            Self::GrayscaleAlpha => "greyscale with alpha",
+            Self::Rgba => "truecolour with alpha",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ColorType {
+    //Value stored in the IHDR chunk
+    pub fn value(&self) -> u8 {
+        match self {
+            Self::Grayscale => 0,
+            Self::Rgb => 2,
+            Self::Indexed => 3,
+            Self::GrayscaleAlpha => 4,
+            Self::Rgba => 6,
+        }
+    }
+
+    //Number of samples per pixel
+    pub fn channels(&self) -> u8 {
+        match self {
+            Self::Grayscale | Self::Indexed => 1,
+            Self::GrayscaleAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        matches!(self, Self::GrayscaleAlpha | Self::Rgba)
+    }
+
+    //Bit depths allowed by the spec for this colour type
+    pub fn is_valid_bit_depth(&self, bit_depth: u8) -> bool {
+        match self {
+            Self::Grayscale => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            Self::Indexed => matches!(bit_depth, 1 | 2 | 4 | 8),
+            Self::Rgb | Self::GrayscaleAlpha | Self::Rgba => matches!(bit_depth, 8 | 16),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interlace {
+    None,
+    Adam7,
+}
+
+//Starting column and row, then column and row steps of each Adam7 pass
+pub const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+//Image header, the content of the IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlace: Interlace,
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != ChunkType::IHDR {
+            return Err(Box::new(IhdrError::NotIhdr(chunk.chunk_type().to_string())));
+        }
+        let data = chunk.data();
+        if data.len() != Self::LENGTH {
+            return Err(Box::new(IhdrError::InvalidLength(data.len())));
+        }
+        let width = u32::from_be_bytes(data[0..4].try_into()?);
+        let height = u32::from_be_bytes(data[4..8].try_into()?);
+        if width == 0 || height == 0 || width > Chunk::MAX_LENGTH || height > Chunk::MAX_LENGTH {
+            return Err(Box::new(IhdrError::InvalidDimensions(width, height)));
+        }
+        let bit_depth = data[8];
+        let color_type = ColorType::try_from(data[9])?;
+        if !color_type.is_valid_bit_depth(bit_depth) {
+            return Err(Box::new(IhdrError::InvalidBitDepth(bit_depth, color_type)));
+        }
+        if data[10] != 0 {
+            return Err(Box::new(IhdrError::InvalidCompressionMethod(data[10])));
+        }
+        if data[11] != 0 {
+            return Err(Box::new(IhdrError::InvalidFilterMethod(data[11])));
+        }
+        let interlace = match data[12] {
+            0 => Interlace::None,
+            1 => Interlace::Adam7,
+            method => return Err(Box::new(IhdrError::InvalidInterlaceMethod(method))),
+        };
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlace,
+        })
+    }
+}
+
+impl fmt::Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, {} bit {}{}",
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            match self.interlace {
+                Interlace::None => "",
+                Interlace::Adam7 => ", interlaced",
+            }
+        )
+    }
+}
+
+impl Ihdr {
+    //Size of the IHDR chunk data
+    pub const LENGTH: usize = 13;
+
+    //Header of a non interlaced image
+    pub fn new(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlace: Interlace::None,
+        }
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels() as usize
+    }
+
+    //Bytes per pixel rounded up to 1, the distance used by the scanline filters
+    pub fn filter_distance(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    //Bytes of pixel data in a row of the given width, without the filter type byte
+    pub fn row_length(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /**
+     * Width and height of every sub-image stored in the pixel data: the whole image,
+     * or the 7 Adam7 passes (some of which may be empty for small images).
+     */
+    pub fn passes(&self) -> Vec<(u32, u32)> {
+        match self.interlace {
+            Interlace::None => vec![(self.width, self.height)],
+            Interlace::Adam7 => ADAM7
+                .iter()
+                .map(|&(x, y, dx, dy)| {
+                    let size = |length: u32, start: u32, step: u32| {
+                        (length + step - 1).saturating_sub(start) / step
+                    };
+                    (size(self.width, x, dx), size(self.height, y, dy))
+                })
+                .collect(),
+        }
+    }
+
+    //Size of the whole image once decompressed, filter type bytes included
+    pub fn raw_data_length(&self) -> usize {
+        self.passes()
+            .iter()
+            .filter(|(width, _)| *width > 0)
+            .map(|&(width, height)| (1 + self.row_length(width)) * height as usize)
+            .sum()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(Self::LENGTH);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        //Compression and filter methods are always 0
+        data.extend_from_slice(&[self.bit_depth, self.color_type.value(), 0, 0]);
+        data.push(match self.interlace {
+            Interlace::None => 0,
+            Interlace::Adam7 => 1,
+        });
+        Chunk::new(ChunkType::IHDR, data)
+    }
+}
+
+impl Png {
+    //Parses the IHDR chunk
+    pub fn header(&self) -> Result<Ihdr, Error> {
+        match self.chunk_by_type("IHDR") {
+            Some(chunk) => Ihdr::try_from(chunk),
+            None => Err(Box::new(PngError::ChunkNotFound("IHDR".to_string()))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum IhdrError {
+    NotIhdr(String),
+    InvalidLength(usize),
+    InvalidDimensions(u32, u32),
+    InvalidColorType(u8),
+    InvalidBitDepth(u8, ColorType),
+    InvalidCompressionMethod(u8),
+    InvalidFilterMethod(u8),
+    InvalidInterlaceMethod(u8),
+}
+
+impl std::error::Error for IhdrError {}
+
+impl fmt::Display for IhdrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotIhdr(chunk_type) => write!(f, "expected IHDR chunk, current: {}", chunk_type),
+            Self::InvalidLength(length) => write!(f, "IHDR length must be 13, current: {}", length),
+            Self::InvalidDimensions(width, height) => {
+                write!(f, "invalid image dimensions: {}x{}", width, height)
+            }
+            Self::InvalidColorType(value) => write!(f, "invalid colour type: {}", value),
+            Self::InvalidBitDepth(bit_depth, color_type) => {
+                write!(f, "invalid bit depth {} for {}", bit_depth, color_type)
+            }
+            Self::InvalidCompressionMethod(method) => {
+                write!(f, "invalid compression method: {}", method)
+            }
+            Self::InvalidFilterMethod(method) => write!(f, "invalid filter method: {}", method),
+            Self::InvalidInterlaceMethod(method) => {
+                write!(f, "invalid interlace method: {}", method)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+
+    fn ihdr_chunk(data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::IHDR, data.to_vec())
+    }
+
+    #[test]
+    pub fn test_parse_ihdr() {
+        let chunk = ihdr_chunk(&[0, 0, 1, 0, 0, 0, 0, 200, 16, 6, 0, 0, 1]);
+        let ihdr = Ihdr::try_from(&chunk).unwrap();
+        assert_eq!(ihdr.width, 256);
+        assert_eq!(ihdr.height, 200);
+        assert_eq!(ihdr.bit_depth, 16);
+        assert_eq!(ihdr.color_type, ColorType::Rgba);
+        assert_eq!(ihdr.interlace, Interlace::Adam7);
+        assert_eq!(ihdr.bits_per_pixel(), 64);
+    }
+
+    #[test]
+    pub fn test_ihdr_round_trip() {
+        let ihdr = Ihdr::new(3, 7, 4, ColorType::Indexed);
+        let actual = Ihdr::try_from(&ihdr.to_chunk()).unwrap();
+        assert_eq!(actual, ihdr);
+        assert_eq!(ihdr.row_length(3), 2);
+        assert_eq!(ihdr.raw_data_length(), 21);
+        assert_eq!(ihdr.filter_distance(), 1);
+    }
+
+    #[test]
+    pub fn test_adam7_passes() {
+        let mut ihdr = Ihdr::new(5, 3, 8, ColorType::Grayscale);
+        ihdr.interlace = Interlace::Adam7;
+        assert_eq!(
+            ihdr.passes(),
+            vec![(1, 1), (1, 1), (2, 0), (1, 1), (3, 1), (2, 2), (5, 1)]
+        );
+        //Empty passes have no rows, so no filter type bytes either
+        assert_eq!(ihdr.raw_data_length(), 2 + 2 + 2 + 4 + 2 * 3 + 6);
+    }
+
+    #[test]
+    pub fn test_invalid_ihdr() {
+        assert!(Ihdr::try_from(&ihdr_chunk(&[0; 12])).is_err());
+        //Zero width
+        assert!(Ihdr::try_from(&ihdr_chunk(&[0, 0, 0, 0, 0, 0, 0, 1, 8, 2, 0, 0, 0])).is_err());
+        //16 bit indexed colour
+        assert!(Ihdr::try_from(&ihdr_chunk(&[0, 0, 0, 1, 0, 0, 0, 1, 16, 3, 0, 0, 0])).is_err());
+        //Unknown colour type
+        assert!(Ihdr::try_from(&ihdr_chunk(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 5, 0, 0, 0])).is_err());
+        //Unknown interlace method
+        assert!(Ihdr::try_from(&ihdr_chunk(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 2])).is_err());
+    }
+
+    #[test]
+    pub fn test_png_header() {
+        let png = PngBuilder::new(5, 4).color(&[1, 2]).build().unwrap();
+        let ihdr = png.header().unwrap();
+        assert_eq!(ihdr, Ihdr::new(5, 4, 8, ColorType::GrayscaleAlpha));
+        assert!(Png::from_chunks(Vec::new()).header().is_err());
+    }
+}
@@ -0,0 +1,342 @@
+/*!
+ * Human readable views of chunk contents, as shown by `pngme inspect`.
+ */
+use crate::ancillary::{
+    Background, Calibration, CodingParameters, ContentLightLevel, Exif, Histogram, ImageOffset,
+    MasteringDisplay, PhysicalScale, SignificantBits, StereoMode, SuggestedPalette,
+};
+use crate::apng::{AnimationControl, FrameControl};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::metadata::{
+    Chromaticities, Gamma, IccProfile, PhysUnit, PhysicalDimensions, RenderingIntent, Timestamp,
+};
+use crate::palette::{Palette, Transparency};
+use crate::text::TextEntry;
+use crate::xmp;
+use crate::Error;
+use std::fmt::Write;
+
+pub type Fields = Vec<(&'static str, String)>;
+
+/**
+ * Decodes the fields of a known chunk type, None for chunks with no known structure.
+ * `header` is needed to make sense of tRNS, bKGD and sBIT, left undecoded without it.
+ */
+pub fn describe(chunk: &Chunk, header: Option<&Ihdr>) -> Result<Option<Fields>, Error> {
+    let chunk_type = chunk.chunk_type();
+    let data = chunk.data();
+    let fields = if *chunk_type == ChunkType::IHDR {
+        let ihdr = Ihdr::try_from(chunk)?;
+        vec![
+            ("width", ihdr.width.to_string()),
+            ("height", ihdr.height.to_string()),
+            ("bit depth", ihdr.bit_depth.to_string()),
+            ("colour type", ihdr.color_type.to_string()),
+            ("interlace", format!("{:?}", ihdr.interlace).to_lowercase()),
+        ]
+    } else if *chunk_type == ChunkType::PLTE {
+        vec![("entries", Palette::try_from(chunk)?.len().to_string())]
+    } else if let Some(header) = header.filter(|_| *chunk_type == ChunkType::tRNS) {
+        let transparency = Transparency::parse(chunk, header)?;
+        vec![("transparency", format!("{:?}", transparency))]
+    } else if *chunk_type == ChunkType::IDAT || *chunk_type == ChunkType::IEND {
+        Vec::new()
+    } else if let Some(header) = header.filter(|_| *chunk_type == ChunkType::bKGD) {
+        vec![("background", Background::parse(chunk, header)?.to_string())]
+    } else if let Some(header) = header.filter(|_| *chunk_type == ChunkType::sBIT) {
+        let bits = SignificantBits::parse(chunk, header)?;
+        let bits: Vec<String> = bits.0.iter().map(u8::to_string).collect();
+        vec![("significant bits", bits.join(","))]
+    } else if *chunk_type == ChunkType::tEXt {
+        let (keyword, text) = split_keyword(data);
+        vec![("keyword", latin1(keyword)), ("text", latin1(text))]
+    } else if *chunk_type == ChunkType::zTXt {
+        let entry = TextEntry::try_from(chunk)?;
+        vec![("keyword", entry.keyword), ("text", entry.text)]
+    } else if *chunk_type == ChunkType::iTXt {
+        itxt_fields(chunk)?
+    } else if *chunk_type == ChunkType::iCCP {
+        let profile = IccProfile::try_from(chunk)?;
+        vec![
+            ("profile name", profile.name),
+            ("profile size", format!("{} bytes", profile.profile.len())),
+        ]
+    } else if *chunk_type == ChunkType::hIST {
+        let histogram = Histogram::try_from(chunk)?;
+        vec![
+            ("entries", histogram.0.len().to_string()),
+            (
+                "most used",
+                histogram.0.iter().max().unwrap_or(&0).to_string(),
+            ),
+        ]
+    } else if *chunk_type == ChunkType::sPLT {
+        let palette = SuggestedPalette::try_from(chunk)?;
+        vec![
+            ("name", palette.name),
+            ("sample depth", palette.sample_depth.to_string()),
+            ("entries", palette.entries.len().to_string()),
+        ]
+    } else if *chunk_type == ChunkType::eXIf {
+        let exif = Exif::try_from(chunk)?;
+        let order = if exif.big_endian {
+            "big endian"
+        } else {
+            "little endian"
+        };
+        vec![
+            ("byte order", order.to_string()),
+            ("size", format!("{} bytes", exif.length)),
+        ]
+    } else if *chunk_type == ChunkType::oFFs {
+        let offset = ImageOffset::try_from(chunk)?;
+        vec![
+            ("offset", format!("{},{}", offset.x, offset.y)),
+            ("unit", offset.unit.to_string()),
+        ]
+    } else if *chunk_type == ChunkType::pCAL {
+        let calibration = Calibration::try_from(chunk)?;
+        vec![
+            ("name", calibration.name),
+            (
+                "sample range",
+                format!(
+                    "{} to {}",
+                    calibration.original_zero, calibration.original_max
+                ),
+            ),
+            ("equation", calibration.equation.to_string()),
+            ("unit", calibration.unit),
+            ("parameters", calibration.parameters.join(", ")),
+        ]
+    } else if *chunk_type == ChunkType::sCAL {
+        let scale = PhysicalScale::try_from(chunk)?;
+        vec![
+            ("pixel width", scale.width.to_string()),
+            ("pixel height", scale.height.to_string()),
+            ("unit", scale.unit.to_string()),
+        ]
+    } else if *chunk_type == ChunkType::sTER {
+        vec![("layout", StereoMode::try_from(chunk)?.to_string())]
+    } else if *chunk_type == ChunkType::cICP {
+        let cicp = CodingParameters::try_from(chunk)?;
+        let named = |code: u8, name: Option<&str>| match name {
+            Some(name) => format!("{} ({})", code, name),
+            None => code.to_string(),
+        };
+        vec![
+            ("primaries", named(cicp.primaries, cicp.primaries_name())),
+            ("transfer", named(cicp.transfer, cicp.transfer_name())),
+            ("full range", cicp.full_range.to_string()),
+        ]
+    } else if *chunk_type == ChunkType::mDCV {
+        let display = MasteringDisplay::try_from(chunk)?;
+        let point = |(x, y): (u16, u16)| format!("{:.4},{:.4}", x as f64 * 2e-5, y as f64 * 2e-5);
+        vec![
+            ("red", point(display.primaries[0])),
+            ("green", point(display.primaries[1])),
+            ("blue", point(display.primaries[2])),
+            ("white point", point(display.white)),
+            (
+                "luminance",
+                format!(
+                    "{:.4} to {:.1} cd/m²",
+                    display.min_luminance as f64 * 1e-4,
+                    display.max_luminance as f64 * 1e-4
+                ),
+            ),
+        ]
+    } else if *chunk_type == ChunkType::cLLI {
+        let level = ContentLightLevel::try_from(chunk)?;
+        vec![
+            (
+                "max content light level",
+                format!("{:.1} cd/m²", level.max_content as f64 * 1e-4),
+            ),
+            (
+                "max frame average light level",
+                format!("{:.1} cd/m²", level.max_frame_average as f64 * 1e-4),
+            ),
+        ]
+    } else if *chunk_type == ChunkType::pHYs {
+        let phys = PhysicalDimensions::try_from(chunk)?;
+        let unit = match phys.unit {
+            PhysUnit::Meter => "pixels per meter",
+            PhysUnit::Unknown => "aspect ratio only",
+        };
+        let mut fields = vec![
+            ("x", phys.pixels_per_unit_x.to_string()),
+            ("y", phys.pixels_per_unit_y.to_string()),
+            ("unit", unit.to_string()),
+        ];
+        if let Some((x, y)) = phys.dpi() {
+            fields.push(("dpi", format!("{:.0}x{:.0}", x, y)));
+        }
+        fields
+    } else if *chunk_type == ChunkType::tIME {
+        vec![("modified", Timestamp::try_from(chunk)?.to_string())]
+    } else if *chunk_type == ChunkType::gAMA {
+        let gamma = Gamma::try_from(chunk)?;
+        vec![
+            ("gamma", gamma.to_string()),
+            ("display gamma", format!("{:.2}", 1.0 / gamma.value())),
+        ]
+    } else if *chunk_type == ChunkType::cHRM {
+        let chrm = Chromaticities::try_from(chunk)?;
+        let point = |(x, y): (u32, u32)| format!("{:.5},{:.5}", x as f64 / 1e5, y as f64 / 1e5);
+        vec![
+            ("white point", point(chrm.white)),
+            ("red", point(chrm.red)),
+            ("green", point(chrm.green)),
+            ("blue", point(chrm.blue)),
+        ]
+    } else if *chunk_type == ChunkType::sRGB {
+        let intent = RenderingIntent::try_from(chunk)?;
+        vec![("rendering intent", intent.to_string())]
+    } else if *chunk_type == ChunkType::acTL {
+        let control = AnimationControl::try_from(chunk)?;
+        vec![
+            ("frames", control.num_frames.to_string()),
+            ("plays", control.num_plays.to_string()),
+        ]
+    } else if *chunk_type == ChunkType::fcTL {
+        let control = FrameControl::try_from(chunk)?;
+        vec![
+            ("sequence number", control.sequence_number.to_string()),
+            ("size", format!("{}x{}", control.width, control.height)),
+            (
+                "offset",
+                format!("{},{}", control.x_offset, control.y_offset),
+            ),
+            ("delay", format!("{:.3}s", control.delay())),
+        ]
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(fields))
+}
+
+//Keyword, language tag, translated keyword and text of an iTXt chunk
+fn itxt_fields(chunk: &Chunk) -> Result<Fields, Error> {
+    if xmp::is_xmp_chunk(chunk) {
+        let packet = xmp::from_chunk(chunk)?;
+        return Ok(vec![
+            ("keyword", xmp::XMP_KEYWORD.to_string()),
+            ("xmp packet", format!("{} bytes", packet.len())),
+        ]);
+    }
+    let (keyword, rest) = split_keyword(chunk.data());
+    let mut fields = vec![("keyword", latin1(keyword))];
+    if let [compressed, _, rest @ ..] = rest {
+        let (language, rest) = split_keyword(rest);
+        let (translated, text) = split_keyword(rest);
+        fields.push(("language", String::from_utf8_lossy(language).into_owned()));
+        fields.push((
+            "translated keyword",
+            String::from_utf8_lossy(translated).into_owned(),
+        ));
+        let text = if *compressed == 0 {
+            String::from_utf8_lossy(text).into_owned()
+        } else {
+            format!("{} compressed bytes", text.len())
+        };
+        fields.push(("text", text));
+    }
+    Ok(fields)
+}
+
+//Keyword and value of a tEXt-like chunk, separated by the first null byte
+fn split_keyword(data: &[u8]) -> (&[u8], &[u8]) {
+    match data.iter().position(|&byte| byte == 0) {
+        Some(index) => (&data[..index], &data[index + 1..]),
+        None => (data, &[]),
+    }
+}
+
+//tEXt is Latin-1, whose code points match the first 256 of Unicode
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+/**
+ * Classic 16 bytes per line hexdump of the first `limit` bytes:
+ * offset, hex bytes and the printable ASCII characters.
+ */
+pub fn hexdump(data: &[u8], limit: usize) -> String {
+    let mut dump = String::new();
+    for (line, bytes) in data[..data.len().min(limit)].chunks(16).enumerate() {
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let _ = writeln!(
+            dump,
+            "{:08x}  {:<47}  |{}|",
+            line * 16,
+            hex.join(" "),
+            ascii
+        );
+    }
+    if data.len() > limit {
+        let _ = writeln!(dump, "... {} more bytes", data.len() - limit);
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+    use std::str::FromStr;
+
+    #[test]
+    pub fn test_describe_known_chunks() {
+        let header = Ihdr::new(3, 2, 8, ColorType::Rgb);
+        let fields = describe(&header.to_chunk(), None).unwrap().unwrap();
+        assert_eq!(fields[0], ("width", "3".to_string()));
+
+        let text = Chunk::new(ChunkType::tEXt, b"Author\0J\xe9r\xf4me".to_vec());
+        let fields = describe(&text, None).unwrap().unwrap();
+        assert_eq!(fields[1], ("text", "Jérôme".to_string()));
+
+        let gamma = Chunk::new(ChunkType::gAMA, 45455u32.to_be_bytes().to_vec());
+        let fields = describe(&gamma, None).unwrap().unwrap();
+        assert_eq!(fields[1], ("display gamma", "2.20".to_string()));
+        let chrm = describe(&Chromaticities::SRGB.to_chunk(), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chrm[0], ("white point", "0.31270,0.32900".to_string()));
+
+        let background = Chunk::new(ChunkType::bKGD, vec![0, 0, 0, 255, 0, 0]);
+        assert!(describe(&background, None).unwrap().is_none());
+        let fields = describe(&background, Some(&header)).unwrap().unwrap();
+        assert_eq!(fields[0], ("background", "rgb 0,255,0".to_string()));
+    }
+
+    #[test]
+    pub fn test_describe_unknown_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"secret".to_vec());
+        assert!(describe(&chunk, None).unwrap().is_none());
+        let broken = Chunk::new(ChunkType::tIME, vec![1]);
+        assert!(describe(&broken, None).is_err());
+    }
+
+    #[test]
+    pub fn test_hexdump() {
+        let dump = hexdump(b"Hello, PNG\x00\x01 world!", 16);
+        assert_eq!(
+            dump,
+            "00000000  48 65 6c 6c 6f 2c 20 50 4e 47 00 01 20 77 6f 72  |Hello, PNG.. wor|\n\
+             ... 3 more bytes\n"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+/*!
+ * Passwords kept in the platform keychain, `--key-from keyring:<name>`.
+ */
+use keyring::Entry;
+use pngme::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+//Service of every entry, the name given on the command line being the user
+const SERVICE: &str = "pngme";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    Keyring(String),
+}
+
+impl KeySource {
+    fn entry(&self) -> Result<Entry> {
+        let Self::Keyring(name) = self;
+        Entry::new(SERVICE, name).map_err(|err| Box::new(KeychainError::Keyring(err)) as Error)
+    }
+
+    //The password stored for the source, None when there is none yet
+    pub fn get(&self) -> Result<Option<Vec<u8>>> {
+        match self.entry()?.get_secret() {
+            Ok(password) => Ok(Some(password)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(Box::new(KeychainError::Keyring(err))),
+        }
+    }
+
+    pub fn store(&self, password: &[u8]) -> Result<()> {
+        self.entry()?
+            .set_secret(password)
+            .map_err(|err| Box::new(KeychainError::Keyring(err)) as Error)
+    }
+}
+
+impl FromStr for KeySource {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("keyring", name)) if !name.is_empty() => Ok(Self::Keyring(name.to_string())),
+            _ => Err(Box::new(KeychainError::InvalidSource(s.to_string()))),
+        }
+    }
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keyring(name) => write!(f, "keyring:{}", name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeychainError {
+    InvalidSource(String),
+    NotFound(KeySource),
+    Keyring(keyring::Error),
+}
+
+impl std::error::Error for KeychainError {}
+
+impl fmt::Display for KeychainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSource(source) => {
+                write!(f, "invalid key source {}, expected keyring:<name>", source)
+            }
+            Self::NotFound(source) => {
+                write!(f, "no password in {}, encode stores one there", source)
+            }
+            Self::Keyring(err) => write!(f, "keychain error: {}", err),
+        }
+    }
+}
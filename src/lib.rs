@@ -0,0 +1,43 @@
+pub mod analyze;
+pub mod ancillary;
+pub mod apng;
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod bitplane;
+pub mod capacity;
+pub mod chunk;
+pub mod chunk_type;
+pub mod cover;
+pub mod crypto;
+pub mod decoy;
+pub mod diff;
+pub mod fragment;
+pub mod ihdr;
+pub mod inspect;
+pub mod metadata;
+pub mod named;
+pub mod normalize;
+pub mod ops;
+pub mod palette;
+pub mod payload;
+pub mod pixels;
+pub mod png;
+pub mod polyglot;
+pub mod recover;
+pub mod redundancy;
+pub mod schema;
+pub mod search;
+pub mod shard;
+pub mod spread;
+pub mod stats;
+pub mod stego;
+pub mod stream;
+pub mod template;
+pub mod text;
+pub mod trailing;
+pub mod validate;
+pub mod xmp;
+
+//Send + Sync so errors can cross threads and async tasks
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,6 +1,84 @@
-mod chunk_type;
+mod args;
+mod batch;
+mod commands;
+mod config;
+mod exit;
+#[cfg(feature = "keyring")]
+mod keychain;
+mod logger;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "watch")]
+mod watch;
 
-pub type Error = Box<dyn std::error::Error>;
-fn main() {
-    println!("PNGme!");
+use args::{Cli, PngMeArgs};
+use config::Config;
+use pngme::Result;
+use std::process::ExitCode;
+use std::time::Instant;
+
+fn main() -> ExitCode {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return ExitCode::from(exit::FAILURE);
+        }
+    };
+    let cli = Cli::parse_with(&config);
+    logger::init(cli.verbose, cli.quiet);
+    commands::set_backup(cli.backup(&config));
+    let start = Instant::now();
+    let result = run(cli);
+    log::info!("finished in {:.2?}", start.elapsed());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            exit::code(&err)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        PngMeArgs::Encode(args) => commands::encode(*args),
+        PngMeArgs::Decode(args) => commands::decode(args, cli.format),
+        PngMeArgs::Shard(args) => commands::shard(args),
+        PngMeArgs::Unshard(args) => commands::unshard(args),
+        PngMeArgs::Verify(args) => commands::verify(args),
+        PngMeArgs::VerifyPayload(args) => commands::verify_payload(args),
+        PngMeArgs::LsPayloads(args) => commands::ls_payloads(args, cli.format),
+        PngMeArgs::Extract(args) => commands::extract(args),
+        PngMeArgs::Remove(args) => commands::remove(args),
+        PngMeArgs::Print(args) => commands::print(args),
+        PngMeArgs::List(args) => commands::list(args, cli.format),
+        PngMeArgs::Inspect(args) => commands::inspect(args, cli.format),
+        PngMeArgs::Diff(args) => commands::diff(args, cli.format),
+        PngMeArgs::Grep(args) => commands::grep(args, cli.format),
+        PngMeArgs::Stats(args) => commands::stats(args, cli.format),
+        PngMeArgs::CopyChunks(args) => commands::copy_chunks(args),
+        PngMeArgs::Scrub(args) => commands::scrub(args),
+        PngMeArgs::Normalize(args) => commands::normalize(args),
+        PngMeArgs::Repair(args) => commands::repair(args),
+        PngMeArgs::Migrate(args) => commands::migrate(args),
+        PngMeArgs::Meta(args) => commands::meta(args, cli.format),
+        PngMeArgs::Xmp(args) => commands::xmp(args),
+        PngMeArgs::Time(args) => commands::time(args, cli.format),
+        PngMeArgs::Icc(args) => commands::icc(args),
+        PngMeArgs::Color(args) => commands::color(args, cli.format),
+        PngMeArgs::Dpi(args) => commands::dpi(args, cli.format),
+        PngMeArgs::Ext(args) => commands::ext(args, cli.format),
+        PngMeArgs::Validate(args) => commands::validate(args, cli.format),
+        PngMeArgs::Optimize(args) => commands::optimize(args),
+        PngMeArgs::Capacity(args) => commands::capacity(args),
+        PngMeArgs::Analyze(args) => commands::analyze(args, cli.format),
+        PngMeArgs::Polyglot(args) => commands::polyglot(args),
+        PngMeArgs::Bitplanes(args) => commands::bitplanes(args),
+        PngMeArgs::Cover(args) => commands::cover(args),
+        #[cfg(feature = "tui")]
+        PngMeArgs::Tui(args) => tui::run(args),
+        #[cfg(feature = "watch")]
+        PngMeArgs::Watch(args) => watch::run(args),
+    }
 }
@@ -0,0 +1,833 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::text;
+use crate::Error;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysUnit {
+    //Only the aspect ratio is known
+    Unknown,
+    Meter,
+}
+
+//Content of the pHYs chunk, the intended pixel size or aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    pub unit: PhysUnit,
+}
+
+impl TryFrom<&Chunk> for PhysicalDimensions {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, &ChunkType::pHYs, PhysicalDimensions::LENGTH)?;
+        let unit = match data[8] {
+            0 => PhysUnit::Unknown,
+            1 => PhysUnit::Meter,
+            unit => return Err(Box::new(MetadataError::InvalidUnit(unit))),
+        };
+        Ok(PhysicalDimensions {
+            pixels_per_unit_x: u32::from_be_bytes(data[0..4].try_into()?),
+            pixels_per_unit_y: u32::from_be_bytes(data[4..8].try_into()?),
+            unit,
+        })
+    }
+}
+
+impl PhysicalDimensions {
+    pub const LENGTH: usize = 9;
+    const INCHES_PER_METER: f64 = 39.3701;
+
+    //Horizontal and vertical dots per inch, None without a physical unit
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            PhysUnit::Meter => Some((
+                self.pixels_per_unit_x as f64 / Self::INCHES_PER_METER,
+                self.pixels_per_unit_y as f64 / Self::INCHES_PER_METER,
+            )),
+            PhysUnit::Unknown => None,
+        }
+    }
+
+    //Rounded to the nearest pixel per meter, 300 dpi being 11811
+    pub fn from_dpi(x: f64, y: f64) -> Result<PhysicalDimensions, Error> {
+        let pixels_per_meter = |dpi: f64| {
+            let pixels = (dpi * Self::INCHES_PER_METER).round();
+            if !(1.0..=u32::MAX as f64).contains(&pixels) {
+                return Err(Box::new(MetadataError::InvalidDpi(dpi)) as Error);
+            }
+            Ok(pixels as u32)
+        };
+        Ok(PhysicalDimensions {
+            pixels_per_unit_x: pixels_per_meter(x)?,
+            pixels_per_unit_y: pixels_per_meter(y)?,
+            unit: PhysUnit::Meter,
+        })
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(Self::LENGTH);
+        data.extend_from_slice(&self.pixels_per_unit_x.to_be_bytes());
+        data.extend_from_slice(&self.pixels_per_unit_y.to_be_bytes());
+        data.push(match self.unit {
+            PhysUnit::Unknown => 0,
+            PhysUnit::Meter => 1,
+        });
+        Chunk::new(ChunkType::pHYs, data)
+    }
+}
+
+//Content of the tIME chunk, the last modification time in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    //Up to 60 for leap seconds
+    pub second: u8,
+}
+
+impl TryFrom<&Chunk> for Timestamp {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, &ChunkType::tIME, Timestamp::LENGTH)?;
+        let timestamp = Timestamp {
+            year: u16::from_be_bytes([data[0], data[1]]),
+            month: data[2],
+            day: data[3],
+            hour: data[4],
+            minute: data[5],
+            second: data[6],
+        };
+        if !timestamp.is_valid() {
+            return Err(Box::new(MetadataError::InvalidTimestamp(timestamp)));
+        }
+        Ok(timestamp)
+    }
+}
+
+impl Timestamp {
+    pub const LENGTH: usize = 7;
+
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour <= 23
+            && self.minute <= 59
+            && self.second <= 60
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.year.to_be_bytes().to_vec();
+        data.extend_from_slice(&[self.month, self.day, self.hour, self.minute, self.second]);
+        Chunk::new(ChunkType::tIME, data)
+    }
+
+    pub fn now() -> Timestamp {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs() as i64)
+            .unwrap_or(0);
+        Timestamp::from_unix(seconds).expect("the current year fits in a u16")
+    }
+
+    //The time `seconds` after the Unix epoch, None past the years a tIME chunk holds
+    pub fn from_unix(seconds: i64) -> Option<Timestamp> {
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let time = seconds.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        Some(Timestamp {
+            year: u16::try_from(year).ok()?,
+            month,
+            day,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        })
+    }
+
+    //Seconds since the Unix epoch, a leap second counting as the next one
+    pub fn to_unix(&self) -> i64 {
+        days_from_civil(self.year as i64, self.month, self.day) * SECONDS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+}
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+//Days since 1970-01-01 of a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    //Years start in March so the leap day ends them
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+//Inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+//RFC 3339, e.g. 2024-01-31T13:00:00+01:00, converted to UTC. Fractions of a second are dropped
+impl FromStr for Timestamp {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Box::new(MetadataError::InvalidTime(s.to_string())) as Error;
+        let number = |range: std::ops::Range<usize>| -> Result<i64, Error> {
+            let digits = s.get(range).ok_or_else(invalid)?;
+            if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            digits.parse().map_err(|_| invalid())
+        };
+        let bytes = s.as_bytes();
+        if bytes.len() < 20
+            || bytes[4] != b'-'
+            || bytes[7] != b'-'
+            || !matches!(bytes[10], b'T' | b't' | b' ')
+            || bytes[13] != b':'
+            || bytes[16] != b':'
+        {
+            return Err(invalid());
+        }
+        let timestamp = Timestamp {
+            year: number(0..4)? as u16,
+            month: number(5..7)? as u8,
+            day: number(8..10)? as u8,
+            hour: number(11..13)? as u8,
+            minute: number(14..16)? as u8,
+            second: number(17..19)? as u8,
+        };
+        let mut rest = &s[19..];
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+            if digits == 0 {
+                return Err(invalid());
+            }
+            rest = &fraction[digits..];
+        }
+        let offset = match rest.as_bytes() {
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+                let offset =
+                    number(s.len() - 5..s.len() - 3)? * 3600 + number(s.len() - 2..s.len())? * 60;
+                if *sign == b'+' {
+                    offset
+                } else {
+                    -offset
+                }
+            }
+            _ => return Err(invalid()),
+        };
+        if !timestamp.is_valid() || offset.abs() >= SECONDS_PER_DAY {
+            return Err(invalid());
+        }
+        //A leap second is kept as second 60 of the minute it ends
+        let leap = timestamp.second == 60;
+        let local = timestamp.to_unix() - leap as i64;
+        //The round trip catches days past the end of the month, such as February 30
+        if Timestamp::from_unix(local).map(|local| (local.month, local.day))
+            != Some((timestamp.month, timestamp.day))
+        {
+            return Err(invalid());
+        }
+        if offset == 0 {
+            return Ok(timestamp);
+        }
+        let seconds = local - offset;
+        let mut utc = Timestamp::from_unix(seconds).ok_or_else(invalid)?;
+        if leap {
+            utc.second = 60;
+        }
+        Ok(utc)
+    }
+}
+
+//ISO 8601, e.g. 2024-01-31T12:00:00Z
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+//gAMA and cHRM values are stored multiplied by this
+const COLOR_SCALE: f64 = 100_000.0;
+
+//Content of the gAMA chunk, the file gamma times 100000, 45455 for the usual 1/2.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gamma(pub u32);
+
+impl Gamma {
+    pub const LENGTH: usize = 4;
+    //The gamma the sRGB chunk implies
+    pub const SRGB: Gamma = Gamma(45455);
+
+    pub fn value(&self) -> f64 {
+        self.0 as f64 / COLOR_SCALE
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::gAMA, self.0.to_be_bytes().to_vec())
+    }
+}
+
+impl TryFrom<&Chunk> for Gamma {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, &ChunkType::gAMA, Gamma::LENGTH)?;
+        //The spec makes 0 invalid, it has no display gamma
+        match u32::from_be_bytes(data.try_into()?) {
+            0 => Err(Box::new(MetadataError::ZeroGamma)),
+            gamma => Ok(Gamma(gamma)),
+        }
+    }
+}
+
+//The file gamma such as 0.45455, values above 1 being taken as the display gamma, 2.2 for 1/2.2
+impl FromStr for Gamma {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Box::new(MetadataError::InvalidGamma(s.to_string())) as Error;
+        let gamma: f64 = s.parse().map_err(|_| invalid())?;
+        let gamma = if gamma > 1.0 { 1.0 / gamma } else { gamma };
+        let scaled = (gamma * COLOR_SCALE).round();
+        if !(1.0..=u32::MAX as f64).contains(&scaled) {
+            return Err(invalid());
+        }
+        Ok(Gamma(scaled as u32))
+    }
+}
+
+impl fmt::Display for Gamma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.5}", self.value())
+    }
+}
+
+//Content of the cHRM chunk, the x and y chromaticities times 100000 of the white point and primaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white: (u32, u32),
+    pub red: (u32, u32),
+    pub green: (u32, u32),
+    pub blue: (u32, u32),
+}
+
+impl Chromaticities {
+    pub const LENGTH: usize = 32;
+    //The chromaticities the sRGB chunk implies, those of BT.709 with a D65 white point
+    pub const SRGB: Chromaticities = Chromaticities {
+        white: (31270, 32900),
+        red: (64000, 33000),
+        green: (30000, 60000),
+        blue: (15000, 6000),
+    };
+
+    fn points(&self) -> [(u32, u32); 4] {
+        [self.white, self.red, self.green, self.blue]
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = self
+            .points()
+            .iter()
+            .flat_map(|&(x, y)| [x.to_be_bytes(), y.to_be_bytes()])
+            .flatten()
+            .collect();
+        Chunk::new(ChunkType::cHRM, data)
+    }
+}
+
+impl TryFrom<&Chunk> for Chromaticities {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, &ChunkType::cHRM, Chromaticities::LENGTH)?;
+        let point = |index: usize| {
+            let value = |offset: usize| {
+                u32::from_be_bytes(data[offset..offset + 4].try_into().expect("4 bytes"))
+            };
+            (value(index * 8), value(index * 8 + 4))
+        };
+        Ok(Chromaticities {
+            white: point(0),
+            red: point(1),
+            green: point(2),
+            blue: point(3),
+        })
+    }
+}
+
+//srgb, or the 8 comma separated x and y of the white point, red, green and blue
+impl FromStr for Chromaticities {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("srgb") {
+            return Ok(Chromaticities::SRGB);
+        }
+        let invalid = || Box::new(MetadataError::InvalidChromaticities(s.to_string())) as Error;
+        let values = s
+            .split(',')
+            .map(|value| {
+                let value: f64 = value.trim().parse().map_err(|_| invalid())?;
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(invalid());
+                }
+                Ok((value * COLOR_SCALE).round() as u32)
+            })
+            .collect::<Result<Vec<u32>, Error>>()?;
+        match values[..] {
+            [wx, wy, rx, ry, gx, gy, bx, by] => Ok(Chromaticities {
+                white: (wx, wy),
+                red: (rx, ry),
+                green: (gx, gy),
+                blue: (bx, by),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+//Same format FromStr reads
+impl fmt::Display for Chromaticities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = self
+            .points()
+            .iter()
+            .flat_map(|&(x, y)| [x, y])
+            .map(|value| format!("{:.5}", value as f64 / COLOR_SCALE))
+            .collect();
+        write!(f, "{}", values.join(","))
+    }
+}
+
+//Content of the sRGB chunk, which marks the image as sRGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+impl RenderingIntent {
+    pub const LENGTH: usize = 1;
+    const ALL: [RenderingIntent; 4] = [
+        Self::Perceptual,
+        Self::RelativeColorimetric,
+        Self::Saturation,
+        Self::AbsoluteColorimetric,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Perceptual => "perceptual",
+            Self::RelativeColorimetric => "relative-colorimetric",
+            Self::Saturation => "saturation",
+            Self::AbsoluteColorimetric => "absolute-colorimetric",
+        }
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::sRGB, vec![*self as u8])
+    }
+}
+
+impl TryFrom<&Chunk> for RenderingIntent {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, &ChunkType::sRGB, RenderingIntent::LENGTH)?;
+        Self::ALL
+            .get(data[0] as usize)
+            .copied()
+            .ok_or_else(|| Box::new(MetadataError::InvalidRenderingIntent(data[0])) as Error)
+    }
+}
+
+impl FromStr for RenderingIntent {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|intent| intent.name() == s)
+            .ok_or_else(|| Box::new(MetadataError::UnknownRenderingIntent(s.to_string())) as Error)
+    }
+}
+
+impl fmt::Display for RenderingIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+//The colour space hints of a PNG, from its gAMA, cHRM and sRGB chunks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColorInfo {
+    pub gamma: Option<Gamma>,
+    pub chromaticities: Option<Chromaticities>,
+    pub rendering_intent: Option<RenderingIntent>,
+}
+
+impl ColorInfo {
+    //Chunks for the values that are set, in the order gAMA, cHRM, sRGB
+    pub fn to_chunks(&self) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        chunks.extend(self.gamma.map(|gamma| gamma.to_chunk()));
+        chunks.extend(self.chromaticities.map(|chrm| chrm.to_chunk()));
+        chunks.extend(self.rendering_intent.map(|intent| intent.to_chunk()));
+        chunks
+    }
+
+    //Takes in the value of a gAMA, cHRM or sRGB chunk, other chunks are left alone
+    pub fn read(&mut self, chunk: &Chunk) -> Result<(), Error> {
+        let chunk_type = chunk.chunk_type();
+        if *chunk_type == ChunkType::gAMA {
+            self.gamma = Some(Gamma::try_from(chunk)?);
+        } else if *chunk_type == ChunkType::cHRM {
+            self.chromaticities = Some(Chromaticities::try_from(chunk)?);
+        } else if *chunk_type == ChunkType::sRGB {
+            self.rendering_intent = Some(RenderingIntent::try_from(chunk)?);
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Content of the iCCP chunk, an embedded ICC color profile: the profile name, a keyword
+ * as in tEXt chunks, a null separator, the compression method, always 0 for zlib, and
+ * the zlib compressed profile.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccProfile {
+    pub name: String,
+    pub profile: Vec<u8>,
+}
+
+impl IccProfile {
+    //Length of the profile header, which holds the `acsp` signature
+    const HEADER_LENGTH: usize = 128;
+
+    pub fn new(name: &str, profile: Vec<u8>) -> Result<IccProfile, Error> {
+        text::validate_keyword(name)?;
+        if profile.len() < Self::HEADER_LENGTH || profile[36..40] != *b"acsp" {
+            return Err(Box::new(MetadataError::NotIcc));
+        }
+        Ok(IccProfile {
+            name: name.to_string(),
+            profile,
+        })
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk, Error> {
+        let mut data: Vec<u8> = self.name.chars().map(|c| c as u8).collect();
+        //Separator and zlib compression method
+        data.extend_from_slice(&[0, 0]);
+        let mut encoder = ZlibEncoder::new(data, Compression::best());
+        encoder.write_all(&self.profile)?;
+        Ok(Chunk::new(ChunkType::iCCP, encoder.finish()?))
+    }
+}
+
+impl TryFrom<&Chunk> for IccProfile {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != ChunkType::iCCP {
+            return Err(Box::new(MetadataError::WrongChunk(
+                chunk.chunk_type().to_string(),
+            )));
+        }
+        let data = chunk.data();
+        let separator = data.iter().position(|&byte| byte == 0).ok_or_else(|| {
+            MetadataError::InvalidLength(chunk.chunk_type().to_string(), data.len())
+        })?;
+        //The name is Latin-1, whose code points match the first 256 of Unicode
+        let name: String = data[..separator].iter().map(|&byte| byte as char).collect();
+        let profile = match data.get(separator + 1..) {
+            Some([0, compressed @ ..]) => text::inflate(compressed)?,
+            Some([method, ..]) => return Err(Box::new(MetadataError::InvalidCompression(*method))),
+            _ => {
+                return Err(Box::new(MetadataError::InvalidLength(
+                    chunk.chunk_type().to_string(),
+                    data.len(),
+                )))
+            }
+        };
+        IccProfile::new(&name, profile)
+    }
+}
+
+impl Png {
+    //Parses the gAMA, cHRM and sRGB chunks
+    pub fn color_info(&self) -> Result<ColorInfo, Error> {
+        let mut info = ColorInfo::default();
+        for chunk in self.chunks() {
+            info.read(chunk)?;
+        }
+        Ok(info)
+    }
+
+    //Parses the iCCP chunk, if any
+    pub fn icc_profile(&self) -> Result<Option<IccProfile>, Error> {
+        self.chunk_by_type("iCCP")
+            .map(IccProfile::try_from)
+            .transpose()
+    }
+
+    //Parses the pHYs chunk, if any
+    pub fn physical_dimensions(&self) -> Result<Option<PhysicalDimensions>, Error> {
+        self.chunk_by_type("pHYs")
+            .map(PhysicalDimensions::try_from)
+            .transpose()
+    }
+
+    //Parses the tIME chunk, if any
+    pub fn last_modified(&self) -> Result<Option<Timestamp>, Error> {
+        self.chunk_by_type("tIME")
+            .map(Timestamp::try_from)
+            .transpose()
+    }
+}
+
+fn expect_chunk<'a>(
+    chunk: &'a Chunk,
+    chunk_type: &ChunkType,
+    length: usize,
+) -> Result<&'a [u8], Error> {
+    if chunk.chunk_type() != chunk_type {
+        return Err(Box::new(MetadataError::WrongChunk(
+            chunk.chunk_type().to_string(),
+        )));
+    }
+    let data = chunk.data();
+    if data.len() != length {
+        return Err(Box::new(MetadataError::InvalidLength(
+            chunk_type.to_string(),
+            data.len(),
+        )));
+    }
+    Ok(data)
+}
+
+#[derive(Debug)]
+pub enum MetadataError {
+    WrongChunk(String),
+    InvalidLength(String, usize),
+    InvalidUnit(u8),
+    InvalidTimestamp(Timestamp),
+    InvalidTime(String),
+    InvalidCompression(u8),
+    NotIcc,
+    InvalidDpi(f64),
+    InvalidGamma(String),
+    ZeroGamma,
+    InvalidChromaticities(String),
+    InvalidRenderingIntent(u8),
+    UnknownRenderingIntent(String),
+}
+
+impl std::error::Error for MetadataError {}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongChunk(chunk_type) => write!(f, "unexpected chunk: {}", chunk_type),
+            Self::InvalidLength(chunk_type, length) => {
+                write!(f, "invalid {} length: {}", chunk_type, length)
+            }
+            Self::InvalidUnit(unit) => write!(f, "invalid pHYs unit: {}", unit),
+            Self::InvalidTimestamp(timestamp) => write!(f, "invalid tIME: {}", timestamp),
+            Self::InvalidCompression(method) => {
+                write!(f, "unknown iCCP compression method {}", method)
+            }
+            Self::NotIcc => write!(f, "not an ICC profile, it has no acsp signature"),
+            Self::InvalidDpi(dpi) => write!(f, "invalid resolution {} dpi", dpi),
+            Self::InvalidGamma(gamma) => {
+                write!(f, "invalid gamma {}, expected a number such as 0.45455", gamma)
+            }
+            Self::ZeroGamma => write!(f, "invalid gAMA gamma: 0"),
+            Self::InvalidChromaticities(chrm) => write!(
+                f,
+                "invalid chromaticities {}, expected srgb or 8 comma separated values from 0 to 1",
+                chrm
+            ),
+            Self::InvalidRenderingIntent(intent) => {
+                write!(f, "invalid sRGB rendering intent: {}", intent)
+            }
+            Self::UnknownRenderingIntent(intent) => write!(
+                f,
+                "unknown rendering intent {}, expected perceptual, relative-colorimetric, saturation or absolute-colorimetric",
+                intent
+            ),
+            Self::InvalidTime(time) => write!(
+                f,
+                "invalid time {:?}, expected RFC 3339 such as 2024-01-31T12:00:00Z",
+                time
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_physical_dimensions() {
+        let phys = PhysicalDimensions {
+            pixels_per_unit_x: 3780,
+            pixels_per_unit_y: 3780,
+            unit: PhysUnit::Meter,
+        };
+        let parsed = PhysicalDimensions::try_from(&phys.to_chunk()).unwrap();
+        assert_eq!(parsed, phys);
+        let (x, _) = parsed.dpi().unwrap();
+        assert_eq!(x.round(), 96.0);
+
+        let chunk = Chunk::new(ChunkType::pHYs, vec![0, 0, 0, 1, 0, 0, 0, 1, 2]);
+        assert!(PhysicalDimensions::try_from(&chunk).is_err());
+
+        let print = PhysicalDimensions::from_dpi(300.0, 600.0).unwrap();
+        assert_eq!(
+            (print.pixels_per_unit_x, print.pixels_per_unit_y),
+            (11811, 23622)
+        );
+        let (x, y) = print.dpi().unwrap();
+        assert_eq!((x.round(), y.round()), (300.0, 600.0));
+        assert!(PhysicalDimensions::from_dpi(0.0, 300.0).is_err());
+    }
+
+    #[test]
+    pub fn test_timestamp() {
+        let chunk = Chunk::new(ChunkType::tIME, vec![0x07, 0xe8, 1, 31, 12, 0, 5]);
+        let timestamp = Timestamp::try_from(&chunk).unwrap();
+        assert_eq!(timestamp.to_string(), "2024-01-31T12:00:05Z");
+        assert_eq!(timestamp.to_chunk().data(), chunk.data());
+
+        let chunk = Chunk::new(ChunkType::tIME, vec![0x07, 0xe8, 13, 31, 12, 0, 5]);
+        assert!(Timestamp::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    pub fn test_timestamp_conversions() {
+        let parse = |s: &str| Timestamp::from_str(s).map(|timestamp| timestamp.to_string());
+        assert_eq!(
+            parse("2024-02-29T23:30:00Z").unwrap(),
+            "2024-02-29T23:30:00Z"
+        );
+        assert_eq!(
+            parse("2024-03-01T01:30:00.25+02:00").unwrap(),
+            "2024-02-29T23:30:00Z"
+        );
+        assert_eq!(
+            parse("1969-12-31 20:00:00-05:00").unwrap(),
+            "1970-01-01T01:00:00Z"
+        );
+        assert_eq!(
+            parse("2016-12-31T23:59:60z").unwrap(),
+            "2016-12-31T23:59:60Z"
+        );
+        for invalid in [
+            "2023-02-29T00:00:00Z",
+            "2024-1-31T00:00:00Z",
+            "2024-01-31T00:00:00+1",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+        let timestamp = Timestamp::from_str("2038-01-19T03:14:08Z").unwrap();
+        assert_eq!(timestamp.to_unix(), 1 << 31);
+        assert_eq!(Timestamp::from_unix(1 << 31), Some(timestamp));
+        assert_eq!(
+            Timestamp::from_unix(0).unwrap().to_string(),
+            "1970-01-01T00:00:00Z"
+        );
+    }
+
+    #[test]
+    pub fn test_color_info() {
+        assert_eq!(Gamma::from_str("0.45455").unwrap(), Gamma::SRGB);
+        assert_eq!(Gamma::from_str("2.2").unwrap(), Gamma::SRGB);
+        assert!(Gamma::from_str("-1").is_err());
+        assert!(Gamma::try_from(&Chunk::new(ChunkType::gAMA, vec![0; 4])).is_err());
+        let chrm = Chromaticities::from_str(&Chromaticities::SRGB.to_string()).unwrap();
+        assert_eq!(chrm, Chromaticities::SRGB);
+        assert!(Chromaticities::from_str("0.3,0.3").is_err());
+        assert_eq!(
+            RenderingIntent::from_str("saturation").unwrap(),
+            RenderingIntent::Saturation
+        );
+        let info = ColorInfo {
+            gamma: Some(Gamma::SRGB),
+            chromaticities: Some(chrm),
+            rendering_intent: Some(RenderingIntent::RelativeColorimetric),
+        };
+        let chunks = info.to_chunks();
+        assert_eq!(chunks[1].length(), 32);
+        assert_eq!(chunks[2].data(), [1]);
+        let mut read = ColorInfo::default();
+        for chunk in &chunks {
+            read.read(chunk).unwrap();
+        }
+        assert_eq!(read, info);
+        let broken = Chunk::new(ChunkType::sRGB, vec![4]);
+        assert!(read.read(&broken).is_err());
+    }
+
+    #[test]
+    pub fn test_icc_profile() {
+        let mut profile = vec![0; 200];
+        profile[36..40].copy_from_slice(b"acsp");
+        let icc = IccProfile::new("sRGB IEC61966-2.1", profile.clone()).unwrap();
+        let chunk = icc.to_chunk().unwrap();
+        assert!(chunk.data().starts_with(b"sRGB IEC61966-2.1\0\0"));
+        assert!(chunk.length() < 100);
+        assert_eq!(IccProfile::try_from(&chunk).unwrap(), icc);
+        assert!(IccProfile::new("Profile", vec![0; 200]).is_err());
+        assert!(IccProfile::new(" Profile", profile).is_err());
+        let mut data = chunk.data().to_vec();
+        data[18] = 1;
+        assert!(IccProfile::try_from(&Chunk::new(ChunkType::iCCP, data)).is_err());
+    }
+
+    #[test]
+    pub fn test_png_metadata() {
+        let png = Png::from_chunks(vec![Chunk::new(
+            ChunkType::tIME,
+            vec![0x07, 0xe8, 1, 31, 12, 0, 5],
+        )]);
+        assert_eq!(png.last_modified().unwrap().unwrap().year, 2024);
+        assert!(png.physical_dimensions().unwrap().is_none());
+    }
+}
@@ -0,0 +1,215 @@
+/*!
+ * Several payloads sharing a chunk type, told apart by name.
+ * Every chunk of a named payload starts with `NAME_MAGIC`, the length of the name as
+ * a u8 and the UTF-8 name, followed by what a chunk of an unnamed payload holds, a
+ * plain payload or a part as made by `fragment::split`.
+ * The names are listed in a table of contents chunk of type `TOC_CHUNK_TYPE`: the
+ * entry count as a big endian u16, then for every entry the name, its length as a u8
+ * first, the chunk type and the payload length as a big endian u64.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::fragment;
+use crate::Error;
+use std::fmt;
+use std::str::FromStr;
+
+pub const NAME_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'N'];
+//Private, ancillary and safe to copy, like the chunks holding the payloads
+pub const TOC_CHUNK_TYPE: &str = "pmTc";
+
+//Splits `payload` as `fragment::split` does and tags every chunk with `name`
+pub fn split(
+    chunk_type: &ChunkType,
+    name: &str,
+    payload: &[u8],
+    part_length: usize,
+) -> Result<Vec<Chunk>, Error> {
+    let header = header(name)?;
+    let chunks = fragment::split(chunk_type, payload, part_length)?
+        .into_iter()
+        .map(|chunk| {
+            let mut data = header.clone();
+            data.extend_from_slice(chunk.data());
+            Chunk::new(chunk_type.clone(), data)
+        })
+        .collect();
+    Ok(chunks)
+}
+
+fn header(name: &str) -> Result<Vec<u8>, Error> {
+    let length = u8::try_from(name.len())
+        .ok()
+        .filter(|&length| length > 0)
+        .ok_or_else(|| NamedError::InvalidName(name.to_string()))?;
+    let mut header = NAME_MAGIC.to_vec();
+    header.push(length);
+    header.extend_from_slice(name.as_bytes());
+    Ok(header)
+}
+
+//Name and the rest of the data of a named chunk, None for any other chunk
+pub fn parse_name(data: &[u8]) -> Option<(&str, &[u8])> {
+    let (&length, rest) = data.strip_prefix(&NAME_MAGIC)?.split_first()?;
+    let name = std::str::from_utf8(rest.get(..length as usize)?).ok()?;
+    Some((name, &rest[length as usize..]))
+}
+
+//Rebuilds the payload called `name` from chunks of a single type, None if it isn't there
+pub fn reassemble<'a, I>(chunks: I, name: &str) -> Result<Option<Vec<u8>>, Error>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    let parts: Vec<Chunk> = chunks
+        .into_iter()
+        .filter_map(|chunk| match parse_name(chunk.data()) {
+            Some((chunk_name, data)) if chunk_name == name => {
+                Some(Chunk::new(chunk.chunk_type().clone(), data.to_vec()))
+            }
+            _ => None,
+        })
+        .collect();
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    fragment::reassemble(&parts).map(Some)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    pub name: String,
+    pub chunk_type: ChunkType,
+    pub length: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toc {
+    //In the order the payloads were first stored
+    pub entries: Vec<TocEntry>,
+}
+
+impl Toc {
+    //Adds the entry, or updates the one with the same name and chunk type
+    pub fn insert(&mut self, entry: TocEntry) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|existing| existing.name == entry.name && existing.chunk_type == entry.chunk_type)
+        {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk, Error> {
+        let count = u16::try_from(self.entries.len())
+            .map_err(|_| NamedError::TooManyEntries(self.entries.len()))?;
+        let mut data = count.to_be_bytes().to_vec();
+        for entry in &self.entries {
+            data.extend_from_slice(&header(&entry.name)?[NAME_MAGIC.len()..]);
+            data.extend_from_slice(&entry.chunk_type.bytes());
+            data.extend_from_slice(&entry.length.to_be_bytes());
+        }
+        Ok(Chunk::new(ChunkType::from_str(TOC_CHUNK_TYPE)?, data))
+    }
+
+    pub fn from_chunk(chunk: &Chunk) -> Result<Toc, Error> {
+        let data = chunk.data();
+        let (count, mut rest) = data
+            .split_first_chunk::<2>()
+            .ok_or(NamedError::InvalidToc)?;
+        let mut entries = Vec::new();
+        for _ in 0..u16::from_be_bytes(*count) {
+            let (&length, entry) = rest.split_first().ok_or(NamedError::InvalidToc)?;
+            let (name, entry) = entry
+                .split_at_checked(length as usize)
+                .ok_or(NamedError::InvalidToc)?;
+            let name = String::from_utf8(name.to_vec()).map_err(|_| NamedError::InvalidToc)?;
+            let (chunk_type, entry) = entry
+                .split_first_chunk::<4>()
+                .ok_or(NamedError::InvalidToc)?;
+            let (length, entry) = entry
+                .split_first_chunk::<8>()
+                .ok_or(NamedError::InvalidToc)?;
+            entries.push(TocEntry {
+                name,
+                chunk_type: ChunkType::try_from(*chunk_type)?,
+                length: u64::from_be_bytes(*length),
+            });
+            rest = entry;
+        }
+        Ok(Toc { entries })
+    }
+}
+
+#[derive(Debug)]
+pub enum NamedError {
+    InvalidName(String),
+    TooManyEntries(usize),
+    InvalidToc,
+}
+
+impl std::error::Error for NamedError {}
+
+impl fmt::Display for NamedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => {
+                write!(
+                    f,
+                    "invalid payload name {:?}, expected 1 to 255 bytes",
+                    name
+                )
+            }
+            Self::TooManyEntries(count) => {
+                write!(
+                    f,
+                    "{} named payloads don't fit in a table of contents",
+                    count
+                )
+            }
+            Self::InvalidToc => write!(f, "table of contents chunk is damaged"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_split_reassemble() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let mut chunks = split(&chunk_type, "notes", b"a long message split up", 8).unwrap();
+        chunks.extend(split(&chunk_type, "todo", b"short", 8).unwrap());
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(parse_name(chunks[3].data()), Some(("todo", &b"short"[..])));
+        assert_eq!(
+            reassemble(&chunks, "notes").unwrap().unwrap(),
+            b"a long message split up"
+        );
+        assert_eq!(reassemble(&chunks, "todo").unwrap().unwrap(), b"short");
+        assert!(reassemble(&chunks, "other").unwrap().is_none());
+        assert!(split(&chunk_type, "", b"short", 8).is_err());
+    }
+
+    #[test]
+    pub fn test_toc() {
+        let mut toc = Toc::default();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        for (name, length) in [("notes", 10), ("todo", 3), ("notes", 12)] {
+            toc.insert(TocEntry {
+                name: name.to_string(),
+                chunk_type: chunk_type.clone(),
+                length,
+            });
+        }
+        assert_eq!(toc.entries.len(), 2);
+        assert_eq!(toc.entries[0].length, 12);
+        let chunk = toc.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), TOC_CHUNK_TYPE);
+        assert_eq!(Toc::from_chunk(&chunk).unwrap(), toc);
+        let truncated = Chunk::new(chunk.chunk_type().clone(), chunk.data()[..9].to_vec());
+        assert!(Toc::from_chunk(&truncated).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+/*!
+ * Rewriting a file the way mainstream encoders do, so that one pngme wrote doesn't
+ * stand out: the chunks in the order libpng writes them, text before the pixel data,
+ * no tIME or text naming the software or the time of the last edit, and the pixel
+ * data filtered and deflated again with the defaults of libpng. The pixels stay the
+ * same, so do other chunks, payloads included.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::{ChunkOrdering, ChunkType};
+use crate::png::Png;
+use crate::text;
+use crate::Error;
+use std::fmt;
+
+//Text keywords that tell which tool wrote the file, and when
+pub const IDENTIFYING_KEYWORDS: [&str; 5] = [
+    "Software",
+    "Creation Time",
+    "date:create",
+    "date:modify",
+    "date:timestamp",
+];
+
+#[derive(Debug, Clone)]
+pub struct Normalized {
+    //Identifying chunks, in file order
+    pub removed: Vec<Chunk>,
+    //Whether the other chunks changed order
+    pub reordered: bool,
+    //Compressed size of the pixel data, before and after
+    pub pixel_data: (usize, usize),
+}
+
+impl Png {
+    //Normalizes the file in place, animated PNGs aren't supported
+    pub fn normalize(&mut self) -> Result<Normalized, Error> {
+        if self.is_animated() {
+            return Err(Box::new(NormalizeError::Animated));
+        }
+        let scanlines = self.scanlines()?;
+        let old_length = self.idat_data().len();
+        let (removed, mut chunks): (Vec<Chunk>, Vec<Chunk>) =
+            self.chunks().iter().cloned().partition(is_identifying);
+        let order: Vec<ChunkType> = chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type().clone())
+            .collect();
+        //Stable, chunks of the same rank keep their order
+        chunks.sort_by_key(|chunk| rank(chunk.chunk_type()));
+        let reordered = chunks
+            .iter()
+            .zip(&order)
+            .any(|(chunk, chunk_type)| chunk.chunk_type() != chunk_type);
+        *self.chunks_mut() = chunks;
+        let new_length = self.set_scanlines_libpng(&scanlines)?;
+        Ok(Normalized {
+            removed,
+            reordered,
+            pixel_data: (old_length, new_length),
+        })
+    }
+}
+
+fn is_identifying(chunk: &Chunk) -> bool {
+    *chunk.chunk_type() == ChunkType::tIME
+        || text::keyword(chunk)
+            .is_some_and(|keyword| IDENTIFYING_KEYWORDS.contains(&keyword.as_str()))
+}
+
+//Position of the chunk type in the files libpng writes
+fn rank(chunk_type: &ChunkType) -> u8 {
+    match chunk_type.ordering() {
+        ChunkOrdering::First => 0,
+        ChunkOrdering::BeforePalette => 1,
+        ChunkOrdering::Palette => 2,
+        ChunkOrdering::AfterPalette => 3,
+        ChunkOrdering::BeforeData => 4,
+        ChunkOrdering::Anywhere => 5,
+        ChunkOrdering::Data => 6,
+        ChunkOrdering::Frame => 7,
+        ChunkOrdering::Last => 8,
+    }
+}
+
+#[derive(Debug)]
+pub enum NormalizeError {
+    Animated,
+}
+
+impl std::error::Error for NormalizeError {}
+
+impl fmt::Display for NormalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Animated => write!(f, "animated PNGs can't be normalized"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+
+    #[test]
+    pub fn test_normalize() {
+        let mut png = PngBuilder::new(64, 64)
+            .color(&[10, 20, 30])
+            .build()
+            .unwrap();
+        let pixels = png.scanlines().unwrap();
+        png.append_chunk(Chunk::new(ChunkType::tIME, vec![7, 234, 1, 1, 0, 0, 0]));
+        png.insert_chunk_ordered(text::text_chunk("Software", "pngme").unwrap());
+        png.insert_chunk_ordered(text::text_chunk("Title", "Kept").unwrap());
+        //After IDAT, where libpng doesn't put them
+        let iend = png.chunks().len() - 1;
+        png.chunks_mut()
+            .insert(iend, Chunk::new(ChunkType::gAMA, vec![0, 0, 177, 143]));
+
+        let normalized = png.normalize().unwrap();
+        assert_eq!(normalized.removed.len(), 2);
+        assert!(normalized.reordered);
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "gAMA", "tEXt", "IDAT", "IEND"]);
+        assert_eq!(png.scanlines().unwrap(), pixels);
+        assert_eq!(normalized.pixel_data.1, png.idat_data().len());
+
+        let normalized = png.normalize().unwrap();
+        assert!(normalized.removed.is_empty() && !normalized.reordered);
+    }
+}
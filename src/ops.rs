@@ -0,0 +1,1846 @@
+/*!
+ * Message operations over any `Read`/`Write`, so they work the same on files,
+ * in-memory buffers, sockets or archive entries.
+ * Chunks are streamed from input to output, only one chunk is in memory at a time.
+ * The `*_chunks` variants take already parsed chunks, e.g. from a `SharedPngReader`.
+ */
+use crate::ancillary::Extensions;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::decoy;
+use crate::fragment;
+use crate::metadata::{ColorInfo, IccProfile, PhysicalDimensions, Timestamp};
+use crate::named::{self, Toc, TocEntry};
+use crate::payload;
+use crate::png::{Png, PngError};
+use crate::stream::{PngReader, PngWriter};
+use crate::text::{self, InternationalText, TextEntry};
+use crate::validate::ValidationIssue;
+use crate::xmp::{self, XmpError};
+use crate::Error;
+use bytes::Bytes;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+//A chunk whose CRC was recomputed by `repair`
+#[derive(Debug, Clone)]
+pub struct RepairedChunk {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub old_crc: u32,
+    pub new_crc: u32,
+}
+
+/**
+ * Copies the PNG from `input` to `output`, adding `chunk` right before IEND.
+ * In an animated PNG it goes before the first fcTL or IDAT instead, where it can't
+ * split a frame from its data.
+ */
+pub fn encode<R: Read, W: Write>(input: R, output: W, chunk: Chunk) -> Result<(), Error> {
+    encode_chunks(PngReader::new(input)?, output, chunk)
+}
+
+pub fn encode_chunks<I, W>(chunks: I, output: W, chunk: Chunk) -> Result<(), Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    insert_chunks(chunks, output, vec![chunk])
+}
+
+//What encoding does with chunks of the payload type that are already in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Existing {
+    //Fail instead of storing a second payload of the type, the default
+    Reject,
+    //Remove them, the new payload takes their place
+    Replace,
+    //Keep them next to the new payload
+    Append,
+}
+
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    //Payloads over this many bytes are split as `fragment::split` does
+    pub part_length: usize,
+    pub existing: Existing,
+    //Random chunks stored along with the payload, see `decoy`
+    pub decoys: usize,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            part_length: fragment::DEFAULT_PART_LENGTH,
+            existing: Existing::Reject,
+            decoys: 0,
+        }
+    }
+}
+
+//Same as `encode` for a payload of any size, returns the chunks it replaced
+pub fn encode_payload<R: Read, W: Write>(
+    input: R,
+    output: W,
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    options: &EncodeOptions,
+) -> Result<Vec<Chunk>, Error> {
+    encode_payload_chunks(PngReader::new(input)?, output, chunk_type, payload, options)
+}
+
+pub fn encode_payload_chunks<I, W>(
+    chunks: I,
+    output: W,
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    options: &EncodeOptions,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    encode_payload_chunks_with(chunks, output, chunk_type, payload, None, options)
+}
+
+/**
+ * Same as `encode_payload_chunks`, with `hidden` slipped in among the decoys, of which
+ * there is then at least one. Stored as bare ciphertext in a private type, see
+ * `crypto::encrypt_bare`, it can't be told from them: the payload can be given away
+ * while denying anything else is there. Chunks of the hidden type already in the file
+ * are handled like those of `chunk_type`.
+ */
+pub fn encode_deniable_payload_chunks<I, W>(
+    chunks: I,
+    output: W,
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    hidden: Chunk,
+    options: &EncodeOptions,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    encode_payload_chunks_with(chunks, output, chunk_type, payload, Some(hidden), options)
+}
+
+fn encode_payload_chunks_with<I, W>(
+    chunks: I,
+    output: W,
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    hidden: Option<Chunk>,
+    options: &EncodeOptions,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let new_chunks = fragment::split(chunk_type, payload, options.part_length)?;
+    let mut types = vec![chunk_type.clone()];
+    let decoys = match hidden {
+        Some(hidden) => {
+            types.push(hidden.chunk_type().clone());
+            let count = options.decoys.max(decoy::MIN_HIDDEN_DECOYS);
+            let mut decoys = decoy::generate(count, &new_chunks, &types);
+            decoys.push(hidden);
+            decoys
+        }
+        None => decoy::generate(options.decoys, &new_chunks, &[]),
+    };
+    let new_chunks = decoy::mix(new_chunks, decoys);
+    replace_and_insert(chunks, output, &types, new_chunks, options)
+}
+
+/**
+ * Stores the manifest chunk of a payload spread as `spread::spread` does and its
+ * pieces, scattered at random among the decoys. Only chunks of the manifest type count
+ * as already in the file, the pieces of a payload they replace stay behind.
+ */
+pub fn encode_spread_payload_chunks<I, W>(
+    chunks: I,
+    output: W,
+    manifest: Chunk,
+    pieces: Vec<Chunk>,
+    options: &EncodeOptions,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let types = [manifest.chunk_type().clone()];
+    let mut scattered = decoy::generate(options.decoys, &pieces, &[]);
+    scattered.extend(pieces);
+    let new_chunks = decoy::mix(vec![manifest], scattered);
+    replace_and_insert(chunks, output, &types, new_chunks, options)
+}
+
+//Inserts `new_chunks`, handling the chunks of `types` already there as `options` says
+fn replace_and_insert<I, W>(
+    chunks: I,
+    output: W,
+    types: &[ChunkType],
+    new_chunks: Vec<Chunk>,
+    options: &EncodeOptions,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let mut replaced = Vec::new();
+    let kept = chunks.into_iter().filter_map(|chunk| match chunk {
+        Ok(chunk) if types.contains(chunk.chunk_type()) => match options.existing {
+            Existing::Reject => Some(Err(Box::new(PngError::ChunkExists(
+                chunk.chunk_type().to_string(),
+            )) as Error)),
+            Existing::Replace => {
+                replaced.push(chunk);
+                None
+            }
+            Existing::Append => Some(Ok(chunk)),
+        },
+        chunk => Some(chunk),
+    });
+    insert_chunks(kept, output, new_chunks)?;
+    Ok(replaced)
+}
+
+/**
+ * Same as `encode_payload_chunks` for a payload stored under `name`, see `named`.
+ * Only payloads of the same name count as existing, and since two payloads can't
+ * share a name `Existing::Append` fails like `Existing::Reject`.
+ * The table of contents is updated, or added if the file has none.
+ */
+pub fn encode_named_payload_chunks<I, W>(
+    chunks: I,
+    output: W,
+    chunk_type: &ChunkType,
+    name: &str,
+    payload: &[u8],
+    options: &EncodeOptions,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let new_chunks = named::split(chunk_type, name, payload, options.part_length)?;
+    let decoys = decoy::generate(options.decoys, &new_chunks, &[]);
+    let new_chunks = decoy::mix(new_chunks, decoys);
+    let toc_type = ChunkType::from_str(named::TOC_CHUNK_TYPE)?;
+    //The old table of contents comes before the insertion point, where the new one goes
+    let toc = RefCell::new(Toc::default());
+    let mut replaced = Vec::new();
+    let kept = chunks.into_iter().filter_map(|chunk| {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => return Some(Err(err)),
+        };
+        if *chunk.chunk_type() == toc_type {
+            let old = chunk.verify_crc().and_then(|()| Toc::from_chunk(&chunk));
+            return match old {
+                Ok(old) => {
+                    *toc.borrow_mut() = old;
+                    None
+                }
+                Err(err) => Some(Err(err)),
+            };
+        }
+        let same_name = chunk.chunk_type() == chunk_type
+            && named::parse_name(chunk.data()).is_some_and(|(existing, _)| existing == name);
+        if !same_name {
+            return Some(Ok(chunk));
+        }
+        match options.existing {
+            Existing::Replace => {
+                replaced.push(chunk);
+                None
+            }
+            Existing::Reject | Existing::Append => Some(Err(Box::new(PngError::PayloadExists(
+                name.to_string(),
+            )) as Error)),
+        }
+    });
+    insert_chunks_with(kept, output, || {
+        let mut toc = toc.borrow_mut();
+        toc.insert(TocEntry {
+            name: name.to_string(),
+            chunk_type: chunk_type.clone(),
+            length: payload.len() as u64,
+        });
+        let mut chunks = vec![toc.to_chunk()?];
+        chunks.extend(new_chunks);
+        Ok(chunks)
+    })?;
+    Ok(replaced)
+}
+
+fn insert_chunks<I, W>(chunks: I, output: W, new_chunks: Vec<Chunk>) -> Result<(), Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    insert_chunks_with(chunks, output, || Ok(new_chunks))
+}
+
+//Same as `insert_chunks`, the new chunks are only made once the insertion point is reached
+fn insert_chunks_with<I, W, F>(chunks: I, output: W, new_chunks: F) -> Result<(), Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+    F: FnOnce() -> Result<Vec<Chunk>, Error>,
+{
+    let mut writer = PngWriter::new(output)?;
+    //The new chunks go right before IEND, or at the end if the file has none
+    let mut new_chunks = Some(new_chunks);
+    let mut position = EncodePosition::default();
+    for existing in chunks {
+        let existing = existing?;
+        existing.verify_crc()?;
+        if position.is_reached(existing.chunk_type()) {
+            if let Some(new_chunks) = new_chunks.take() {
+                let new_chunks = new_chunks()?;
+                log::debug!(
+                    "inserting {} chunk(s) before {}",
+                    new_chunks.len(),
+                    existing.chunk_type()
+                );
+                for chunk in &new_chunks {
+                    writer.write_chunk(chunk)?;
+                }
+            }
+        }
+        writer.write_chunk(&existing)?;
+    }
+    if let Some(new_chunks) = new_chunks {
+        let new_chunks = new_chunks()?;
+        log::debug!("inserting {} chunk(s) at the end", new_chunks.len());
+        for chunk in &new_chunks {
+            writer.write_chunk(chunk)?;
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+//Tracks where `encode` inserts its chunk while the input is streamed through
+#[derive(Default)]
+pub(crate) struct EncodePosition {
+    //acTL must come before any frame, so the file is known to be animated in time
+    animated: bool,
+}
+
+impl EncodePosition {
+    //Whether the new chunk goes right before a chunk of the given type
+    pub(crate) fn is_reached(&mut self, chunk_type: &ChunkType) -> bool {
+        self.animated |= *chunk_type == ChunkType::acTL;
+        let frame_start =
+            self.animated && (*chunk_type == ChunkType::fcTL || *chunk_type == ChunkType::IDAT);
+        frame_start || *chunk_type == ChunkType::IEND
+    }
+}
+
+//Returns the first chunk of the given type
+pub fn decode<R: Read>(input: R, chunk_type: &str) -> Result<Chunk, Error> {
+    decode_chunks(PngReader::new(input)?, chunk_type)
+}
+
+pub fn decode_chunks<I>(chunks: I, chunk_type: &str) -> Result<Chunk, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    decode_nth_chunk(chunks, chunk_type, 0)
+}
+
+/**
+ * Returns the chunk of the given type at position `n` among them, counted from 0.
+ * Fails on the first chunk that can't be read before it, rather than counting it.
+ */
+pub fn decode_nth_chunk<I>(chunks: I, chunk_type: &str, n: usize) -> Result<Chunk, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut seen = 0;
+    for chunk in chunks {
+        let chunk = chunk?;
+        if chunk.chunk_type().to_string() != chunk_type {
+            continue;
+        }
+        if seen == n {
+            chunk.verify_crc()?;
+            return Ok(chunk);
+        }
+        seen += 1;
+    }
+    Err(Box::new(PngError::ChunkNotFound(chunk_type.to_string())))
+}
+
+//Returns the payload stored in chunks of the given type, reassembling its parts
+pub fn decode_payload<R: Read>(input: R, chunk_type: &str) -> Result<Vec<u8>, Error> {
+    decode_payload_chunks(PngReader::new(input)?, chunk_type)
+}
+
+pub fn decode_payload_chunks<I>(chunks: I, chunk_type: &str) -> Result<Vec<u8>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut matching = Vec::new();
+    for chunk in chunks {
+        let chunk = chunk?;
+        //Named payloads of the type are left to `decode_named_payload_chunks`
+        if chunk.chunk_type().to_string() == chunk_type && named::parse_name(chunk.data()).is_none()
+        {
+            chunk.verify_crc()?;
+            //A plain chunk holds the whole payload, no need to read further
+            if matching.is_empty() && fragment::parse_part(chunk.data()).is_none() {
+                return Ok(chunk.data().to_vec());
+            }
+            matching.push(chunk);
+        }
+    }
+    if matching.is_empty() {
+        return Err(Box::new(PngError::ChunkNotFound(chunk_type.to_string())));
+    }
+    fragment::reassemble(&matching)
+}
+
+//Returns the payload stored under `name` in chunks of the given type
+pub fn decode_named_payload_chunks<I>(
+    chunks: I,
+    chunk_type: &str,
+    name: &str,
+) -> Result<Vec<u8>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut matching = Vec::new();
+    for chunk in chunks {
+        let chunk = chunk?;
+        if chunk.chunk_type().to_string() == chunk_type {
+            chunk.verify_crc()?;
+            matching.push(chunk);
+        }
+    }
+    named::reassemble(&matching, name)?
+        .ok_or_else(|| Box::new(PngError::PayloadNotFound(name.to_string())) as Error)
+}
+
+//The table of contents of the named payloads, empty when the file has none
+pub fn read_toc<I>(chunks: I) -> Result<Toc, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    for chunk in chunks {
+        let chunk = chunk?;
+        if chunk.chunk_type().to_string() == named::TOC_CHUNK_TYPE {
+            chunk.verify_crc()?;
+            return Toc::from_chunk(&chunk);
+        }
+    }
+    Ok(Toc::default())
+}
+
+/**
+ * Copies the PNG from `input` to `output` with `entry` as the only text of its
+ * keyword, returning the text it replaced. The new tEXt chunk goes where `encode`
+ * puts its chunk.
+ */
+pub fn set_text<R: Read, W: Write>(
+    input: R,
+    output: W,
+    entry: &TextEntry,
+) -> Result<Option<TextEntry>, Error> {
+    set_text_chunks(PngReader::new(input)?, output, entry)
+}
+
+pub fn set_text_chunks<I, W>(
+    chunks: I,
+    output: W,
+    entry: &TextEntry,
+) -> Result<Option<TextEntry>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let new_chunk = entry.to_chunk()?;
+    let mut replaced = None;
+    let kept = chunks.into_iter().filter_map(|chunk| match chunk {
+        Ok(chunk) if text::is_text_chunk(chunk.chunk_type()) => match TextEntry::try_from(&chunk) {
+            Ok(old) if old.keyword == entry.keyword => {
+                replaced.get_or_insert(old);
+                None
+            }
+            //Damaged text chunks are left alone
+            _ => Some(Ok(chunk)),
+        },
+        chunk => Some(chunk),
+    });
+    insert_chunks(kept, output, vec![new_chunk])?;
+    Ok(replaced)
+}
+
+//Every tEXt and zTXt chunk of the PNG, in file order
+pub fn read_text<I>(chunks: I) -> Result<Vec<TextEntry>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut entries = Vec::new();
+    for chunk in chunks {
+        let chunk = chunk?;
+        if text::is_text_chunk(chunk.chunk_type()) {
+            chunk.verify_crc()?;
+            entries.push(TextEntry::try_from(&chunk)?);
+        }
+    }
+    Ok(entries)
+}
+
+/**
+ * Copies the PNG with `entry` as the only iTXt text of its keyword and language,
+ * returning the one it replaced. Translations to other languages are kept.
+ */
+pub fn set_international_text<R: Read, W: Write>(
+    input: R,
+    output: W,
+    entry: &InternationalText,
+) -> Result<Option<InternationalText>, Error> {
+    set_international_text_chunks(PngReader::new(input)?, output, entry)
+}
+
+pub fn set_international_text_chunks<I, W>(
+    chunks: I,
+    output: W,
+    entry: &InternationalText,
+) -> Result<Option<InternationalText>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let new_chunk = entry.to_chunk()?;
+    let mut replaced = None;
+    let kept = chunks.into_iter().filter_map(|chunk| match chunk {
+        Ok(chunk) if *chunk.chunk_type() == ChunkType::iTXt => {
+            match InternationalText::try_from(&chunk) {
+                Ok(old)
+                    if old.keyword == entry.keyword
+                        && old.language.eq_ignore_ascii_case(&entry.language) =>
+                {
+                    replaced.get_or_insert(old);
+                    None
+                }
+                //Damaged text chunks are left alone
+                _ => Some(Ok(chunk)),
+            }
+        }
+        chunk => Some(chunk),
+    });
+    insert_chunks(kept, output, vec![new_chunk])?;
+    Ok(replaced)
+}
+
+//Every tEXt, zTXt and iTXt chunk of the PNG in file order, the XMP packet left out
+pub fn read_international_text<I>(chunks: I) -> Result<Vec<InternationalText>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut entries = Vec::new();
+    for chunk in chunks {
+        let chunk = chunk?;
+        if text::is_text_chunk(chunk.chunk_type()) {
+            chunk.verify_crc()?;
+            entries.push(TextEntry::try_from(&chunk)?.into());
+        } else if *chunk.chunk_type() == ChunkType::iTXt && !xmp::is_xmp_chunk(&chunk) {
+            chunk.verify_crc()?;
+            entries.push(InternationalText::try_from(&chunk)?);
+        }
+    }
+    Ok(entries)
+}
+
+/**
+ * Copies the PNG with `profile` as its ICC profile, right after IHDR as the spec wants
+ * it before PLTE and IDAT. Returns the chunks it replaces: the previous iCCP chunk and
+ * the sRGB chunk, which the spec doesn't allow next to an ICC profile.
+ */
+pub fn embed_icc<R: Read, W: Write>(
+    input: R,
+    output: W,
+    profile: &IccProfile,
+) -> Result<Vec<Chunk>, Error> {
+    embed_icc_chunks(PngReader::new(input)?, output, profile)
+}
+
+pub fn embed_icc_chunks<I, W>(
+    chunks: I,
+    output: W,
+    profile: &IccProfile,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    replace_after_header(
+        chunks,
+        output,
+        vec![profile.to_chunk()?],
+        &[ChunkType::iCCP, ChunkType::sRGB],
+    )
+}
+
+/**
+ * Copies the PNG with the colour space hints of `info` that are set, replacing the
+ * chunks already there, right after IHDR. Setting the rendering intent also replaces
+ * the iCCP chunk, the spec allowing only one of sRGB and iCCP. Returns the chunks it
+ * replaces.
+ */
+pub fn set_color<R: Read, W: Write>(
+    input: R,
+    output: W,
+    info: &ColorInfo,
+) -> Result<Vec<Chunk>, Error> {
+    set_color_chunks(PngReader::new(input)?, output, info)
+}
+
+pub fn set_color_chunks<I, W>(chunks: I, output: W, info: &ColorInfo) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let new_chunks = info.to_chunks();
+    let mut replaced_types: Vec<ChunkType> = new_chunks
+        .iter()
+        .map(|chunk| chunk.chunk_type().clone())
+        .collect();
+    if info.rendering_intent.is_some() {
+        replaced_types.push(ChunkType::iCCP);
+    }
+    replace_after_header(chunks, output, new_chunks, &replaced_types)
+}
+
+//The colour space hints of the PNG, from its gAMA, cHRM and sRGB chunks
+pub fn read_color<I>(chunks: I) -> Result<ColorInfo, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut info = ColorInfo::default();
+    for chunk in chunks {
+        let chunk = chunk?;
+        //They must come before the image data
+        if *chunk.chunk_type() == ChunkType::IDAT {
+            break;
+        }
+        chunk.verify_crc()?;
+        info.read(&chunk)?;
+    }
+    Ok(info)
+}
+
+//Copies the PNG with the oFFs, sCAL and sTER chunks that are set, returns the ones it replaces
+pub fn set_extensions<R: Read, W: Write>(
+    input: R,
+    output: W,
+    extensions: &Extensions,
+) -> Result<Vec<Chunk>, Error> {
+    set_extensions_chunks(PngReader::new(input)?, output, extensions)
+}
+
+pub fn set_extensions_chunks<I, W>(
+    chunks: I,
+    output: W,
+    extensions: &Extensions,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let new_chunks = extensions.to_chunks();
+    let replaced_types: Vec<ChunkType> = new_chunks
+        .iter()
+        .map(|chunk| chunk.chunk_type().clone())
+        .collect();
+    replace_after_header(chunks, output, new_chunks, &replaced_types)
+}
+
+//The placement, pixel size and stereo layout of the PNG, from its oFFs, sCAL and sTER chunks
+pub fn read_extensions<I>(chunks: I) -> Result<Extensions, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut extensions = Extensions::default();
+    for chunk in chunks {
+        let chunk = chunk?;
+        //They must come before the image data
+        if *chunk.chunk_type() == ChunkType::IDAT {
+            break;
+        }
+        chunk.verify_crc()?;
+        extensions.read(&chunk)?;
+    }
+    Ok(extensions)
+}
+
+//Copies the PNG with `phys` as its pHYs chunk, returns the one it replaced
+pub fn set_phys<R: Read, W: Write>(
+    input: R,
+    output: W,
+    phys: &PhysicalDimensions,
+) -> Result<Option<PhysicalDimensions>, Error> {
+    set_phys_chunks(PngReader::new(input)?, output, phys)
+}
+
+pub fn set_phys_chunks<I, W>(
+    chunks: I,
+    output: W,
+    phys: &PhysicalDimensions,
+) -> Result<Option<PhysicalDimensions>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let replaced = replace_after_header(chunks, output, vec![phys.to_chunk()], &[ChunkType::pHYs])?;
+    //A damaged pHYs chunk is replaced all the same
+    Ok(replaced
+        .first()
+        .and_then(|chunk| PhysicalDimensions::try_from(chunk).ok()))
+}
+
+//The pHYs chunk of the PNG, if any
+pub fn read_phys<I>(chunks: I) -> Result<Option<PhysicalDimensions>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    for chunk in chunks {
+        let chunk = chunk?;
+        if *chunk.chunk_type() == ChunkType::pHYs {
+            chunk.verify_crc()?;
+            return PhysicalDimensions::try_from(&chunk).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+//Copies the PNG without the chunks of `replaced_types`, with `new_chunks` right after IHDR
+fn replace_after_header<I, W>(
+    chunks: I,
+    output: W,
+    new_chunks: Vec<Chunk>,
+    replaced_types: &[ChunkType],
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let mut writer = PngWriter::new(output)?;
+    let mut new_chunks = Some(new_chunks);
+    let mut replaced = Vec::new();
+    for chunk in chunks {
+        let chunk = chunk?;
+        chunk.verify_crc()?;
+        if replaced_types.contains(chunk.chunk_type()) {
+            replaced.push(chunk);
+            continue;
+        }
+        writer.write_chunk(&chunk)?;
+        if *chunk.chunk_type() == ChunkType::IHDR {
+            for new_chunk in new_chunks.take().into_iter().flatten() {
+                writer.write_chunk(&new_chunk)?;
+            }
+        }
+    }
+    writer.finish()?;
+    if new_chunks.is_some() {
+        return Err(Box::new(PngError::ChunkNotFound(
+            ChunkType::IHDR.to_string(),
+        )));
+    }
+    Ok(replaced)
+}
+
+//The ICC profile of the PNG, if any
+pub fn read_icc<I>(chunks: I) -> Result<Option<IccProfile>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    for chunk in chunks {
+        let chunk = chunk?;
+        if *chunk.chunk_type() == ChunkType::iCCP {
+            chunk.verify_crc()?;
+            return IccProfile::try_from(&chunk).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/**
+ * The chunks with the tIME chunk set to `timestamp`: the first tIME chunk is replaced,
+ * later ones are dropped and one is added before IEND when there is none.
+ */
+pub fn touch<I>(chunks: I, timestamp: Timestamp) -> impl Iterator<Item = Result<Chunk, Error>>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut time = Some(timestamp.to_chunk());
+    let mut seen = false;
+    chunks.into_iter().flat_map(move |chunk| match chunk {
+        Ok(chunk) if *chunk.chunk_type() == ChunkType::tIME => {
+            seen = true;
+            time.take().map(Ok).into_iter().collect()
+        }
+        Ok(chunk) if *chunk.chunk_type() == ChunkType::IEND && !seen => {
+            let mut chunks: Vec<Result<Chunk, Error>> = time.take().map(Ok).into_iter().collect();
+            chunks.push(Ok(chunk));
+            chunks
+        }
+        chunk => vec![chunk],
+    })
+}
+
+/**
+ * The chunks with the text chunks `text` added before IEND. Text chunks of any type
+ * with the same keyword as one of them are dropped, see `text::keyword`.
+ */
+pub fn stamp_text<I>(chunks: I, text: Vec<Chunk>) -> impl Iterator<Item = Result<Chunk, Error>>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let keywords: Vec<String> = text.iter().filter_map(text::keyword).collect();
+    let mut text = Some(text);
+    chunks.into_iter().flat_map(move |chunk| match chunk {
+        Ok(chunk) if text::keyword(&chunk).is_some_and(|keyword| keywords.contains(&keyword)) => {
+            vec![]
+        }
+        Ok(chunk) if *chunk.chunk_type() == ChunkType::IEND => {
+            let mut chunks: Vec<Result<Chunk, Error>> =
+                text.take().into_iter().flatten().map(Ok).collect();
+            chunks.push(Ok(chunk));
+            chunks
+        }
+        chunk => vec![chunk],
+    })
+}
+
+//Copies the PNG with its tIME chunk set to `timestamp`, returns the one it replaced
+pub fn set_time<R: Read, W: Write>(
+    input: R,
+    output: W,
+    timestamp: Timestamp,
+) -> Result<Option<Timestamp>, Error> {
+    set_time_chunks(PngReader::new(input)?, output, timestamp)
+}
+
+pub fn set_time_chunks<I, W>(
+    chunks: I,
+    output: W,
+    timestamp: Timestamp,
+) -> Result<Option<Timestamp>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let mut writer = PngWriter::new(output)?;
+    let mut previous = None;
+    let chunks = chunks.into_iter().inspect(|chunk| match chunk {
+        Ok(chunk) if *chunk.chunk_type() == ChunkType::tIME && previous.is_none() => {
+            //A damaged tIME chunk is replaced all the same
+            previous = Timestamp::try_from(chunk).ok();
+        }
+        _ => {}
+    });
+    for chunk in touch(chunks, timestamp) {
+        let chunk = chunk?;
+        chunk.verify_crc()?;
+        writer.write_chunk(&chunk)?;
+    }
+    writer.finish()?;
+    Ok(previous)
+}
+
+//The tIME chunk of the PNG, if any
+pub fn read_time<I>(chunks: I) -> Result<Option<Timestamp>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    for chunk in chunks {
+        let chunk = chunk?;
+        if *chunk.chunk_type() == ChunkType::tIME {
+            chunk.verify_crc()?;
+            return Timestamp::try_from(&chunk).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+//Copies the PNG with `packet` as its only XMP packet, true if it replaced one
+pub fn set_xmp<R: Read, W: Write>(input: R, output: W, packet: &str) -> Result<bool, Error> {
+    set_xmp_chunks(PngReader::new(input)?, output, packet)
+}
+
+pub fn set_xmp_chunks<I, W>(chunks: I, output: W, packet: &str) -> Result<bool, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let new_chunk = xmp::to_chunk(packet)?;
+    let mut replaced = false;
+    let kept = chunks.into_iter().filter(|chunk| match chunk {
+        Ok(chunk) if xmp::is_xmp_chunk(chunk) => {
+            replaced = true;
+            false
+        }
+        _ => true,
+    });
+    insert_chunks(kept, output, vec![new_chunk])?;
+    Ok(replaced)
+}
+
+//Packet of the first XMP chunk of the PNG, if any
+pub fn read_xmp<I>(chunks: I) -> Result<Option<String>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    for chunk in chunks {
+        let chunk = chunk?;
+        if xmp::is_xmp_chunk(&chunk) {
+            chunk.verify_crc()?;
+            return xmp::from_chunk(&chunk).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+//Copies the PNG without its XMP chunks, returns how many there were
+pub fn strip_xmp<R: Read, W: Write>(input: R, output: W) -> Result<usize, Error> {
+    strip_xmp_chunks(PngReader::new(input)?, output)
+}
+
+pub fn strip_xmp_chunks<I, W>(chunks: I, output: W) -> Result<usize, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let mut writer = PngWriter::new(output)?;
+    let mut stripped = 0;
+    for chunk in chunks {
+        let chunk = chunk?;
+        chunk.verify_crc()?;
+        if xmp::is_xmp_chunk(&chunk) {
+            stripped += 1;
+            continue;
+        }
+        writer.write_chunk(&chunk)?;
+    }
+    writer.finish()?;
+    if stripped == 0 {
+        return Err(Box::new(XmpError::NotFound));
+    }
+    Ok(stripped)
+}
+
+//Copies the PNG from `input` to `output` without the first chunk of the given type
+pub fn remove<R: Read, W: Write>(input: R, output: W, chunk_type: &str) -> Result<Chunk, Error> {
+    remove_chunks(PngReader::new(input)?, output, chunk_type)
+}
+
+pub fn remove_chunks<I, W>(chunks: I, output: W, chunk_type: &str) -> Result<Chunk, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let mut removed = remove_occurrences(chunks, output, chunk_type, Occurrence::First)?;
+    Ok(removed.remove(0))
+}
+
+//Which chunks of a type an operation applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occurrence {
+    First,
+    //Counted from 0 among the chunks of the type
+    Nth(usize),
+    All,
+}
+
+impl Occurrence {
+    pub fn matches(&self, nth: usize) -> bool {
+        match self {
+            Self::First => nth == 0,
+            Self::Nth(n) => nth == *n,
+            Self::All => true,
+        }
+    }
+}
+
+/**
+ * Copies the PNG from `input` to `output` without the selected chunks of the given
+ * type, failing if none of them is found. Returns the removed chunks in file order.
+ */
+pub fn remove_occurrences<I, W>(
+    chunks: I,
+    output: W,
+    chunk_type: &str,
+    occurrence: Occurrence,
+) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let mut writer = PngWriter::new(output)?;
+    let mut removed = Vec::new();
+    let mut nth = 0;
+    for chunk in chunks {
+        let chunk = chunk?;
+        chunk.verify_crc()?;
+        if chunk.chunk_type().to_string() == chunk_type {
+            nth += 1;
+            if occurrence.matches(nth - 1) {
+                log::debug!("removing {} chunk #{}", chunk_type, nth - 1);
+                removed.push(chunk);
+                continue;
+            }
+        }
+        writer.write_chunk(&chunk)?;
+    }
+    writer.finish()?;
+    if removed.is_empty() {
+        return Err(Box::new(PngError::ChunkNotFound(chunk_type.to_string())));
+    }
+    Ok(removed)
+}
+
+//Copies the PNG from `input` to `output` recomputing every invalid CRC
+pub fn repair<R: Read, W: Write>(input: R, output: W) -> Result<Vec<RepairedChunk>, Error> {
+    repair_chunks(PngReader::new(input)?, output)
+}
+
+pub fn repair_chunks<I, W>(chunks: I, output: W) -> Result<Vec<RepairedChunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let mut writer = PngWriter::new(output)?;
+    let mut repaired = Vec::new();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let mut chunk = chunk?;
+        let old_crc = chunk.crc();
+        if chunk.repair_crc() {
+            log::debug!(
+                "repairing crc of {} chunk {}: {} -> {}",
+                chunk.chunk_type(),
+                index,
+                old_crc,
+                chunk.crc()
+            );
+            repaired.push(RepairedChunk {
+                index,
+                chunk_type: chunk.chunk_type().clone(),
+                old_crc,
+                new_crc: chunk.crc(),
+            });
+        }
+        writer.write_chunk(&chunk)?;
+    }
+    writer.finish()?;
+    Ok(repaired)
+}
+
+/**
+ * Copies the PNG from `input` to `output` with the payloads in an older format
+ * rewritten as `payload::migrate` does, returning the chunk type of each one.
+ * Only chunks of `chunk_type` are looked at, or of every private ancillary type that
+ * starts with one of the magics of pngme, a plain text message is only migrated in a
+ * chunk of `chunk_type` since any program could have stored the text.
+ * A plain chunk is migrated on its own, the parts of a split payload are reassembled
+ * and split again with `part_length`. Named payloads always have an envelope.
+ * The whole file is read first since the parts can be anywhere in it.
+ */
+pub fn migrate<R: Read, W: Write>(
+    input: R,
+    output: W,
+    chunk_type: Option<&ChunkType>,
+    part_length: usize,
+) -> Result<Vec<ChunkType>, Error> {
+    migrate_chunks(PngReader::new(input)?, output, chunk_type, part_length)
+}
+
+pub fn migrate_chunks<I, W>(
+    chunks: I,
+    output: W,
+    chunk_type: Option<&ChunkType>,
+    part_length: usize,
+) -> Result<Vec<ChunkType>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let chunks = chunks.into_iter().collect::<Result<Vec<_>, _>>()?;
+    let is_part = |chunk: &Chunk| fragment::parse_part(chunk.data()).is_some();
+    let is_candidate = |chunk: &Chunk| {
+        let candidate_type = match chunk_type {
+            Some(chunk_type) => chunk.chunk_type() == chunk_type,
+            None => {
+                !chunk.chunk_type().is_critical()
+                    && !chunk.chunk_type().is_public()
+                    && chunk.chunk_type().to_string() != named::TOC_CHUNK_TYPE
+                    && (chunk.data().starts_with(&payload::ENVELOPE_MAGIC)
+                        || payload::is_legacy(chunk.data())
+                        || is_part(chunk))
+            }
+        };
+        candidate_type && named::parse_name(chunk.data()).is_none()
+    };
+
+    //The chunks replacing the parts of each split payload, None to keep them
+    let mut split: Vec<(ChunkType, Option<Vec<Chunk>>)> = Vec::new();
+    for chunk in chunks
+        .iter()
+        .filter(|chunk| is_candidate(chunk) && is_part(chunk))
+    {
+        let chunk_type = chunk.chunk_type();
+        if split.iter().any(|(split_type, _)| split_type == chunk_type) {
+            continue;
+        }
+        let parts = chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type() == chunk_type && is_part(chunk));
+        let replacement = match payload::migrate(&fragment::reassemble(parts)?)? {
+            Some(payload) => Some(fragment::split(chunk_type, &payload, part_length)?),
+            None => None,
+        };
+        split.push((chunk_type.clone(), replacement));
+    }
+
+    let mut writer = PngWriter::new(output)?;
+    let mut migrated = Vec::new();
+    for chunk in &chunks {
+        if !is_candidate(chunk) {
+            writer.write_chunk(chunk)?;
+            continue;
+        }
+        if !is_part(chunk) {
+            match payload::migrate(chunk.data())? {
+                Some(payload) => {
+                    log::debug!("migrating {} chunk", chunk.chunk_type());
+                    writer.write_chunk(&Chunk::new(chunk.chunk_type().clone(), payload))?;
+                    migrated.push(chunk.chunk_type().clone());
+                }
+                None => writer.write_chunk(chunk)?,
+            }
+            continue;
+        }
+        //The new parts take the place of the first old one, the others are dropped
+        match split
+            .iter_mut()
+            .find(|(split_type, _)| split_type == chunk.chunk_type())
+        {
+            Some((_, Some(replacement))) => {
+                if !replacement.is_empty() {
+                    log::debug!("migrating split {} payload", chunk.chunk_type());
+                    for new_chunk in replacement.drain(..) {
+                        writer.write_chunk(&new_chunk)?;
+                    }
+                    migrated.push(chunk.chunk_type().clone());
+                }
+            }
+            _ => writer.write_chunk(chunk)?,
+        }
+    }
+    writer.finish()?;
+    Ok(migrated)
+}
+
+/**
+ * What `scrub` keeps: critical chunks and the ancillary ones in `keep`, or safe to copy
+ * with `keep_safe_to_copy`. When `strip` isn't empty only its types are removed instead.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ScrubOptions {
+    pub keep: Vec<ChunkType>,
+    pub keep_safe_to_copy: bool,
+    pub strip: Vec<ChunkType>,
+    //Also remove kept chunks that duplicate an earlier one byte for byte
+    pub dedup: bool,
+}
+
+impl ScrubOptions {
+    fn keeps(&self, chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_critical() {
+            return true;
+        }
+        if !self.strip.is_empty() {
+            return !self.strip.contains(chunk_type);
+        }
+        self.keep.contains(chunk_type) || (self.keep_safe_to_copy && chunk_type.is_safe_to_copy())
+    }
+}
+
+//Copies the PNG from `input` to `output` without its ancillary chunks, returning them
+pub fn scrub<R: Read, W: Write>(
+    input: R,
+    output: W,
+    options: &ScrubOptions,
+) -> Result<Vec<Chunk>, Error> {
+    scrub_chunks(PngReader::new(input)?, output, options)
+}
+
+pub fn scrub_chunks<I, W>(chunks: I, output: W, options: &ScrubOptions) -> Result<Vec<Chunk>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+    W: Write,
+{
+    let mut writer = PngWriter::new(output)?;
+    let mut removed = Vec::new();
+    let mut seen = HashSet::new();
+    for chunk in chunks {
+        let chunk = chunk?;
+        let duplicate = options.dedup
+            && !chunk.chunk_type().is_critical()
+            && !seen.insert((chunk.chunk_type().bytes(), chunk.shared_data().clone()));
+        if options.keeps(&chunk) && !duplicate {
+            writer.write_chunk(&chunk)?;
+        } else {
+            log::debug!(
+                "removing {} chunk, {}",
+                chunk.chunk_type(),
+                if duplicate { "duplicate" } else { "ancillary" }
+            );
+            removed.push(chunk);
+        }
+    }
+    writer.finish()?;
+    Ok(removed)
+}
+
+//Reads the whole PNG and checks it, see `Png::validate`
+pub fn validate<R: Read>(mut input: R) -> Result<Vec<ValidationIssue>, Error> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        return Ok(vec![ValidationIssue::invalid_signature()]);
+    }
+    Ok(Png::try_from(Bytes::from(bytes))?.validate())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ancillary::{ImageOffset, StereoMode};
+    use crate::metadata::{Gamma, RenderingIntent};
+    use crate::png::PngBuilder;
+    use crate::spread;
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        PngBuilder::new(4, 4).build().unwrap().as_bytes()
+    }
+
+    fn message_chunk(message: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            message.as_bytes().to_vec(),
+        )
+    }
+
+    #[test]
+    pub fn test_encode_decode_in_memory() {
+        let mut encoded = Vec::new();
+        encode(
+            testing_bytes().as_slice(),
+            &mut encoded,
+            message_chunk("Hello"),
+        )
+        .unwrap();
+        let chunk = decode(encoded.as_slice(), "ruSt").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "Hello");
+
+        let png = Png::try_from(encoded.as_slice()).unwrap();
+        assert_eq!(
+            png.chunks()[png.chunks().len() - 2]
+                .chunk_type()
+                .to_string(),
+            "ruSt"
+        );
+    }
+
+    #[test]
+    pub fn test_encode_animated() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::acTL, vec![0, 0, 0, 1, 0, 0, 0, 0]));
+        let mut encoded = Vec::new();
+        encode(
+            png.as_bytes().as_slice(),
+            &mut encoded,
+            message_chunk("Hello"),
+        )
+        .unwrap();
+        let png = Png::try_from(encoded.as_slice()).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "acTL", "ruSt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    pub fn test_migrate() {
+        let split_type = ChunkType::from_str("spLt").unwrap();
+        let mut input = Vec::new();
+        encode(
+            testing_bytes().as_slice(),
+            &mut input,
+            message_chunk("Hello"),
+        )
+        .unwrap();
+        let mut with_parts = Vec::new();
+        encode_payload(
+            input.as_slice(),
+            &mut with_parts,
+            &split_type,
+            b"an old message split up",
+            &EncodeOptions {
+                part_length: 8,
+                ..EncodeOptions::default()
+            },
+        )
+        .unwrap();
+
+        //The plain text message is only migrated when its chunk type is given
+        let mut parts_migrated = Vec::new();
+        let types = migrate(with_parts.as_slice(), &mut parts_migrated, None, 1024).unwrap();
+        assert_eq!(types, [split_type]);
+        let mut migrated = Vec::new();
+        let message_type = ChunkType::from_str("ruSt").unwrap();
+        let types = migrate(
+            parts_migrated.as_slice(),
+            &mut migrated,
+            Some(&message_type),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(types, [message_type]);
+        for (chunk_type, message) in [
+            ("ruSt", &b"Hello"[..]),
+            ("spLt", b"an old message split up"),
+        ] {
+            let payload = decode_payload(migrated.as_slice(), chunk_type).unwrap();
+            let (_, content) = payload::Envelope::open(&payload, |_, body| Ok(body.to_vec()))
+                .unwrap()
+                .unwrap();
+            assert_eq!(content, message);
+        }
+        let png = Png::try_from(migrated.as_slice()).unwrap();
+        assert_eq!(png.chunks_by_type("spLt").count(), 1);
+
+        let mut again = Vec::new();
+        assert!(migrate(migrated.as_slice(), &mut again, None, 1024)
+            .unwrap()
+            .is_empty());
+        assert_eq!(again, migrated);
+    }
+
+    #[test]
+    pub fn test_set_text() {
+        let entry = |keyword: &str, text: &str| TextEntry::new(keyword, text).unwrap();
+        let mut first = Vec::new();
+        let replaced = set_text(
+            testing_bytes().as_slice(),
+            &mut first,
+            &entry("Author", "Jane"),
+        )
+        .unwrap();
+        assert!(replaced.is_none());
+        let mut second = Vec::new();
+        set_text(first.as_slice(), &mut second, &entry("Title", "Crab")).unwrap();
+        let mut third = Vec::new();
+        let replaced = set_text(second.as_slice(), &mut third, &entry("Author", "John")).unwrap();
+        assert_eq!(replaced, Some(entry("Author", "Jane")));
+        let entries = read_text(PngReader::new(third.as_slice()).unwrap()).unwrap();
+        assert_eq!(entries, [entry("Title", "Crab"), entry("Author", "John")]);
+    }
+
+    #[test]
+    pub fn test_embed_icc() {
+        let mut data = vec![0; 300];
+        data[36..40].copy_from_slice(b"acsp");
+        let profile = IccProfile::new("Display P3", data).unwrap();
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::sRGB, vec![0]));
+        let mut embedded = Vec::new();
+        let replaced = embed_icc(png.as_bytes().as_slice(), &mut embedded, &profile).unwrap();
+        assert_eq!(replaced.len(), 1);
+        assert_eq!(*replaced[0].chunk_type(), ChunkType::sRGB);
+        let png = Png::try_from(embedded.as_slice()).unwrap();
+        assert_eq!(*png.chunks()[1].chunk_type(), ChunkType::iCCP);
+        assert_eq!(png.icc_profile().unwrap(), Some(profile.clone()));
+        let mut again = Vec::new();
+        let replaced = embed_icc(embedded.as_slice(), &mut again, &profile).unwrap();
+        assert_eq!(*replaced[0].chunk_type(), ChunkType::iCCP);
+        assert_eq!(again, embedded);
+        let read = read_icc(PngReader::new(testing_bytes().as_slice()).unwrap()).unwrap();
+        assert!(read.is_none());
+    }
+
+    #[test]
+    pub fn test_set_color() {
+        let mut data = vec![0; 300];
+        data[36..40].copy_from_slice(b"acsp");
+        let profile = IccProfile::new("Display P3", data).unwrap();
+        let mut with_icc = Vec::new();
+        embed_icc(testing_bytes().as_slice(), &mut with_icc, &profile).unwrap();
+        let gamma = ColorInfo {
+            gamma: Some(Gamma(100_000)),
+            ..ColorInfo::default()
+        };
+        let mut first = Vec::new();
+        let replaced = set_color(with_icc.as_slice(), &mut first, &gamma).unwrap();
+        assert!(replaced.is_empty());
+        let srgb = ColorInfo {
+            gamma: Some(Gamma::SRGB),
+            rendering_intent: Some(RenderingIntent::Perceptual),
+            ..ColorInfo::default()
+        };
+        let mut second = Vec::new();
+        let replaced: Vec<String> = set_color(first.as_slice(), &mut second, &srgb)
+            .unwrap()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(replaced, ["gAMA", "iCCP"]);
+        let read = read_color(PngReader::new(second.as_slice()).unwrap()).unwrap();
+        assert_eq!(read, srgb);
+    }
+
+    #[test]
+    pub fn test_set_extensions() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::sTER, vec![0]));
+        let extensions = Extensions {
+            offset: Some(ImageOffset::from_str("10,20").unwrap()),
+            scale: None,
+            stereo: Some(StereoMode::DivergingFuse),
+        };
+        let mut output = Vec::new();
+        let replaced = set_extensions(png.as_bytes().as_slice(), &mut output, &extensions).unwrap();
+        assert_eq!(replaced.len(), 1);
+        assert_eq!(*replaced[0].chunk_type(), ChunkType::sTER);
+        let read = read_extensions(PngReader::new(output.as_slice()).unwrap()).unwrap();
+        assert_eq!(read, extensions);
+        assert!(Png::try_from(output.as_slice())
+            .unwrap()
+            .validate()
+            .is_empty());
+    }
+
+    #[test]
+    pub fn test_set_phys() {
+        let phys = PhysicalDimensions::from_dpi(300.0, 300.0).unwrap();
+        let mut first = Vec::new();
+        assert!(set_phys(testing_bytes().as_slice(), &mut first, &phys)
+            .unwrap()
+            .is_none());
+        let mut second = Vec::new();
+        let screen = PhysicalDimensions::from_dpi(72.0, 72.0).unwrap();
+        assert_eq!(
+            set_phys(first.as_slice(), &mut second, &screen).unwrap(),
+            Some(phys)
+        );
+        let read = read_phys(PngReader::new(second.as_slice()).unwrap()).unwrap();
+        assert_eq!(read, Some(screen));
+        let png = Png::try_from(second.as_slice()).unwrap();
+        assert_eq!(*png.chunks()[1].chunk_type(), ChunkType::pHYs);
+    }
+
+    #[test]
+    pub fn test_international_text() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(TextEntry::new("Title", "Cat").unwrap().to_chunk().unwrap());
+        let mut bytes = png.as_bytes();
+        for (language, text) in [("fr", "Chien"), ("de", "Katze"), ("fr", "Chat")] {
+            let entry = InternationalText::new("Title", language, text).unwrap();
+            let mut output = Vec::new();
+            set_international_text(bytes.as_slice(), &mut output, &entry).unwrap();
+            bytes = output;
+        }
+        let entries = read_international_text(PngReader::new(bytes.as_slice()).unwrap()).unwrap();
+        let texts: Vec<&str> = entries.iter().map(|entry| entry.text.as_str()).collect();
+        assert_eq!(texts, ["Cat", "Katze", "Chat"]);
+        assert_eq!(
+            text::select_language(&entries, Some("fr-FR")).unwrap().text,
+            "Chat"
+        );
+    }
+
+    #[test]
+    pub fn test_stamp_text() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(TextEntry::new("Author", "Old").unwrap().to_chunk().unwrap());
+        png.insert_chunk_ordered(TextEntry::new("Title", "Kept").unwrap().to_chunk().unwrap());
+        let text = vec![
+            text::text_chunk("Author", "Zoë").unwrap(),
+            text::text_chunk("Comment", "猫").unwrap(),
+        ];
+        let chunks = stamp_text(png.chunks().iter().cloned().map(Ok), text);
+        let png = Png::from_chunks(chunks.collect::<Result<_, _>>().unwrap());
+        assert_eq!(png.text("Author").unwrap().as_deref(), Some("Zoë"));
+        assert_eq!(png.text("Title").unwrap().as_deref(), Some("Kept"));
+        assert_eq!(png.chunks_by_type("iTXt").count(), 1);
+        assert!(png.validate().is_empty());
+    }
+
+    #[test]
+    pub fn test_set_time() {
+        let time = |s: &str| Timestamp::from_str(s).unwrap();
+        let mut first = Vec::new();
+        let previous = set_time(
+            testing_bytes().as_slice(),
+            &mut first,
+            time("2024-01-31T12:00:00Z"),
+        )
+        .unwrap();
+        assert!(previous.is_none());
+        let png = Png::try_from(first.as_slice()).unwrap();
+        assert_eq!(
+            png.chunks()[png.chunks().len() - 2].chunk_type(),
+            &ChunkType::tIME
+        );
+        let mut second = Vec::new();
+        let previous = set_time(first.as_slice(), &mut second, time("2025-06-01T08:00:00Z"));
+        assert_eq!(previous.unwrap(), Some(time("2024-01-31T12:00:00Z")));
+        let read = read_time(PngReader::new(second.as_slice()).unwrap()).unwrap();
+        assert_eq!(read, Some(time("2025-06-01T08:00:00Z")));
+        assert_eq!(second.len(), first.len());
+    }
+
+    #[test]
+    pub fn test_xmp() {
+        let packet = |creator: &str| {
+            format!(
+                r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><dc:creator>{}</dc:creator></x:xmpmeta>"#,
+                creator
+            )
+        };
+        let mut first = Vec::new();
+        assert!(!set_xmp(testing_bytes().as_slice(), &mut first, &packet("Jane")).unwrap());
+        let mut second = Vec::new();
+        assert!(set_xmp(first.as_slice(), &mut second, &packet("John")).unwrap());
+        let read = |bytes: &[u8]| read_xmp(PngReader::new(bytes).unwrap()).unwrap();
+        assert_eq!(read(&second), Some(packet("John")));
+        let mut stripped = Vec::new();
+        assert_eq!(strip_xmp(second.as_slice(), &mut stripped).unwrap(), 1);
+        assert_eq!(read(&stripped), None);
+        assert!(strip_xmp(stripped.as_slice(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    pub fn test_named_payloads() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let options = EncodeOptions {
+            part_length: 8,
+            ..EncodeOptions::default()
+        };
+        let encode = |input: &[u8], name: &str, payload: &[u8], options: &EncodeOptions| {
+            let mut encoded = Vec::new();
+            let chunks = PngReader::new(input).unwrap();
+            encode_named_payload_chunks(chunks, &mut encoded, &chunk_type, name, payload, options)
+                .map(|replaced| (encoded, replaced))
+        };
+        let (encoded, _) = encode(
+            &testing_bytes(),
+            "notes",
+            b"a long message split up",
+            &options,
+        )
+        .unwrap();
+        let (encoded, _) = encode(&encoded, "todo", b"short", &options).unwrap();
+        assert!(encode(&encoded, "todo", b"again", &options).is_err());
+        let replace = EncodeOptions {
+            existing: Existing::Replace,
+            ..options
+        };
+        let (encoded, replaced) = encode(&encoded, "todo", b"shorter", &replace).unwrap();
+        assert_eq!(replaced.len(), 1);
+
+        let chunks = || PngReader::new(encoded.as_slice()).unwrap();
+        assert_eq!(
+            decode_named_payload_chunks(chunks(), "ruSt", "notes").unwrap(),
+            b"a long message split up"
+        );
+        assert_eq!(
+            decode_named_payload_chunks(chunks(), "ruSt", "todo").unwrap(),
+            b"shorter"
+        );
+        assert!(decode_named_payload_chunks(chunks(), "ruSt", "other").is_err());
+        //Named payloads aren't mistaken for an unnamed one
+        assert!(decode_payload_chunks(chunks(), "ruSt").is_err());
+        let toc = read_toc(chunks()).unwrap();
+        let names: Vec<_> = toc
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.length))
+            .collect();
+        assert_eq!(names, [("notes", 23), ("todo", 7)]);
+        let png = Png::try_from(encoded.as_slice()).unwrap();
+        assert_eq!(png.chunks_by_type(named::TOC_CHUNK_TYPE).count(), 1);
+    }
+
+    #[test]
+    pub fn test_encode_decode_payload() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let payload: Vec<u8> = (0..=255).cycle().take(5000).collect();
+        let mut encoded = Vec::new();
+        let options = EncodeOptions {
+            part_length: 1024,
+            ..EncodeOptions::default()
+        };
+        encode_payload(
+            testing_bytes().as_slice(),
+            &mut encoded,
+            &chunk_type,
+            &payload,
+            &options,
+        )
+        .unwrap();
+        let png = Png::try_from(encoded.as_slice()).unwrap();
+        assert_eq!(png.chunks_by_type("ruSt").count(), 5);
+        assert_eq!(decode_payload(encoded.as_slice(), "ruSt").unwrap(), payload);
+
+        let mut replaced = Vec::new();
+        let options = EncodeOptions {
+            existing: Existing::Replace,
+            ..options
+        };
+        let removed = encode_payload(
+            encoded.as_slice(),
+            &mut replaced,
+            &chunk_type,
+            b"Short",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(removed.len(), 5);
+        assert_eq!(
+            decode_payload(replaced.as_slice(), "ruSt").unwrap(),
+            b"Short"
+        );
+        let options = EncodeOptions {
+            existing: Existing::Reject,
+            ..options
+        };
+        assert!(encode_payload(
+            replaced.as_slice(),
+            Vec::new(),
+            &chunk_type,
+            b"Again",
+            &options
+        )
+        .is_err());
+
+        let mut encoded = Vec::new();
+        encode(
+            testing_bytes().as_slice(),
+            &mut encoded,
+            message_chunk("Hello"),
+        )
+        .unwrap();
+        assert_eq!(
+            decode_payload(encoded.as_slice(), "ruSt").unwrap(),
+            b"Hello"
+        );
+    }
+
+    #[test]
+    pub fn test_encode_deniable_payload() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let hidden = Chunk::new(ChunkType::from_str("hiDe").unwrap(), vec![7; 40]);
+        let mut encoded = Vec::new();
+        let input = testing_bytes();
+        let chunks = PngReader::new(input.as_slice()).unwrap();
+        let options = EncodeOptions::default();
+        encode_deniable_payload_chunks(
+            chunks,
+            &mut encoded,
+            &chunk_type,
+            b"decoy",
+            hidden,
+            &options,
+        )
+        .unwrap();
+        let png = Png::try_from(encoded.as_slice()).unwrap();
+        assert_eq!(
+            decode_payload(encoded.as_slice(), "ruSt").unwrap(),
+            b"decoy"
+        );
+        assert_eq!(png.chunk_by_type("hiDe").unwrap().data(), [7; 40]);
+        //The hidden chunk and several decoys
+        let private = png
+            .chunks()
+            .iter()
+            .filter(|chunk| !chunk.chunk_type().is_public() && *chunk.chunk_type() != chunk_type)
+            .count();
+        assert_eq!(private, 1 + decoy::MIN_HIDDEN_DECOYS);
+
+        //An existing chunk of the hidden type counts too
+        let other = ChunkType::from_str("otHr").unwrap();
+        let hidden = Chunk::new(ChunkType::from_str("hiDe").unwrap(), vec![8; 40]);
+        let chunks = PngReader::new(encoded.as_slice()).unwrap();
+        let result =
+            encode_deniable_payload_chunks(chunks, Vec::new(), &other, b"", hidden, &options);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_encode_spread_payload() {
+        let payload: Vec<u8> = (0..=255).cycle().take(600).collect();
+        let (pieces, manifest) = spread::spread(&payload, 4).unwrap();
+        let manifest = Chunk::new(ChunkType::from_str("maNi").unwrap(), manifest.to_bytes());
+        let mut encoded = Vec::new();
+        let input = testing_bytes();
+        let chunks = PngReader::new(input.as_slice()).unwrap();
+        let options = EncodeOptions {
+            decoys: 2,
+            ..EncodeOptions::default()
+        };
+        encode_spread_payload_chunks(chunks, &mut encoded, manifest, pieces, &options).unwrap();
+        let png = Png::try_from(encoded.as_slice()).unwrap();
+        let plain = Png::try_from(input.as_slice()).unwrap();
+        assert_eq!(png.chunks().len(), plain.chunks().len() + 7);
+        let manifest = png.chunk_by_type("maNi").unwrap().data();
+        let manifest = spread::Manifest::parse(manifest).unwrap().unwrap();
+        assert_eq!(spread::gather(png.chunks(), &manifest).unwrap(), payload);
+    }
+
+    #[test]
+    pub fn test_decode_nth_chunk() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(message_chunk("First"));
+        png.insert_chunk_ordered(message_chunk("Second"));
+        let chunks = || PngReader::new(Cursor::new(png.as_bytes())).unwrap();
+        let chunk = decode_nth_chunk(chunks(), "ruSt", 1).unwrap();
+        assert_eq!(chunk.data(), b"Second");
+        assert!(decode_nth_chunk(chunks(), "ruSt", 2).is_err());
+        //A read error is returned, not counted as one of the chunks
+        let unreadable = vec![
+            Ok(message_chunk("First")),
+            Err("unreadable chunk".into()),
+            Ok(message_chunk("Second")),
+        ];
+        let err = decode_nth_chunk(unreadable, "ruSt", 1).unwrap_err();
+        assert_eq!(err.to_string(), "unreadable chunk");
+    }
+
+    #[test]
+    pub fn test_remove_in_memory() {
+        let mut encoded = Vec::new();
+        encode(
+            testing_bytes().as_slice(),
+            &mut encoded,
+            message_chunk("Hello"),
+        )
+        .unwrap();
+        let mut removed = Vec::new();
+        let chunk = remove(encoded.as_slice(), &mut removed, "ruSt").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "Hello");
+        assert_eq!(removed, testing_bytes());
+        assert!(remove(removed.as_slice(), Vec::new(), "ruSt").is_err());
+    }
+
+    #[test]
+    pub fn test_remove_occurrences() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        for message in ["First", "Second", "Third"] {
+            png.insert_chunk_ordered(message_chunk(message));
+        }
+        let chunks = || PngReader::new(Cursor::new(png.as_bytes())).unwrap();
+
+        let mut output = Vec::new();
+        let removed =
+            remove_occurrences(chunks(), &mut output, "ruSt", Occurrence::Nth(1)).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].data(), b"Second");
+        let remaining = Png::try_from(output.as_slice()).unwrap();
+        assert_eq!(remaining.chunks_by_type("ruSt").count(), 2);
+
+        let removed = remove_occurrences(chunks(), Vec::new(), "ruSt", Occurrence::All).unwrap();
+        assert_eq!(removed.len(), 3);
+        assert!(remove_occurrences(chunks(), Vec::new(), "ruSt", Occurrence::Nth(3)).is_err());
+    }
+
+    #[test]
+    pub fn test_repair_in_memory() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(decode(bytes.as_slice(), "IEND").is_err());
+
+        let mut repaired = Vec::new();
+        let chunks = repair(bytes.as_slice(), &mut repaired).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].chunk_type, ChunkType::IEND);
+        assert_eq!(repaired, testing_bytes());
+    }
+
+    #[test]
+    pub fn test_scrub_in_memory() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::pHYs, vec![0; 9]));
+        png.insert_chunk_ordered(Chunk::new(ChunkType::pHYs, vec![0; 9]));
+        png.insert_chunk_ordered(message_chunk("Hello"));
+        let bytes = png.as_bytes();
+
+        let mut scrubbed = Vec::new();
+        let removed = scrub(bytes.as_slice(), &mut scrubbed, &ScrubOptions::default()).unwrap();
+        assert_eq!(removed.len(), 3);
+        assert_eq!(scrubbed, testing_bytes());
+
+        let options = ScrubOptions {
+            keep: vec![ChunkType::pHYs],
+            dedup: true,
+            ..ScrubOptions::default()
+        };
+        let mut scrubbed = Vec::new();
+        let removed = scrub(bytes.as_slice(), &mut scrubbed, &options).unwrap();
+        assert_eq!(removed.len(), 2);
+        let scrubbed = Png::try_from(scrubbed.as_slice()).unwrap();
+        assert_eq!(scrubbed.chunks_by_type("pHYs").count(), 1);
+    }
+
+    #[test]
+    pub fn test_scrub_keep_and_strip() {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::gAMA, vec![0, 0, 177, 143]));
+        png.insert_chunk_ordered(Chunk::new(ChunkType::tIME, vec![7, 232, 1, 31, 12, 0, 0]));
+        png.insert_chunk_ordered(Chunk::new(ChunkType::tEXt, b"Author\0Jane".to_vec()));
+        png.insert_chunk_ordered(Chunk::new(ChunkType::pHYs, vec![0; 9]));
+        let bytes = png.as_bytes();
+        let removed = |options: ScrubOptions| -> Vec<String> {
+            scrub(bytes.as_slice(), &mut Vec::new(), &options)
+                .unwrap()
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string())
+                .collect()
+        };
+        let safe_to_copy = ScrubOptions {
+            keep_safe_to_copy: true,
+            keep: vec![ChunkType::gAMA],
+            ..ScrubOptions::default()
+        };
+        assert_eq!(removed(safe_to_copy), ["tIME"]);
+        let strip = ScrubOptions {
+            strip: vec![ChunkType::tEXt, ChunkType::tIME],
+            ..ScrubOptions::default()
+        };
+        let mut stripped = removed(strip);
+        stripped.sort();
+        assert_eq!(stripped, ["tEXt", "tIME"]);
+    }
+
+    #[test]
+    pub fn test_validate_in_memory() {
+        assert!(validate(testing_bytes().as_slice()).unwrap().is_empty());
+        let issues = validate(&b"not a png"[..]).unwrap();
+        assert_eq!(issues, vec![ValidationIssue::invalid_signature()]);
+    }
+}
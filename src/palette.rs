@@ -0,0 +1,230 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::Error;
+use std::fmt;
+
+//Content of the PLTE chunk, 1 to 256 RGB entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    entries: Vec<[u8; 3]>,
+}
+
+impl TryFrom<&Chunk> for Palette {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != ChunkType::PLTE {
+            return Err(Box::new(PaletteError::WrongChunk(
+                chunk.chunk_type().to_string(),
+            )));
+        }
+        let data = chunk.data();
+        if data.is_empty() || !data.len().is_multiple_of(3) || data.len() > 256 * 3 {
+            return Err(Box::new(PaletteError::InvalidPaletteLength(data.len())));
+        }
+        let entries = data
+            .chunks_exact(3)
+            .map(|entry| [entry[0], entry[1], entry[2]])
+            .collect();
+        Ok(Palette { entries })
+    }
+}
+
+impl Palette {
+    pub fn new(entries: Vec<[u8; 3]>) -> Result<Palette, Error> {
+        if entries.is_empty() || entries.len() > 256 {
+            return Err(Box::new(PaletteError::InvalidPaletteLength(
+                entries.len() * 3,
+            )));
+        }
+        Ok(Palette { entries })
+    }
+
+    pub fn entries(&self) -> &[[u8; 3]] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    //Always false, a palette has at least one entry
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::PLTE, self.entries.concat())
+    }
+}
+
+/**
+ * Content of the tRNS chunk, its layout depends on the colour type:
+ * a single transparent grey level or RGB colour, or an alpha value per palette entry.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    Grayscale(u16),
+    Rgb(u16, u16, u16),
+    //Alpha of the first palette entries, missing entries are opaque
+    Indexed(Vec<u8>),
+}
+
+impl Transparency {
+    pub fn parse(chunk: &Chunk, header: &Ihdr) -> Result<Transparency, Error> {
+        if *chunk.chunk_type() != ChunkType::tRNS {
+            return Err(Box::new(PaletteError::WrongChunk(
+                chunk.chunk_type().to_string(),
+            )));
+        }
+        let data = chunk.data();
+        let sample = |index: usize| u16::from_be_bytes([data[index * 2], data[index * 2 + 1]]);
+        match header.color_type {
+            ColorType::Grayscale if data.len() == 2 => Ok(Self::Grayscale(sample(0))),
+            ColorType::Rgb if data.len() == 6 => Ok(Self::Rgb(sample(0), sample(1), sample(2))),
+            ColorType::Indexed if data.len() <= 256 => Ok(Self::Indexed(data.to_vec())),
+            ColorType::Grayscale | ColorType::Rgb | ColorType::Indexed => Err(Box::new(
+                PaletteError::InvalidTransparencyLength(data.len(), header.color_type),
+            )),
+            ColorType::GrayscaleAlpha | ColorType::Rgba => Err(Box::new(
+                PaletteError::UnexpectedTransparency(header.color_type),
+            )),
+        }
+    }
+
+    //Alpha of the given palette entry
+    pub fn palette_alpha(&self, index: usize) -> u8 {
+        match self {
+            Self::Indexed(alphas) => alphas.get(index).copied().unwrap_or(255),
+            _ => 255,
+        }
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = match self {
+            Self::Grayscale(gray) => gray.to_be_bytes().to_vec(),
+            Self::Rgb(red, green, blue) => [red, green, blue]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            Self::Indexed(alphas) => alphas.clone(),
+        };
+        Chunk::new(ChunkType::tRNS, data)
+    }
+}
+
+impl Png {
+    //Parses the PLTE chunk, if any
+    pub fn palette(&self) -> Result<Option<Palette>, Error> {
+        self.chunk_by_type("PLTE")
+            .map(Palette::try_from)
+            .transpose()
+    }
+
+    //Parses the tRNS chunk, if any, checking it against the header and palette
+    pub fn transparency(&self) -> Result<Option<Transparency>, Error> {
+        let chunk = match self.chunk_by_type("tRNS") {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+        let transparency = Transparency::parse(chunk, &self.header()?)?;
+        if let Transparency::Indexed(alphas) = &transparency {
+            let entries = self.palette()?.map_or(0, |palette| palette.len());
+            if alphas.len() > entries {
+                return Err(Box::new(PaletteError::TooManyAlphas(alphas.len(), entries)));
+            }
+        }
+        Ok(Some(transparency))
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    WrongChunk(String),
+    InvalidPaletteLength(usize),
+    InvalidTransparencyLength(usize, ColorType),
+    UnexpectedTransparency(ColorType),
+    TooManyAlphas(usize, usize),
+}
+
+impl std::error::Error for PaletteError {}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongChunk(chunk_type) => write!(f, "unexpected chunk: {}", chunk_type),
+            Self::InvalidPaletteLength(length) => write!(
+                f,
+                "PLTE length must be a multiple of 3 up to 768, current: {}",
+                length
+            ),
+            Self::InvalidTransparencyLength(length, color_type) => {
+                write!(f, "invalid tRNS length {} for {}", length, color_type)
+            }
+            Self::UnexpectedTransparency(color_type) => {
+                write!(f, "tRNS is not allowed for {}", color_type)
+            }
+            Self::TooManyAlphas(alphas, entries) => write!(
+                f,
+                "tRNS has {} entries but the palette only has {}",
+                alphas, entries
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indexed_png(palette: &[u8], transparency: &[u8]) -> Png {
+        let header = Ihdr::new(1, 1, 8, ColorType::Indexed);
+        Png::from_chunks(vec![
+            header.to_chunk(),
+            Chunk::new(ChunkType::PLTE, palette.to_vec()),
+            Chunk::new(ChunkType::tRNS, transparency.to_vec()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ])
+    }
+
+    #[test]
+    pub fn test_palette() {
+        let png = indexed_png(&[255, 0, 0, 0, 255, 0], &[0]);
+        let palette = png.palette().unwrap().unwrap();
+        assert_eq!(palette.entries(), &[[255, 0, 0], [0, 255, 0]]);
+        assert_eq!(palette.to_chunk().data(), &[255, 0, 0, 0, 255, 0]);
+    }
+
+    #[test]
+    pub fn test_invalid_palette() {
+        let chunk = Chunk::new(ChunkType::PLTE, vec![1, 2]);
+        assert!(Palette::try_from(&chunk).is_err());
+        assert!(Palette::new(Vec::new()).is_err());
+        assert!(Palette::new(vec![[0; 3]; 257]).is_err());
+    }
+
+    #[test]
+    pub fn test_indexed_transparency() {
+        let png = indexed_png(&[255, 0, 0, 0, 255, 0], &[128]);
+        let transparency = png.transparency().unwrap().unwrap();
+        assert_eq!(transparency, Transparency::Indexed(vec![128]));
+        assert_eq!(transparency.palette_alpha(0), 128);
+        assert_eq!(transparency.palette_alpha(1), 255);
+
+        let png = indexed_png(&[255, 0, 0], &[128, 64]);
+        assert!(png.transparency().is_err());
+    }
+
+    #[test]
+    pub fn test_truecolor_transparency() {
+        let header = Ihdr::new(1, 1, 16, ColorType::Rgb);
+        let chunk = Transparency::Rgb(1, 2, 0xffff).to_chunk();
+        assert_eq!(chunk.data(), &[0, 1, 0, 2, 255, 255]);
+        let transparency = Transparency::parse(&chunk, &header).unwrap();
+        assert_eq!(transparency, Transparency::Rgb(1, 2, 0xffff));
+
+        let header = Ihdr::new(1, 1, 8, ColorType::Rgba);
+        assert!(Transparency::parse(&chunk, &header).is_err());
+    }
+}
@@ -0,0 +1,729 @@
+/*!
+ * The envelope encode wraps every payload in, so decode knows what was done to it.
+ * It starts with `ENVELOPE_MAGIC`, which like `fragment::PART_MAGIC` starts with a byte
+ * that is never valid UTF-8, so a plain text message is never taken for an envelope.
+ * Then come the version, a flags byte telling which optional fields follow, the
+ * compression and encryption markers, and the SHA-256 of the original content, its
+ * CRC-32 as a big endian u32 in version 1 envelopes. The optional fields are the file
+ * name, its length as a big endian u16 first, the MIME type, its length as a u8 first,
+ * and the encode time in seconds since the Unix epoch as a big endian u64. The body
+ * fills the rest of the payload: the content compressed first, then encrypted.
+ * A change to the layout comes with a new version, older versions stay readable and
+ * `migrate` rewrites payloads in an older format.
+ * Before envelopes a payload was a plain text message or a file behind `FILE_MAGIC`,
+ * the length of its name as a big endian u16, the name and the size of its content as
+ * a big endian u64, either one possibly compressed behind `COMPRESSED_MAGIC` and the
+ * method byte, then encrypted with a password or to age recipients. `open_legacy`
+ * still reads them.
+ */
+use crate::crypto;
+use crate::text;
+use crate::Error;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use crc::{Crc, CRC_32_ISO_HDLC};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+pub const ENVELOPE_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'V'];
+pub const ENVELOPE_VERSION: u8 = 2;
+pub const FILE_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'F'];
+pub const COMPRESSED_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'Z'];
+const FLAG_NAME: u8 = 1;
+const FLAG_MIME: u8 = 2;
+const FLAG_TIMESTAMP: u8 = 4;
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+//What an envelope records of the original content to detect damage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    //Only in version 1 envelopes
+    Crc32(u32),
+    Sha256([u8; 32]),
+}
+
+impl Checksum {
+    pub fn matches(&self, content: &[u8]) -> bool {
+        match self {
+            Self::Crc32(crc) => CRC32.checksum(content) == *crc,
+            Self::Sha256(digest) => Sha256::digest(content).as_slice() == digest,
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Crc32(crc) => write!(f, "crc32 {:08x}", crc),
+            Self::Sha256(digest) => {
+                write!(f, "sha256 ")?;
+                digest.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zlib,
+    //Only available with the zstd feature, decoding included
+    Zstd,
+}
+
+impl Compression {
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(zstd::stream::encode_all(data, 19)?),
+            #[cfg(not(feature = "zstd"))]
+            Self::Zstd => Err(Box::new(PayloadError::Unsupported(self))),
+        }
+    }
+
+    //The method byte of payloads written before envelopes
+    fn from_legacy_id(id: u8) -> Result<Self, Error> {
+        match id {
+            0 => Ok(Self::Zlib),
+            1 => Ok(Self::Zstd),
+            _ => Err(Box::new(PayloadError::UnknownCompression(id))),
+        }
+    }
+
+    //Fails rather than exhaust memory on a payload that inflates too much
+    fn decompress(self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let decompressed = match self {
+            Self::Zlib => text::read_inflated(ZlibDecoder::new(data))?,
+            #[cfg(feature = "zstd")]
+            Self::Zstd => text::read_inflated(zstd::stream::read::Decoder::new(data)?)?,
+            #[cfg(not(feature = "zstd"))]
+            Self::Zstd => return Err(Box::new(PayloadError::Unsupported(self))),
+        };
+        decompressed.ok_or_else(|| Box::new(PayloadError::TooLarge) as Error)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zlib" => Ok(Self::Zlib),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(Box::new(PayloadError::InvalidCompression(s.to_string()))),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Zlib => "zlib",
+            Self::Zstd => "zstd",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//How the body was encrypted, the keys and their parameters are part of the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    //`crypto::encrypt` with a password
+    Password,
+    //`crypto::encrypt_to` for age recipients
+    Age,
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Password => "password",
+            Self::Age => "age",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Envelope {
+    //Name of an embedded file, None for a message
+    pub name: Option<String>,
+    pub mime: Option<String>,
+    //Seconds since the Unix epoch when the payload was encoded
+    pub timestamp: Option<u64>,
+    pub compression: Option<Compression>,
+    pub encryption: Option<Encryption>,
+}
+
+//An envelope read from a payload, its body still compressed and encrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sealed<'a> {
+    pub version: u8,
+    pub envelope: Envelope,
+    //Of the original content
+    pub checksum: Checksum,
+    pub body: &'a [u8],
+}
+
+impl Envelope {
+    /**
+     * Compresses `content` then hands it to `encrypt` if the envelope says it's
+     * encrypted, and prefixes the result with the envelope.
+     */
+    pub fn seal<F>(&self, content: &[u8], encrypt: F) -> Result<Vec<u8>, Error>
+    where
+        F: FnOnce(&[u8]) -> Result<Vec<u8>, Error>,
+    {
+        let mut body = match self.compression {
+            Some(method) => method.compress(content)?,
+            None => content.to_vec(),
+        };
+        if self.encryption.is_some() {
+            body = encrypt(&body)?;
+        }
+
+        let mut flags = 0;
+        let mut fields = Vec::new();
+        if let Some(name) = &self.name {
+            let length = u16::try_from(name.len())
+                .map_err(|_| PayloadError::FieldTooLong("file name", name.len()))?;
+            flags |= FLAG_NAME;
+            fields.extend_from_slice(&length.to_be_bytes());
+            fields.extend_from_slice(name.as_bytes());
+        }
+        if let Some(mime) = &self.mime {
+            let length = u8::try_from(mime.len())
+                .map_err(|_| PayloadError::FieldTooLong("MIME type", mime.len()))?;
+            flags |= FLAG_MIME;
+            fields.push(length);
+            fields.extend_from_slice(mime.as_bytes());
+        }
+        if let Some(timestamp) = self.timestamp {
+            flags |= FLAG_TIMESTAMP;
+            fields.extend_from_slice(&timestamp.to_be_bytes());
+        }
+
+        let mut payload = Vec::with_capacity(40 + fields.len() + body.len());
+        payload.extend_from_slice(&ENVELOPE_MAGIC);
+        payload.push(ENVELOPE_VERSION);
+        payload.push(flags);
+        payload.push(match self.compression {
+            None => 0,
+            Some(Compression::Zlib) => 1,
+            Some(Compression::Zstd) => 2,
+        });
+        payload.push(match self.encryption {
+            None => 0,
+            Some(Encryption::Password) => 1,
+            Some(Encryption::Age) => 2,
+        });
+        payload.extend_from_slice(&Sha256::digest(content));
+        payload.extend_from_slice(&fields);
+        payload.extend_from_slice(&body);
+        Ok(payload)
+    }
+
+    //Reads the envelope without touching the body, None for a payload without one
+    pub fn parse(payload: &[u8]) -> Result<Option<Sealed<'_>>, Error> {
+        let rest = match payload.strip_prefix(&ENVELOPE_MAGIC) {
+            Some(rest) => rest,
+            None => return Ok(None),
+        };
+        let (&[version, flags, compression, encryption], rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or(PayloadError::Truncated)?;
+        if version == 0 || version > ENVELOPE_VERSION {
+            return Err(Box::new(PayloadError::UnsupportedVersion(version)));
+        }
+        if flags & !(FLAG_NAME | FLAG_MIME | FLAG_TIMESTAMP) != 0 {
+            return Err(Box::new(PayloadError::UnknownFlags(flags)));
+        }
+        let compression = match compression {
+            0 => None,
+            1 => Some(Compression::Zlib),
+            2 => Some(Compression::Zstd),
+            _ => return Err(Box::new(PayloadError::UnknownCompression(compression))),
+        };
+        let encryption = match encryption {
+            0 => None,
+            1 => Some(Encryption::Password),
+            2 => Some(Encryption::Age),
+            _ => return Err(Box::new(PayloadError::UnknownEncryption(encryption))),
+        };
+        let (checksum, mut rest) = if version == 1 {
+            let (crc, rest) = rest
+                .split_first_chunk::<4>()
+                .ok_or(PayloadError::Truncated)?;
+            (Checksum::Crc32(u32::from_be_bytes(*crc)), rest)
+        } else {
+            let (digest, rest) = rest
+                .split_first_chunk::<32>()
+                .ok_or(PayloadError::Truncated)?;
+            (Checksum::Sha256(*digest), rest)
+        };
+
+        let mut envelope = Envelope {
+            compression,
+            encryption,
+            ..Envelope::default()
+        };
+        if flags & FLAG_NAME != 0 {
+            let (length, field) = rest
+                .split_first_chunk::<2>()
+                .ok_or(PayloadError::Truncated)?;
+            let (name, field) = split_text(field, u16::from_be_bytes(*length) as usize)?;
+            envelope.name = Some(name);
+            rest = field;
+        }
+        if flags & FLAG_MIME != 0 {
+            let (&length, field) = rest.split_first().ok_or(PayloadError::Truncated)?;
+            let (mime, field) = split_text(field, length as usize)?;
+            envelope.mime = Some(mime);
+            rest = field;
+        }
+        if flags & FLAG_TIMESTAMP != 0 {
+            let (timestamp, field) = rest
+                .split_first_chunk::<8>()
+                .ok_or(PayloadError::Truncated)?;
+            envelope.timestamp = Some(u64::from_be_bytes(*timestamp));
+            rest = field;
+        }
+        Ok(Some(Sealed {
+            version,
+            envelope,
+            checksum,
+            body: rest,
+        }))
+    }
+
+    /**
+     * The envelope and original content of a payload, None for one without envelope.
+     * `decrypt` is only called for an encrypted body, the checksum is checked last.
+     */
+    pub fn open<F>(payload: &[u8], decrypt: F) -> Result<Option<(Envelope, Vec<u8>)>, Error>
+    where
+        F: FnOnce(Encryption, &[u8]) -> Result<Vec<u8>, Error>,
+    {
+        let sealed = match Envelope::parse(payload)? {
+            Some(sealed) => sealed,
+            None => return Ok(None),
+        };
+        let mut content = match sealed.envelope.encryption {
+            Some(encryption) => decrypt(encryption, sealed.body)?,
+            None => sealed.body.to_vec(),
+        };
+        if let Some(method) = sealed.envelope.compression {
+            content = method.decompress(&content)?;
+        }
+        if !sealed.checksum.matches(&content) {
+            return Err(Box::new(PayloadError::ChecksumMismatch));
+        }
+        Ok(Some((sealed.envelope, content)))
+    }
+}
+
+//Whether a payload is in one of the formats written before envelopes, text aside
+pub fn is_legacy(payload: &[u8]) -> bool {
+    payload.starts_with(&FILE_MAGIC)
+        || payload.starts_with(&COMPRESSED_MAGIC)
+        || crypto::is_encrypted(payload)
+}
+
+/**
+ * The envelope and content of a payload written before envelopes, None for a plain text
+ * message or anything else. `decrypt` is handed the whole payload when it is encrypted.
+ * Nothing but the size of a file tells whether the content is damaged.
+ */
+pub fn open_legacy<F>(payload: &[u8], decrypt: F) -> Result<Option<(Envelope, Vec<u8>)>, Error>
+where
+    F: FnOnce(Encryption, &[u8]) -> Result<Vec<u8>, Error>,
+{
+    if !is_legacy(payload) {
+        return Ok(None);
+    }
+    let mut envelope = Envelope::default();
+    let mut content = if payload.starts_with(&crypto::ENCRYPTED_MAGIC) {
+        envelope.encryption = Some(Encryption::Password);
+        decrypt(Encryption::Password, payload)?
+    } else if payload.starts_with(crypto::AGE_MAGIC) {
+        envelope.encryption = Some(Encryption::Age);
+        decrypt(Encryption::Age, payload)?
+    } else {
+        payload.to_vec()
+    };
+    if let Some(rest) = content.strip_prefix(&COMPRESSED_MAGIC) {
+        let (&id, compressed) = rest.split_first().ok_or(PayloadError::Truncated)?;
+        let method = Compression::from_legacy_id(id)?;
+        envelope.compression = Some(method);
+        content = method.decompress(compressed)?;
+    }
+    if let Some(rest) = content.strip_prefix(&FILE_MAGIC) {
+        let (length, rest) = rest
+            .split_first_chunk::<2>()
+            .ok_or(PayloadError::Truncated)?;
+        let (name, rest) = split_text(rest, u16::from_be_bytes(*length) as usize)?;
+        let (size, file) = rest
+            .split_first_chunk::<8>()
+            .ok_or(PayloadError::Truncated)?;
+        let size = u64::from_be_bytes(*size);
+        if size != file.len() as u64 {
+            return Err(Box::new(PayloadError::SizeMismatch(size, file.len())));
+        }
+        envelope.mime = guess_mime(&name).map(str::to_string);
+        envelope.name = Some(name);
+        content = file.to_vec();
+    } else if std::str::from_utf8(&content).is_ok() {
+        envelope.mime = Some("text/plain".to_string());
+    }
+    Ok(Some((envelope, content)))
+}
+
+/**
+ * Rewrites a payload in an older format as a current envelope, None when there is
+ * nothing to migrate. The older formats are version 1 envelopes and those `open_legacy`
+ * reads, files and compressed payloads, along with the plain text message. Encrypted
+ * ones are left as they are since resealing them takes the key. Other payloads without
+ * envelope aren't touched, nothing tells what they hold.
+ */
+pub fn migrate(payload: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    //Fails on an envelope newer than this release rather than taking it for text
+    match Envelope::parse(payload)? {
+        Some(sealed)
+            if sealed.version == ENVELOPE_VERSION || sealed.envelope.encryption.is_some() =>
+        {
+            Ok(None)
+        }
+        Some(_) => {
+            let (envelope, content) = Envelope::open(payload, |_, body| Ok(body.to_vec()))?
+                .expect("the payload has an envelope");
+            envelope.seal(&content, |body| Ok(body.to_vec())).map(Some)
+        }
+        None if crypto::is_encrypted(payload) => Ok(None),
+        None if is_legacy(payload) => {
+            let (envelope, content) = open_legacy(payload, |_, body| Ok(body.to_vec()))?
+                .expect("the payload is in a legacy format");
+            envelope.seal(&content, |body| Ok(body.to_vec())).map(Some)
+        }
+        None if std::str::from_utf8(payload).is_err() => Ok(None),
+        None => {
+            let envelope = Envelope {
+                mime: Some("text/plain".to_string()),
+                ..Envelope::default()
+            };
+            envelope.seal(payload, |body| Ok(body.to_vec())).map(Some)
+        }
+    }
+}
+
+fn split_text(data: &[u8], length: usize) -> Result<(String, &[u8]), Error> {
+    if data.len() < length {
+        return Err(Box::new(PayloadError::Truncated));
+    }
+    let (text, rest) = data.split_at(length);
+    let text = String::from_utf8(text.to_vec()).map_err(|_| PayloadError::InvalidText)?;
+    Ok((text, rest))
+}
+
+//MIME type of the common file types, by extension
+pub fn guess_mime(name: &str) -> Option<&'static str> {
+    let (_, extension) = name.rsplit_once('.')?;
+    let mime = match extension.to_ascii_lowercase().as_str() {
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+//Text form of a payload given or shown on the command line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    //The bytes as they are
+    #[default]
+    Raw,
+    Base64,
+    Hex,
+}
+
+impl Encoding {
+    //Bytes of the text form, whitespace is ignored so wrapped text can be pasted
+    pub fn decode(self, text: &[u8]) -> Result<Vec<u8>, Error> {
+        if self == Self::Raw {
+            return Ok(text.to_vec());
+        }
+        let text: Vec<u8> = text
+            .iter()
+            .copied()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect();
+        if self == Self::Base64 {
+            return Ok(BASE64.decode(text)?);
+        }
+        if !text.len().is_multiple_of(2) {
+            return Err(Box::new(PayloadError::InvalidHex));
+        }
+        text.chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or_else(|| Box::new(PayloadError::InvalidHex) as Error)
+            })
+            .collect()
+    }
+
+    pub fn encode(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Raw => bytes.to_vec(),
+            Self::Base64 => BASE64.encode(bytes).into_bytes(),
+            Self::Hex => bytes
+                .iter()
+                .flat_map(|byte| format!("{:02x}", byte).into_bytes())
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "base64" => Ok(Self::Base64),
+            "hex" => Ok(Self::Hex),
+            _ => Err(Box::new(PayloadError::InvalidEncoding(s.to_string()))),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Raw => "raw",
+            Self::Base64 => "base64",
+            Self::Hex => "hex",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub enum PayloadError {
+    FieldTooLong(&'static str, usize),
+    InvalidText,
+    Truncated,
+    UnsupportedVersion(u8),
+    UnknownFlags(u8),
+    ChecksumMismatch,
+    SizeMismatch(u64, usize),
+    TooLarge,
+    InvalidCompression(String),
+    UnknownCompression(u8),
+    UnknownEncryption(u8),
+    Unsupported(Compression),
+    InvalidEncoding(String),
+    InvalidHex,
+}
+
+impl std::error::Error for PayloadError {}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FieldTooLong(field, length) => {
+                write!(f, "{} of {} bytes is too long", field, length)
+            }
+            Self::InvalidText => write!(f, "payload envelope holds text that isn't UTF-8"),
+            Self::Truncated => write!(f, "payload envelope is truncated"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "payload envelope version {} isn't supported", version)
+            }
+            Self::UnknownFlags(flags) => {
+                write!(f, "payload envelope has unknown flags {:#04x}", flags)
+            }
+            Self::ChecksumMismatch => write!(f, "payload checksum doesn't match, it is damaged"),
+            Self::SizeMismatch(expected, actual) => write!(
+                f,
+                "embedded file should be {} bytes but {} were found",
+                expected, actual
+            ),
+            Self::TooLarge => write!(
+                f,
+                "compressed payload inflates to more than {} bytes",
+                text::MAX_INFLATED_LENGTH
+            ),
+            Self::InvalidCompression(name) => {
+                write!(f, "unknown compression {}, expected zlib or zstd", name)
+            }
+            Self::UnknownCompression(id) => write!(f, "payload uses unknown compression {}", id),
+            Self::UnknownEncryption(id) => write!(f, "payload uses unknown encryption {}", id),
+            Self::InvalidEncoding(name) => {
+                write!(f, "unknown encoding {}, expected raw, base64 or hex", name)
+            }
+            Self::InvalidHex => write!(f, "invalid hex, expected pairs of hex digits"),
+            Self::Unsupported(method) => {
+                write!(f, "{} compression needs the {} feature", method, method)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Reversible stand in for encryption
+    fn flip(data: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(data.iter().map(|byte| !byte).collect())
+    }
+
+    #[test]
+    pub fn test_seal_open() {
+        let envelope = Envelope {
+            name: Some("secret.pdf".to_string()),
+            mime: guess_mime("secret.pdf").map(String::from),
+            timestamp: Some(1_700_000_000),
+            compression: Some(Compression::Zlib),
+            encryption: Some(Encryption::Password),
+        };
+        let content = b"%PDF ".repeat(100);
+        let payload = envelope.seal(&content, flip).unwrap();
+        assert!(payload.len() < content.len());
+        let sealed = Envelope::parse(&payload).unwrap().unwrap();
+        assert_eq!(sealed.envelope, envelope);
+
+        let (opened, opened_content) = Envelope::open(&payload, |encryption, body| {
+            assert_eq!(encryption, Encryption::Password);
+            flip(body)
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(opened, envelope);
+        assert_eq!(opened_content, content);
+        assert!(Envelope::open(b"just a message", |_, body| flip(body))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    pub fn test_open_damaged() {
+        let mut payload = Envelope::default().seal(b"content", flip).unwrap();
+        assert_eq!(payload.len(), 40 + 7);
+        *payload.last_mut().unwrap() ^= 1;
+        assert!(Envelope::open(&payload, |_, body| flip(body)).is_err());
+        payload[4] = ENVELOPE_VERSION + 1;
+        assert!(Envelope::parse(&payload).is_err());
+        assert!(Envelope::parse(&payload[..7]).is_err());
+    }
+
+    #[test]
+    pub fn test_migrate() {
+        let migrated = migrate(b"an old message").unwrap().unwrap();
+        let (envelope, content) = Envelope::open(&migrated, |_, body| flip(body))
+            .unwrap()
+            .unwrap();
+        assert_eq!(envelope.mime.as_deref(), Some("text/plain"));
+        assert_eq!(content, b"an old message");
+        assert!(migrate(&migrated).unwrap().is_none());
+        //A version 1 envelope of the same message, with its CRC-32
+        let mut version_1 = ENVELOPE_MAGIC.to_vec();
+        version_1.extend_from_slice(&[1, FLAG_MIME, 0, 0]);
+        version_1.extend_from_slice(&CRC32.checksum(b"an old message").to_be_bytes());
+        version_1.push(10);
+        version_1.extend_from_slice(b"text/plainan old message");
+        assert_eq!(
+            Envelope::parse(&version_1).unwrap().unwrap().checksum,
+            Checksum::Crc32(CRC32.checksum(b"an old message"))
+        );
+        assert_eq!(migrate(&version_1).unwrap().unwrap(), migrated);
+        assert!(migrate(b"\x89binary").unwrap().is_none());
+
+        //A zlib compressed file as encode wrote it before envelopes
+        let mut file = FILE_MAGIC.to_vec();
+        file.extend_from_slice(&5u16.to_be_bytes());
+        file.extend_from_slice(b"a.txt");
+        file.extend_from_slice(&7u64.to_be_bytes());
+        file.extend_from_slice(b"content");
+        let mut legacy = COMPRESSED_MAGIC.to_vec();
+        legacy.push(0);
+        legacy.extend_from_slice(&Compression::Zlib.compress(&file).unwrap());
+        let (envelope, content) = open_legacy(&legacy, |_, body| flip(body)).unwrap().unwrap();
+        assert_eq!(envelope.name.as_deref(), Some("a.txt"));
+        assert_eq!(envelope.compression, Some(Compression::Zlib));
+        assert_eq!(content, b"content");
+        let migrated = migrate(&legacy).unwrap().unwrap();
+        assert_eq!(
+            Envelope::open(&migrated, |_, body| flip(body)).unwrap(),
+            Some((envelope, content))
+        );
+        assert!(open_legacy(&file[..file.len() - 1], |_, body| flip(body)).is_err());
+        let mut encrypted = crypto::ENCRYPTED_MAGIC.to_vec();
+        encrypted.extend_from_slice(&file);
+        assert!(migrate(&encrypted).unwrap().is_none());
+        assert!(open_legacy(b"an old message", |_, body| flip(body))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    pub fn test_encodings() {
+        let bytes = b"\x00\xffhi";
+        for encoding in [Encoding::Raw, Encoding::Base64, Encoding::Hex] {
+            assert_eq!(encoding.decode(&encoding.encode(bytes)).unwrap(), bytes);
+        }
+        assert_eq!(Encoding::Hex.encode(bytes), b"00ff6869");
+        assert_eq!(Encoding::Base64.decode(b"AP9o\naQ==\n").unwrap(), bytes);
+        assert!(Encoding::Hex.decode(b"0g").is_err());
+        assert!(Encoding::Hex.decode(b"012").is_err());
+        assert_eq!(Encoding::Raw.decode(b"a b\n").unwrap(), b"a b\n");
+    }
+
+    #[test]
+    pub fn test_compression_methods() {
+        let content = b"la ".repeat(1000);
+        for method in [Compression::Zlib, Compression::Zstd] {
+            let envelope = Envelope {
+                compression: Some(method),
+                ..Envelope::default()
+            };
+            let payload = match envelope.seal(&content, flip) {
+                Ok(payload) => payload,
+                //zstd without the feature
+                Err(_) => continue,
+            };
+            assert!(payload.len() < content.len());
+            let (_, opened) = Envelope::open(&payload, |_, body| flip(body))
+                .unwrap()
+                .unwrap();
+            assert_eq!(opened, content);
+        }
+        //A small body inflating past the limit is refused
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder
+            .write_all(&vec![0; text::MAX_INFLATED_LENGTH as usize + 1])
+            .unwrap();
+        let bomb = encoder.finish().unwrap();
+        assert!(bomb.len() < 1 << 20);
+        assert!(Compression::Zlib.decompress(&bomb).is_err());
+    }
+}
@@ -0,0 +1,556 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{Ihdr, Interlace, ADAM7};
+use crate::png::{Png, PngError};
+use crate::Error;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fmt;
+use std::io::{Read, Write};
+
+impl Png {
+    //Data of every IDAT chunk concatenated in file order, still zlib compressed
+    pub fn idat_data(&self) -> Vec<u8> {
+        self.chunks_by_type("IDAT")
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect()
+    }
+
+    /**
+     * Decompresses the IDAT stream into the raw filtered scanlines: for each row of
+     * each sub-image, a filter type byte followed by the filtered pixel bytes.
+     * The output size is checked against the one expected from IHDR, which also
+     * protects against decompression bombs.
+     */
+    pub fn inflate_idat(&self) -> Result<Vec<u8>, Error> {
+        let expected = self.header()?.raw_data_length();
+        let idat_data = self.idat_data();
+        let mut raw = Vec::with_capacity(expected);
+        ZlibDecoder::new(idat_data.as_slice())
+            .take(expected as u64 + 1)
+            .read_to_end(&mut raw)?;
+        if raw.len() != expected {
+            return Err(Box::new(PixelError::UnexpectedDataLength(
+                expected,
+                raw.len(),
+            )));
+        }
+        Ok(raw)
+    }
+
+    //Largest IDAT chunk written by `set_idat_data`
+    pub const IDAT_CHUNK_LENGTH: usize = 1 << 20;
+    //zlib level and IDAT chunk length libpng writes with by default
+    pub const LIBPNG_LEVEL: u32 = 6;
+    pub const LIBPNG_IDAT_CHUNK_LENGTH: usize = 8192;
+
+    /**
+     * Replaces the pixel data with the given zlib stream, split into IDAT chunks of
+     * at most `IDAT_CHUNK_LENGTH` bytes where the first IDAT chunk was.
+     */
+    pub fn set_idat_data(&mut self, compressed: &[u8]) -> Result<(), Error> {
+        self.split_idat_data(compressed, Self::IDAT_CHUNK_LENGTH)
+    }
+
+    fn split_idat_data(&mut self, compressed: &[u8], chunk_length: usize) -> Result<(), Error> {
+        let chunks = self.chunks_mut();
+        let index = chunks
+            .iter()
+            .position(|chunk| *chunk.chunk_type() == ChunkType::IDAT)
+            .ok_or_else(|| PngError::ChunkNotFound(ChunkType::IDAT.to_string()))?;
+        chunks.retain(|chunk| *chunk.chunk_type() != ChunkType::IDAT);
+        let idat = compressed
+            .chunks(chunk_length)
+            .map(|data| Chunk::new(ChunkType::IDAT, data.to_vec()));
+        chunks.splice(index..index, idat);
+        Ok(())
+    }
+
+    /**
+     * Deflates the pixel data again at the given zlib level, 0 to 9.
+     * Returns the new compressed size, callers wanting the smallest file should compare
+     * it with `idat_data().len()` beforehand.
+     */
+    pub fn recompress(&mut self, level: u32) -> Result<usize, Error> {
+        if level > 9 {
+            return Err(Box::new(PixelError::InvalidCompressionLevel(level)));
+        }
+        let raw = self.inflate_idat()?;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+        encoder.write_all(&raw)?;
+        let compressed = encoder.finish()?;
+        self.set_idat_data(&compressed)?;
+        Ok(compressed.len())
+    }
+
+    //Same as `recompress` using zopfli, much slower but usually a few percent smaller
+    #[cfg(feature = "zopfli")]
+    pub fn recompress_zopfli(&mut self) -> Result<usize, Error> {
+        let raw = self.inflate_idat()?;
+        let mut compressed = Vec::new();
+        zopfli::compress(
+            zopfli::Options::default(),
+            zopfli::Format::Zlib,
+            raw.as_slice(),
+            &mut compressed,
+        )?;
+        self.set_idat_data(&compressed)?;
+        Ok(compressed.len())
+    }
+}
+
+/**
+ * Unfiltered pixel data, one row per image line without the filter type byte.
+ * Samples are packed as in the PNG data: big endian for 16 bit depths and several
+ * pixels per byte under 8 bits, so rows are `header.row_length(width)` bytes long.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scanlines {
+    header: Ihdr,
+    data: Vec<u8>,
+}
+
+impl Scanlines {
+    pub fn header(&self) -> &Ihdr {
+        &self.header
+    }
+
+    pub fn row_length(&self) -> usize {
+        self.header.row_length(self.header.width)
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks_exact(self.row_length())
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [u8]> {
+        let row_length = self.row_length();
+        self.data.chunks_exact_mut(row_length)
+    }
+
+    //Every row back to back
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Png {
+    /**
+     * Decompresses and unfilters the pixel data, returning the rows of the image.
+     * Interlaced images are reassembled from their Adam7 passes.
+     */
+    pub fn scanlines(&self) -> Result<Scanlines, Error> {
+        let header = self.header()?;
+        let raw = self.inflate_idat()?;
+        let data = match header.interlace {
+            Interlace::None => unfilter(&header, header.width, header.height, &raw)?,
+            Interlace::Adam7 => deinterlace(&header, &raw)?,
+        };
+        Ok(Scanlines { header, data })
+    }
+}
+
+//Reverses the scanline filters of a sub-image of the given size
+fn unfilter(header: &Ihdr, width: u32, height: u32, raw: &[u8]) -> Result<Vec<u8>, Error> {
+    let row_length = header.row_length(width);
+    let distance = header.filter_distance();
+    let mut data = vec![0; row_length * height as usize];
+    for (row, filtered) in raw.chunks_exact(row_length + 1).enumerate() {
+        let (filter, filtered) = (filtered[0], &filtered[1..]);
+        let (previous, current) = data.split_at_mut(row * row_length);
+        let previous = previous.get(previous.len().saturating_sub(row_length)..);
+        let previous = previous.filter(|_| row > 0);
+        let current = &mut current[..row_length];
+        for index in 0..row_length {
+            let left = if index >= distance {
+                current[index - distance]
+            } else {
+                0
+            };
+            let up = previous.map_or(0, |previous| previous[index]);
+            let up_left = match previous {
+                Some(previous) if index >= distance => previous[index - distance],
+                _ => 0,
+            };
+            let predictor =
+                predict(filter, left, up, up_left).ok_or(PixelError::InvalidFilterType(filter))?;
+            current[index] = filtered[index].wrapping_add(predictor);
+        }
+    }
+    Ok(data)
+}
+
+//Value a filter type predicts from the neighbouring bytes, None for unknown types
+fn predict(filter: u8, left: u8, up: u8, up_left: u8) -> Option<u8> {
+    match filter {
+        0 => Some(0),
+        1 => Some(left),
+        2 => Some(up),
+        3 => Some(((left as u16 + up as u16) / 2) as u8),
+        4 => Some(paeth(left, up, up_left)),
+        _ => None,
+    }
+}
+
+pub(crate) fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+//Unfilters every Adam7 pass and places its pixels back in the full image
+fn deinterlace(header: &Ihdr, raw: &[u8]) -> Result<Vec<u8>, Error> {
+    let row_length = header.row_length(header.width);
+    let bits = header.bits_per_pixel();
+    let mut data = vec![0; row_length * header.height as usize];
+    let mut offset = 0;
+    for (&(width, height), &(x0, y0, dx, dy)) in header.passes().iter().zip(ADAM7.iter()) {
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let pass_length = (1 + header.row_length(width)) * height as usize;
+        let pass = unfilter(header, width, height, &raw[offset..offset + pass_length])?;
+        offset += pass_length;
+        let pass_row_length = header.row_length(width);
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let target_x = x0 as usize + x * dx as usize;
+                let target_y = y0 as usize + y * dy as usize;
+                copy_pixel(
+                    &pass,
+                    (y * pass_row_length) * 8 + x * bits,
+                    &mut data,
+                    (target_y * row_length) * 8 + target_x * bits,
+                    bits,
+                );
+            }
+        }
+    }
+    Ok(data)
+}
+
+impl Png {
+    /**
+     * Filters and compresses `scanlines` into the pixel data, interlaced again when
+     * the image is. Every row gets the filter type with the smallest sum of absolute
+     * differences, the heuristic libpng uses. Returns the new compressed size.
+     */
+    pub fn set_scanlines(&mut self, scanlines: &Scanlines) -> Result<usize, Error> {
+        self.write_scanlines(scanlines, Compression::best(), Self::IDAT_CHUNK_LENGTH)
+    }
+
+    /**
+     * Same as `set_scanlines` with the defaults of libpng, `LIBPNG_LEVEL` and
+     * `LIBPNG_IDAT_CHUNK_LENGTH`, which most files are written with.
+     */
+    pub fn set_scanlines_libpng(&mut self, scanlines: &Scanlines) -> Result<usize, Error> {
+        self.write_scanlines(
+            scanlines,
+            Compression::new(Self::LIBPNG_LEVEL),
+            Self::LIBPNG_IDAT_CHUNK_LENGTH,
+        )
+    }
+
+    fn write_scanlines(
+        &mut self,
+        scanlines: &Scanlines,
+        level: Compression,
+        chunk_length: usize,
+    ) -> Result<usize, Error> {
+        let header = self.header()?;
+        if header != scanlines.header {
+            return Err(Box::new(PixelError::HeaderMismatch));
+        }
+        let raw = match header.interlace {
+            Interlace::None => filter(&header, header.width, &scanlines.data),
+            Interlace::Adam7 => interlace(&header, &scanlines.data),
+        };
+        let mut encoder = ZlibEncoder::new(Vec::new(), level);
+        encoder.write_all(&raw)?;
+        let compressed = encoder.finish()?;
+        self.split_idat_data(&compressed, chunk_length)?;
+        Ok(compressed.len())
+    }
+}
+
+//Filters the rows of a sub-image of the given width, each after its filter type byte
+fn filter(header: &Ihdr, width: u32, data: &[u8]) -> Vec<u8> {
+    let row_length = header.row_length(width);
+    let distance = header.filter_distance();
+    let mut raw = Vec::with_capacity(data.len() + data.len() / row_length);
+    let mut candidate = vec![0; row_length];
+    let mut best = Vec::with_capacity(row_length);
+    for (row, current) in data.chunks_exact(row_length).enumerate() {
+        let previous = (row > 0).then(|| &data[(row - 1) * row_length..row * row_length]);
+        let mut best_filter = 0;
+        let mut best_score = u64::MAX;
+        for filter in 0..5 {
+            for index in 0..row_length {
+                let left = if index >= distance {
+                    current[index - distance]
+                } else {
+                    0
+                };
+                let up = previous.map_or(0, |previous| previous[index]);
+                let up_left = match previous {
+                    Some(previous) if index >= distance => previous[index - distance],
+                    _ => 0,
+                };
+                let predictor = predict(filter, left, up, up_left).expect("filter types 0 to 4");
+                candidate[index] = current[index].wrapping_sub(predictor);
+            }
+            //Bytes read as signed, the closer to 0 the better they compress
+            let score = candidate
+                .iter()
+                .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                .sum();
+            if score < best_score {
+                best_score = score;
+                best_filter = filter;
+                best.clone_from(&candidate);
+            }
+        }
+        raw.push(best_filter);
+        raw.extend_from_slice(&best);
+    }
+    raw
+}
+
+//Splits the full image into its Adam7 passes and filters each, reverse of `deinterlace`
+fn interlace(header: &Ihdr, data: &[u8]) -> Vec<u8> {
+    let row_length = header.row_length(header.width);
+    let bits = header.bits_per_pixel();
+    let mut raw = Vec::with_capacity(header.raw_data_length());
+    for (&(width, height), &(x0, y0, dx, dy)) in header.passes().iter().zip(ADAM7.iter()) {
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let pass_row_length = header.row_length(width);
+        let mut pass = vec![0; pass_row_length * height as usize];
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let source_x = x0 as usize + x * dx as usize;
+                let source_y = y0 as usize + y * dy as usize;
+                copy_pixel(
+                    data,
+                    (source_y * row_length) * 8 + source_x * bits,
+                    &mut pass,
+                    (y * pass_row_length) * 8 + x * bits,
+                    bits,
+                );
+            }
+        }
+        raw.extend(filter(header, width, &pass));
+    }
+    raw
+}
+
+//Copies `bits` bits between bit offsets, pixels are byte aligned from 8 bits up
+fn copy_pixel(source: &[u8], from: usize, target: &mut [u8], to: usize, bits: usize) {
+    if bits >= 8 {
+        let length = bits / 8;
+        target[to / 8..to / 8 + length].copy_from_slice(&source[from / 8..from / 8 + length]);
+        return;
+    }
+    let mask = (1u8 << bits) - 1;
+    let value = (source[from / 8] >> (8 - bits - from % 8)) & mask;
+    let shift = 8 - bits - to % 8;
+    target[to / 8] = (target[to / 8] & !(mask << shift)) | (value << shift);
+}
+
+#[derive(Debug)]
+pub enum PixelError {
+    UnexpectedDataLength(usize, usize),
+    InvalidFilterType(u8),
+    InvalidCompressionLevel(u32),
+    HeaderMismatch,
+}
+
+impl std::error::Error for PixelError {}
+
+impl fmt::Display for PixelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedDataLength(expected, actual) => write!(
+                f,
+                "decompressed image data should be {} bytes, current: {}",
+                expected, actual
+            ),
+            Self::InvalidFilterType(filter) => write!(f, "invalid filter type: {}", filter),
+            Self::InvalidCompressionLevel(level) => {
+                write!(f, "compression level must be 0 to 9, current: {}", level)
+            }
+            Self::HeaderMismatch => {
+                write!(f, "scanlines are from an image of another size or format")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+    use crate::png::PngBuilder;
+
+    #[test]
+    pub fn test_idat_data_concatenates_chunks() {
+        let png = PngBuilder::new(4, 4).build().unwrap();
+        let compressed = png.idat_data();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        let mut chunks = png.chunks().to_vec();
+        chunks[1] = Chunk::new(ChunkType::IDAT, first.to_vec());
+        chunks.insert(2, Chunk::new(ChunkType::IDAT, second.to_vec()));
+
+        let split = Png::from_chunks(chunks);
+        assert_eq!(split.idat_data(), compressed);
+        assert_eq!(split.inflate_idat().unwrap(), png.inflate_idat().unwrap());
+    }
+
+    #[test]
+    pub fn test_inflate_idat() {
+        let png = PngBuilder::new(2, 3).color(&[9, 8, 7]).build().unwrap();
+        let raw = png.inflate_idat().unwrap();
+        assert_eq!(raw.len(), 3 * (1 + 2 * 3));
+        assert_eq!(&raw[..7], &[0, 9, 8, 7, 9, 8, 7]);
+    }
+
+    //Compresses raw filtered scanlines into a PNG with the given header
+    fn png_from_raw(header: Ihdr, raw: &[u8]) -> Png {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw).unwrap();
+        Png::from_chunks(vec![
+            header.to_chunk(),
+            Chunk::new(ChunkType::IDAT, encoder.finish().unwrap()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ])
+    }
+
+    #[test]
+    pub fn test_scanlines_filters() {
+        let header = Ihdr::new(2, 5, 8, ColorType::Grayscale);
+        #[rustfmt::skip]
+        let raw = [
+            0, 10, 20,
+            1, 5, 5,
+            2, 1, 1,
+            3, 2, 2,
+            4, 1, 1,
+        ];
+        let scanlines = png_from_raw(header, &raw).scanlines().unwrap();
+        let rows: Vec<&[u8]> = scanlines.rows().collect();
+        assert_eq!(
+            rows,
+            vec![&[10, 20][..], &[5, 10], &[6, 11], &[5, 10], &[6, 11]]
+        );
+    }
+
+    #[test]
+    pub fn test_scanlines_invalid_filter() {
+        let header = Ihdr::new(1, 1, 8, ColorType::Grayscale);
+        assert!(png_from_raw(header, &[5, 0]).scanlines().is_err());
+    }
+
+    #[test]
+    pub fn test_scanlines_adam7() {
+        let mut header = Ihdr::new(3, 2, 8, ColorType::Grayscale);
+        header.interlace = Interlace::Adam7;
+        //Passes 1, 4, 6 and 7 hold pixels (0,0), (2,0), (1,0) and row 1
+        let raw = [0, 1, 0, 3, 0, 2, 0, 4, 5, 6];
+        let scanlines = png_from_raw(header, &raw).scanlines().unwrap();
+        assert_eq!(scanlines.data(), &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    pub fn test_scanlines_adam7_packed() {
+        let mut header = Ihdr::new(2, 2, 1, ColorType::Grayscale);
+        header.interlace = Interlace::Adam7;
+        //Pass 1 holds (0,0), pass 6 holds (1,0) and pass 7 holds row 1
+        let raw = [0, 0b1000_0000, 0, 0b0000_0000, 0, 0b0100_0000];
+        let scanlines = png_from_raw(header, &raw).scanlines().unwrap();
+        assert_eq!(scanlines.data(), &[0b1000_0000, 0b0100_0000]);
+    }
+
+    #[test]
+    pub fn test_inflate_idat_wrong_length() {
+        let png = PngBuilder::new(2, 3).build().unwrap();
+        let mut chunks = png.chunks().to_vec();
+        //Claims a bigger image than the pixel data holds
+        chunks[0] = PngBuilder::new(2, 4).build().unwrap().chunks()[0].clone();
+        assert!(Png::from_chunks(chunks).inflate_idat().is_err());
+    }
+
+    #[test]
+    pub fn test_recompress() {
+        let mut png = PngBuilder::new(64, 64).build().unwrap();
+        let raw = png.inflate_idat().unwrap();
+        let stored = png.recompress(0).unwrap();
+        assert!(stored > raw.len());
+        let compressed = png.recompress(9).unwrap();
+        assert!(compressed < stored);
+        assert_eq!(png.idat_data().len(), compressed);
+        assert_eq!(png.inflate_idat().unwrap(), raw);
+        assert!(png.recompress(10).is_err());
+    }
+
+    #[test]
+    pub fn test_set_idat_data_merges_chunks() {
+        let png = PngBuilder::new(4, 4).build().unwrap();
+        let compressed = png.idat_data();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        let mut chunks = png.chunks().to_vec();
+        chunks[1] = Chunk::new(ChunkType::IDAT, first.to_vec());
+        chunks.insert(2, Chunk::new(ChunkType::IDAT, second.to_vec()));
+
+        let mut split = Png::from_chunks(chunks);
+        split.set_idat_data(&compressed).unwrap();
+        assert_eq!(split.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    pub fn test_set_scanlines() {
+        let header = Ihdr::new(2, 5, 8, ColorType::Grayscale);
+        let raw = [0, 10, 20, 1, 5, 5, 2, 1, 1, 3, 2, 2, 4, 1, 1];
+        let mut png = png_from_raw(header, &raw);
+        let mut scanlines = png.scanlines().unwrap();
+        scanlines.data_mut()[3] ^= 1;
+        png.set_scanlines(&scanlines).unwrap();
+        assert_eq!(png.scanlines().unwrap(), scanlines);
+
+        let mut header = Ihdr::new(3, 2, 8, ColorType::Grayscale);
+        header.interlace = Interlace::Adam7;
+        let mut png = png_from_raw(header, &[0, 1, 0, 3, 0, 2, 0, 4, 5, 6]);
+        let scanlines = png.scanlines().unwrap();
+        png.set_scanlines(&scanlines).unwrap();
+        assert_eq!(png.scanlines().unwrap().data(), &[1, 2, 3, 4, 5, 6]);
+
+        let other = PngBuilder::new(3, 2).build().unwrap().scanlines().unwrap();
+        assert!(png.set_scanlines(&other).is_err());
+    }
+
+    #[cfg(feature = "zopfli")]
+    #[test]
+    pub fn test_recompress_zopfli() {
+        let mut png = PngBuilder::new(16, 16).build().unwrap();
+        let raw = png.inflate_idat().unwrap();
+        png.recompress_zopfli().unwrap();
+        assert_eq!(png.inflate_idat().unwrap(), raw);
+    }
+}
@@ -0,0 +1,951 @@
+use crate::chunk::{Chunk, CRC_32};
+use crate::chunk_type::{ChunkOrdering, ChunkType};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::stream::{PngReader, PngWriter, SharedPngReader};
+use crate::validate::{IssueCode, ValidationIssue};
+use crate::Error;
+use bytes::Bytes;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+#[derive(Debug, Clone)]
+pub struct Png {
+    chunks: Vec<Chunk>,
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let chunks = PngReader::new(bytes)?.collect::<Result<Vec<Chunk>, Error>>()?;
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+/**
+ * Parses a PNG without copying any chunk data, every chunk points into `bytes`.
+ * Chunks only get their own buffer when their data is replaced.
+ */
+impl TryFrom<Bytes> for Png {
+    type Error = Error;
+    fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
+        let chunks = SharedPngReader::new(bytes)?.collect::<Result<Vec<Chunk>, Error>>()?;
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+impl fmt::Display for Png {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Png {{")?;
+        for chunk in &self.chunks {
+            writeln!(f, "  {}", chunk.to_string().replace('\n', "\n  "))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Png {
+    //Every PNG file starts with these 8 bytes
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
+        Png { chunks }
+    }
+
+    /**
+     * Parses a PNG without reading the pixel data of IDAT chunks, only their position
+     * in `reader` is recorded. Message operations never need pixel data, so this keeps
+     * memory use down to the size of the metadata for large images.
+     */
+    pub fn from_reader_skip_pixel_data<R: Read + Seek>(reader: R) -> Result<SkimmedPng<R>, Error> {
+        let mut reader = PngReader::new(reader)?;
+        let mut chunks = Vec::new();
+        let mut pixel_data = Vec::new();
+        while let Some((length, chunk_type)) = reader.read_header()? {
+            let end = chunk_type == ChunkType::IEND;
+            if chunk_type == ChunkType::IDAT {
+                let offset = reader.get_mut().stream_position()?;
+                reader.get_mut().seek(SeekFrom::Current(length as i64))?;
+                let crc = reader.read_crc()?;
+                chunks.push(Chunk::from_parts(chunk_type, Bytes::new(), crc));
+                pixel_data.push((offset, length));
+            } else {
+                //Read as it comes, a crafted length mustn't allocate up front
+                let mut data = Vec::new();
+                reader
+                    .get_mut()
+                    .take(length as u64)
+                    .read_to_end(&mut data)?;
+                if data.len() != length as usize {
+                    return Err(Box::new(io::Error::from(io::ErrorKind::UnexpectedEof)));
+                }
+                let crc = reader.read_crc()?;
+                chunks.push(Chunk::from_parts(chunk_type, Bytes::from(data), crc));
+            }
+            //Like `PngReader`, what follows IEND isn't read
+            if end {
+                break;
+            }
+        }
+        Ok(SkimmedPng {
+            png: Png::from_chunks(chunks),
+            source: reader.into_inner(),
+            pixel_data,
+        })
+    }
+
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        self.chunks.push(chunk);
+    }
+
+    /**
+     * Inserts the chunk in a position allowed by the PNG chunk ordering rules:
+     * IHDR first, IEND last, colour space chunks before PLTE and IDAT,
+     * PLTE, tRNS, bKGD, hIST, pHYs, sPLT, eXIf and acTL before IDAT.
+     * Any other chunk goes right before IEND, after every chunk already present.
+     * In an animated PNG those go before the first fcTL or IDAT instead, so they never
+     * end up between a frame control chunk and its data.
+     */
+    pub fn insert_chunk_ordered(&mut self, chunk: Chunk) {
+        let index = self.ordered_position(chunk.chunk_type());
+        self.chunks.insert(index, chunk);
+    }
+
+    fn ordered_position(&self, chunk_type: &ChunkType) -> usize {
+        let end = self
+            .first_position(&[ChunkType::IEND])
+            .unwrap_or(self.chunks.len());
+        let before = |types: &[ChunkType]| self.first_position(types).unwrap_or(end).min(end);
+        match chunk_type.ordering() {
+            ChunkOrdering::First => 0,
+            ChunkOrdering::Last => self.chunks.len(),
+            ChunkOrdering::Palette => before(&[
+                ChunkType::tRNS,
+                ChunkType::bKGD,
+                ChunkType::hIST,
+                ChunkType::IDAT,
+            ]),
+            ChunkOrdering::BeforePalette => before(&[ChunkType::PLTE, ChunkType::IDAT]),
+            ChunkOrdering::AfterPalette | ChunkOrdering::BeforeData => before(&[ChunkType::IDAT]),
+            //Right after the last IDAT so they stay consecutive
+            ChunkOrdering::Data => self
+                .chunks
+                .iter()
+                .rposition(|chunk| *chunk.chunk_type() == ChunkType::IDAT)
+                .map_or(end, |index| index + 1),
+            ChunkOrdering::Frame => end,
+            ChunkOrdering::Anywhere if self.is_animated() => {
+                before(&[ChunkType::fcTL, ChunkType::IDAT])
+            }
+            ChunkOrdering::Anywhere => end,
+        }
+    }
+
+    //Index of the first chunk whose type is one of the given types
+    fn first_position(&self, types: &[ChunkType]) -> Option<usize> {
+        self.chunks
+            .iter()
+            .position(|chunk| types.contains(chunk.chunk_type()))
+    }
+
+    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk, Error> {
+        match self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+        {
+            Some(index) => Ok(self.chunks.remove(index)),
+            None => Err(Box::new(PngError::ChunkNotFound(chunk_type.to_string()))),
+        }
+    }
+
+    //Removes the chunk at the given position in the file
+    pub fn remove_chunk_at(&mut self, index: usize) -> Result<Chunk, Error> {
+        if index >= self.chunks.len() {
+            return Err(Box::new(PngError::ChunkIndexOutOfRange(
+                index,
+                self.chunks.len(),
+            )));
+        }
+        Ok(self.chunks.remove(index))
+    }
+
+    //Removes every chunk of the given type, returning them in file order
+    pub fn remove_all_chunks(&mut self, chunk_type: &str) -> Vec<Chunk> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| chunk.chunk_type().to_string() == chunk_type);
+        self.chunks = kept;
+        removed
+    }
+
+    //Replaces the first chunk of the given type in place, returning the previous one
+    pub fn replace_chunk(&mut self, chunk_type: &ChunkType, chunk: Chunk) -> Result<Chunk, Error> {
+        match self
+            .chunks
+            .iter_mut()
+            .find(|existing| existing.chunk_type() == chunk_type)
+        {
+            Some(existing) => Ok(std::mem::replace(existing, chunk)),
+            None => Err(Box::new(PngError::ChunkNotFound(chunk_type.to_string()))),
+        }
+    }
+
+    /**
+     * Removes ancillary chunks that are byte-identical to an earlier chunk, which
+     * tools re-saving a file tend to leave behind. Returns the removed chunks.
+     */
+    pub fn dedup_chunks(&mut self) -> Vec<Chunk> {
+        let mut seen = HashSet::new();
+        let mut removed = Vec::new();
+        self.chunks.retain(|chunk| {
+            if chunk.chunk_type().is_critical() {
+                return true;
+            }
+            let key = (
+                chunk.chunk_type().bytes(),
+                chunk.shared_data().clone(),
+                chunk.crc(),
+            );
+            let first = seen.insert(key);
+            if !first {
+                removed.push(chunk.clone());
+            }
+            first
+        });
+        removed
+    }
+
+    /**
+     * Copies the chunks of the given types from `source`, in their source order and
+     * following the chunk ordering rules. Chunks the spec allows only once replace
+     * the ones already present. Critical chunks are never copied, unsafe-to-copy ones
+     * depend on the image data and need `allow_unsafe`. Returns the number copied.
+     */
+    pub fn copy_chunks_from(
+        &mut self,
+        source: &Png,
+        types: &[ChunkType],
+        allow_unsafe: bool,
+    ) -> Result<usize, Error> {
+        let selected: Vec<&Chunk> = source
+            .chunks
+            .iter()
+            .filter(|chunk| types.contains(chunk.chunk_type()))
+            .collect();
+        for chunk in &selected {
+            let chunk_type = chunk.chunk_type();
+            if chunk_type.is_critical() {
+                return Err(Box::new(PngError::CriticalChunk(chunk_type.to_string())));
+            }
+            if !chunk_type.is_safe_to_copy() && !allow_unsafe {
+                return Err(Box::new(PngError::UnsafeToCopy(chunk_type.to_string())));
+            }
+        }
+        for chunk in &selected {
+            if chunk.chunk_type().is_unique() {
+                self.remove_all_chunks(&chunk.chunk_type().to_string());
+            }
+        }
+        for chunk in &selected {
+            self.insert_chunk_ordered((*chunk).clone());
+        }
+        Ok(selected.len())
+    }
+
+    /**
+     * Copies the public safe-to-copy ancillary chunks of `source`, its text, pHYs,
+     * eXIf and similar metadata, following the chunk ordering rules. Private chunks
+     * such as payloads stay behind. Chunks the spec allows only once replace the ones
+     * already present, others are skipped when an identical one is. Returns the
+     * number copied.
+     */
+    pub fn copy_metadata_from(&mut self, source: &Png) -> usize {
+        let selected: Vec<&Chunk> = source
+            .chunks
+            .iter()
+            .filter(|chunk| {
+                let chunk_type = chunk.chunk_type();
+                !chunk_type.is_critical() && chunk_type.is_public() && chunk_type.is_safe_to_copy()
+            })
+            .filter(|chunk| {
+                chunk.chunk_type().is_unique()
+                    || !self.chunks.iter().any(|present| {
+                        present.chunk_type() == chunk.chunk_type() && present.data() == chunk.data()
+                    })
+            })
+            .collect();
+        for chunk in &selected {
+            if chunk.chunk_type().is_unique() {
+                self.remove_all_chunks(&chunk.chunk_type().to_string());
+            }
+        }
+        for chunk in &selected {
+            self.insert_chunk_ordered((*chunk).clone());
+        }
+        selected.len()
+    }
+
+    //Returns the 8 byte PNG signature
+    pub fn signature(&self) -> &[u8; 8] {
+        &Self::STANDARD_HEADER
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    //Returns the first chunk of the given type
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    //Returns every chunk of the given type, in file order
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    pub(crate) fn chunks_mut(&mut self) -> &mut Vec<Chunk> {
+        &mut self.chunks
+    }
+
+    pub fn chunks_by_type_mut<'a>(
+        &'a mut self,
+        chunk_type: &'a str,
+    ) -> impl Iterator<Item = &'a mut Chunk> {
+        self.chunks
+            .iter_mut()
+            .filter(move |chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        Self::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(self.chunks.iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect()
+    }
+}
+
+/**
+ * A PNG parsed with `Png::from_reader_skip_pixel_data`.
+ * IDAT chunks are kept as empty placeholders carrying their original CRC, their data
+ * stays in the source and is copied back in when the PNG is written out. Their CRC
+ * doesn't match the empty data, check them with `validate` rather than `Png::validate`.
+ */
+pub struct SkimmedPng<R: Read + Seek> {
+    png: Png,
+    source: R,
+    //Offset and length of the data of every IDAT chunk, in file order
+    pixel_data: Vec<(u64, u32)>,
+}
+
+impl<R: Read + Seek> SkimmedPng<R> {
+    //IDAT chunks are placeholders here, see `pixel_data_length` and `validate`
+    pub fn png(&self) -> &Png {
+        &self.png
+    }
+
+    //IDAT chunks must not be added, removed or reordered
+    pub fn png_mut(&mut self) -> &mut Png {
+        &mut self.png
+    }
+
+    //Total length of the pixel data left in the source
+    pub fn pixel_data_length(&self) -> u64 {
+        self.pixel_data
+            .iter()
+            .map(|(_, length)| *length as u64)
+            .sum()
+    }
+
+    //`Png::validate` with the CRC of every IDAT placeholder checked against its data in the source
+    pub fn validate(&mut self) -> Result<Vec<ValidationIssue>, Error> {
+        let mut pixel_data = self.pixel_data.iter();
+        let mut invalid = Vec::new();
+        for (index, chunk) in self.png.chunks.iter().enumerate() {
+            if *chunk.chunk_type() != ChunkType::IDAT {
+                continue;
+            }
+            let Some((offset, length)) = pixel_data.next() else {
+                break;
+            };
+            self.source.seek(SeekFrom::Start(*offset))?;
+            let mut digest = CRC_32.digest();
+            digest.update(&chunk.chunk_type().bytes());
+            let mut data = (&mut self.source).take(*length as u64);
+            let mut buffer = [0; 8192];
+            loop {
+                match data.read(&mut buffer)? {
+                    0 => break,
+                    read => digest.update(&buffer[..read]),
+                }
+            }
+            if digest.finalize() != chunk.crc() {
+                invalid.push(index);
+            }
+        }
+        let mut issues: Vec<ValidationIssue> = self
+            .png
+            .validate()
+            .into_iter()
+            .filter(|issue| {
+                issue.code != IssueCode::InvalidCrc
+                    || issue
+                        .chunk_index
+                        .is_none_or(|index| *self.png.chunks[index].chunk_type() != ChunkType::IDAT)
+            })
+            .collect();
+        issues.extend(invalid.into_iter().map(|index| {
+            let chunk = &self.png.chunks[index];
+            ValidationIssue::chunk(IssueCode::InvalidCrc, index, chunk, "has an invalid CRC")
+        }));
+        issues.sort_by_key(|issue| issue.chunk_index);
+        Ok(issues)
+    }
+
+    //Writes the whole PNG, splicing the pixel data back in from the source
+    pub fn write_to<W: Write>(&mut self, writer: W) -> Result<W, Error> {
+        let idat_count = self.png.chunks_by_type("IDAT").count();
+        if idat_count != self.pixel_data.len() {
+            return Err(Box::new(PngError::PixelDataChanged(
+                self.pixel_data.len(),
+                idat_count,
+            )));
+        }
+        let mut writer = PngWriter::new(writer)?;
+        let mut pixel_data = self.pixel_data.iter();
+        for chunk in &self.png.chunks {
+            if *chunk.chunk_type() == ChunkType::IDAT {
+                if let Some((offset, length)) = pixel_data.next() {
+                    self.source.seek(SeekFrom::Start(*offset))?;
+                    writer.write_raw_chunk(
+                        chunk.chunk_type(),
+                        *length,
+                        &mut self.source,
+                        chunk.crc(),
+                    )?;
+                }
+            } else {
+                writer.write_chunk(chunk)?;
+            }
+        }
+        writer.finish()
+    }
+
+    pub fn into_inner(self) -> (Png, R) {
+        (self.png, self.source)
+    }
+}
+
+/**
+ * Builds a minimal valid PNG (IHDR, IDAT and IEND) filled with a single colour.
+ * The colour is given as 8 bit samples, its length selects the colour type:
+ * 1 = greyscale, 2 = greyscale with alpha, 3 = RGB, 4 = RGBA.
+ */
+#[derive(Debug, Clone)]
+pub struct PngBuilder {
+    width: u32,
+    height: u32,
+    color: Vec<u8>,
+}
+
+impl PngBuilder {
+    //Defaults to opaque black RGB
+    pub fn new(width: u32, height: u32) -> PngBuilder {
+        PngBuilder {
+            width,
+            height,
+            color: vec![0, 0, 0],
+        }
+    }
+
+    pub fn color(mut self, color: &[u8]) -> PngBuilder {
+        self.color = color.to_vec();
+        self
+    }
+
+    pub fn build(&self) -> Result<Png, Error> {
+        let invalid =
+            || -> Error { Box::new(PngError::InvalidDimensions(self.width, self.height)) };
+        let limit = (1 << 31) - 1;
+        if self.width == 0 || self.height == 0 || self.width > limit || self.height > limit {
+            return Err(invalid());
+        }
+        let color_type = match self.color.len() {
+            1 => ColorType::Grayscale,
+            2 => ColorType::GrayscaleAlpha,
+            3 => ColorType::Rgb,
+            4 => ColorType::Rgba,
+            length => return Err(Box::new(PngError::InvalidColor(length))),
+        };
+        let header = Ihdr::new(self.width, self.height, 8, color_type);
+
+        //Every scanline starts with filter type 0 (none), its length is checked before
+        //anything is allocated
+        let length = (self.color.len() as u32)
+            .checked_mul(self.width)
+            .and_then(|length| length.checked_add(1))
+            .ok_or_else(invalid)?;
+        let mut scanline = Vec::with_capacity(length as usize);
+        scanline.push(0);
+        for _ in 0..self.width {
+            scanline.extend_from_slice(&self.color);
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for _ in 0..self.height {
+            encoder.write_all(&scanline)?;
+        }
+
+        Ok(Png::from_chunks(vec![
+            header.to_chunk(),
+            Chunk::new(ChunkType::IDAT, encoder.finish()?),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]))
+    }
+}
+
+#[derive(Debug)]
+pub enum PngError {
+    InvalidSignature([u8; 8]),
+    ChunkNotFound(String),
+    InvalidDimensions(u32, u32),
+    InvalidColor(usize),
+    PixelDataChanged(usize, usize),
+    ChunkIndexOutOfRange(usize, usize),
+    CriticalChunk(String),
+    UnsafeToCopy(String),
+    ChunkExists(String),
+    PayloadNotFound(String),
+    PayloadExists(String),
+}
+
+impl std::error::Error for PngError {}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSignature(signature) => write!(f, "invalid signature: {:?}", signature),
+            Self::ChunkNotFound(chunk_type) => write!(f, "chunk not found: {}", chunk_type),
+            Self::InvalidDimensions(width, height) => {
+                write!(f, "invalid image dimensions: {}x{}", width, height)
+            }
+            Self::PixelDataChanged(expected, actual) => write!(
+                f,
+                "IDAT chunks can't be added or removed, expected: {}, current: {}",
+                expected, actual
+            ),
+            Self::InvalidColor(length) => {
+                write!(f, "colour must have 1 to 4 samples, current: {}", length)
+            }
+            Self::ChunkIndexOutOfRange(index, chunks) => {
+                write!(f, "no chunk at index {}, the file has {}", index, chunks)
+            }
+            Self::CriticalChunk(chunk_type) => {
+                write!(f, "{} is a critical chunk and can't be copied", chunk_type)
+            }
+            Self::UnsafeToCopy(chunk_type) => write!(
+                f,
+                "{} is not safe to copy, it may not match the other image",
+                chunk_type
+            ),
+            Self::ChunkExists(chunk_type) => {
+                write!(
+                    f,
+                    "the file already has a {} chunk, replace or append to it",
+                    chunk_type
+                )
+            }
+            Self::PayloadNotFound(name) => write!(f, "no payload named {}", name),
+            Self::PayloadExists(name) => {
+                write!(
+                    f,
+                    "the file already has a payload named {}, replace it",
+                    name
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use std::str::FromStr;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk"),
+            chunk_from_strings("miDl", "I am another chunk"),
+            chunk_from_strings("LASt", "I am the last chunk"),
+        ]
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(testing_chunks())
+    }
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        let chunk_type = ChunkType::from_str(chunk_type).unwrap();
+        Chunk::new(chunk_type, data.as_bytes().to_vec())
+    }
+
+    #[test]
+    pub fn test_from_chunks() {
+        let png = Png::from_chunks(testing_chunks());
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    pub fn test_valid_from_bytes() {
+        let bytes = testing_png().as_bytes();
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    pub fn test_valid_from_shared_bytes() {
+        let bytes = Bytes::from(testing_png().as_bytes());
+        let png = Png::try_from(bytes.clone()).unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+
+        let start = bytes.as_ptr() as usize;
+        for chunk in png.chunks() {
+            let data = chunk.data().as_ptr() as usize;
+            assert!(data >= start && data < start + bytes.len());
+        }
+    }
+
+    #[test]
+    pub fn test_invalid_shared_bytes() {
+        assert!(Png::try_from(Bytes::from_static(&[137, 80])).is_err());
+        let bytes = testing_png().as_bytes();
+        let truncated = Bytes::copy_from_slice(&bytes[..bytes.len() - 1]);
+        assert!(Png::try_from(truncated).is_err());
+    }
+
+    #[test]
+    pub fn test_invalid_header() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[1] = 81;
+        let png = Png::try_from(bytes.as_ref());
+        assert!(png.is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_by_type() {
+        let png = testing_png();
+        let chunk = png.chunk_by_type("FrSt").unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+    }
+
+    #[test]
+    pub fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "First message"));
+        png.append_chunk(chunk_from_strings("TeSt", "Second message"));
+        let messages: Vec<String> = png
+            .chunks_by_type("TeSt")
+            .map(|chunk| chunk.data_as_string().unwrap())
+            .collect();
+        assert_eq!(messages, vec!["First message", "Second message"]);
+        assert_eq!(png.chunks_by_type("NoNe").count(), 0);
+    }
+
+    #[test]
+    pub fn test_chunks_by_type_mut() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "First message"));
+        png.append_chunk(chunk_from_strings("TeSt", "Second message"));
+        assert_eq!(png.chunks_by_type_mut("TeSt").count(), 2);
+        assert_eq!(png.chunks_by_type_mut("FrSt").count(), 1);
+    }
+
+    #[test]
+    pub fn test_append_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message"));
+        let chunk = png.chunk_by_type("TeSt").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+    }
+
+    fn testing_image() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header"),
+            chunk_from_strings("PLTE", "palette"),
+            chunk_from_strings("IDAT", "pixels"),
+            chunk_from_strings("IDAT", "more pixels"),
+            chunk_from_strings("IEND", ""),
+        ])
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    pub fn test_insert_chunk_ordered() {
+        let mut png = testing_image();
+        png.insert_chunk_ordered(chunk_from_strings("ruSt", "Message"));
+        png.insert_chunk_ordered(chunk_from_strings("gAMA", "gamma"));
+        png.insert_chunk_ordered(chunk_from_strings("tRNS", "transparency"));
+        png.insert_chunk_ordered(chunk_from_strings("pHYs", "dimensions"));
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "gAMA", "PLTE", "tRNS", "pHYs", "IDAT", "IDAT", "ruSt", "IEND"]
+        );
+    }
+
+    #[test]
+    pub fn test_insert_chunk_ordered_palette() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header"),
+            chunk_from_strings("tRNS", "transparency"),
+            chunk_from_strings("IDAT", "pixels"),
+            chunk_from_strings("IEND", ""),
+        ]);
+        png.insert_chunk_ordered(chunk_from_strings("PLTE", "palette"));
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "PLTE", "tRNS", "IDAT", "IEND"]
+        );
+    }
+
+    #[test]
+    pub fn test_insert_chunk_ordered_animated() {
+        let mut png = PngBuilder::new(2, 2).build().unwrap();
+        png.insert_chunk_ordered(Chunk::new(ChunkType::acTL, vec![0, 0, 0, 1, 0, 0, 0, 0]));
+        png.insert_chunk_ordered(Chunk::new(ChunkType::fcTL, vec![0; 26]));
+        png.insert_chunk_ordered(chunk_from_strings("ruSt", "hidden"));
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        let fctl = types.iter().position(|t| t == "fcTL").unwrap();
+        let rust = types.iter().position(|t| t == "ruSt").unwrap();
+        let idat = types.iter().position(|t| t == "IDAT").unwrap();
+        assert!(rust < idat && rust < fctl);
+        assert_eq!(fctl, types.len() - 2);
+    }
+
+    #[test]
+    pub fn test_insert_chunk_ordered_without_iend() {
+        let mut png = Png::from_chunks(vec![chunk_from_strings("IHDR", "header")]);
+        png.insert_chunk_ordered(chunk_from_strings("ruSt", "Message"));
+        png.insert_chunk_ordered(chunk_from_strings("IEND", ""));
+        assert_eq!(chunk_types(&png), vec!["IHDR", "ruSt", "IEND"]);
+    }
+
+    #[test]
+    pub fn test_remove_first_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message"));
+        png.remove_first_chunk("TeSt").unwrap();
+        assert!(png.chunk_by_type("TeSt").is_none());
+        assert!(png.remove_first_chunk("TeSt").is_err());
+    }
+
+    #[test]
+    pub fn test_remove_chunk_at() {
+        let mut png = testing_png();
+        let removed = png.remove_chunk_at(1).unwrap();
+        assert_eq!(removed.chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.remove_chunk_at(2).is_err());
+    }
+
+    #[test]
+    pub fn test_remove_all_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "First"));
+        png.append_chunk(chunk_from_strings("TeSt", "Second"));
+        let removed = png.remove_all_chunks("TeSt");
+        assert_eq!(removed.len(), 2);
+        assert_eq!(removed[1].data(), b"Second");
+        assert_eq!(png.chunks().len(), 3);
+        assert!(png.remove_all_chunks("TeSt").is_empty());
+    }
+
+    #[test]
+    pub fn test_replace_chunk() {
+        let mut png = testing_png();
+        let chunk_type = ChunkType::from_str("miDl").unwrap();
+        let previous = png
+            .replace_chunk(&chunk_type, chunk_from_strings("miDl", "Replaced"))
+            .unwrap();
+        assert_eq!(&previous.data_as_string().unwrap(), "I am another chunk");
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(&png.chunks()[1].data_as_string().unwrap(), "Replaced");
+
+        let chunk_type = ChunkType::from_str("NoNe").unwrap();
+        let replaced = png.replace_chunk(&chunk_type, chunk_from_strings("NoNe", "Nothing"));
+        assert!(replaced.is_err());
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    pub fn test_dedup_chunks() {
+        let mut png = PngBuilder::new(2, 2).build().unwrap();
+        let idat = png.chunks()[1].clone();
+        png.insert_chunk_ordered(chunk_from_strings("ruSt", "Message"));
+        png.insert_chunk_ordered(chunk_from_strings("ruSt", "Message"));
+        png.insert_chunk_ordered(chunk_from_strings("ruSt", "Other"));
+        png.insert_chunk_ordered(idat.clone());
+
+        let removed = png.dedup_chunks();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].data(), b"Message");
+        assert_eq!(png.chunks_by_type("ruSt").count(), 2);
+        assert_eq!(png.chunks_by_type("IDAT").count(), 2);
+        assert!(png.dedup_chunks().is_empty());
+    }
+
+    #[test]
+    pub fn test_copy_chunks_from() {
+        let mut source = PngBuilder::new(2, 2).build().unwrap();
+        source.insert_chunk_ordered(chunk_from_strings("ruSt", "First"));
+        source.insert_chunk_ordered(chunk_from_strings("ruSt", "Second"));
+        source.insert_chunk_ordered(Chunk::new(ChunkType::pHYs, vec![1; 9]));
+        source.insert_chunk_ordered(chunk_from_strings("RuSt", "Unsafe"));
+        let mut target = PngBuilder::new(3, 3).build().unwrap();
+        target.insert_chunk_ordered(Chunk::new(ChunkType::pHYs, vec![0; 9]));
+
+        let types = [ChunkType::from_str("ruSt").unwrap(), ChunkType::pHYs];
+        assert_eq!(target.copy_chunks_from(&source, &types, false).unwrap(), 3);
+        assert_eq!(target.chunks_by_type("ruSt").count(), 2);
+        assert_eq!(target.chunk_by_type("pHYs").unwrap().data(), &[1; 9]);
+        assert!(target.validate().is_empty());
+
+        let unsafe_type = [ChunkType::from_str("RuSt").unwrap()];
+        assert!(target
+            .copy_chunks_from(&source, &unsafe_type, false)
+            .is_err());
+        assert!(target
+            .copy_chunks_from(&source, &[ChunkType::IDAT], true)
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_copy_metadata_from() {
+        let mut source = PngBuilder::new(2, 2).build().unwrap();
+        source.insert_chunk_ordered(chunk_from_strings("tEXt", "Author\0Me"));
+        source.insert_chunk_ordered(chunk_from_strings("tEXt", "Title\0Cat"));
+        source.insert_chunk_ordered(Chunk::new(ChunkType::pHYs, vec![1; 9]));
+        source.insert_chunk_ordered(Chunk::new(ChunkType::gAMA, vec![0, 0, 177, 143]));
+        source.insert_chunk_ordered(chunk_from_strings("ruSt", "Payload"));
+        let mut target = PngBuilder::new(3, 3).build().unwrap();
+        target.insert_chunk_ordered(chunk_from_strings("tEXt", "Author\0Me"));
+        target.insert_chunk_ordered(Chunk::new(ChunkType::pHYs, vec![0; 9]));
+
+        assert_eq!(target.copy_metadata_from(&source), 2);
+        assert_eq!(
+            chunk_types(&target),
+            vec!["IHDR", "pHYs", "IDAT", "tEXt", "tEXt", "IEND"]
+        );
+        assert_eq!(target.chunk_by_type("pHYs").unwrap().data(), &[1; 9]);
+        assert!(target.validate().is_empty());
+        assert_eq!(target.copy_metadata_from(&source), 1);
+    }
+
+    #[test]
+    pub fn test_as_bytes_round_trip() {
+        let bytes = testing_png().as_bytes();
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    pub fn test_builder() {
+        let png = PngBuilder::new(3, 2)
+            .color(&[255, 0, 0, 255])
+            .build()
+            .unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(
+            png.chunk_by_type("IHDR").unwrap().data(),
+            &[0, 0, 0, 3, 0, 0, 0, 2, 8, 6, 0, 0, 0]
+        );
+
+        let mut pixels = Vec::new();
+        let idat = png.chunk_by_type("IDAT").unwrap().data();
+        flate2::read::ZlibDecoder::new(idat)
+            .read_to_end(&mut pixels)
+            .unwrap();
+        assert_eq!(pixels.len(), 2 * (1 + 3 * 4));
+        assert_eq!(&pixels[..5], &[0, 255, 0, 0, 255]);
+
+        let bytes = png.as_bytes();
+        assert!(Png::try_from(bytes.as_ref()).is_ok());
+    }
+
+    #[test]
+    pub fn test_builder_invalid() {
+        assert!(PngBuilder::new(0, 10).build().is_err());
+        assert!(PngBuilder::new(10, 10).color(&[]).build().is_err());
+        assert!(PngBuilder::new(10, 10)
+            .color(&[1, 2, 3, 4, 5])
+            .build()
+            .is_err());
+        assert!(PngBuilder::new(10, 1 << 31).build().is_err());
+        //The RGB scanline would be longer than 4 GiB
+        assert!(PngBuilder::new((1 << 31) - 1, 1).build().is_err());
+    }
+
+    #[test]
+    pub fn test_skip_pixel_data() {
+        let png = PngBuilder::new(64, 64).build().unwrap();
+        let bytes = png.as_bytes();
+        let idat_length = png.chunk_by_type("IDAT").unwrap().length();
+
+        let mut skimmed = Png::from_reader_skip_pixel_data(Cursor::new(bytes)).unwrap();
+        assert!(skimmed
+            .png()
+            .chunk_by_type("IDAT")
+            .unwrap()
+            .data()
+            .is_empty());
+        assert_eq!(skimmed.pixel_data_length(), idat_length as u64);
+
+        let message = chunk_from_strings("ruSt", "Message");
+        skimmed.png_mut().insert_chunk_ordered(message.clone());
+        let written = skimmed.write_to(Vec::new()).unwrap();
+
+        let mut expected = png.clone();
+        expected.insert_chunk_ordered(message);
+        assert_eq!(written, expected.as_bytes());
+        //The placeholders aren't taken for damaged chunks, damaged pixel data is found
+        assert_eq!(skimmed.validate().unwrap(), []);
+        let mut damaged = written;
+        let end = damaged.len() - 13;
+        damaged[end] ^= 1;
+        let mut skimmed = Png::from_reader_skip_pixel_data(Cursor::new(damaged)).unwrap();
+        let issues = skimmed.validate().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].code, IssueCode::InvalidCrc);
+    }
+
+    #[test]
+    pub fn test_skip_pixel_data_changed() {
+        let bytes = PngBuilder::new(4, 4).build().unwrap().as_bytes();
+        let mut skimmed = Png::from_reader_skip_pixel_data(Cursor::new(bytes)).unwrap();
+        skimmed.png_mut().remove_first_chunk("IDAT").unwrap();
+        assert!(skimmed.write_to(Vec::new()).is_err());
+    }
+
+    #[test]
+    pub fn test_png_trait_impls() {
+        let _png_string = format!("{}", testing_png());
+    }
+}
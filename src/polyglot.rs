@@ -0,0 +1,263 @@
+/*!
+ * Files that are a PNG and a ZIP archive at once. Decoders stop reading at IEND while
+ * ZIP readers start from the end of central directory record at the end of the file,
+ * so an archive appended to an image opens as both. The offsets the archive records
+ * count from the start of the file, `create` moves them past the image so that strict
+ * ZIP readers find every entry too. ZIP64 archives aren't supported.
+ */
+use crate::png::Png;
+use crate::trailing::{self, TrailingError};
+use crate::Error;
+use std::fmt;
+
+const LOCAL_HEADER_SIGNATURE: [u8; 4] = [b'P', b'K', 3, 4];
+const CENTRAL_HEADER_SIGNATURE: [u8; 4] = [b'P', b'K', 1, 2];
+const END_SIGNATURE: [u8; 4] = [b'P', b'K', 5, 6];
+const ZIP64_LOCATOR_SIGNATURE: [u8; 4] = [b'P', b'K', 6, 7];
+const CENTRAL_HEADER_LENGTH: usize = 46;
+const END_LENGTH: usize = 22;
+const ZIP64_LOCATOR_LENGTH: usize = 20;
+
+//A ZIP archive found in a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Archive {
+    //Where its first entry starts in the file
+    pub offset: usize,
+    pub entries: usize,
+}
+
+//Where the offsets recorded by an archive are and what they point to
+struct Directory {
+    //Positions of the local header offsets of the central directory entries, and of
+    //the central directory offset in the end record
+    entry_offsets: Vec<usize>,
+    directory_offset: usize,
+    //Where the central directory starts
+    start: usize,
+    //What the recorded offsets are relative to, 0 when they are right
+    base: usize,
+    //Where the entries start, from their recorded offsets
+    locals: Vec<usize>,
+}
+
+/**
+ * Appends `archive` to the PNG file `png`, fixing the offsets of the archive so that
+ * the result is a valid ZIP file. The PNG must not have data after IEND already.
+ */
+pub fn create(png: &[u8], archive: &[u8]) -> Result<Vec<u8>, Error> {
+    let (image, rest) = trailing::split(png)?;
+    if !rest.is_empty() {
+        return Err(Box::new(TrailingError::TrailingDataExists));
+    }
+    Png::try_from(image)?;
+    let directory = directory(archive)?;
+    let shift = image.len() + directory.base;
+    let mut bytes = Vec::with_capacity(image.len() + archive.len());
+    bytes.extend_from_slice(image);
+    bytes.extend_from_slice(archive);
+    for &position in &directory.entry_offsets {
+        let offset = read_u32(archive, position).ok_or(PolyglotError::InvalidArchive)?;
+        write_offset(&mut bytes[image.len()..], position, offset as usize + shift)?;
+    }
+    write_offset(
+        &mut bytes[image.len()..],
+        directory.directory_offset,
+        image.len() + directory.start,
+    )?;
+    Ok(bytes)
+}
+
+//The ZIP archive ending `bytes`, if there is a readable one
+pub fn find(bytes: &[u8]) -> Option<Archive> {
+    let directory = directory(bytes).ok()?;
+    let offset = directory
+        .locals
+        .iter()
+        .min()
+        .copied()
+        .unwrap_or(directory.start);
+    Some(Archive {
+        offset,
+        entries: directory.locals.len(),
+    })
+}
+
+fn directory(bytes: &[u8]) -> Result<Directory, PolyglotError> {
+    let end = end_record(bytes).ok_or(PolyglotError::NotAnArchive)?;
+    let entries = read_u16(bytes, end + 10).ok_or(PolyglotError::InvalidArchive)?;
+    let size = read_u32(bytes, end + 12).ok_or(PolyglotError::InvalidArchive)?;
+    let recorded = read_u32(bytes, end + 16).ok_or(PolyglotError::InvalidArchive)?;
+    let zip64_locator = end
+        .checked_sub(ZIP64_LOCATOR_LENGTH)
+        .is_some_and(|locator| bytes[locator..].starts_with(&ZIP64_LOCATOR_SIGNATURE));
+    if entries == u16::MAX || size == u32::MAX || recorded == u32::MAX || zip64_locator {
+        return Err(PolyglotError::Zip64);
+    }
+    //The central directory comes right before the end record
+    let start = end
+        .checked_sub(size as usize)
+        .ok_or(PolyglotError::InvalidArchive)?;
+    let base = start
+        .checked_sub(recorded as usize)
+        .ok_or(PolyglotError::InvalidArchive)?;
+    let mut directory = Directory {
+        entry_offsets: Vec::with_capacity(entries as usize),
+        directory_offset: end + 16,
+        start,
+        base,
+        locals: Vec::with_capacity(entries as usize),
+    };
+    let mut position = start;
+    for _ in 0..entries {
+        let header = bytes
+            .get(position..position + CENTRAL_HEADER_LENGTH)
+            .filter(|header| header.starts_with(&CENTRAL_HEADER_SIGNATURE))
+            .ok_or(PolyglotError::InvalidArchive)?;
+        let variable: usize = [28, 30, 32]
+            .iter()
+            .map(|&field| u16::from_le_bytes([header[field], header[field + 1]]) as usize)
+            .sum();
+        let offset = u32::from_le_bytes([header[42], header[43], header[44], header[45]]);
+        if offset == u32::MAX {
+            return Err(PolyglotError::Zip64);
+        }
+        let local = base + offset as usize;
+        if !bytes
+            .get(local..)
+            .is_some_and(|local| local.starts_with(&LOCAL_HEADER_SIGNATURE))
+        {
+            return Err(PolyglotError::InvalidArchive);
+        }
+        directory.entry_offsets.push(position + 42);
+        directory.locals.push(local);
+        position += CENTRAL_HEADER_LENGTH + variable;
+    }
+    if position > end {
+        return Err(PolyglotError::InvalidArchive);
+    }
+    Ok(directory)
+}
+
+//The end of central directory record, searched from the end past the longest comment
+fn end_record(bytes: &[u8]) -> Option<usize> {
+    let last = bytes.len().checked_sub(END_LENGTH)?;
+    let first = last.saturating_sub(u16::MAX as usize);
+    (first..=last).rev().find(|&position| {
+        bytes[position..].starts_with(&END_SIGNATURE)
+            && read_u16(bytes, position + 20) == Some((bytes.len() - position - END_LENGTH) as u16)
+    })
+}
+
+fn read_u16(bytes: &[u8], position: usize) -> Option<u16> {
+    let field = bytes.get(position..position + 2)?;
+    Some(u16::from_le_bytes([field[0], field[1]]))
+}
+
+fn read_u32(bytes: &[u8], position: usize) -> Option<u32> {
+    let field = bytes.get(position..position + 4)?;
+    Some(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+}
+
+fn write_offset(bytes: &mut [u8], position: usize, offset: usize) -> Result<(), PolyglotError> {
+    //u32::MAX would mark a ZIP64 offset
+    let offset = u32::try_from(offset)
+        .ok()
+        .filter(|&offset| offset != u32::MAX)
+        .ok_or(PolyglotError::TooLarge)?;
+    bytes[position..position + 4].copy_from_slice(&offset.to_le_bytes());
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum PolyglotError {
+    NotAnArchive,
+    InvalidArchive,
+    Zip64,
+    TooLarge,
+}
+
+impl std::error::Error for PolyglotError {}
+
+impl fmt::Display for PolyglotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAnArchive => {
+                write!(f, "no end of central directory record, not a ZIP archive")
+            }
+            Self::InvalidArchive => {
+                write!(f, "the central directory of the ZIP archive is damaged")
+            }
+            Self::Zip64 => write!(f, "ZIP64 archives aren't supported"),
+            Self::TooLarge => write!(f, "the image and the archive are too large for ZIP offsets"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+
+    //A stored entry, its CRC left out
+    fn zip(name: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut sizes = Vec::new();
+        sizes.extend_from_slice(&[0; 4]);
+        sizes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        sizes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        sizes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        sizes.extend_from_slice(&[0; 2]);
+        let mut bytes = LOCAL_HEADER_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&sizes);
+        bytes.extend_from_slice(name);
+        bytes.extend_from_slice(data);
+        let start = bytes.len();
+        bytes.extend_from_slice(&CENTRAL_HEADER_SIGNATURE);
+        bytes.extend_from_slice(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&sizes);
+        bytes.extend_from_slice(&[0; 14]);
+        bytes.extend_from_slice(name);
+        let size = bytes.len() - start;
+        bytes.extend_from_slice(&END_SIGNATURE);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+        bytes.extend_from_slice(&(size as u32).to_le_bytes());
+        bytes.extend_from_slice(&(start as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    }
+
+    #[test]
+    pub fn test_create_and_find() {
+        let png = PngBuilder::new(2, 2).build().unwrap().as_bytes();
+        let archive = zip(b"hello.txt", b"hello");
+        assert_eq!(
+            find(&archive),
+            Some(Archive {
+                offset: 0,
+                entries: 1
+            })
+        );
+        let polyglot = create(&png, &archive).unwrap();
+        assert_eq!(trailing::split(&polyglot).unwrap().0, png.as_slice());
+        assert_eq!(
+            find(&polyglot),
+            Some(Archive {
+                offset: png.len(),
+                entries: 1
+            })
+        );
+        //Its offsets now count from the start of the file
+        assert_eq!(directory(&polyglot).ok().map(|found| found.base), Some(0));
+        let mut appended = png.clone();
+        appended.extend_from_slice(&archive);
+        assert_eq!(
+            directory(&appended).ok().map(|found| found.base),
+            Some(png.len())
+        );
+
+        assert!(create(&png, b"not an archive").is_err());
+        assert!(create(&polyglot, &archive).is_err());
+        assert!(create(&archive, &archive).is_err());
+        assert_eq!(find(&png), None);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::chunk::{Chunk, CRC_32};
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::Error;
+use bytes::Bytes;
+use std::io::Read;
+use std::ops::Range;
+
+//What could be salvaged from a damaged PNG
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    pub png: Png,
+    //Byte ranges of the input that couldn't be read as part of any chunk
+    pub skipped: Vec<Range<usize>>,
+}
+
+impl Png {
+    /**
+     * Reads as many chunks as possible from a truncated or corrupted PNG.
+     * A chunk is only accepted when its type bytes are valid and its CRC matches.
+     * When a length field is garbage, the real end of the chunk is searched by looking
+     * for a matching CRC, and failing that the input is scanned forward up to the next
+     * plausible chunk boundary.
+     */
+    pub fn recover<R: Read>(mut reader: R) -> Result<Recovery, Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let bytes = Bytes::from(bytes);
+
+        let mut offset = if bytes.starts_with(&Png::STANDARD_HEADER) {
+            Png::STANDARD_HEADER.len()
+        } else {
+            0
+        };
+        let mut chunks = Vec::new();
+        let mut skipped: Vec<Range<usize>> = Vec::new();
+        while offset < bytes.len() {
+            if let Some((chunk, size)) = intact_chunk(&bytes, offset) {
+                chunks.push(chunk);
+                offset += size;
+                continue;
+            }
+            if let Some((chunk, size)) = chunk_with_bad_length(&bytes, offset) {
+                chunks.push(chunk);
+                offset += size;
+                continue;
+            }
+
+            let next = (offset + 1..bytes.len())
+                .find(|&start| intact_chunk(&bytes, start).is_some())
+                .unwrap_or(bytes.len());
+            match skipped.last_mut() {
+                Some(range) if range.end == offset => range.end = next,
+                _ => skipped.push(offset..next),
+            }
+            offset = next;
+        }
+
+        Ok(Recovery {
+            png: Png::from_chunks(chunks),
+            skipped,
+        })
+    }
+}
+
+//Parses the chunk starting at `offset` if it is fully intact
+fn intact_chunk(bytes: &Bytes, offset: usize) -> Option<(Chunk, usize)> {
+    chunk_type_at(bytes, offset)?;
+    let (chunk, size) = Chunk::from_shared(&bytes.slice(offset..)).ok()?;
+    if chunk.is_crc_valid() {
+        Some((chunk, size))
+    } else {
+        None
+    }
+}
+
+/**
+ * Looks for the end of a chunk with a valid type whose length field is damaged,
+ * by finding the first position followed by the CRC of everything read so far.
+ */
+fn chunk_with_bad_length(bytes: &Bytes, offset: usize) -> Option<(Chunk, usize)> {
+    let chunk_type = chunk_type_at(bytes, offset)?;
+    let data_start = offset + 8;
+    let mut digest = CRC_32.digest();
+    digest.update(&chunk_type.bytes());
+    let mut end = data_start;
+    while end + 4 <= bytes.len() && end - data_start <= Chunk::MAX_LENGTH as usize {
+        let crc = u32::from_be_bytes(bytes[end..end + 4].try_into().ok()?);
+        if digest.clone().finalize() == crc {
+            let data = bytes[data_start..end].to_vec();
+            return Some((Chunk::new(chunk_type, data), end + 4 - offset));
+        }
+        digest.update(&bytes[end..end + 1]);
+        end += 1;
+    }
+    None
+}
+
+//The chunk type at `offset`, if its bytes form a valid chunk type
+fn chunk_type_at(bytes: &[u8], offset: usize) -> Option<ChunkType> {
+    let type_bytes: [u8; 4] = bytes.get(offset + 4..offset + 8)?.try_into().ok()?;
+    let chunk_type = ChunkType::try_from(type_bytes).ok()?;
+    if chunk_type.is_valid() {
+        Some(chunk_type)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let mut png = PngBuilder::new(4, 4).build().unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        png.insert_chunk_ordered(Chunk::new(chunk_type, b"hidden message".to_vec()));
+        png.as_bytes()
+    }
+
+    fn message(recovery: &Recovery) -> Option<String> {
+        recovery
+            .png
+            .chunk_by_type("ruSt")
+            .map(|chunk| chunk.data_as_string().unwrap())
+    }
+
+    #[test]
+    pub fn test_recover_intact() {
+        let bytes = testing_bytes();
+        let recovery = Png::recover(bytes.as_slice()).unwrap();
+        assert!(recovery.skipped.is_empty());
+        assert_eq!(recovery.png.as_bytes(), bytes);
+    }
+
+    #[test]
+    pub fn test_recover_garbage_length() {
+        let mut bytes = testing_bytes();
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        //IDAT length field, right after the signature and IHDR
+        let idat = 8 + png.chunks()[0].as_bytes().len();
+        bytes[idat..idat + 4].copy_from_slice(&[0x7f, 0xff, 0xff, 0xff]);
+
+        let recovery = Png::recover(bytes.as_slice()).unwrap();
+        assert!(recovery.skipped.is_empty());
+        assert_eq!(recovery.png.chunks().len(), 4);
+        assert_eq!(message(&recovery).unwrap(), "hidden message");
+    }
+
+    #[test]
+    pub fn test_recover_skips_garbage() {
+        let mut bytes = testing_bytes();
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        let idat = 8 + png.chunks()[0].as_bytes().len();
+        //Damage the IDAT type so no chunk can be found there
+        bytes[idat + 4] = b'1';
+
+        let recovery = Png::recover(bytes.as_slice()).unwrap();
+        assert_eq!(recovery.skipped.len(), 1);
+        assert_eq!(recovery.skipped[0].start, idat);
+        assert_eq!(message(&recovery).unwrap(), "hidden message");
+    }
+
+    #[test]
+    pub fn test_recover_truncated() {
+        let bytes = testing_bytes();
+        let recovery = Png::recover(&bytes[..bytes.len() - 6]).unwrap();
+        assert_eq!(message(&recovery).unwrap(), "hidden message");
+        assert!(recovery.png.chunk_by_type("IEND").is_none());
+        assert_eq!(recovery.skipped.len(), 1);
+    }
+}
@@ -0,0 +1,311 @@
+/*!
+ * Copies of a payload in several places of a file, so that an edit losing some of them
+ * leaves the others: chunks of the payload type before the pixel data and before IEND,
+ * the low bits of the colour samples and the bytes after IEND. Editors drop unknown
+ * chunks and trailing data while any change to the pixels wipes their low bits, an edit
+ * rarely loses all of them.
+ * A copy starts with `COPY_MAGIC`, its number counted from 1, the number of copies and
+ * the CRC-32 of the payload, which tells a damaged copy from an intact one.
+ */
+use crate::chunk::{Chunk, CRC_32};
+use crate::chunk_type::ChunkType;
+use crate::png::{Png, PngError};
+use crate::stego::{self, Channels};
+use crate::trailing;
+use crate::Error;
+use std::fmt;
+
+pub const COPY_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'C'];
+const HEADER_LENGTH: usize = COPY_MAGIC.len() + 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    BeforeData,
+    BeforeEnd,
+    Pixels,
+    AfterIend,
+}
+
+impl Location {
+    //In the order copies are stored and read
+    pub const ALL: [Location; 4] = [
+        Location::BeforeData,
+        Location::BeforeEnd,
+        Location::Pixels,
+        Location::AfterIend,
+    ];
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BeforeData => write!(f, "the chunk before the pixel data"),
+            Self::BeforeEnd => write!(f, "the chunk before IEND"),
+            Self::Pixels => write!(f, "the pixels"),
+            Self::AfterIend => write!(f, "the bytes after IEND"),
+        }
+    }
+}
+
+//The payload of the first intact copy
+#[derive(Debug, Clone)]
+pub struct Recovered {
+    pub payload: Vec<u8>,
+    pub location: Location,
+    //Copies found damaged before it, in the order they were read
+    pub damaged: Vec<Location>,
+}
+
+pub fn wrap(payload: &[u8], index: u8, count: u8) -> Vec<u8> {
+    let mut copy = Vec::with_capacity(HEADER_LENGTH + payload.len());
+    copy.extend_from_slice(&COPY_MAGIC);
+    copy.extend_from_slice(&[index, count]);
+    copy.extend_from_slice(&CRC_32.checksum(payload).to_be_bytes());
+    copy.extend_from_slice(payload);
+    copy
+}
+
+//The payload of a copy, failing when it isn't one or its checksum doesn't match
+pub fn unwrap(copy: &[u8]) -> Result<&[u8], Error> {
+    if !copy.starts_with(&COPY_MAGIC) {
+        return Err(Box::new(RedundancyError::NotACopy));
+    }
+    let header = copy.get(..HEADER_LENGTH).ok_or(RedundancyError::Damaged)?;
+    let crc = u32::from_be_bytes([header[6], header[7], header[8], header[9]]);
+    let payload = &copy[HEADER_LENGTH..];
+    if CRC_32.checksum(payload) != crc {
+        return Err(Box::new(RedundancyError::Damaged));
+    }
+    Ok(payload)
+}
+
+/**
+ * The file `png` with `count` copies of `payload` in the first locations of
+ * `Location::ALL` it has room for, the pixels of animated images and of those too small
+ * for the payload left out. The copy in the pixels is scattered with `key` when given.
+ * Returns the bytes of the file and the locations of the copies.
+ */
+pub fn store(
+    mut png: Png,
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    count: u8,
+    key: Option<&[u8]>,
+) -> Result<(Vec<u8>, Vec<Location>), Error> {
+    if chunk_type.is_critical() {
+        return Err(Box::new(RedundancyError::CriticalChunk(
+            chunk_type.to_string(),
+        )));
+    }
+    if count == 0 {
+        return Err(Box::new(RedundancyError::NoCopies));
+    }
+    if png.chunk_by_type(&chunk_type.to_string()).is_some() {
+        return Err(Box::new(PngError::ChunkExists(chunk_type.to_string())));
+    }
+    let salt = if key.is_some() { stego::SALT_LENGTH } else { 0 };
+    let length = (HEADER_LENGTH + payload.len() + salt) as u64;
+    let pixels = !png.is_animated()
+        && stego::capacity(&png.header()?).is_ok_and(|capacity| capacity >= length);
+    let locations: Vec<Location> = Location::ALL
+        .into_iter()
+        .filter(|&location| location != Location::Pixels || pixels)
+        .take(count as usize)
+        .collect();
+    if locations.len() < count as usize {
+        return Err(Box::new(RedundancyError::TooManyCopies(
+            count,
+            locations.len(),
+        )));
+    }
+    let copies: Vec<(Location, Vec<u8>)> = locations
+        .iter()
+        .zip(1..)
+        .map(|(&location, index)| (location, wrap(payload, index, count)))
+        .collect();
+    //Before the chunks, setting the pixels writes the pixel data again
+    if let Some((_, copy)) = copies
+        .iter()
+        .find(|(location, _)| *location == Location::Pixels)
+    {
+        png.embed_lsb(copy, Channels::Colour, key)?;
+    }
+    let mut trailing = Vec::new();
+    for (location, copy) in copies {
+        let chunk = || Chunk::new(chunk_type.clone(), copy.clone());
+        match location {
+            Location::BeforeData => {
+                let index = data_position(&png);
+                png.chunks_mut().insert(index, chunk());
+            }
+            Location::BeforeEnd => {
+                let index = png
+                    .chunks()
+                    .iter()
+                    .position(|chunk| *chunk.chunk_type() == ChunkType::IEND)
+                    .unwrap_or(png.chunks().len());
+                png.chunks_mut().insert(index, chunk());
+            }
+            Location::Pixels => {}
+            Location::AfterIend => trailing = copy,
+        }
+    }
+    let mut bytes = png.as_bytes();
+    bytes.extend_from_slice(&trailing);
+    Ok((bytes, locations))
+}
+
+/**
+ * The payload from the first intact copy in a file written by `store`, reading the
+ * chunks of `chunk_type`, then the pixels with `key`, then the bytes after IEND.
+ * Damaged chunks are skipped, so are the bytes of a truncated file.
+ */
+pub fn recover(bytes: &[u8], chunk_type: &str, key: Option<&[u8]>) -> Result<Recovered, Error> {
+    let (image, rest) = trailing::split(bytes).unwrap_or((bytes, &[]));
+    let png = Png::recover(image)?.png;
+    let mut damaged = Vec::new();
+    let mut found = |location: Location, copy: &[u8]| match unwrap(copy) {
+        Ok(payload) => Some(Recovered {
+            payload: payload.to_vec(),
+            location,
+            damaged: damaged.clone(),
+        }),
+        Err(_) => {
+            damaged.push(location);
+            None
+        }
+    };
+    let data = data_position(&png);
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if chunk.chunk_type().to_string() != chunk_type || !chunk.data().starts_with(&COPY_MAGIC) {
+            continue;
+        }
+        let location = if index < data {
+            Location::BeforeData
+        } else {
+            Location::BeforeEnd
+        };
+        if let Some(recovered) = found(location, chunk.data()) {
+            return Ok(recovered);
+        }
+    }
+    //Only images with intact pixel data give the copy back
+    if let Ok(copy) = png.extract_lsb(Channels::Colour, key) {
+        if copy.starts_with(&COPY_MAGIC) {
+            if let Some(recovered) = found(Location::Pixels, &copy) {
+                return Ok(recovered);
+            }
+        }
+    }
+    if rest.starts_with(&COPY_MAGIC) {
+        if let Some(recovered) = found(Location::AfterIend, rest) {
+            return Ok(recovered);
+        }
+    }
+    match damaged.len() {
+        0 => Err(Box::new(RedundancyError::NoCopies)),
+        count => Err(Box::new(RedundancyError::AllDamaged(count))),
+    }
+}
+
+//Where the chunks before the pixel data go, before the first fcTL of an animated image
+fn data_position(png: &Png) -> usize {
+    png.chunks()
+        .iter()
+        .position(|chunk| [ChunkType::IDAT, ChunkType::fcTL].contains(chunk.chunk_type()))
+        .unwrap_or(png.chunks().len())
+}
+
+#[derive(Debug)]
+pub enum RedundancyError {
+    NoCopies,
+    TooManyCopies(u8, usize),
+    CriticalChunk(String),
+    NotACopy,
+    Damaged,
+    AllDamaged(usize),
+}
+
+impl std::error::Error for RedundancyError {}
+
+impl fmt::Display for RedundancyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoCopies => write!(f, "no copies of a payload found"),
+            Self::TooManyCopies(count, available) => write!(
+                f,
+                "{} copies asked for, the image has room for {}",
+                count, available
+            ),
+            Self::CriticalChunk(chunk_type) => {
+                write!(
+                    f,
+                    "{} is critical, copies need an ancillary type",
+                    chunk_type
+                )
+            }
+            Self::NotACopy => write!(f, "not a copy of a payload"),
+            Self::Damaged => write!(f, "copy checksum doesn't match, it is damaged"),
+            Self::AllDamaged(count) => {
+                write!(f, "all {} copies of the payload found are damaged", count)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+    use std::str::FromStr;
+
+    #[test]
+    pub fn test_store_and_recover() {
+        let png = PngBuilder::new(16, 16)
+            .color(&[90, 120, 150])
+            .build()
+            .unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let (bytes, locations) = store(png.clone(), &chunk_type, b"hello", 4, None).unwrap();
+        assert_eq!(locations, Location::ALL);
+        let recovered = recover(&bytes, "ruSt", None).unwrap();
+        assert_eq!(recovered.payload, b"hello");
+        assert_eq!(recovered.location, Location::BeforeData);
+
+        //The chunks lost and the bytes after IEND damaged
+        let (image, rest) = trailing::split(&bytes).unwrap();
+        let mut stripped = Png::try_from(image).unwrap();
+        stripped.remove_all_chunks("ruSt");
+        let mut edited = stripped.as_bytes();
+        edited.extend_from_slice(rest);
+        let last = edited.len() - 1;
+        edited[last] ^= 1;
+        let recovered = recover(&edited, "ruSt", None).unwrap();
+        assert_eq!(recovered.location, Location::Pixels);
+        stripped
+            .embed_lsb(b"other", Channels::Colour, None)
+            .unwrap();
+        let mut edited = stripped.as_bytes();
+        edited.extend_from_slice(rest);
+        assert_eq!(recover(&edited, "ruSt", None).unwrap().damaged, []);
+        let last = edited.len() - 1;
+        edited[last] ^= 1;
+        assert!(recover(&edited, "ruSt", None).is_err());
+        edited[last] ^= 1;
+        assert_eq!(recover(&edited, "ruSt", None).unwrap().payload, b"hello");
+
+        assert!(store(png.clone(), &chunk_type, &[0; 200], 4, None).is_err());
+        assert_eq!(
+            store(png.clone(), &chunk_type, &[0; 200], 3, None)
+                .unwrap()
+                .1,
+            [
+                Location::BeforeData,
+                Location::BeforeEnd,
+                Location::AfterIend
+            ]
+        );
+        assert!(recover(&png.as_bytes(), "ruSt", None).is_err());
+        assert!(unwrap(b"hello").is_err());
+    }
+}
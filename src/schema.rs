@@ -0,0 +1,258 @@
+/*!
+ * User-defined layouts of private chunk types, so inspect and decode can show their
+ * fields by name. A schema file, TOML or JSON, maps chunk types to their fields in
+ * order:
+ *
+ * ```toml
+ * [lvLs]
+ * #Numbers are big-endian, as in the rest of a PNG, unless set
+ * little_endian = false
+ * fields = [
+ *     { name = "version", type = "u8" },
+ *     { name = "level", type = "string" },
+ *     { name = "score", type = "f32" },
+ *     { name = "checksum", type = "bytes", length = 4 },
+ *     { name = "extra", type = "bytes" },
+ * ]
+ * ```
+ *
+ * Integers are u8 to u64 and i8 to i64, floats f32 and f64. A string ends at a null
+ * byte or with the data. Bytes, shown in hex, take `length` bytes or what is left.
+ */
+use crate::chunk_type::ChunkType;
+use crate::Error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    String,
+    Bytes,
+}
+
+impl FieldType {
+    //Length of numbers, None for strings and bytes
+    fn width(&self) -> Option<usize> {
+        match self {
+            Self::U8 | Self::I8 => Some(1),
+            Self::U16 | Self::I16 => Some(2),
+            Self::U32 | Self::I32 | Self::F32 => Some(4),
+            Self::U64 | Self::I64 | Self::F64 => Some(8),
+            Self::String | Self::Bytes => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    //Only for bytes, the rest of the data when left out
+    pub length: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChunkSchema {
+    #[serde(default)]
+    pub little_endian: bool,
+    pub fields: Vec<FieldSchema>,
+}
+
+impl ChunkSchema {
+    //Named values of the fields in `data`, which must hold them all and nothing more
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<(String, String)>, Error> {
+        let mut rest = data;
+        let mut values = Vec::with_capacity(self.fields.len());
+        for field in &self.fields {
+            let truncated = || Box::new(SchemaError::Truncated(field.name.clone())) as Error;
+            let (value, length) = match (field.field_type, field.field_type.width()) {
+                (field_type, Some(width)) => {
+                    let bytes = rest.get(..width).ok_or_else(truncated)?;
+                    (self.number(field_type, bytes), width)
+                }
+                (FieldType::String, None) => {
+                    let end = rest.iter().position(|&byte| byte == 0);
+                    let text = &rest[..end.unwrap_or(rest.len())];
+                    let value = String::from_utf8_lossy(text).into_owned();
+                    (value, end.map_or(rest.len(), |end| end + 1))
+                }
+                (_, None) => {
+                    let length = field.length.unwrap_or(rest.len());
+                    let bytes = rest.get(..length).ok_or_else(truncated)?;
+                    (hex(bytes), length)
+                }
+            };
+            values.push((field.name.clone(), value));
+            rest = &rest[length..];
+        }
+        if !rest.is_empty() {
+            return Err(Box::new(SchemaError::TrailingBytes(rest.len())));
+        }
+        Ok(values)
+    }
+
+    fn number(&self, field_type: FieldType, bytes: &[u8]) -> String {
+        //Widened to 64 bits little-endian, then sign-extended for signed types
+        let mut wide = [0; 8];
+        for (index, &byte) in bytes.iter().enumerate() {
+            let position = if self.little_endian {
+                index
+            } else {
+                bytes.len() - 1 - index
+            };
+            wide[position] = byte;
+        }
+        let unsigned = u64::from_le_bytes(wide);
+        let shift = 64 - 8 * bytes.len() as u32;
+        let signed = ((unsigned << shift) as i64) >> shift;
+        match field_type {
+            FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64 => signed.to_string(),
+            FieldType::F32 => f32::from_bits(unsigned as u32).to_string(),
+            FieldType::F64 => f64::from_bits(unsigned).to_string(),
+            _ => unsigned.to_string(),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+//Every schema of a file, by chunk type
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaSet {
+    schemas: BTreeMap<String, ChunkSchema>,
+}
+
+impl SchemaSet {
+    pub fn get(&self, chunk_type: &ChunkType) -> Option<&ChunkSchema> {
+        self.schemas.get(&chunk_type.to_string())
+    }
+}
+
+//JSON when the text starts with a brace, TOML otherwise
+impl FromStr for SchemaSet {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let schemas: BTreeMap<String, ChunkSchema> = if s.trim_start().starts_with('{') {
+            serde_json::from_str(s).map_err(|err| SchemaError::Invalid(err.to_string()))?
+        } else {
+            toml::from_str(s).map_err(|err| SchemaError::Invalid(err.message().to_string()))?
+        };
+        for (chunk_type, schema) in &schemas {
+            ChunkType::from_str(chunk_type)?;
+            for field in &schema.fields {
+                if field.length.is_some() && field.field_type != FieldType::Bytes {
+                    return Err(Box::new(SchemaError::LengthNotBytes(field.name.clone())));
+                }
+            }
+        }
+        Ok(SchemaSet { schemas })
+    }
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Invalid(String),
+    LengthNotBytes(String),
+    Truncated(String),
+    TrailingBytes(usize),
+}
+
+impl std::error::Error for SchemaError {}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(message) => write!(f, "invalid schema: {}", message),
+            Self::LengthNotBytes(field) => {
+                write!(f, "field {} has a length but isn't bytes", field)
+            }
+            Self::Truncated(field) => write!(f, "chunk data ends before field {}", field),
+            Self::TrailingBytes(count) => {
+                write!(f, "{} bytes left after the last field of the schema", count)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        [lvLs]
+        fields = [
+            { name = "version", type = "u8" },
+            { name = "level", type = "string" },
+            { name = "delta", type = "i16" },
+            { name = "score", type = "f32" },
+            { name = "extra", type = "bytes" },
+        ]
+    "#;
+
+    #[test]
+    pub fn test_decode() {
+        let schemas = SchemaSet::from_str(SCHEMA).unwrap();
+        let schema = schemas.get(&ChunkType::from_str("lvLs").unwrap()).unwrap();
+        let mut data = vec![2];
+        data.extend_from_slice(b"castle\0");
+        data.extend_from_slice(&(-3i16).to_be_bytes());
+        data.extend_from_slice(&1.5f32.to_be_bytes());
+        data.extend_from_slice(&[0xca, 0xfe]);
+        let values = schema.decode(&data).unwrap();
+        let values: Vec<(&str, &str)> = values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("version", "2"),
+                ("level", "castle"),
+                ("delta", "-3"),
+                ("score", "1.5"),
+                ("extra", "cafe"),
+            ]
+        );
+        assert!(schema.decode(&data[..9]).is_err());
+
+        let little = ChunkSchema {
+            little_endian: true,
+            fields: vec![FieldSchema {
+                name: "count".to_string(),
+                field_type: FieldType::U32,
+                length: None,
+            }],
+        };
+        assert_eq!(little.decode(&[1, 1, 0, 0]).unwrap()[0].1, "257");
+        assert!(little.decode(&[1, 1, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    pub fn test_parse_schemas() {
+        let json = r#"{"lvLs": {"fields": [{"name": "version", "type": "u8"}]}}"#;
+        assert!(SchemaSet::from_str(json).is_ok());
+        assert!(SchemaSet::from_str("[toolong]\nfields = []").is_err());
+        let length = "[lvLs]\nfields = [{ name = \"n\", type = \"u8\", length = 2 }]";
+        assert!(SchemaSet::from_str(length).is_err());
+        let unknown = "[lvLs]\nfields = [{ name = \"n\", type = \"u128\" }]";
+        assert!(SchemaSet::from_str(unknown).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+/*!
+ * Searching ancillary chunks for a pattern, as done by `pngme grep`.
+ * Text chunks are searched by value, compressed ones once inflated,
+ * every other ancillary chunk by its raw data.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::text;
+use crate::Error;
+use regex::bytes::Regex;
+use std::borrow::Cow;
+
+//Bytes of context shown on each side of a match
+const CONTEXT: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub chunk_index: usize,
+    pub chunk_type: ChunkType,
+    //Offset of the match in the searched content, the value for text chunks
+    pub offset: usize,
+    pub snippet: String,
+}
+
+/**
+ * Every match of `pattern` in the ancillary chunks, in file order.
+ * Text chunks that fail to inflate, or inflate to more than `text::MAX_INFLATED_LENGTH`,
+ * are searched as raw data instead, with a warning.
+ */
+pub fn search<I>(chunks: I, pattern: &Regex) -> Result<Vec<SearchMatch>, Error>
+where
+    I: IntoIterator<Item = Result<Chunk, Error>>,
+{
+    let mut matches = Vec::new();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let chunk = chunk?;
+        if chunk.chunk_type().is_critical() {
+            continue;
+        }
+        let content = searchable_content(&chunk).unwrap_or(Cow::Borrowed(chunk.data()));
+        for found in pattern.find_iter(&content) {
+            matches.push(SearchMatch {
+                chunk_index: index,
+                chunk_type: chunk.chunk_type().clone(),
+                offset: found.start(),
+                snippet: snippet(&content, found.start(), found.end()),
+            });
+        }
+    }
+    Ok(matches)
+}
+
+//Value of a text chunk, inflated if needed, None for other chunks and broken ones
+fn searchable_content(chunk: &Chunk) -> Option<Cow<'_, [u8]>> {
+    let data = chunk.data();
+    let chunk_type = chunk.chunk_type();
+    let keyword_end = data.iter().position(|&byte| byte == 0)?;
+    let rest = &data[keyword_end + 1..];
+    if *chunk_type == ChunkType::tEXt {
+        Some(Cow::Borrowed(rest))
+    } else if *chunk_type == ChunkType::zTXt {
+        //Compression method byte, then the zlib stream
+        inflate(chunk_type, rest.get(1..)?).map(Cow::Owned)
+    } else if *chunk_type == ChunkType::iTXt {
+        //Compression flag and method, then null terminated language tag and translated keyword
+        let (&compressed, rest) = rest.split_first()?;
+        let rest = rest.get(1..)?;
+        let language_end = rest.iter().position(|&byte| byte == 0)?;
+        let rest = &rest[language_end + 1..];
+        let translated_end = rest.iter().position(|&byte| byte == 0)?;
+        let text = &rest[translated_end + 1..];
+        if compressed == 0 {
+            Some(Cow::Borrowed(text))
+        } else {
+            inflate(chunk_type, text).map(Cow::Owned)
+        }
+    } else {
+        None
+    }
+}
+
+//None once the text doesn't inflate, or inflates to too much
+fn inflate(chunk_type: &ChunkType, compressed: &[u8]) -> Option<Vec<u8>> {
+    match text::inflate(compressed) {
+        Ok(inflated) => Some(inflated),
+        Err(error) => {
+            log::warn!(
+                "searching the raw data of a {} chunk: {}",
+                chunk_type,
+                error
+            );
+            None
+        }
+    }
+}
+
+//The match with some context, unprintable bytes shown as dots
+fn snippet(content: &[u8], start: usize, end: usize) -> String {
+    let from = start.saturating_sub(CONTEXT);
+    let to = (end + CONTEXT).min(content.len());
+    String::from_utf8_lossy(&content[from..to])
+        .chars()
+        .map(|c| if c.is_control() { '.' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    fn found(chunks: Vec<Chunk>, pattern: &str) -> Vec<SearchMatch> {
+        let pattern = Regex::new(pattern).unwrap();
+        search(chunks.into_iter().map(Ok), &pattern).unwrap()
+    }
+
+    #[test]
+    pub fn test_search_raw_chunks() {
+        let chunks = vec![
+            Chunk::new(ChunkType::IDAT, b"secret".to_vec()),
+            Chunk::new(
+                ChunkType::from_str("ruSt").unwrap(),
+                b"a secret note".to_vec(),
+            ),
+        ];
+        let matches = found(chunks, "sec.et");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].chunk_index, 1);
+        assert_eq!(matches[0].offset, 2);
+        assert_eq!(matches[0].snippet, "a secret note");
+    }
+
+    #[test]
+    pub fn test_search_text_values() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hidden in the zlib stream").unwrap();
+        let mut itxt = b"Comment\x00\x01\x00en\x00\x00".to_vec();
+        itxt.extend(encoder.finish().unwrap());
+        let chunks = vec![
+            Chunk::new(ChunkType::tEXt, b"Author\x00Jane".to_vec()),
+            Chunk::new(ChunkType::iTXt, itxt),
+        ];
+        assert_eq!(found(chunks.clone(), "zlib")[0].chunk_type, ChunkType::iTXt);
+        //The keyword isn't part of the value
+        assert!(found(chunks.clone(), "Author").is_empty());
+        assert_eq!(found(chunks, "^Jane$").len(), 1);
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+ * Payloads split into shards stored in several files. The shards are either parts of
+ * the payload, every one needed, or shares of Shamir's secret sharing over GF(256),
+ * any `threshold` of which give the payload back while fewer tell nothing about it,
+ * each as long as the payload.
+ * A shard starts with `SHARD_MAGIC`, 8 random bytes shared by the shards of a payload,
+ * the scheme, 0 for parts and 1 for shares, the index of the shard counted from 1,
+ * the number of shards and the threshold, then its data.
+ */
+use crate::Error;
+use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
+use std::fmt;
+
+pub const SHARD_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'S'];
+const HEADER_LENGTH: usize = SHARD_MAGIC.len() + 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Parts,
+    Shamir,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shard {
+    //The same for every shard of a payload
+    pub set: [u8; 8],
+    pub scheme: Scheme,
+    //From 1, the x coordinate of a share
+    pub index: u8,
+    pub count: u8,
+    //Shards needed to rebuild the payload, `count` for parts
+    pub threshold: u8,
+    pub data: Vec<u8>,
+}
+
+impl Shard {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + self.data.len());
+        bytes.extend_from_slice(&SHARD_MAGIC);
+        bytes.extend_from_slice(&self.set);
+        bytes.push(match self.scheme {
+            Scheme::Parts => 0,
+            Scheme::Shamir => 1,
+        });
+        bytes.extend_from_slice(&[self.index, self.count, self.threshold]);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Shard, Error> {
+        if !data.starts_with(&SHARD_MAGIC) {
+            return Err(Box::new(ShardError::NotAShard));
+        }
+        let header = data.get(..HEADER_LENGTH).ok_or(ShardError::Truncated)?;
+        let mut set = [0; 8];
+        set.copy_from_slice(&header[4..12]);
+        let scheme = match header[12] {
+            0 => Scheme::Parts,
+            1 => Scheme::Shamir,
+            scheme => return Err(Box::new(ShardError::UnknownScheme(scheme))),
+        };
+        let (index, count, threshold) = (header[13], header[14], header[15]);
+        if index == 0 || index > count || threshold == 0 || threshold > count {
+            return Err(Box::new(ShardError::Truncated));
+        }
+        Ok(Shard {
+            set,
+            scheme,
+            index,
+            count,
+            threshold,
+            data: data[HEADER_LENGTH..].to_vec(),
+        })
+    }
+}
+
+/**
+ * Splits `payload` into `count` shards: Shamir shares of which any `threshold` are
+ * enough, or with no threshold parts of nearly equal length, all of them needed.
+ */
+pub fn split(payload: &[u8], count: u8, threshold: Option<u8>) -> Result<Vec<Shard>, Error> {
+    if count == 0 {
+        return Err(Box::new(ShardError::NoShards));
+    }
+    let mut set = [0; 8];
+    OsRng.fill_bytes(&mut set);
+    let shard = |index: u8, scheme, threshold, data| Shard {
+        set,
+        scheme,
+        index,
+        count,
+        threshold,
+        data,
+    };
+    let threshold = match threshold {
+        //A single share would be the payload itself
+        Some(threshold) if threshold < 2 || threshold > count => {
+            return Err(Box::new(ShardError::InvalidThreshold(threshold, count)))
+        }
+        Some(threshold) => threshold,
+        None => {
+            let length = payload.len().div_ceil(count as usize);
+            return Ok((1..=count)
+                .map(|index| {
+                    let start = (length * (index as usize - 1)).min(payload.len());
+                    let end = (start + length).min(payload.len());
+                    shard(index, Scheme::Parts, count, payload[start..end].to_vec())
+                })
+                .collect());
+        }
+    };
+    let mut shares: Vec<Vec<u8>> = vec![Vec::with_capacity(payload.len()); count as usize];
+    let mut coefficients = vec![0; threshold as usize - 1];
+    for &byte in payload {
+        OsRng.fill_bytes(&mut coefficients);
+        for (x, share) in (1..=count).zip(&mut shares) {
+            //Horner's rule, from the highest coefficient down to the secret
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |y, &coefficient| multiply(y, x) ^ coefficient);
+            share.push(multiply(y, x) ^ byte);
+        }
+    }
+    Ok(shares
+        .into_iter()
+        .zip(1..=count)
+        .map(|(data, index)| shard(index, Scheme::Shamir, threshold, data))
+        .collect())
+}
+
+//Rebuilds the payload from shards of one `split`, duplicates tolerated
+pub fn combine(shards: &[Shard]) -> Result<Vec<u8>, Error> {
+    let first = shards.first().ok_or(ShardError::NotEnoughShards(0, 1))?;
+    if shards.iter().any(|shard| {
+        (shard.set, shard.scheme, shard.count, shard.threshold)
+            != (first.set, first.scheme, first.count, first.threshold)
+    }) {
+        return Err(Box::new(ShardError::MixedSets));
+    }
+    let mut distinct: Vec<&Shard> = Vec::with_capacity(shards.len());
+    for shard in shards {
+        if !distinct.iter().any(|found| found.index == shard.index) {
+            distinct.push(shard);
+        }
+    }
+    if distinct.len() < first.threshold as usize {
+        let error = ShardError::NotEnoughShards(distinct.len(), first.threshold as usize);
+        return Err(Box::new(error));
+    }
+    distinct.sort_by_key(|shard| shard.index);
+    if first.scheme == Scheme::Parts {
+        return Ok(distinct
+            .iter()
+            .flat_map(|shard| shard.data.clone())
+            .collect());
+    }
+    let shares = &distinct[..first.threshold as usize];
+    let length = shares[0].data.len();
+    if shares.iter().any(|share| share.data.len() != length) {
+        return Err(Box::new(ShardError::MixedSets));
+    }
+    //Lagrange basis polynomials at 0
+    let weights: Vec<u8> = shares
+        .iter()
+        .map(|share| {
+            shares
+                .iter()
+                .filter(|other| other.index != share.index)
+                .fold(1, |weight, other| {
+                    multiply(weight, divide(other.index, other.index ^ share.index))
+                })
+        })
+        .collect();
+    Ok((0..length)
+        .map(|position| {
+            shares
+                .iter()
+                .zip(&weights)
+                .fold(0, |byte, (share, &weight)| {
+                    byte ^ multiply(share.data[position], weight)
+                })
+        })
+        .collect())
+}
+
+//In GF(256) with the AES polynomial
+fn multiply(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 == 1 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+//`b` is never 0, the indexes of two distinct shares differ
+fn divide(a: u8, b: u8) -> u8 {
+    //b^254 is the inverse of b as the multiplicative group has 255 elements
+    let inverse = (0..254).fold(1, |inverse, _| multiply(inverse, b));
+    multiply(a, inverse)
+}
+
+#[derive(Debug)]
+pub enum ShardError {
+    NoShards,
+    InvalidThreshold(u8, u8),
+    NotAShard,
+    Truncated,
+    UnknownScheme(u8),
+    MixedSets,
+    NotEnoughShards(usize, usize),
+}
+
+impl std::error::Error for ShardError {}
+
+impl fmt::Display for ShardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoShards => write!(f, "a payload can't be split into 0 shards"),
+            Self::InvalidThreshold(threshold, count) => write!(
+                f,
+                "threshold {} must be between 2 and the {} shards",
+                threshold, count
+            ),
+            Self::NotAShard => write!(f, "not a shard of a payload"),
+            Self::Truncated => write!(f, "shard header is truncated or invalid"),
+            Self::UnknownScheme(scheme) => write!(f, "unknown shard scheme {}", scheme),
+            Self::MixedSets => write!(f, "the shards belong to different payloads"),
+            Self::NotEnoughShards(found, needed) => write!(
+                f,
+                "{} shard(s) found, {} needed to rebuild the payload",
+                found, needed
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_split_and_combine() {
+        let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let parts = split(&payload, 3, None).unwrap();
+        assert!(parts
+            .iter()
+            .all(|part| part.data.len() == 334 || part.data.len() == 332));
+        let mut reversed = parts.clone();
+        reversed.reverse();
+        assert_eq!(combine(&reversed).unwrap(), payload);
+        assert!(combine(&parts[..2]).is_err());
+
+        let shares = split(&payload, 5, Some(3)).unwrap();
+        let parsed: Vec<Shard> = shares
+            .iter()
+            .map(|share| Shard::parse(&share.to_bytes()).unwrap())
+            .collect();
+        assert_eq!(parsed, shares);
+        assert_eq!(combine(&shares[2..]).unwrap(), payload);
+        assert_eq!(
+            combine(&[shares[4].clone(), shares[0].clone(), shares[2].clone()]).unwrap(),
+            payload
+        );
+        assert!(combine(&[shares[1].clone(), shares[1].clone(), shares[3].clone()]).is_err());
+        assert!(combine(&[shares[0].clone(), parts[0].clone()]).is_err());
+
+        assert!(split(&payload, 2, Some(3)).is_err());
+        assert!(split(&payload, 2, Some(1)).is_err());
+        assert!(Shard::parse(b"other").is_err());
+        assert_eq!(divide(multiply(7, 200), 200), 7);
+    }
+}
@@ -0,0 +1,226 @@
+/*!
+ * Payloads spread over several ancillary chunks of different types, so that no single
+ * large unknown chunk stands out. Each piece goes into a zTXt chunk or a compressed
+ * iTXt one under an ordinary keyword, in base64 since their text has to be printable,
+ * its compressed form being as random as ciphertext, or as is into a private chunk of
+ * a random type.
+ * A manifest lists the pieces in order: `MANIFEST_MAGIC`, then for each piece the type
+ * of its chunk and the CRC of that chunk as a big endian u32, which tells it apart
+ * from the other chunks of its type wherever it ends up in the file.
+ * Encode keeps the manifest encrypted in the `--stealth` chunk type of the password,
+ * so without the password nothing tells which chunks belong together.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::decoy;
+use crate::text;
+use crate::Error;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fmt;
+use std::io::Write;
+
+pub const MANIFEST_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'L'];
+//Chunk type and CRC
+const PIECE_LENGTH: usize = 8;
+//Common enough not to draw attention
+const KEYWORDS: [&str; 5] = ["Comment", "Description", "Disclaimer", "Source", "Warning"];
+const ZLIB_METHOD: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    pub chunk_type: ChunkType,
+    pub crc: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub pieces: Vec<Piece>,
+}
+
+impl Manifest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MANIFEST_MAGIC.len() + self.pieces.len() * PIECE_LENGTH);
+        bytes.extend_from_slice(&MANIFEST_MAGIC);
+        for piece in &self.pieces {
+            bytes.extend_from_slice(&piece.chunk_type.bytes());
+            bytes.extend_from_slice(&piece.crc.to_be_bytes());
+        }
+        bytes
+    }
+
+    //None for data that doesn't start with `MANIFEST_MAGIC`
+    pub fn parse(data: &[u8]) -> Result<Option<Manifest>, Error> {
+        let Some(rest) = data.strip_prefix(&MANIFEST_MAGIC) else {
+            return Ok(None);
+        };
+        if rest.len() % PIECE_LENGTH != 0 {
+            return Err(Box::new(SpreadError::Truncated));
+        }
+        let pieces = rest
+            .chunks_exact(PIECE_LENGTH)
+            .map(|piece| {
+                let (chunk_type, crc) = piece.split_at(4);
+                Ok(Piece {
+                    chunk_type: ChunkType::try_from([
+                        chunk_type[0],
+                        chunk_type[1],
+                        chunk_type[2],
+                        chunk_type[3],
+                    ])?,
+                    crc: u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Some(Manifest { pieces }))
+    }
+}
+
+/**
+ * Splits `payload` into `count` pieces of nearly equal length, each in a chunk of a
+ * randomly picked kind, and the manifest listing them.
+ */
+pub fn spread(payload: &[u8], count: usize) -> Result<(Vec<Chunk>, Manifest), Error> {
+    if count == 0 {
+        return Err(Box::new(SpreadError::NoPieces));
+    }
+    let length = payload.len().div_ceil(count).max(1);
+    let mut chunks = Vec::with_capacity(count);
+    for index in 0..count {
+        let start = (index * length).min(payload.len());
+        let end = (start + length).min(payload.len());
+        chunks.push(carrier(&payload[start..end])?);
+    }
+    let pieces = chunks
+        .iter()
+        .map(|chunk| Piece {
+            chunk_type: chunk.chunk_type().clone(),
+            crc: chunk.crc(),
+        })
+        .collect();
+    Ok((chunks, Manifest { pieces }))
+}
+
+//Puts the pieces listed by `manifest` found among `chunks` back together
+pub fn gather(chunks: &[Chunk], manifest: &Manifest) -> Result<Vec<u8>, Error> {
+    let mut payload = Vec::new();
+    for piece in &manifest.pieces {
+        let chunk = chunks
+            .iter()
+            .find(|chunk| *chunk.chunk_type() == piece.chunk_type && chunk.crc() == piece.crc)
+            .ok_or_else(|| SpreadError::MissingPiece(piece.chunk_type.clone()))?;
+        payload.extend_from_slice(&content(chunk)?);
+    }
+    Ok(payload)
+}
+
+fn carrier(piece: &[u8]) -> Result<Chunk, Error> {
+    let keyword = KEYWORDS[OsRng.next_u32() as usize % KEYWORDS.len()];
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    let chunk_type = match OsRng.next_u32() % 3 {
+        0 => return Ok(Chunk::new(decoy::random_chunk_type(), piece.to_vec())),
+        1 => {
+            data.push(ZLIB_METHOD);
+            ChunkType::zTXt
+        }
+        _ => {
+            //Compressed, no language and no translated keyword
+            data.extend_from_slice(&[1, ZLIB_METHOD, 0, 0]);
+            ChunkType::iTXt
+        }
+    };
+    let mut encoder = ZlibEncoder::new(data, Compression::best());
+    encoder.write_all(BASE64.encode(piece).as_bytes())?;
+    Ok(Chunk::new(chunk_type, encoder.finish()?))
+}
+
+//The piece held by a chunk `carrier` made
+fn content(chunk: &Chunk) -> Result<Vec<u8>, Error> {
+    let chunk_type = chunk.chunk_type();
+    let invalid = || Box::new(SpreadError::InvalidCarrier(chunk_type.clone())) as Error;
+    //Past the keyword and its null separator
+    let rest = chunk
+        .data()
+        .iter()
+        .position(|&byte| byte == 0)
+        .map(|end| &chunk.data()[end + 1..]);
+    let compressed = if *chunk_type == ChunkType::zTXt {
+        rest.and_then(|rest| rest.strip_prefix(&[ZLIB_METHOD]))
+    } else if *chunk_type == ChunkType::iTXt {
+        rest.and_then(|rest| rest.strip_prefix(&[1, ZLIB_METHOD, 0, 0]))
+    } else {
+        return Ok(chunk.data().to_vec());
+    };
+    let text = text::inflate(compressed.ok_or_else(invalid)?)?;
+    BASE64.decode(text).map_err(|_| invalid())
+}
+
+#[derive(Debug)]
+pub enum SpreadError {
+    NoPieces,
+    Truncated,
+    MissingPiece(ChunkType),
+    InvalidCarrier(ChunkType),
+}
+
+impl std::error::Error for SpreadError {}
+
+impl fmt::Display for SpreadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoPieces => write!(f, "a payload can't be spread over 0 chunks"),
+            Self::Truncated => write!(f, "spread payload manifest is truncated"),
+            Self::MissingPiece(chunk_type) => {
+                write!(
+                    f,
+                    "a piece of the payload in a {} chunk is missing",
+                    chunk_type
+                )
+            }
+            Self::InvalidCarrier(chunk_type) => {
+                write!(
+                    f,
+                    "{} chunk doesn't hold a piece of the payload",
+                    chunk_type
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_spread_and_gather() {
+        let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let (mut chunks, manifest) = spread(&payload, 7).unwrap();
+        assert_eq!(chunks.len(), 7);
+        assert!(chunks
+            .iter()
+            .all(|chunk| !chunk.chunk_type().is_critical() && chunk.length() < 200));
+        //Text chunks hold printable text like any other
+        let texts = crate::ops::read_international_text(chunks.iter().cloned().map(Ok)).unwrap();
+        assert!(texts
+            .iter()
+            .all(|entry| entry.text.bytes().all(|byte| byte.is_ascii_graphic())));
+        let parsed = Manifest::parse(&manifest.to_bytes()).unwrap().unwrap();
+        assert_eq!(parsed, manifest);
+
+        //Found wherever they are and among other chunks
+        chunks.reverse();
+        chunks.push(Chunk::new(ChunkType::zTXt, b"Comment\0\0x".to_vec()));
+        assert_eq!(gather(&chunks, &manifest).unwrap(), payload);
+        chunks.remove(3);
+        assert!(gather(&chunks, &manifest).is_err());
+
+        assert!(spread(&payload, 0).is_err());
+        assert_eq!(Manifest::parse(b"other").unwrap(), None);
+        assert!(Manifest::parse(&manifest.to_bytes()[..10]).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+/*!
+ * Chunk usage statistics over one or many files, as reported by `pngme stats`.
+ */
+use crate::analyze;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::fragment;
+use crate::png::Png;
+use crate::Error;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TypeStats {
+    pub count: usize,
+    //Chunk data only, without length, type and CRC
+    pub bytes: u64,
+    //Entropy of each chunk times its length, summed
+    entropy_bits: f64,
+}
+
+impl TypeStats {
+    //Shannon entropy of the chunks in bits per byte, weighted by their length
+    pub fn entropy(&self) -> f64 {
+        if self.bytes == 0 {
+            return 0.0;
+        }
+        self.entropy_bits / self.bytes as f64
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub files: usize,
+    //Whole file sizes, signature and chunk metadata included
+    pub total_bytes: u64,
+    pub critical_chunks: usize,
+    pub ancillary_chunks: usize,
+    pub by_type: BTreeMap<String, TypeStats>,
+}
+
+//Why a chunk might carry hidden data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Reason {
+    //Private chunks have no meaning to anything but the program that wrote them
+    Private,
+    //Public but not defined by the spec or a registered extension
+    Unregistered,
+    //Payload split across several chunks, see `fragment`
+    Fragmented,
+    //Decoders stop at IEND so anything after it is invisible
+    AfterIend,
+    //Close to random data in a type that isn't compressed, see `analyze::is_near_random`
+    HighEntropy,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::Private => "private chunk",
+            Self::Unregistered => "unregistered public chunk",
+            Self::Fragmented => "payload split across chunks",
+            Self::AfterIend => "after IEND",
+            Self::HighEntropy => "near 8 bits/byte entropy",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Indicator {
+    pub chunk_type: ChunkType,
+    pub reason: Reason,
+    //Chunks of the type flagged for this reason
+    pub count: usize,
+}
+
+impl Stats {
+    /**
+     * Adds the chunks of one file and returns the likely hidden data indicators found
+     * in it, one per chunk type and reason, in order of first appearance.
+     */
+    pub fn add<I>(&mut self, chunks: I) -> Result<Vec<Indicator>, Error>
+    where
+        I: IntoIterator<Item = Result<Chunk, Error>>,
+    {
+        let mut indicators: Vec<Indicator> = Vec::new();
+        let mut flag = |chunk_type: &ChunkType, reason: Reason| match indicators
+            .iter_mut()
+            .find(|indicator| indicator.chunk_type == *chunk_type && indicator.reason == reason)
+        {
+            Some(indicator) => indicator.count += 1,
+            None => indicators.push(Indicator {
+                chunk_type: chunk_type.clone(),
+                reason,
+                count: 1,
+            }),
+        };
+        let mut total_bytes = Png::STANDARD_HEADER.len() as u64;
+        let mut after_iend = false;
+        for chunk in chunks {
+            let chunk = chunk?;
+            let chunk_type = chunk.chunk_type();
+            let entropy = analyze::entropy(chunk.data());
+            total_bytes += chunk.length() as u64 + Chunk::METADATA_LENGTH as u64;
+            let stats = self.by_type.entry(chunk_type.to_string()).or_default();
+            stats.count += 1;
+            stats.bytes += chunk.length() as u64;
+            stats.entropy_bits += entropy * chunk.length() as f64;
+            if chunk_type.is_critical() {
+                self.critical_chunks += 1;
+            } else {
+                self.ancillary_chunks += 1;
+            }
+
+            if after_iend {
+                flag(chunk_type, Reason::AfterIend);
+            }
+            if !chunk_type.is_public() {
+                flag(chunk_type, Reason::Private);
+            } else if !chunk_type.is_registered() {
+                flag(chunk_type, Reason::Unregistered);
+            }
+            if fragment::parse_part(chunk.data()).is_some() {
+                flag(chunk_type, Reason::Fragmented);
+            }
+            if analyze::is_near_random(&chunk, entropy) {
+                flag(chunk_type, Reason::HighEntropy);
+            }
+            after_iend |= *chunk_type == ChunkType::IEND;
+        }
+        self.files += 1;
+        self.total_bytes += total_bytes;
+        Ok(indicators)
+    }
+
+    //Share of the bytes that aren't compressed pixel data, from 0 to 1
+    pub fn overhead(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+        let pixels = self
+            .by_type
+            .get("IDAT")
+            .map(|stats| stats.bytes)
+            .unwrap_or(0);
+        (self.total_bytes - pixels) as f64 / self.total_bytes as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+    use std::str::FromStr;
+
+    #[test]
+    pub fn test_stats() {
+        let mut png = PngBuilder::new(2, 2).build().unwrap();
+        png.insert_payload(&ChunkType::from_str("ruSt").unwrap(), &[1; 20], 8)
+            .unwrap();
+        let mut stats = Stats::default();
+        let indicators = stats.add(png.chunks().iter().cloned().map(Ok)).unwrap();
+        stats.add(png.chunks().iter().cloned().map(Ok)).unwrap();
+
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.by_type["ruSt"].count, 6);
+        assert_eq!(stats.ancillary_chunks, 6);
+        assert_eq!(stats.total_bytes, 2 * png.as_bytes().len() as u64);
+        assert!(stats.overhead() > 0.0 && stats.overhead() < 1.0);
+        assert_eq!(
+            indicators,
+            vec![
+                Indicator {
+                    chunk_type: ChunkType::from_str("ruSt").unwrap(),
+                    reason: Reason::Private,
+                    count: 3
+                },
+                Indicator {
+                    chunk_type: ChunkType::from_str("ruSt").unwrap(),
+                    reason: Reason::Fragmented,
+                    count: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_after_iend() {
+        let mut chunks = PngBuilder::new(2, 2).build().unwrap().chunks().to_vec();
+        chunks.push(Chunk::new(ChunkType::tEXt, b"Comment\0late".to_vec()));
+        let indicators = Stats::default().add(chunks.into_iter().map(Ok)).unwrap();
+        assert_eq!(indicators.len(), 1);
+        assert_eq!(indicators[0].reason, Reason::AfterIend);
+    }
+
+    #[test]
+    pub fn test_high_entropy() {
+        let mut chunks = PngBuilder::new(2, 2).build().unwrap().chunks().to_vec();
+        chunks.insert(1, Chunk::new(ChunkType::eXIf, (0..=255).collect()));
+        chunks.insert(1, Chunk::new(ChunkType::zTXt, (0..=255).collect()));
+        let mut stats = Stats::default();
+        let indicators = stats.add(chunks.into_iter().map(Ok)).unwrap();
+        assert_eq!(indicators.len(), 1);
+        assert_eq!(indicators[0].chunk_type, ChunkType::eXIf);
+        assert_eq!(indicators[0].reason, Reason::HighEntropy);
+        assert!((stats.by_type["eXIf"].entropy() - 8.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,539 @@
+/*!
+ * Payloads hidden in the pixels rather than in chunks of their own, so they survive
+ * tools that strip unknown ancillary chunks, though not lossy conversions or edits
+ * of the image.
+ *
+ * The least significant bit of every colour sample, row after row and alpha left
+ * alone, holds a 32 bit big-endian payload length then the payload, most significant
+ * bit first. Each sample changes by at most one step, which can't be seen.
+ * Only 8 and 16 bit greyscale and truecolour images are supported: under 8 bits
+ * the lowest bit is a visible step, and changing a palette index swaps colours.
+ *
+ * The alpha channel can carry the payload instead, in the pixels that are fully
+ * opaque or fully transparent only, leaving the colours untouched. Indexed images
+ * carry it in the low bits of their palette entries, which shifts each colour of the
+ * image by at most a step and leaves the pixel data alone, but holds at most 92
+ * bytes, 76 with a key.
+ *
+ * With a key the first `SALT_LENGTH` bytes of carrier bits, in order, hold a random
+ * salt, and the rest are visited in an order shuffled by a generator seeded from
+ * Argon2id of the key and that salt. That spreads the payload over the whole image
+ * instead of its first rows and hides where it is from anyone without the key. The
+ * order is what a guess at the key is checked against first, through the length it
+ * gives, which is why it costs as much to derive as the encryption key.
+ */
+use crate::chunk_type::ChunkType;
+use crate::crypto::{self, KdfParams};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::palette::Palette;
+use crate::png::{Png, PngError};
+use crate::Error;
+use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+//Bytes of the payload length written before the payload
+pub const LENGTH_HEADER: usize = 4;
+//Bytes of the salt written before a payload scattered with a key
+pub const SALT_LENGTH: usize = 16;
+
+//Hashed with the key so the seed differs from the other digests of a password
+const SCATTER_CONTEXT: &[u8] = b"pngme-lsb-scatter";
+
+/**
+ * Deterministic generator, SHA-256 of the seed and a block counter. The seed comes
+ * from Argon2id, the hash only stretches it.
+ */
+struct KeyStream {
+    seed: [u8; 32],
+    counter: u64,
+    block: [u8; 32],
+    used: usize,
+}
+
+impl KeyStream {
+    fn new(key: &[u8], salt: &[u8]) -> Result<KeyStream, Error> {
+        let derived = crypto::derive_key(key, salt, &KdfParams::default())?;
+        let seed = Sha256::new()
+            .chain_update(SCATTER_CONTEXT)
+            .chain_update(derived)
+            .finalize()
+            .into();
+        Ok(KeyStream {
+            seed,
+            counter: 0,
+            block: [0; 32],
+            used: 32,
+        })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.used == self.block.len() {
+            self.block = Sha256::new()
+                .chain_update(self.seed)
+                .chain_update(self.counter.to_be_bytes())
+                .finalize()
+                .into();
+            self.counter += 1;
+            self.used = 0;
+        }
+        let bytes = &self.block[self.used..self.used + 8];
+        self.used += 8;
+        u64::from_be_bytes(bytes.try_into().expect("8 bytes"))
+    }
+
+    //Below `bound`, the bias of the multiplication is negligible for image sizes
+    fn below(&mut self, bound: usize) -> usize {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+}
+
+/**
+ * Positions in a shuffled order, a Fisher-Yates shuffle done one step per item so
+ * the first items are the same however many are taken.
+ */
+struct Scatter {
+    positions: Vec<usize>,
+    index: usize,
+    stream: KeyStream,
+}
+
+impl Iterator for Scatter {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        if self.index == self.positions.len() {
+            return None;
+        }
+        let pick = self.index + self.stream.below(self.positions.len() - self.index);
+        self.positions.swap(self.index, pick);
+        self.index += 1;
+        Some(self.positions[self.index - 1])
+    }
+}
+
+//Samples that carry the hidden bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channels {
+    //Every colour sample, alpha left alone
+    Colour,
+    //Only the alpha of pixels that are fully opaque or fully transparent
+    Alpha,
+    //The colour components of the PLTE entries of an indexed image
+    Palette,
+}
+
+//Positions in the scanline data of the bytes whose low bit holds a hidden bit
+fn carrier_bytes(header: &Ihdr, channels: Channels, data: &[u8]) -> Result<Vec<usize>, Error> {
+    match channels {
+        Channels::Colour => Ok(colour_bytes(header)?.collect()),
+        Channels::Alpha => alpha_bytes(header, data),
+        Channels::Palette => Err(Box::new(StegoError::PaletteNotInPixels)),
+    }
+}
+
+//The positions in embedding order, shuffled when there is a generator
+fn order(positions: Vec<usize>, stream: Option<KeyStream>) -> Box<dyn Iterator<Item = usize>> {
+    match stream {
+        Some(stream) => Box::new(Scatter {
+            positions,
+            index: 0,
+            stream,
+        }),
+        None => Box::new(positions.into_iter()),
+    }
+}
+
+//The positions of the salt of a scattered payload, the first ones, and the others
+fn split_salt(mut positions: Vec<usize>) -> Option<(Vec<usize>, Vec<usize>)> {
+    if positions.len() < SALT_LENGTH * 8 {
+        return None;
+    }
+    let rest = positions.split_off(SALT_LENGTH * 8);
+    Some((positions, rest))
+}
+
+fn check_supported(header: &Ihdr) -> Result<(), Error> {
+    if header.color_type == ColorType::Indexed {
+        return Err(Box::new(StegoError::Indexed));
+    }
+    if header.bit_depth < 8 {
+        return Err(Box::new(StegoError::UnsupportedBitDepth(header.bit_depth)));
+    }
+    Ok(())
+}
+
+//Bytes holding the low bit of each colour sample, row after row
+fn colour_bytes(header: &Ihdr) -> Result<impl Iterator<Item = usize>, Error> {
+    check_supported(header)?;
+    //16 bit samples are big endian, their low bit is in the second byte
+    let bytes = header.bit_depth as usize / 8;
+    let channels = header.color_type.channels() as usize;
+    let colours = channels - header.color_type.has_alpha() as usize;
+    let pixels = header.width as usize * header.height as usize;
+    Ok((0..pixels).flat_map(move |pixel| {
+        (0..colours).map(move |channel| (pixel * channels + channel) * bytes + bytes - 1)
+    }))
+}
+
+/**
+ * Same for the alpha samples within a step of fully transparent or opaque, which
+ * changing the low bit keeps that way, so the same pixels are found once the
+ * payload is in. A step of alpha can't be seen where the pixel was already
+ * invisible or solid, unlike in the edges of anti-aliased shapes.
+ */
+fn alpha_bytes(header: &Ihdr, data: &[u8]) -> Result<Vec<usize>, Error> {
+    check_supported(header)?;
+    if !header.color_type.has_alpha() {
+        return Err(Box::new(StegoError::NoAlpha));
+    }
+    let bytes = header.bit_depth as usize / 8;
+    let pixel_length = header.color_type.channels() as usize * bytes;
+    let opaque = (1u32 << header.bit_depth) - 1;
+    Ok(data
+        .chunks_exact(pixel_length)
+        .enumerate()
+        .filter(|(_, pixel)| {
+            let alpha = pixel[pixel_length - bytes..]
+                .iter()
+                .fold(0, |value, &byte| (value << 8) | byte as u32);
+            alpha >> 1 == 0 || alpha >> 1 == opaque >> 1
+        })
+        .map(|(index, _)| (index + 1) * pixel_length - 1)
+        .collect())
+}
+
+//Number of colour samples of an image with this header, one hidden bit each
+pub fn sample_count(header: &Ihdr) -> Result<u64, Error> {
+    Ok(colour_bytes(header)?.count() as u64)
+}
+
+//Largest payload in bytes that fits in this many samples
+fn payload_capacity(samples: u64) -> u64 {
+    (samples / 8).saturating_sub(LENGTH_HEADER as u64)
+}
+
+//Largest payload in bytes that fits in the colour samples of an image with this header,
+//`SALT_LENGTH` less with a key
+pub fn capacity(header: &Ihdr) -> Result<u64, Error> {
+    Ok(payload_capacity(sample_count(header)?))
+}
+
+//Bits of `bytes`, most significant first
+fn bits(bytes: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1))
+}
+
+fn read_bytes<I: Iterator<Item = u8>>(bits: &mut I, count: usize) -> Vec<u8> {
+    (0..count)
+        .map(|_| bits.by_ref().take(8).fold(0, |byte, bit| (byte << 1) | bit))
+        .collect()
+}
+
+impl Png {
+    //Largest payload in bytes the alpha channel holds, which depends on the pixels
+    pub fn alpha_capacity(&self) -> Result<u64, Error> {
+        let header = self.header()?;
+        let samples = alpha_bytes(&header, self.scanlines()?.data())?;
+        Ok(payload_capacity(samples.len() as u64))
+    }
+
+    //Largest payload in bytes the palette of an indexed image holds
+    pub fn palette_capacity(&self) -> Result<u64, Error> {
+        let entries = palette_bytes(self)?;
+        Ok(payload_capacity(entries.len() as u64))
+    }
+
+    /**
+     * Hides `payload` in the low bits of the given channels, replacing any payload
+     * hidden there before, then filters and compresses the pixel data again, or
+     * rewrites PLTE for the palette. The samples are shuffled with `key` when given,
+     * which `extract_lsb` then needs too.
+     */
+    pub fn embed_lsb(
+        &mut self,
+        payload: &[u8],
+        channels: Channels,
+        key: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        if channels == Channels::Palette {
+            let mut data = palette_bytes(self)?;
+            let positions = (0..data.len()).collect();
+            hide(&mut data, positions, payload, key)?;
+            let entries = data
+                .chunks_exact(3)
+                .map(|entry| [entry[0], entry[1], entry[2]])
+                .collect();
+            self.replace_chunk(&ChunkType::PLTE, Palette::new(entries)?.to_chunk())?;
+            return Ok(());
+        }
+        let header = self.header()?;
+        let mut scanlines = self.scanlines()?;
+        let positions = carrier_bytes(&header, channels, scanlines.data())?;
+        hide(scanlines.data_mut(), positions, payload, key)?;
+        self.set_scanlines(&scanlines)?;
+        Ok(())
+    }
+
+    //Reads the payload hidden by `embed_lsb` with the same channels and key
+    pub fn extract_lsb(&self, channels: Channels, key: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+        if channels == Channels::Palette {
+            let data = palette_bytes(self)?;
+            return reveal(&data, (0..data.len()).collect(), key);
+        }
+        let header = self.header()?;
+        let scanlines = self.scanlines()?;
+        let positions = carrier_bytes(&header, channels, scanlines.data())?;
+        reveal(scanlines.data(), positions, key)
+    }
+}
+
+//Every colour component of every palette entry, which all carry a bit
+fn palette_bytes(png: &Png) -> Result<Vec<u8>, Error> {
+    if png.header()?.color_type != ColorType::Indexed {
+        return Err(Box::new(StegoError::NotIndexed));
+    }
+    let palette = png
+        .palette()?
+        .ok_or_else(|| PngError::ChunkNotFound(ChunkType::PLTE.to_string()))?;
+    Ok(palette.entries().concat())
+}
+
+//Writes the length and `payload` in the low bits at `positions` of `data`
+fn hide(
+    data: &mut [u8],
+    positions: Vec<usize>,
+    payload: &[u8],
+    key: Option<&[u8]>,
+) -> Result<(), Error> {
+    let (positions, stream) = match key {
+        Some(key) => {
+            let (salt_positions, positions) =
+                split_salt(positions).ok_or(StegoError::PayloadTooLarge(payload.len(), 0))?;
+            let mut salt = [0; SALT_LENGTH];
+            OsRng.fill_bytes(&mut salt);
+            for (position, bit) in salt_positions.into_iter().zip(bits(&salt)) {
+                data[position] = (data[position] & !1) | bit;
+            }
+            (positions, Some(KeyStream::new(key, &salt)?))
+        }
+        None => (positions, None),
+    };
+    let capacity = payload_capacity(positions.len() as u64);
+    if payload.len() as u64 > capacity {
+        return Err(Box::new(StegoError::PayloadTooLarge(
+            payload.len(),
+            capacity,
+        )));
+    }
+    let mut message = (payload.len() as u32).to_be_bytes().to_vec();
+    message.extend_from_slice(payload);
+    for (position, bit) in order(positions, stream).zip(bits(&message)) {
+        data[position] = (data[position] & !1) | bit;
+    }
+    Ok(())
+}
+
+/**
+ * Reads back what `hide` wrote. Any image has low bits, so a length that doesn't
+ * fit is the only sign that nothing was hidden; an envelope or encryption around
+ * the payload tells for sure.
+ */
+fn reveal(data: &[u8], positions: Vec<usize>, key: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let (positions, stream) = match key {
+        Some(key) => {
+            let (salt_positions, positions) = split_salt(positions).ok_or(StegoError::NoPayload)?;
+            let mut salt_bits = salt_positions
+                .into_iter()
+                .map(|position| data[position] & 1);
+            let salt = read_bytes(&mut salt_bits, SALT_LENGTH);
+            (positions, Some(KeyStream::new(key, &salt)?))
+        }
+        None => (positions, None),
+    };
+    let capacity = payload_capacity(positions.len() as u64);
+    let mut bits = order(positions, stream).map(|position| data[position] & 1);
+    let length = read_bytes(&mut bits, LENGTH_HEADER);
+    let length = u32::from_be_bytes(length.try_into().expect("4 bytes were read"));
+    if length as u64 > capacity {
+        return Err(Box::new(StegoError::NoPayload));
+    }
+    Ok(read_bytes(&mut bits, length as usize))
+}
+
+#[derive(Debug)]
+pub enum StegoError {
+    Indexed,
+    NotIndexed,
+    UnsupportedBitDepth(u8),
+    NoAlpha,
+    PayloadTooLarge(usize, u64),
+    NoPayload,
+    PaletteNotInPixels,
+}
+
+impl std::error::Error for StegoError {}
+
+impl fmt::Display for StegoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Indexed => write!(
+                f,
+                "indexed images can't hide data in their pixels, only in their palette"
+            ),
+            Self::NotIndexed => write!(f, "only indexed images hide data in their palette"),
+            Self::UnsupportedBitDepth(depth) => write!(
+                f,
+                "{} bit images can't hide data in their pixels, only 8 and 16 bit ones",
+                depth
+            ),
+            Self::NoAlpha => write!(f, "image has no alpha channel to hide data in"),
+            Self::PayloadTooLarge(length, capacity) => write!(
+                f,
+                "payload is {} bytes, at most {} fit in this image that way",
+                length, capacity
+            ),
+            Self::NoPayload => write!(f, "no payload hidden in the pixels"),
+            Self::PaletteNotInPixels => {
+                write!(f, "the palette is in the PLTE chunk, not in the pixel data")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+
+    #[test]
+    pub fn test_embed_lsb() {
+        let mut png = PngBuilder::new(8, 8)
+            .color(&[101, 150, 201, 255])
+            .build()
+            .unwrap();
+        let original = png.scanlines().unwrap();
+        //64 pixels of 3 colour samples, less the length
+        assert_eq!(capacity(&png.header().unwrap()).unwrap(), 20);
+        assert!(png.extract_lsb(Channels::Colour, None).is_err());
+
+        png.embed_lsb(b"hidden in the pixels", Channels::Colour, None)
+            .unwrap();
+        assert_eq!(
+            png.extract_lsb(Channels::Colour, None).unwrap(),
+            b"hidden in the pixels"
+        );
+        let changed = png.scanlines().unwrap();
+        for (index, (old, new)) in original.data().iter().zip(changed.data()).enumerate() {
+            assert!(old.abs_diff(*new) <= 1);
+            if index % 4 == 3 {
+                assert_eq!(old, new);
+            }
+        }
+        assert!(png.embed_lsb(&[0; 21], Channels::Colour, None).is_err());
+        png.embed_lsb(b"", Channels::Colour, None).unwrap();
+        assert_eq!(png.extract_lsb(Channels::Colour, None).unwrap(), b"");
+    }
+
+    #[test]
+    pub fn test_embed_lsb_scattered() {
+        let mut png = PngBuilder::new(32, 32).build().unwrap();
+        let original = png.scanlines().unwrap();
+        png.embed_lsb(b"spread out", Channels::Colour, Some(b"key"))
+            .unwrap();
+        assert_eq!(
+            png.extract_lsb(Channels::Colour, Some(b"key")).unwrap(),
+            b"spread out"
+        );
+        assert!(png
+            .extract_lsb(Channels::Colour, Some(b"other"))
+            .map_or(true, |data| data != b"spread out"));
+        assert!(png
+            .extract_lsb(Channels::Colour, None)
+            .map_or(true, |data| data != b"spread out"));
+
+        //Changes reach beyond the first rows that sequential embedding would use
+        let changed = png.scanlines().unwrap();
+        let rows = original.rows().zip(changed.rows());
+        let changed_rows = rows.filter(|(old, new)| old != new).count();
+        assert!(changed_rows > 16);
+
+        let sequential: Vec<usize> = colour_bytes(&png.header().unwrap()).unwrap().collect();
+        let stream = KeyStream::new(b"key", &[0; SALT_LENGTH]).unwrap();
+        let mut scattered: Vec<usize> = order(sequential.clone(), Some(stream)).collect();
+        scattered.sort_unstable();
+        assert_eq!(scattered, sequential);
+    }
+
+    #[test]
+    pub fn test_embed_alpha() {
+        let mut png = PngBuilder::new(8, 8)
+            .color(&[101, 150, 201, 255])
+            .build()
+            .unwrap();
+        //A quarter of the pixels half transparent, which keep their alpha
+        let mut scanlines = png.scanlines().unwrap();
+        for (index, pixel) in scanlines.data_mut().chunks_exact_mut(4).enumerate() {
+            pixel[3] = [255, 0, 128, 1][index % 4];
+        }
+        png.set_scanlines(&scanlines).unwrap();
+        assert_eq!(png.alpha_capacity().unwrap(), 48 / 8 - 4);
+
+        png.embed_lsb(b"hi", Channels::Alpha, None).unwrap();
+        assert_eq!(png.extract_lsb(Channels::Alpha, None).unwrap(), b"hi");
+        let changed = png.scanlines().unwrap();
+        let pixels = scanlines
+            .data()
+            .chunks_exact(4)
+            .zip(changed.data().chunks_exact(4));
+        for (index, (old, new)) in pixels.enumerate() {
+            assert_eq!(old[..3], new[..3]);
+            if index % 4 == 2 {
+                assert_eq!(new[3], 128);
+            }
+        }
+        assert!(png.embed_lsb(b"too long", Channels::Alpha, None).is_err());
+
+        let opaque = PngBuilder::new(4, 4).color(&[1, 2, 3]).build().unwrap();
+        assert!(opaque.alpha_capacity().is_err());
+    }
+
+    #[test]
+    pub fn test_embed_palette() {
+        let entries: Vec<[u8; 3]> = (0..100).map(|index| [index, 100, 255 - index]).collect();
+        let mut png = Png::from_chunks(vec![
+            Ihdr::new(4, 4, 8, ColorType::Indexed).to_chunk(),
+            Palette::new(entries.clone()).unwrap().to_chunk(),
+        ]);
+        //100 entries of 3 components, less the length, and the salt with a key
+        assert_eq!(png.palette_capacity().unwrap(), 37 - 4);
+
+        png.embed_lsb(b"palette", Channels::Palette, Some(b"key"))
+            .unwrap();
+        let extracted = png.extract_lsb(Channels::Palette, Some(b"key"));
+        assert_eq!(extracted.unwrap(), b"palette");
+        let changed = png.palette().unwrap().unwrap();
+        for (old, new) in entries.concat().iter().zip(changed.entries().concat()) {
+            assert!(old.abs_diff(new) <= 1);
+        }
+        assert!(png.embed_lsb(&[0; 34], Channels::Palette, None).is_err());
+        assert!(png
+            .embed_lsb(&[0; 18], Channels::Palette, Some(b"key"))
+            .is_err());
+
+        let truecolour = PngBuilder::new(4, 4).build().unwrap();
+        assert!(truecolour.palette_capacity().is_err());
+        assert!(carrier_bytes(&truecolour.header().unwrap(), Channels::Palette, &[]).is_err());
+    }
+
+    #[test]
+    pub fn test_lsb_unsupported_images() {
+        let indexed = Ihdr::new(4, 4, 8, ColorType::Indexed);
+        assert!(capacity(&indexed).is_err());
+        let packed = Ihdr::new(4, 4, 4, ColorType::Grayscale);
+        assert!(capacity(&packed).is_err());
+        let deep = Ihdr::new(4, 4, 16, ColorType::Rgb);
+        assert_eq!(capacity(&deep).unwrap(), 2);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::chunk::{Chunk, ChunkError};
+use crate::chunk_type::ChunkType;
+use crate::png::{Png, PngError};
+use crate::Error;
+use bytes::Bytes;
+use std::io::{self, Read, Write};
+
+/**
+ * Reads a PNG one chunk at a time.
+ * Only the chunk currently being read is kept in memory, so files of any size
+ * can be processed with constant memory usage (bounded by the largest chunk).
+ * Reading stops at IEND, the bytes after it are counted and skipped, see `trailing`.
+ */
+pub struct PngReader<R: Read> {
+    reader: R,
+    finished: bool,
+    //Set once IEND has been read
+    ended: bool,
+    //File offset of the next chunk
+    offset: u64,
+}
+
+impl<R: Read> PngReader<R> {
+    //Reads and checks the PNG signature, leaving the reader at the first chunk
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut signature = [0; 8];
+        reader.read_exact(&mut signature)?;
+        if signature != Png::STANDARD_HEADER {
+            return Err(Box::new(PngError::InvalidSignature(signature)));
+        }
+        Ok(PngReader {
+            reader,
+            finished: false,
+            ended: false,
+            offset: signature.len() as u64,
+        })
+    }
+
+    //Returns the next chunk, or None once IEND or the end of the input has been reached
+    pub fn read_chunk(&mut self) -> Result<Option<Chunk>, Error> {
+        if self.ended {
+            let trailing = io::copy(&mut self.reader, &mut io::sink())?;
+            warn_trailing(trailing);
+            return Ok(None);
+        }
+        let (length, chunk_type) = match self.read_header()? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(Box::new(io::Error::from(io::ErrorKind::UnexpectedEof)));
+        }
+
+        let crc = self.read_crc()?;
+        log::trace!(
+            "read {} chunk at offset {}, {} bytes",
+            chunk_type,
+            self.offset,
+            length
+        );
+        self.offset += length as u64 + Chunk::METADATA_LENGTH as u64;
+        self.ended = chunk_type == ChunkType::IEND;
+        Ok(Some(Chunk::from_parts(chunk_type, Bytes::from(data), crc)))
+    }
+
+    //Reads the length and type of the next chunk, None at the end of the input
+    pub(crate) fn read_header(&mut self) -> Result<Option<(u32, ChunkType)>, Error> {
+        let mut length = [0; 4];
+        if !self.fill_or_eof(&mut length)? {
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes(length);
+        if length > Chunk::MAX_LENGTH {
+            return Err(Box::new(ChunkError::InvalidLength(length)));
+        }
+
+        let mut chunk_type = [0; 4];
+        self.reader.read_exact(&mut chunk_type)?;
+        Ok(Some((length, ChunkType::try_from(chunk_type)?)))
+    }
+
+    pub(crate) fn read_crc(&mut self) -> Result<u32, Error> {
+        let mut crc = [0; 4];
+        self.reader.read_exact(&mut crc)?;
+        Ok(u32::from_be_bytes(crc))
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    //Fills the buffer, returning false if the input ended before the first byte
+    fn fill_or_eof(&mut self, buf: &mut [u8]) -> Result<bool, Error> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(Box::new(io::Error::from(io::ErrorKind::UnexpectedEof))),
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(Box::new(err)),
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for PngReader<R> {
+    type Item = Result<Chunk, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.read_chunk() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+//Data after IEND isn't part of the image, it is only reported
+fn warn_trailing(length: u64) {
+    if length > 0 {
+        log::warn!(
+            "ignoring {} bytes after IEND, extract --after-iend reads them",
+            length
+        );
+    }
+}
+
+/**
+ * Iterates over the chunks of a PNG that is already in memory (or memory-mapped).
+ * Every chunk points into the shared buffer, so no chunk data is copied.
+ * Like `PngReader` it stops at IEND.
+ */
+pub struct SharedPngReader {
+    rest: Bytes,
+    //File offset of `rest`
+    offset: usize,
+    ended: bool,
+}
+
+impl SharedPngReader {
+    //Checks the PNG signature, leaving the reader at the first chunk
+    pub fn new(bytes: Bytes) -> Result<Self, Error> {
+        if bytes.len() < 8 || bytes[..8] != Png::STANDARD_HEADER {
+            let mut signature = [0; 8];
+            let length = bytes.len().min(8);
+            signature[..length].copy_from_slice(&bytes[..length]);
+            return Err(Box::new(PngError::InvalidSignature(signature)));
+        }
+        Ok(SharedPngReader {
+            rest: bytes.slice(8..),
+            offset: 8,
+            ended: false,
+        })
+    }
+}
+
+impl Iterator for SharedPngReader {
+    type Item = Result<Chunk, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ended {
+            warn_trailing(self.rest.len() as u64);
+            self.rest.clear();
+        }
+        if self.rest.is_empty() {
+            return None;
+        }
+        match Chunk::from_shared(&self.rest) {
+            Ok((chunk, size)) => {
+                log::trace!(
+                    "read {} chunk at offset {}, {} bytes",
+                    chunk.chunk_type(),
+                    self.offset,
+                    chunk.length()
+                );
+                self.rest = self.rest.slice(size..);
+                self.offset += size;
+                self.ended = *chunk.chunk_type() == ChunkType::IEND;
+                Some(Ok(chunk))
+            }
+            Err(err) => {
+                self.rest.clear();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+//Writes a PNG one chunk at a time, the counterpart of PngReader
+pub struct PngWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PngWriter<W> {
+    //Writes the PNG signature, chunks can be written right after
+    pub fn new(mut writer: W) -> Result<Self, Error> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(PngWriter { writer })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<(), Error> {
+        self.writer.write_all(&chunk.length().to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        self.writer.write_all(&chunk.crc().to_be_bytes())?;
+        Ok(())
+    }
+
+    //Writes a chunk whose data is copied from `data`, which must yield exactly `length` bytes
+    pub fn write_raw_chunk<D: Read>(
+        &mut self,
+        chunk_type: &ChunkType,
+        length: u32,
+        data: D,
+        crc: u32,
+    ) -> Result<(), Error> {
+        self.writer.write_all(&length.to_be_bytes())?;
+        self.writer.write_all(&chunk_type.bytes())?;
+        let copied = io::copy(&mut data.take(length as u64), &mut self.writer)?;
+        if copied != length as u64 {
+            return Err(Box::new(io::Error::from(io::ErrorKind::UnexpectedEof)));
+        }
+        self.writer.write_all(&crc.to_be_bytes())?;
+        Ok(())
+    }
+
+    //Flushes and returns the underlying writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        let chunks = ["FrSt", "miDl", "LASt"]
+            .iter()
+            .map(|chunk_type| {
+                Chunk::new(
+                    ChunkType::from_str(chunk_type).unwrap(),
+                    chunk_type.as_bytes().to_vec(),
+                )
+            })
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    pub fn test_reader_yields_chunks_in_order() {
+        let bytes = testing_png().as_bytes();
+        let reader = PngReader::new(bytes.as_slice()).unwrap();
+        let types: Vec<String> = reader
+            .map(|chunk| chunk.unwrap().chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["FrSt", "miDl", "LASt"]);
+    }
+
+    #[test]
+    pub fn test_reader_invalid_signature() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[0] = 0;
+        assert!(PngReader::new(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    pub fn test_reader_truncated_chunk() {
+        let bytes = testing_png().as_bytes();
+        let reader = PngReader::new(&bytes[..bytes.len() - 2]).unwrap();
+        let chunks: Vec<Result<Chunk, Error>> = reader.collect();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[2].is_err());
+    }
+
+    #[test]
+    pub fn test_readers_stop_at_iend() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::IEND, Vec::new()));
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"appended after IEND");
+        let chunks: Vec<Chunk> = PngReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(chunks.len(), 4);
+        let shared: Vec<Chunk> = SharedPngReader::new(Bytes::from(bytes))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(shared.len(), 4);
+        assert_eq!(Png::from_chunks(shared).as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    pub fn test_shared_reader() {
+        let bytes = Bytes::from(testing_png().as_bytes());
+        let reader = SharedPngReader::new(bytes.clone()).unwrap();
+        let chunks: Vec<Chunk> = reader.map(|chunk| chunk.unwrap()).collect();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2].data(), b"LASt");
+
+        let truncated = bytes.slice(..bytes.len() - 1);
+        let reader = SharedPngReader::new(truncated).unwrap();
+        let chunks: Vec<Result<Chunk, Error>> = reader.collect();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[2].is_err());
+    }
+
+    #[test]
+    pub fn test_writer_round_trip() {
+        let png = testing_png();
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        for chunk in png.chunks() {
+            writer.write_chunk(chunk).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, png.as_bytes());
+    }
+}
@@ -0,0 +1,195 @@
+/*!
+ * Metadata templates, TOML files declaring the text, modification time and
+ * resolution to stamp onto images, such as the author and license of a batch of
+ * exports.
+ *
+ * ```toml
+ * #"now" or an RFC 3339 time
+ * time = "now"
+ * dpi = 300
+ *
+ * #Latin-1 text, tEXt or zTXt chunks
+ * [text]
+ * Author = "Example Studio"
+ * Copyright = "2026 Example Studio"
+ *
+ * #UTF-8 text, iTXt chunks
+ * [itxt]
+ * Title = "Château"
+ * ```
+ */
+use crate::chunk::Chunk;
+use crate::metadata::{PhysicalDimensions, Timestamp};
+use crate::png::Png;
+use crate::text::{self, TextEntry};
+use crate::Error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+const NOW: &str = "now";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFile {
+    time: Option<String>,
+    dpi: Option<f64>,
+    #[serde(default)]
+    text: BTreeMap<String, String>,
+    #[serde(default)]
+    itxt: BTreeMap<String, String>,
+}
+
+/**
+ * The chunks of a template, built once when it is parsed so a time of "now" is the
+ * same in every file it is applied to.
+ */
+#[derive(Debug, Clone)]
+pub struct MetadataTemplate {
+    chunks: Vec<Chunk>,
+}
+
+impl MetadataTemplate {
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /**
+     * Adds the template chunks to `png` following the chunk ordering rules. Each
+     * replaces the text chunks with the same keyword, whatever their type, or the
+     * tIME and pHYs chunks already there. Returns the number of chunks replaced.
+     */
+    pub fn apply(&self, png: &mut Png) -> usize {
+        let mut replaced = 0;
+        for chunk in &self.chunks {
+            let keyword = text::keyword(chunk);
+            let positions: Vec<usize> = png
+                .chunks()
+                .iter()
+                .enumerate()
+                .filter(|(_, present)| match &keyword {
+                    Some(keyword) => text::keyword(present).as_ref() == Some(keyword),
+                    None => present.chunk_type() == chunk.chunk_type(),
+                })
+                .map(|(index, _)| index)
+                .collect();
+            for &index in positions.iter().rev() {
+                png.remove_chunk_at(index)
+                    .expect("the position is within the chunks");
+            }
+            replaced += positions.len();
+            png.insert_chunk_ordered(chunk.clone());
+        }
+        replaced
+    }
+}
+
+impl FromStr for MetadataTemplate {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let file: TemplateFile =
+            toml::from_str(s).map_err(|err| TemplateError::Invalid(err.message().to_string()))?;
+        let mut chunks = Vec::new();
+        if let Some(time) = &file.time {
+            let timestamp = match time.as_str() {
+                NOW => Timestamp::now(),
+                time => Timestamp::from_str(time)?,
+            };
+            chunks.push(timestamp.to_chunk());
+        }
+        if let Some(dpi) = file.dpi {
+            chunks.push(PhysicalDimensions::from_dpi(dpi, dpi)?.to_chunk());
+        }
+        for (keyword, value) in &file.text {
+            chunks.push(TextEntry::new(keyword, value)?.to_chunk()?);
+        }
+        for (keyword, value) in &file.itxt {
+            if file.text.contains_key(keyword) {
+                return Err(Box::new(TemplateError::DuplicateKeyword(keyword.clone())));
+            }
+            chunks.push(text::international_chunk(keyword, value)?);
+        }
+        if chunks.is_empty() {
+            return Err(Box::new(TemplateError::Empty));
+        }
+        Ok(MetadataTemplate { chunks })
+    }
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Invalid(String),
+    DuplicateKeyword(String),
+    Empty,
+}
+
+impl std::error::Error for TemplateError {}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(message) => write!(f, "invalid template: {}", message),
+            Self::DuplicateKeyword(keyword) => {
+                write!(f, "keyword {} is in both text and itxt", keyword)
+            }
+            Self::Empty => write!(f, "template declares no metadata"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::png::PngBuilder;
+
+    const TEMPLATE: &str = r#"
+        time = "2026-01-02T03:04:05Z"
+        dpi = 300
+
+        [text]
+        Author = "Studio"
+
+        [itxt]
+        Title = "猫"
+    "#;
+
+    #[test]
+    pub fn test_parse_template() {
+        let template = MetadataTemplate::from_str(TEMPLATE).unwrap();
+        let types: Vec<String> = template
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["tIME", "pHYs", "tEXt", "iTXt"]);
+        assert!(MetadataTemplate::from_str("").is_err());
+        assert!(MetadataTemplate::from_str("colour = 1").is_err());
+        assert!(MetadataTemplate::from_str("time = \"yesterday\"").is_err());
+        let duplicate = "[text]\nAuthor = \"a\"\n[itxt]\nAuthor = \"b\"";
+        assert!(MetadataTemplate::from_str(duplicate).is_err());
+    }
+
+    #[test]
+    pub fn test_apply_template() {
+        let template = MetadataTemplate::from_str(TEMPLATE).unwrap();
+        let mut png = PngBuilder::new(2, 2).build().unwrap();
+        png.insert_chunk_ordered(
+            TextEntry::new("Author", "Someone")
+                .unwrap()
+                .to_chunk()
+                .unwrap(),
+        );
+        png.insert_chunk_ordered(TextEntry::new("Title", "Cat").unwrap().to_chunk().unwrap());
+        png.insert_chunk_ordered(Chunk::new(ChunkType::pHYs, vec![0; 9]));
+
+        assert_eq!(template.apply(&mut png), 3);
+        assert_eq!(png.text("Author").unwrap().as_deref(), Some("Studio"));
+        assert_eq!(png.chunks_by_type("pHYs").count(), 1);
+        assert_eq!(png.chunks_by_type("iTXt").count(), 1);
+        assert!(png.text("Title").unwrap().is_none());
+        assert!(png.validate().is_empty());
+        assert_eq!(template.apply(&mut png), 4);
+    }
+}
@@ -0,0 +1,479 @@
+/*!
+ * Textual metadata in tEXt and zTXt chunks, such as the Author or Title of the image.
+ * A tEXt chunk holds a keyword, a null separator and the text, both in Latin-1.
+ * A zTXt chunk holds the keyword, the separator, a compression method byte, always 0
+ * for zlib, and the zlib compressed text.
+ * Keywords are 1 to 79 printable Latin-1 characters without leading, trailing or
+ * consecutive spaces, the text can hold any Latin-1 character but null.
+ * Text outside Latin-1 goes in an iTXt chunk, see `InternationalText`, which can also
+ * tag it with its language so translations of a keyword coexist.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::Error;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+pub const MAX_KEYWORD_LENGTH: usize = 79;
+//Texts longer than this, in bytes, are stored compressed in a zTXt chunk
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+//Guards against zlib bombs in zTXt chunks, and zlib and zstd ones in payloads
+pub const MAX_INFLATED_LENGTH: u64 = 64 << 20;
+const ZLIB_METHOD: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntry {
+    pub keyword: String,
+    pub text: String,
+}
+
+impl TextEntry {
+    pub fn new(keyword: &str, text: &str) -> Result<TextEntry, Error> {
+        validate_keyword(keyword)?;
+        if text.contains('\0') {
+            return Err(Box::new(TextError::NullInText));
+        }
+        to_latin1(text)?;
+        Ok(TextEntry {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    //A tEXt chunk, or a zTXt one when the text is over `COMPRESSION_THRESHOLD`
+    pub fn to_chunk(&self) -> Result<Chunk, Error> {
+        let text = to_latin1(&self.text)?;
+        let mut data = to_latin1(&self.keyword)?;
+        data.push(0);
+        if text.len() <= COMPRESSION_THRESHOLD {
+            data.extend_from_slice(&text);
+            return Ok(Chunk::new(ChunkType::tEXt, data));
+        }
+        data.push(ZLIB_METHOD);
+        let mut encoder = ZlibEncoder::new(data, Compression::best());
+        encoder.write_all(&text)?;
+        Ok(Chunk::new(ChunkType::zTXt, encoder.finish()?))
+    }
+}
+
+//Whether the chunk type is one `TextEntry` reads, tEXt or zTXt
+pub fn is_text_chunk(chunk_type: &ChunkType) -> bool {
+    *chunk_type == ChunkType::tEXt || *chunk_type == ChunkType::zTXt
+}
+
+/**
+ * Content of an iTXt chunk: UTF-8 text, optionally tagged with the language it is in,
+ * such as en-US, and with the keyword translated to that language.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalText {
+    pub keyword: String,
+    //Empty for untagged text
+    pub language: String,
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+impl InternationalText {
+    pub fn new(keyword: &str, language: &str, text: &str) -> Result<InternationalText, Error> {
+        validate_keyword(keyword)?;
+        validate_language(language)?;
+        if text.contains('\0') {
+            return Err(Box::new(TextError::NullInText));
+        }
+        Ok(InternationalText {
+            keyword: keyword.to_string(),
+            language: language.to_string(),
+            translated_keyword: String::new(),
+            text: text.to_string(),
+        })
+    }
+
+    //The text is compressed when over `COMPRESSION_THRESHOLD` bytes, like `TextEntry` does
+    pub fn to_chunk(&self) -> Result<Chunk, Error> {
+        let mut data = to_latin1(&self.keyword)?;
+        data.push(0);
+        let compressed = self.text.len() > COMPRESSION_THRESHOLD;
+        data.extend_from_slice(&[compressed as u8, ZLIB_METHOD]);
+        data.extend_from_slice(self.language.as_bytes());
+        data.push(0);
+        data.extend_from_slice(self.translated_keyword.as_bytes());
+        data.push(0);
+        if !compressed {
+            data.extend_from_slice(self.text.as_bytes());
+            return Ok(Chunk::new(ChunkType::iTXt, data));
+        }
+        let mut encoder = ZlibEncoder::new(data, Compression::best());
+        encoder.write_all(self.text.as_bytes())?;
+        Ok(Chunk::new(ChunkType::iTXt, encoder.finish()?))
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalText {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != ChunkType::iTXt {
+            return Err(Box::new(TextError::WrongChunk(
+                chunk.chunk_type().to_string(),
+            )));
+        }
+        let mut fields = chunk.data().splitn(2, |&byte| byte == 0);
+        let keyword = from_latin1(fields.next().unwrap_or_default());
+        let rest = fields.next().ok_or(TextError::MissingSeparator)?;
+        let (flags, rest) = rest
+            .split_first_chunk::<2>()
+            .ok_or(TextError::MissingCompression)?;
+        let mut fields = rest.splitn(3, |&byte| byte == 0);
+        let language = fields.next().unwrap_or_default();
+        let translated_keyword = fields.next().ok_or(TextError::MissingSeparator)?;
+        let text = fields.next().ok_or(TextError::MissingSeparator)?;
+        let text = match flags {
+            [0, _] => text.to_vec(),
+            [1, ZLIB_METHOD] => inflate(text)?,
+            [1, method] => return Err(Box::new(TextError::UnknownCompression(*method))),
+            [flag, _] => return Err(Box::new(TextError::InvalidCompressionFlag(*flag))),
+        };
+        let utf8 = |bytes: Vec<u8>| String::from_utf8(bytes).map_err(|_| TextError::NotUtf8);
+        Ok(InternationalText {
+            keyword,
+            language: utf8(language.to_vec())?,
+            translated_keyword: utf8(translated_keyword.to_vec())?,
+            text: utf8(text)?,
+        })
+    }
+}
+
+//Latin-1 text is untagged text to a reader looking for a language
+impl From<TextEntry> for InternationalText {
+    fn from(entry: TextEntry) -> Self {
+        InternationalText {
+            keyword: entry.keyword,
+            language: String::new(),
+            translated_keyword: String::new(),
+            text: entry.text,
+        }
+    }
+}
+
+//An untagged iTXt chunk, see `InternationalText`
+pub fn international_chunk(keyword: &str, text: &str) -> Result<Chunk, Error> {
+    InternationalText::new(keyword, "", text)?.to_chunk()
+}
+
+//A tEXt or zTXt chunk as `TextEntry` writes them, or an iTXt one for text beyond Latin-1
+pub fn text_chunk(keyword: &str, text: &str) -> Result<Chunk, Error> {
+    if text.chars().all(|c| u8::try_from(c).is_ok()) {
+        TextEntry::new(keyword, text)?.to_chunk()
+    } else {
+        international_chunk(keyword, text)
+    }
+}
+
+/**
+ * The entry of `entries`, texts of one keyword, best matching `language`, in the
+ * manner of an RFC 4647 lookup: the same tag ignoring case, then the tag cut down
+ * subtag by subtag, en-US then en, then another tag of the same language such as
+ * en-GB, then untagged text, then the first entry. Without a language untagged text
+ * comes first.
+ */
+pub fn select_language<'a>(
+    entries: &'a [InternationalText],
+    language: Option<&str>,
+) -> Option<&'a InternationalText> {
+    let tagged = |tag: &str| {
+        entries
+            .iter()
+            .find(|entry| entry.language.eq_ignore_ascii_case(tag))
+    };
+    let mut found = None;
+    if let Some(language) = language {
+        let mut tag = language;
+        loop {
+            found = found.or_else(|| tagged(tag));
+            match tag.rsplit_once('-') {
+                Some((shorter, _)) => tag = shorter,
+                None => break,
+            }
+        }
+        found = found.or_else(|| {
+            entries.iter().find(|entry| {
+                let primary = entry.language.split('-').next().unwrap_or_default();
+                !primary.is_empty() && primary.eq_ignore_ascii_case(tag)
+            })
+        });
+    }
+    found.or_else(|| tagged("")).or(entries.first())
+}
+
+//A BCP 47 language tag such as en-US or zh-Hant, letters and digits in subtags of 1 to 8
+pub fn validate_language(language: &str) -> Result<(), Error> {
+    if language.is_empty() {
+        return Ok(());
+    }
+    let valid = language.split('-').all(|subtag| {
+        (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+    if !valid {
+        return Err(Box::new(TextError::InvalidLanguage(language.to_string())));
+    }
+    Ok(())
+}
+
+//Keyword of a tEXt, zTXt or iTXt chunk, None for other chunks
+pub fn keyword(chunk: &Chunk) -> Option<String> {
+    if !is_text_chunk(chunk.chunk_type()) && *chunk.chunk_type() != ChunkType::iTXt {
+        return None;
+    }
+    let data = chunk.data();
+    let separator = data.iter().position(|&byte| byte == 0)?;
+    Some(from_latin1(&data[..separator]))
+}
+
+impl TryFrom<&Chunk> for TextEntry {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if !is_text_chunk(chunk.chunk_type()) {
+            return Err(Box::new(TextError::WrongChunk(
+                chunk.chunk_type().to_string(),
+            )));
+        }
+        let data = chunk.data();
+        let separator = data
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or(TextError::MissingSeparator)?;
+        let keyword = from_latin1(&data[..separator]);
+        validate_keyword(&keyword)?;
+        let rest = &data[separator + 1..];
+        let text = if *chunk.chunk_type() == ChunkType::zTXt {
+            match rest.split_first() {
+                Some((&ZLIB_METHOD, compressed)) => inflate(compressed)?,
+                Some((&method, _)) => return Err(Box::new(TextError::UnknownCompression(method))),
+                None => return Err(Box::new(TextError::MissingCompression)),
+            }
+        } else {
+            rest.to_vec()
+        };
+        Ok(TextEntry {
+            keyword,
+            text: from_latin1(&text),
+        })
+    }
+}
+
+//Inflates the zlib stream of a compressed text chunk
+pub(crate) fn inflate(compressed: &[u8]) -> Result<Vec<u8>, Error> {
+    read_inflated(ZlibDecoder::new(compressed))?
+        .ok_or_else(|| Box::new(TextError::TooLong) as Error)
+}
+
+//Everything a decompressor gives, None once that is more than `MAX_INFLATED_LENGTH`
+pub(crate) fn read_inflated<R: Read>(decoder: R) -> io::Result<Option<Vec<u8>>> {
+    let mut inflated = Vec::new();
+    decoder
+        .take(MAX_INFLATED_LENGTH + 1)
+        .read_to_end(&mut inflated)?;
+    Ok((inflated.len() as u64 <= MAX_INFLATED_LENGTH).then_some(inflated))
+}
+
+//Checks a keyword against the rules of the PNG spec, see the module doc
+pub fn validate_keyword(keyword: &str) -> Result<(), Error> {
+    let length = keyword.chars().count();
+    let invalid =
+        |reason| Err(Box::new(TextError::InvalidKeyword(keyword.to_string(), reason)) as Error);
+    if !(1..=MAX_KEYWORD_LENGTH).contains(&length) {
+        return invalid("it must be 1 to 79 characters long");
+    }
+    if !keyword
+        .chars()
+        .all(|c| matches!(c as u32, 32..=126 | 161..=255))
+    {
+        return invalid("it must only hold printable Latin-1 characters");
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return invalid("it can't have leading, trailing or consecutive spaces");
+    }
+    Ok(())
+}
+
+fn to_latin1(text: &str) -> Result<Vec<u8>, Error> {
+    text.chars()
+        .map(|c| u8::try_from(c).map_err(|_| Box::new(TextError::NotLatin1(c)) as Error))
+        .collect()
+}
+
+//Latin-1 code points match the first 256 of Unicode
+fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+impl Png {
+    //Parses every tEXt and zTXt chunk, in file order
+    pub fn text_entries(&self) -> Result<Vec<TextEntry>, Error> {
+        self.chunks()
+            .iter()
+            .filter(|chunk| is_text_chunk(chunk.chunk_type()))
+            .map(TextEntry::try_from)
+            .collect()
+    }
+
+    //Text of the first text chunk with the keyword, if any
+    pub fn text(&self, keyword: &str) -> Result<Option<String>, Error> {
+        let entry = self
+            .text_entries()?
+            .into_iter()
+            .find(|entry| entry.keyword == keyword);
+        Ok(entry.map(|entry| entry.text))
+    }
+}
+
+#[derive(Debug)]
+pub enum TextError {
+    WrongChunk(String),
+    MissingSeparator,
+    InvalidKeyword(String, &'static str),
+    NullInText,
+    NotLatin1(char),
+    KeywordNotFound(String),
+    MissingCompression,
+    UnknownCompression(u8),
+    TooLong,
+    InvalidCompressionFlag(u8),
+    NotUtf8,
+    InvalidLanguage(String),
+}
+
+impl std::error::Error for TextError {}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongChunk(chunk_type) => write!(f, "unexpected chunk: {}", chunk_type),
+            Self::MissingSeparator => write!(f, "text chunk has no null separator"),
+            Self::InvalidKeyword(keyword, reason) => {
+                write!(f, "invalid keyword {:?}, {}", keyword, reason)
+            }
+            Self::NullInText => write!(f, "text can't hold a null character"),
+            Self::NotLatin1(c) => write!(f, "{:?} isn't a Latin-1 character", c),
+            Self::KeywordNotFound(keyword) => write!(f, "no text with keyword {}", keyword),
+            Self::MissingCompression => write!(f, "zTXt chunk has no compression method"),
+            Self::UnknownCompression(method) => {
+                write!(f, "unknown zTXt compression method {}", method)
+            }
+            Self::InvalidCompressionFlag(flag) => {
+                write!(f, "invalid iTXt compression flag {}", flag)
+            }
+            Self::NotUtf8 => write!(f, "iTXt text isn't UTF-8"),
+            Self::InvalidLanguage(language) => {
+                write!(
+                    f,
+                    "invalid language tag {:?}, expected one such as en-US",
+                    language
+                )
+            }
+            Self::TooLong => write!(
+                f,
+                "compressed text inflates to more than {} bytes",
+                MAX_INFLATED_LENGTH
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_text_entry() {
+        let entry = TextEntry::new("Author", "Jérôme").unwrap();
+        let chunk = entry.to_chunk().unwrap();
+        assert_eq!(chunk.data(), b"Author\0J\xe9r\xf4me");
+        assert_eq!(TextEntry::try_from(&chunk).unwrap(), entry);
+        assert!(TextEntry::new("Author", "emoji 🦀").is_err());
+        assert!(TextEntry::new("Author", "null\0").is_err());
+        let broken = Chunk::new(ChunkType::tEXt, b"no separator".to_vec());
+        assert!(TextEntry::try_from(&broken).is_err());
+    }
+
+    #[test]
+    pub fn test_compressed_text() {
+        let short = TextEntry::new("Comment", &"a".repeat(COMPRESSION_THRESHOLD)).unwrap();
+        assert_eq!(*short.to_chunk().unwrap().chunk_type(), ChunkType::tEXt);
+        let long = TextEntry::new("Comment", &"é".repeat(COMPRESSION_THRESHOLD + 1)).unwrap();
+        let chunk = long.to_chunk().unwrap();
+        assert_eq!(*chunk.chunk_type(), ChunkType::zTXt);
+        assert!(chunk.data().starts_with(b"Comment\0\0"));
+        assert!(chunk.length() < 100);
+        assert_eq!(TextEntry::try_from(&chunk).unwrap(), long);
+        let mut data = chunk.data().to_vec();
+        data[8] = 1;
+        let unknown = Chunk::new(ChunkType::zTXt, data);
+        assert!(TextEntry::try_from(&unknown).is_err());
+    }
+
+    #[test]
+    pub fn test_international_chunk() {
+        let chunk = international_chunk("Title", "猫").unwrap();
+        assert_eq!(*chunk.chunk_type(), ChunkType::iTXt);
+        assert_eq!(chunk.data(), "Title\0\0\0\0\0猫".as_bytes());
+        assert_eq!(keyword(&chunk).as_deref(), Some("Title"));
+        let long = international_chunk("Comment", &"猫".repeat(COMPRESSION_THRESHOLD)).unwrap();
+        assert!(long.data().starts_with(b"Comment\0\x01\0\0\0"));
+        assert!(long.length() < 100);
+        assert!(international_chunk("Title", "null\0").is_err());
+        assert_eq!(
+            *text_chunk("Title", "Chat").unwrap().chunk_type(),
+            ChunkType::tEXt
+        );
+        assert_eq!(
+            *text_chunk("Title", "猫").unwrap().chunk_type(),
+            ChunkType::iTXt
+        );
+        let read = InternationalText::try_from(&long).unwrap();
+        assert_eq!(read.text, "猫".repeat(COMPRESSION_THRESHOLD));
+        let tagged = InternationalText::new("Title", "fr-CA", "Chat").unwrap();
+        assert_eq!(
+            tagged.to_chunk().unwrap().data(),
+            b"Title\0\0\0fr-CA\0\0Chat"
+        );
+        assert_eq!(
+            InternationalText::try_from(&tagged.to_chunk().unwrap()).unwrap(),
+            tagged
+        );
+        assert!(InternationalText::new("Title", "en_US", "Cat").is_err());
+    }
+
+    #[test]
+    pub fn test_select_language() {
+        let entries: Vec<InternationalText> = [("", "Cat"), ("fr", "Chat"), ("en-GB", "Puss")]
+            .iter()
+            .map(|(language, text)| InternationalText::new("Title", language, text).unwrap())
+            .collect();
+        let select = |language| select_language(&entries, language).unwrap().text.as_str();
+        assert_eq!(select(Some("FR")), "Chat");
+        assert_eq!(select(Some("fr-CA")), "Chat");
+        assert_eq!(select(Some("en-US")), "Puss");
+        assert_eq!(select(Some("de")), "Cat");
+        assert_eq!(select(None), "Cat");
+        assert_eq!(
+            select_language(&entries[1..], Some("de")).unwrap().text,
+            "Chat"
+        );
+        assert!(select_language(&[], None).is_none());
+    }
+
+    #[test]
+    pub fn test_validate_keyword() {
+        assert!(validate_keyword("Creation Time").is_ok());
+        assert!(validate_keyword(&"k".repeat(79)).is_ok());
+        for keyword in ["", " Title", "Title ", "Two  spaces", "tab\t", "\u{a0}"] {
+            assert!(validate_keyword(keyword).is_err(), "{:?}", keyword);
+        }
+        assert!(validate_keyword(&"k".repeat(80)).is_err());
+    }
+}
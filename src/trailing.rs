@@ -0,0 +1,72 @@
+/*!
+ * Data appended after the IEND chunk, where decoders stop reading. It is the simplest
+ * way to hide a payload in a PNG and common in the wild, with nothing but the size of
+ * the file to give it away. Chunks stored after IEND count as trailing data too.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{Png, PngError};
+use crate::Error;
+use std::fmt;
+
+/**
+ * Splits a PNG file into the image, up to the end of its first IEND chunk, and the
+ * bytes after it. Only the chunk lengths are read, so damaged chunks don't matter.
+ */
+pub fn split(bytes: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        let mut signature = [0; 8];
+        let length = bytes.len().min(8);
+        signature[..length].copy_from_slice(&bytes[..length]);
+        return Err(Box::new(PngError::InvalidSignature(signature)));
+    }
+    let mut offset = Png::STANDARD_HEADER.len();
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let end = offset + length as usize + Chunk::METADATA_LENGTH;
+        if header[4..] == ChunkType::IEND.bytes() && end <= bytes.len() {
+            return Ok(bytes.split_at(end));
+        }
+        offset = end;
+    }
+    Err(Box::new(TrailingError::NoIend))
+}
+
+#[derive(Debug)]
+pub enum TrailingError {
+    NoIend,
+    NoTrailingData,
+    TrailingDataExists,
+}
+
+impl std::error::Error for TrailingError {}
+
+impl fmt::Display for TrailingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoIend => write!(f, "no IEND chunk, the file is truncated"),
+            Self::NoTrailingData => write!(f, "no data after IEND"),
+            Self::TrailingDataExists => write!(f, "the file already has data after IEND"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+
+    #[test]
+    pub fn test_split() {
+        let png = PngBuilder::new(2, 2).build().unwrap().as_bytes();
+        let mut bytes = png.clone();
+        bytes.extend_from_slice(b"appended");
+        assert_eq!(
+            split(&bytes).unwrap(),
+            (png.as_slice(), b"appended".as_slice())
+        );
+        assert_eq!(split(&png).unwrap().1, b"");
+        assert!(split(&png[..png.len() - 1]).is_err());
+        assert!(split(b"appended").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+/*!
+ * Interactive chunk browser, `pngme tui`.
+ * The file is loaded at start and only written back when saving.
+ */
+use crate::args::TuiArgs;
+use crate::commands;
+use pngme::chunk::Chunk;
+use pngme::ihdr::Ihdr;
+use pngme::inspect;
+use pngme::png::Png;
+use pngme::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::path::PathBuf;
+
+//Bytes of the selected chunk shown in the hexdump
+const HEXDUMP_LIMIT: usize = 4096;
+//Characters of a text payload shown in the preview
+const PREVIEW_LIMIT: usize = 1024;
+
+const HELP: &str = "↑/↓ select  PgUp/PgDn scroll  d delete  s save  q quit";
+
+struct Browser {
+    path: PathBuf,
+    png: Png,
+    header: Option<Ihdr>,
+    selected: ListState,
+    scroll: u16,
+    modified: bool,
+    //Last action result, shown in the status bar
+    status: String,
+    quit_requested: bool,
+}
+
+pub fn run(args: TuiArgs) -> Result<()> {
+    let png = Png::try_from(std::fs::read(&args.file_path)?.as_slice())?;
+    let header = png.header().ok();
+    let mut browser = Browser {
+        path: args.file_path,
+        png,
+        header,
+        selected: ListState::default().with_selected(Some(0)),
+        scroll: 0,
+        modified: false,
+        status: HELP.to_string(),
+        quit_requested: false,
+    };
+    ratatui::run(|terminal| browser.run(terminal))
+}
+
+impl Browser {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key.code)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    //Returns false once the browser should close
+    fn handle_key(&mut self, code: KeyCode) -> Result<bool> {
+        let quit_requested = std::mem::take(&mut self.quit_requested);
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if !self.modified || quit_requested {
+                    return Ok(false);
+                }
+                self.quit_requested = true;
+                self.status = "Unsaved changes, press q again to quit without saving".to_string();
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::Char('d') => self.delete(),
+            KeyCode::Char('s') => self.save()?,
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn select(&mut self, delta: isize) {
+        let count = self.png.chunks().len();
+        if count == 0 {
+            return;
+        }
+        let current = self.selected.selected().unwrap_or(0);
+        let next = current.saturating_add_signed(delta).min(count - 1);
+        self.selected.select(Some(next));
+        self.scroll = 0;
+    }
+
+    fn selected_chunk(&self) -> Option<&Chunk> {
+        self.png.chunks().get(self.selected.selected()?)
+    }
+
+    //Critical chunks are kept, removing one would leave an unreadable image
+    fn delete(&mut self) {
+        let index = match self.selected.selected() {
+            Some(index) => index,
+            None => return,
+        };
+        if self
+            .selected_chunk()
+            .is_some_and(|chunk| chunk.chunk_type().is_critical())
+        {
+            self.status = "Critical chunks can't be deleted".to_string();
+            return;
+        }
+        if let Ok(chunk) = self.png.remove_chunk_at(index) {
+            self.status = format!("Deleted {} chunk #{}", chunk.chunk_type(), index);
+            self.modified = true;
+            let count = self.png.chunks().len();
+            self.selected.select(if count == 0 {
+                None
+            } else {
+                Some(index.min(count - 1))
+            });
+            self.scroll = 0;
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let bytes = self.png.as_bytes();
+        commands::rewrite(&self.path, &self.path, |_, writer| {
+            writer.write_all(&bytes)?;
+            Ok(())
+        })?;
+        self.modified = false;
+        self.status = format!("Saved {} bytes to {}", bytes.len(), self.path.display());
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, detail] =
+            Layout::horizontal([Constraint::Length(30), Constraint::Min(1)]).areas(main);
+
+        let items: Vec<ListItem> = self
+            .png
+            .chunks()
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                ListItem::new(format!(
+                    "{:>3} {} {:>9}",
+                    index,
+                    chunk.chunk_type(),
+                    chunk.length()
+                ))
+            })
+            .collect();
+        let title = format!(
+            "{}{}",
+            self.path.display(),
+            if self.modified { " *" } else { "" }
+        );
+        let chunks = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(chunks, list, &mut self.selected);
+
+        let lines = match self.selected_chunk() {
+            Some(chunk) => self.describe(chunk),
+            None => vec![Line::from("No chunks")],
+        };
+        let detail_block = Paragraph::new(lines)
+            .block(Block::bordered().title("Chunk"))
+            .scroll((self.scroll, 0));
+        frame.render_widget(detail_block, detail);
+        frame.render_widget(Line::from(self.status.as_str()), status);
+    }
+
+    //Decoded fields or text preview of the chunk, followed by its hexdump
+    fn describe(&self, chunk: &Chunk) -> Vec<Line<'static>> {
+        let mut lines = vec![Line::from(format!(
+            "{}, {} bytes, crc {:08x}{}",
+            chunk.chunk_type(),
+            chunk.length(),
+            chunk.crc(),
+            if chunk.is_crc_valid() {
+                ""
+            } else {
+                " (invalid)"
+            }
+        ))];
+        match inspect::describe(chunk, self.header.as_ref()) {
+            Ok(Some(fields)) => {
+                for (name, value) in fields {
+                    lines.push(Line::from(format!("{}: {}", name, value)));
+                }
+            }
+            Ok(None) => {
+                if let Ok(text) = std::str::from_utf8(chunk.data()) {
+                    lines.push(Line::from("Text:"));
+                    let preview: String = text.chars().take(PREVIEW_LIMIT).collect();
+                    lines.extend(preview.lines().map(|line| Line::from(line.to_string())));
+                }
+            }
+            Err(err) => lines.push(Line::from(format!("invalid: {}", err))),
+        }
+        lines.push(Line::from(""));
+        lines.extend(
+            inspect::hexdump(chunk.data(), HEXDUMP_LIMIT)
+                .lines()
+                .map(|line| Line::from(line.to_string())),
+        );
+        lines
+    }
+}
@@ -0,0 +1,301 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::{ChunkOrdering, ChunkType};
+use crate::png::Png;
+use std::collections::HashMap;
+use std::fmt;
+
+//Machine readable reason of a validation issue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueCode {
+    InvalidSignature,
+    MissingIhdr,
+    MissingIdat,
+    MissingIend,
+    OutOfOrder,
+    InvalidCrc,
+    DuplicateChunk,
+    InvalidChunkType,
+}
+
+impl IssueCode {
+    //Stable identifier, meant to be matched by scripts
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidSignature => "invalid-signature",
+            Self::MissingIhdr => "missing-ihdr",
+            Self::MissingIdat => "missing-idat",
+            Self::MissingIend => "missing-iend",
+            Self::OutOfOrder => "out-of-order",
+            Self::InvalidCrc => "invalid-crc",
+            Self::DuplicateChunk => "duplicate-chunk",
+            Self::InvalidChunkType => "invalid-chunk-type",
+        }
+    }
+}
+
+impl fmt::Display for IssueCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub code: IssueCode,
+    //Index of the offending chunk, None for issues about the whole file
+    pub chunk_index: Option<usize>,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    fn file(code: IssueCode, message: &str) -> ValidationIssue {
+        ValidationIssue {
+            code,
+            chunk_index: None,
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn chunk(
+        code: IssueCode,
+        index: usize,
+        chunk: &Chunk,
+        message: &str,
+    ) -> ValidationIssue {
+        ValidationIssue {
+            code,
+            chunk_index: Some(index),
+            message: format!("{} chunk {}", chunk.chunk_type(), message),
+        }
+    }
+
+    //Issue for a file that doesn't start with the PNG signature
+    pub fn invalid_signature() -> ValidationIssue {
+        Self::file(
+            IssueCode::InvalidSignature,
+            "file doesn't start with the PNG signature",
+        )
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chunk_index {
+            Some(index) => write!(f, "[{}] chunk {}: {}", self.code, index, self.message),
+            None => write!(f, "[{}] {}", self.code, self.message),
+        }
+    }
+}
+
+impl Png {
+    /**
+     * Checks the PNG against the structural rules of the spec: IHDR first, IEND last,
+     * at least one IDAT, chunk ordering, unique chunks, CRCs and chunk type bytes.
+     * The signature is checked when parsing, see `ValidationIssue::invalid_signature`.
+     */
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let chunks = self.chunks();
+        let mut issues = Vec::new();
+        let count = |chunk_type: &ChunkType| {
+            chunks
+                .iter()
+                .filter(|chunk| chunk.chunk_type() == chunk_type)
+                .count()
+        };
+        if count(&ChunkType::IHDR) == 0 {
+            issues.push(ValidationIssue::file(
+                IssueCode::MissingIhdr,
+                "no IHDR chunk",
+            ));
+        }
+        if count(&ChunkType::IDAT) == 0 {
+            issues.push(ValidationIssue::file(
+                IssueCode::MissingIdat,
+                "no IDAT chunk",
+            ));
+        }
+        if count(&ChunkType::IEND) == 0 {
+            issues.push(ValidationIssue::file(
+                IssueCode::MissingIend,
+                "no IEND chunk",
+            ));
+        }
+
+        let mut seen: HashMap<[u8; 4], usize> = HashMap::new();
+        let mut seen_palette = false;
+        let mut seen_after_palette = false;
+        let mut seen_data = false;
+        let mut data_ended = false;
+        for (index, chunk) in chunks.iter().enumerate() {
+            let chunk_type = chunk.chunk_type();
+            let out_of_order = |message: &str| {
+                ValidationIssue::chunk(IssueCode::OutOfOrder, index, chunk, message)
+            };
+
+            if !chunk_type.is_valid() {
+                issues.push(ValidationIssue::chunk(
+                    IssueCode::InvalidChunkType,
+                    index,
+                    chunk,
+                    "has an invalid type, letters must be A-Z/a-z with an uppercase third letter",
+                ));
+            }
+            if !chunk.is_crc_valid() {
+                issues.push(ValidationIssue::chunk(
+                    IssueCode::InvalidCrc,
+                    index,
+                    chunk,
+                    "has an invalid CRC",
+                ));
+            }
+            let occurrences = seen.entry(chunk_type.bytes()).or_insert(0);
+            *occurrences += 1;
+            if chunk_type.is_unique() && *occurrences == 2 {
+                issues.push(ValidationIssue::chunk(
+                    IssueCode::DuplicateChunk,
+                    index,
+                    chunk,
+                    "must not appear more than once",
+                ));
+            }
+
+            match chunk_type.ordering() {
+                ChunkOrdering::First if index != 0 => {
+                    issues.push(out_of_order("must be the first chunk"))
+                }
+                ChunkOrdering::Last if index != chunks.len() - 1 => {
+                    issues.push(out_of_order("must be the last chunk"))
+                }
+                ChunkOrdering::Palette if seen_data => {
+                    issues.push(out_of_order("must come before IDAT"))
+                }
+                ChunkOrdering::Palette if seen_after_palette => {
+                    issues.push(out_of_order("must come before tRNS, bKGD and hIST"))
+                }
+                ChunkOrdering::BeforePalette if seen_palette || seen_data => {
+                    issues.push(out_of_order("must come before PLTE and IDAT"))
+                }
+                ChunkOrdering::AfterPalette | ChunkOrdering::BeforeData if seen_data => {
+                    issues.push(out_of_order("must come before IDAT"))
+                }
+                ChunkOrdering::Data if data_ended => issues.push(out_of_order(
+                    "must be consecutive with the other IDAT chunks",
+                )),
+                ChunkOrdering::Frame if *chunk_type == ChunkType::fdAT && !seen_data => {
+                    issues.push(out_of_order("must come after IDAT"))
+                }
+                _ => {}
+            }
+
+            match chunk_type.ordering() {
+                ChunkOrdering::Palette => seen_palette = true,
+                ChunkOrdering::AfterPalette => seen_after_palette = true,
+                ChunkOrdering::Data => seen_data = true,
+                _ if seen_data => data_ended = true,
+                _ => {}
+            }
+        }
+        issues
+    }
+}
+
+#[derive(Debug)]
+pub enum ValidationError {
+    IssuesFound(usize),
+}
+
+impl std::error::Error for ValidationError {}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IssuesFound(count) => write!(f, "{} validation issue(s) found", count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+    use std::str::FromStr;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        let chunk_type = ChunkType::from_str(chunk_type).unwrap();
+        Chunk::new(chunk_type, data.as_bytes().to_vec())
+    }
+
+    fn codes(png: &Png) -> Vec<IssueCode> {
+        png.validate().iter().map(|issue| issue.code).collect()
+    }
+
+    #[test]
+    pub fn test_valid_png() {
+        let mut png = PngBuilder::new(2, 2).build().unwrap();
+        png.insert_chunk_ordered(chunk_from_strings("gAMA", "gamma"));
+        png.insert_chunk_ordered(chunk_from_strings("ruSt", "Message"));
+        assert!(png.validate().is_empty());
+    }
+
+    #[test]
+    pub fn test_missing_chunks() {
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "Message")]);
+        assert_eq!(
+            codes(&png),
+            vec![
+                IssueCode::MissingIhdr,
+                IssueCode::MissingIdat,
+                IssueCode::MissingIend
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_out_of_order_chunks() {
+        let mut png = PngBuilder::new(2, 2).build().unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "Message"));
+        png.append_chunk(chunk_from_strings("pHYs", "dimensions"));
+        let issues = png.validate();
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].code, IssueCode::OutOfOrder);
+        assert_eq!(issues[0].chunk_index, Some(2));
+        assert_eq!(issues[1].chunk_index, Some(4));
+    }
+
+    #[test]
+    pub fn test_frame_data_before_idat() {
+        let png = PngBuilder::new(2, 2).build().unwrap();
+        let mut chunks = png.chunks().to_vec();
+        chunks.insert(1, Chunk::new(ChunkType::fdAT, vec![0; 4]));
+        let issues = Png::from_chunks(chunks).validate();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].code, IssueCode::OutOfOrder);
+        assert_eq!(issues[0].chunk_index, Some(1));
+    }
+
+    #[test]
+    pub fn test_duplicate_and_invalid_chunks() {
+        let png = PngBuilder::new(2, 2).build().unwrap();
+        let mut chunks = png.chunks().to_vec();
+        chunks.insert(1, chunk_from_strings("IHDR", "again"));
+        chunks.insert(2, chunk_from_strings("rust", "reserved bit set"));
+        let mut bytes = chunks[3].as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        chunks[3] = Chunk::try_from(bytes.as_ref()).unwrap();
+
+        let codes = codes(&Png::from_chunks(chunks));
+        assert!(codes.contains(&IssueCode::DuplicateChunk));
+        assert!(codes.contains(&IssueCode::InvalidChunkType));
+        assert!(codes.contains(&IssueCode::InvalidCrc));
+    }
+
+    #[test]
+    pub fn test_issue_display() {
+        let issue = ValidationIssue::invalid_signature();
+        assert_eq!(
+            issue.to_string(),
+            "[invalid-signature] file doesn't start with the PNG signature"
+        );
+    }
+}
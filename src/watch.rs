@@ -0,0 +1,106 @@
+/*!
+ * Running a command on every PNG dropped into a directory, `pngme watch`.
+ */
+use crate::args::{Cli, WatchArgs};
+use crate::batch::FileFilter;
+use clap::Parser;
+use notify::{EventKind, RecursiveMode, Watcher};
+use pngme::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+//Files are processed once no event came for this long, so half-written files are skipped
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+pub fn run(args: WatchArgs) -> Result<()> {
+    let filter = FileFilter::new(&args.include, &args.exclude)?;
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let mode = if args.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher.watch(&args.dir, mode)?;
+    log::info!("watching {}", args.dir.display());
+
+    //Modification times left by the command itself, an in-place edit mustn't trigger it again
+    let mut processed: HashMap<PathBuf, SystemTime> = HashMap::new();
+    let mut pending = BTreeSet::new();
+    loop {
+        let event = if pending.is_empty() {
+            Some(events.recv()?)
+        } else {
+            events.recv_timeout(SETTLE_TIME).ok()
+        };
+        match event {
+            Some(event) => {
+                let event = event?;
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    pending.extend(event.paths);
+                }
+            }
+            None => {
+                for file in std::mem::take(&mut pending) {
+                    let relative = file.strip_prefix(&args.dir).unwrap_or(&file);
+                    if !file.is_file() || !filter.matches(relative) {
+                        continue;
+                    }
+                    if processed.get(&file) == modified(&file).as_ref() {
+                        continue;
+                    }
+                    if let Err(err) = run_command(&args.on_new, &file) {
+                        eprintln!("{}: {}", file.display(), err);
+                    }
+                    if let Some(time) = modified(&file) {
+                        processed.insert(file, time);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn modified(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+//Parses the command with the file inserted after the subcommand name and runs it
+fn run_command(command: &[String], file: &Path) -> Result<()> {
+    let (name, rest) = match command.split_first() {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+    if name == "watch" {
+        return Err(Box::new(WatchError::NestedWatch));
+    }
+    log::info!("{}: running {}", file.display(), name);
+    let argv = ["pngme", name.as_str()]
+        .into_iter()
+        .map(String::from)
+        .chain([file.display().to_string()])
+        .chain(rest.iter().cloned());
+    let cli = Cli::try_parse_from(argv)?;
+    crate::run(cli)
+}
+
+#[derive(Debug)]
+pub enum WatchError {
+    NestedWatch,
+}
+
+impl std::error::Error for WatchError {}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NestedWatch => write!(f, "watch can't run another watch"),
+        }
+    }
+}
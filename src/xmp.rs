@@ -0,0 +1,140 @@
+/*!
+ * XMP packets, stored as the XMP spec asks for PNG files: in an iTXt chunk with the
+ * keyword `XML:com.adobe.xmp`, uncompressed, without language tag nor translated
+ * keyword.
+ * An iTXt chunk holds the keyword, a null separator, the compression flag and method,
+ * the null terminated language tag and translated keyword, then the UTF-8 text, zlib
+ * compressed when the flag is 1. Packets written by other tools are read either way.
+ */
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::text;
+use crate::Error;
+use std::fmt;
+
+pub const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+//Root element every XMP packet holds
+const XMP_ROOT: &str = "x:xmpmeta";
+
+pub fn to_chunk(packet: &str) -> Result<Chunk, Error> {
+    if !packet.contains(XMP_ROOT) {
+        return Err(Box::new(XmpError::NotXmp));
+    }
+    let mut data = XMP_KEYWORD.as_bytes().to_vec();
+    //Separator, no compression, zlib method, empty language tag and translated keyword
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(packet.as_bytes());
+    Ok(Chunk::new(ChunkType::iTXt, data))
+}
+
+pub fn is_xmp_chunk(chunk: &Chunk) -> bool {
+    *chunk.chunk_type() == ChunkType::iTXt
+        && chunk
+            .data()
+            .strip_prefix(XMP_KEYWORD.as_bytes())
+            .is_some_and(|rest| rest.first() == Some(&0))
+}
+
+//The packet held by an XMP chunk, see `is_xmp_chunk`
+pub fn from_chunk(chunk: &Chunk) -> Result<String, Error> {
+    if !is_xmp_chunk(chunk) {
+        return Err(Box::new(XmpError::WrongChunk(
+            chunk.chunk_type().to_string(),
+        )));
+    }
+    let rest = &chunk.data()[XMP_KEYWORD.len() + 1..];
+    let (flags, rest) = rest.split_first_chunk::<2>().ok_or(XmpError::Truncated)?;
+    //Skips the language tag then the translated keyword
+    let mut packet = rest;
+    for _ in 0..2 {
+        let end = packet
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or(XmpError::Truncated)?;
+        packet = &packet[end + 1..];
+    }
+    let packet = match flags {
+        [0, _] => packet.to_vec(),
+        [1, 0] => text::inflate(packet)?,
+        [1, method] => return Err(Box::new(XmpError::UnknownCompression(*method))),
+        [flag, _] => return Err(Box::new(XmpError::InvalidFlag(*flag))),
+    };
+    String::from_utf8(packet).map_err(|_| Box::new(XmpError::NotUtf8) as Error)
+}
+
+impl Png {
+    //Packet of the first XMP chunk, if any
+    pub fn xmp(&self) -> Result<Option<String>, Error> {
+        self.chunks()
+            .iter()
+            .find(|chunk| is_xmp_chunk(chunk))
+            .map(from_chunk)
+            .transpose()
+    }
+}
+
+#[derive(Debug)]
+pub enum XmpError {
+    WrongChunk(String),
+    NotXmp,
+    Truncated,
+    InvalidFlag(u8),
+    UnknownCompression(u8),
+    NotUtf8,
+    NotFound,
+}
+
+impl std::error::Error for XmpError {}
+
+impl fmt::Display for XmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongChunk(chunk_type) => write!(f, "unexpected chunk: {}", chunk_type),
+            Self::NotXmp => write!(f, "not an XMP packet, it has no {} element", XMP_ROOT),
+            Self::Truncated => write!(f, "XMP chunk is truncated"),
+            Self::InvalidFlag(flag) => write!(f, "invalid iTXt compression flag {}", flag),
+            Self::UnknownCompression(method) => {
+                write!(f, "unknown iTXt compression method {}", method)
+            }
+            Self::NotUtf8 => write!(f, "XMP packet isn't valid UTF-8"),
+            Self::NotFound => write!(f, "no XMP packet"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const PACKET: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF/><dc:creator>Jérôme</dc:creator></x:xmpmeta>"#;
+
+    #[test]
+    pub fn test_xmp_chunk() {
+        let chunk = to_chunk(PACKET).unwrap();
+        assert_eq!(*chunk.chunk_type(), ChunkType::iTXt);
+        assert!(chunk
+            .data()
+            .starts_with(b"XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta"));
+        assert!(is_xmp_chunk(&chunk));
+        assert_eq!(from_chunk(&chunk).unwrap(), PACKET);
+        assert!(to_chunk("<html/>").is_err());
+        let other = Chunk::new(ChunkType::iTXt, b"XML:com.adobe.xmp2\0\0\0\0\0".to_vec());
+        assert!(!is_xmp_chunk(&other));
+    }
+
+    #[test]
+    pub fn test_compressed_xmp() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(PACKET.as_bytes()).unwrap();
+        let mut data = b"XML:com.adobe.xmp\0\x01\0en\0XMP\0".to_vec();
+        data.extend_from_slice(&encoder.finish().unwrap());
+        let chunk = Chunk::new(ChunkType::iTXt, data);
+        assert_eq!(from_chunk(&chunk).unwrap(), PACKET);
+        let truncated = Chunk::new(ChunkType::iTXt, b"XML:com.adobe.xmp\0\0\0en".to_vec());
+        assert!(from_chunk(&truncated).is_err());
+    }
+}